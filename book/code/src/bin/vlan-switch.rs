@@ -20,28 +20,50 @@ fn main() -> Result<(), anyhow::Error> {
 
 fn init_tables(pipeline: &mut main_pipeline, m1: [u8; 6], m2: [u8; 6]) {
     // add static forwarding entries
-    pipeline.add_ingress_fwd_fib_entry("forward", &m1, &0u16.to_be_bytes(), 0);
-    pipeline.add_ingress_fwd_fib_entry("forward", &m2, &1u16.to_be_bytes(), 0);
+    pipeline
+        .add_table_entry(
+            "ingress.fwd.fib",
+            "forward",
+            &m1,
+            &0u16.to_be_bytes(),
+            0,
+        )
+        .unwrap();
+    pipeline
+        .add_table_entry(
+            "ingress.fwd.fib",
+            "forward",
+            &m2,
+            &1u16.to_be_bytes(),
+            0,
+        )
+        .unwrap();
 
     // port 0 vlan 47
-    pipeline.add_ingress_vlan_port_vlan_entry(
-        "filter",
-        0u16.to_be_bytes().as_ref(),
-        47u16.to_be_bytes().as_ref(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.vlan.port_vlan",
+            "filter",
+            0u16.to_be_bytes().as_ref(),
+            47u16.to_be_bytes().as_ref(),
+            0,
+        )
+        .unwrap();
 
     // sanity check the table
     let x = pipeline.get_ingress_vlan_port_vlan_entries();
     println!("{:#?}", x);
 
     // port 1 vlan 47
-    pipeline.add_ingress_vlan_port_vlan_entry(
-        "filter",
-        1u16.to_be_bytes().as_ref(),
-        47u16.to_be_bytes().as_ref(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.vlan.port_vlan",
+            "filter",
+            1u16.to_be_bytes().as_ref(),
+            47u16.to_be_bytes().as_ref(),
+            0,
+        )
+        .unwrap();
 }
 
 fn run_test(
@@ -0,0 +1,301 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Descriptions of the architectures the pipeline generator knows how to
+//! drive. The shape of a pipeline, which stages it has and in what order,
+//! comes from the package a program instantiates. What the generated pipeline
+//! cannot learn from the package is which metadata it is responsible for
+//! filling in and reading back, that is what an [`Architecture`] describes.
+
+/// A member of a metadata structure, such as `ingress_metadata_t.port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataMember {
+    /// Name of the metadata structure type.
+    pub ty: String,
+
+    /// Name of the member within the structure.
+    pub member: String,
+}
+
+/// A request to clone a packet, made by setting a flag along with the clone
+/// session that determines the port the clone is sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneRequest {
+    /// Clones the packet when set.
+    pub flag: MetadataMember,
+
+    /// The session the clone is sent through.
+    pub session: MetadataMember,
+}
+
+impl MetadataMember {
+    pub fn new(ty: &str, member: &str) -> Self {
+        Self {
+            ty: ty.into(),
+            member: member.into(),
+        }
+    }
+}
+
+/// How the pipeline exchanges packet disposition with the stages of a
+/// package. Metadata structures are instantiated once per packet and handed
+/// to every stage that takes a parameter of that type.
+#[derive(Debug, Clone)]
+pub struct Architecture {
+    /// Name of the package this architecture describes.
+    pub package: String,
+
+    /// Names for the pipeline members that hold each stage, in package
+    /// parameter order. When empty, the parameter names of the package
+    /// declaration are used.
+    pub stage_names: Vec<String>,
+
+    /// The number of leading stages that make up ingress processing. Egress
+    /// ports are determined once these stages have run, the stages that
+    /// follow run once for each egress port.
+    pub ingress_stages: usize,
+
+    /// Set to the port a packet arrived on before any stage runs.
+    pub ingress_port: Vec<MetadataMember>,
+
+    /// Set to the port a packet leaves on before the egress stages run.
+    /// Unless there is an `egress_spec`, the first of these is read after
+    /// ingress to determine that port.
+    pub egress_port: Vec<MetadataMember>,
+
+    /// When present, read after ingress to determine the port a packet
+    /// leaves on in place of `egress_port`.
+    pub egress_spec: Option<MetadataMember>,
+
+    /// A port that drops the packet when ingress sends it there, or when
+    /// any stage sets `egress_spec` to it.
+    pub drop_port: Option<u128>,
+
+    /// Flags that drop the packet when set by any stage.
+    pub drop: Vec<MetadataMember>,
+
+    /// A drop flag that is set before ingress runs, so that packets are
+    /// dropped unless ingress clears it.
+    pub default_drop: Option<MetadataMember>,
+
+    /// Read after ingress, a nonzero group sends the packet to each port of
+    /// the group in the pipeline's packet replication engine.
+    pub multicast_group: Option<MetadataMember>,
+
+    /// A flag that sends the packet out every port but the one it arrived
+    /// on when set by ingress.
+    pub broadcast: Option<MetadataMember>,
+
+    /// Set to the error a parser recorded. When the program reads this
+    /// member, rejected packets continue through the pipeline with the
+    /// first drop flag set if there is one, otherwise they are dropped by
+    /// the parser. A program is taken to read the member if any lvalue in it
+    /// refers to the member through the metadata struct, so a program that
+    /// only assigns to it still sees rejected packets.
+    pub parser_error: Option<MetadataMember>,
+
+    /// Set when a checksum fails to verify.
+    pub checksum_error: Option<MetadataMember>,
+
+    /// Read after ingress, a packet that is not dropped runs through ingress
+    /// again when this is set. Values the ingress deparser puts out that the
+    /// ingress parser takes are handed to the next pass, the packet is the
+    /// one the pipeline was handed.
+    pub resubmit: Option<MetadataMember>,
+
+    /// Read after ingress, sends the packet as it arrived through egress on
+    /// the port of a clone session, whether or not ingress drops it.
+    pub ingress_clone: Option<CloneRequest>,
+
+    /// Read after egress, sends the packet egress put out back through
+    /// egress on the port of a clone session, whether or not egress drops
+    /// it.
+    pub egress_clone: Option<CloneRequest>,
+
+    /// Set to the path a packet took into ingress, a variant of the member's
+    /// enum type: `NORMAL`, or `RESUBMIT` for resubmitted packets.
+    pub ingress_packet_path: Vec<MetadataMember>,
+
+    /// Set to the path a packet took into egress, a variant of the member's
+    /// enum type: `NORMAL_UNICAST`, `NORMAL_MULTICAST`, `CLONE_I2E` or
+    /// `CLONE_E2E`. Members must share an enum type.
+    pub egress_packet_path: Vec<MetadataMember>,
+}
+
+impl Architecture {
+    /// The SoftNPU architecture. Programs instantiate SoftNPU without
+    /// declaring the package, which takes a parser, ingress and egress
+    /// controls, and optionally a deparser.
+    pub fn softnpu() -> Self {
+        Self {
+            package: "SoftNPU".into(),
+            stage_names: ["parse", "ingress", "egress", "deparse"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            ingress_stages: 2,
+            ingress_port: vec![MetadataMember::new(
+                "ingress_metadata_t",
+                "port",
+            )],
+            egress_port: vec![MetadataMember::new("egress_metadata_t", "port")],
+            egress_spec: None,
+            drop_port: None,
+            drop: vec![MetadataMember::new("egress_metadata_t", "drop")],
+            default_drop: None,
+            multicast_group: None,
+            broadcast: Some(MetadataMember::new(
+                "egress_metadata_t",
+                "broadcast",
+            )),
+            parser_error: Some(MetadataMember::new(
+                "ingress_metadata_t",
+                "parser_error",
+            )),
+            checksum_error: None,
+            resubmit: None,
+            ingress_clone: None,
+            egress_clone: None,
+            ingress_packet_path: Vec::new(),
+            egress_packet_path: Vec::new(),
+        }
+    }
+
+    /// The v1model architecture as found in v1model.p4. Ingress sends a
+    /// packet to a port through `standard_metadata.egress_spec`, and drops it
+    /// by sending it to port 511. Rejected packets continue to ingress with
+    /// the parser error recorded.
+    pub fn v1switch() -> Self {
+        let standard_metadata = "standard_metadata_t";
+        Self {
+            package: "V1Switch".into(),
+            stage_names: [
+                "parse",
+                "verify_checksum",
+                "ingress",
+                "egress",
+                "compute_checksum",
+                "deparse",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            ingress_stages: 3,
+            ingress_port: vec![MetadataMember::new(
+                standard_metadata,
+                "ingress_port",
+            )],
+            egress_port: vec![MetadataMember::new(
+                standard_metadata,
+                "egress_port",
+            )],
+            egress_spec: Some(MetadataMember::new(
+                standard_metadata,
+                "egress_spec",
+            )),
+            drop_port: Some(511),
+            drop: Vec::new(),
+            default_drop: None,
+            multicast_group: None,
+            broadcast: None,
+            parser_error: Some(MetadataMember::new(
+                standard_metadata,
+                "parser_error",
+            )),
+            checksum_error: Some(MetadataMember::new(
+                standard_metadata,
+                "checksum_error",
+            )),
+            resubmit: None,
+            ingress_clone: None,
+            egress_clone: None,
+            ingress_packet_path: Vec::new(),
+            egress_packet_path: Vec::new(),
+        }
+    }
+
+    /// The Portable Switch Architecture as found in psa.p4. The ingress and
+    /// egress pipelines are each a parser, control and deparser. Ingress
+    /// drops packets unless it sends them to a port or multicast group.
+    /// Ingress may also resubmit a packet or clone it to egress, and egress
+    /// may clone the packet it puts out. Recirculation and the PSA externs,
+    /// such as Counter, Register, Hash and InternetChecksum, are not
+    /// provided, see test/src/p4/psa.p4 for the declarations x4c supports.
+    pub fn psa() -> Self {
+        let ingress_parser_input = "psa_ingress_parser_input_metadata_t";
+        let ingress_input = "psa_ingress_input_metadata_t";
+        let ingress_output = "psa_ingress_output_metadata_t";
+        let egress_parser_input = "psa_egress_parser_input_metadata_t";
+        let egress_input = "psa_egress_input_metadata_t";
+        let egress_output = "psa_egress_output_metadata_t";
+        let egress_deparser_input = "psa_egress_deparser_input_metadata_t";
+        Self {
+            package: "PSA_Switch".into(),
+            stage_names: [
+                "ingress_parse",
+                "ingress",
+                "ingress_deparse",
+                "egress_parse",
+                "egress",
+                "egress_deparse",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            ingress_stages: 3,
+            ingress_port: vec![
+                MetadataMember::new(ingress_parser_input, "ingress_port"),
+                MetadataMember::new(ingress_input, "ingress_port"),
+            ],
+            egress_port: vec![
+                MetadataMember::new(egress_parser_input, "egress_port"),
+                MetadataMember::new(egress_input, "egress_port"),
+                MetadataMember::new(egress_deparser_input, "egress_port"),
+            ],
+            egress_spec: Some(MetadataMember::new(
+                ingress_output,
+                "egress_port",
+            )),
+            drop_port: None,
+            drop: vec![
+                MetadataMember::new(ingress_output, "drop"),
+                MetadataMember::new(egress_output, "drop"),
+            ],
+            default_drop: Some(MetadataMember::new(ingress_output, "drop")),
+            multicast_group: Some(MetadataMember::new(
+                ingress_output,
+                "multicast_group",
+            )),
+            broadcast: None,
+            parser_error: Some(MetadataMember::new(
+                ingress_input,
+                "parser_error",
+            )),
+            checksum_error: None,
+            resubmit: Some(MetadataMember::new(ingress_output, "resubmit")),
+            ingress_clone: Some(CloneRequest {
+                flag: MetadataMember::new(ingress_output, "clone"),
+                session: MetadataMember::new(
+                    ingress_output,
+                    "clone_session_id",
+                ),
+            }),
+            egress_clone: Some(CloneRequest {
+                flag: MetadataMember::new(egress_output, "clone"),
+                session: MetadataMember::new(egress_output, "clone_session_id"),
+            }),
+            ingress_packet_path: vec![
+                MetadataMember::new(ingress_parser_input, "packet_path"),
+                MetadataMember::new(ingress_input, "packet_path"),
+            ],
+            egress_packet_path: vec![
+                MetadataMember::new(egress_parser_input, "packet_path"),
+                MetadataMember::new(egress_input, "packet_path"),
+            ],
+        }
+    }
+
+    /// Architectures that are built into the compiler.
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::softnpu(), Self::v1switch(), Self::psa()]
+    }
+}
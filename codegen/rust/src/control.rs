@@ -2,13 +2,15 @@
 
 use crate::{
     expression::ExpressionGenerator,
+    function::FunctionGenerator,
     qualified_table_function_name, rust_type,
     statement::{StatementContext, StatementGenerator},
-    try_extract_prefix_len, Context,
+    try_extract_prefix_len, type_size, verifies_checksums, Context,
 };
 use p4::ast::{
-    Action, Control, ControlParameter, Direction, ExpressionKind,
-    KeySetElementValue, MatchKind, Table, Type, AST,
+    Action, ActionRef, BinOp, Control, ControlParameter, Direction, Expression,
+    ExpressionKind, KeySetElementValue, MatchKind, Table, Type,
+    UserDefinedType, AST,
 };
 use p4::hlir::Hlir;
 use p4::util::resolve_lvalue;
@@ -35,13 +37,21 @@ impl<'a> ControlGenerator<'a> {
             self.generate_control(control);
         }
 
-        if let Some(ingress) = self.ast.get_control("ingress") {
-            self.generate_top_level_control(ingress);
-        };
-
-        if let Some(egress) = self.ast.get_control("egress") {
-            self.generate_top_level_control(egress);
-        };
+        // the controls a package is instantiated with are the top level
+        // controls of the pipeline
+        let mut top_level: Vec<&Control> = Vec::new();
+        if let Some(inst) = &self.ast.package_instance {
+            for arg in self.ast.package_instance_blocks(inst) {
+                if let Some(c) = self.ast.get_control(arg) {
+                    if !top_level.iter().any(|t| t.name == c.name) {
+                        top_level.push(c);
+                    }
+                }
+            }
+        }
+        for control in top_level {
+            self.generate_top_level_control(control);
+        }
     }
 
     fn generate_top_level_control(&mut self, control: &Control) {
@@ -89,6 +99,12 @@ impl<'a> ControlGenerator<'a> {
             self.generate_control_action(control, action);
         }
 
+        for f in &control.functions {
+            let params = self.scope_parameters(control);
+            FunctionGenerator::new(self.ast, self.hlir, self.ctx)
+                .generate_function(Some(control), f, params);
+        }
+
         let tables = control.tables(self.ast);
         for (cs, table) in tables {
             let c = cs.last().unwrap().1;
@@ -117,6 +133,10 @@ impl<'a> ControlGenerator<'a> {
             });
         }
 
+        if verifies_checksums(control, self.ast) {
+            params.push(quote! { checksum_error: &mut bool });
+        }
+
         let name = format_ident!("{}_apply", control.name);
         let apply_body = self.generate_control_apply_body(control);
         let sig = quote! {
@@ -125,6 +145,8 @@ impl<'a> ControlGenerator<'a> {
         self.ctx.functions.insert(
             name.to_string(),
             quote! {
+                // tables are passed in one argument each
+                #[allow(clippy::too_many_arguments)]
                 pub fn #name #sig {
                     #apply_body
                 }
@@ -145,10 +167,21 @@ impl<'a> ControlGenerator<'a> {
             match arg.ty {
                 Type::UserDefined(ref typename) => {
                     match self.ast.get_user_defined_type(typename) {
-                        Some(_udt) => {
+                        Some(udt) => {
                             let name = format_ident!("{}", arg.name);
                             let ty = rust_type(&arg.ty);
+                            // extern objects such as packet_out are
+                            // directionless and may be modified by their
+                            // methods
+                            let mutable =
+                                matches!(udt, UserDefinedType::Extern(_));
                             match &arg.direction {
+                                _ if mutable => {
+                                    params.push(quote! {
+                                        #name: &mut #ty
+                                    });
+                                    types.push(quote! { &mut #ty });
+                                }
                                 Direction::Out | Direction::InOut => {
                                     params.push(quote! {
                                         #name: &mut #ty
@@ -197,8 +230,9 @@ impl<'a> ControlGenerator<'a> {
         (params, types)
     }
 
-    fn generate_control_action(&mut self, control: &Control, action: &Action) {
-        let name = format_ident!("{}_action_{}", control.name, action.name);
+    /// The parameters of the control followed by its extern instances. These
+    /// are passed to actions and functions local to the control.
+    fn scope_parameters(&mut self, control: &Control) -> Vec<TokenStream> {
         let (mut params, _) = self.control_parameters(control);
 
         for var in &control.variables {
@@ -213,6 +247,13 @@ impl<'a> ControlGenerator<'a> {
             }
         }
 
+        params
+    }
+
+    fn generate_control_action(&mut self, control: &Control, action: &Action) {
+        let name = format_ident!("{}_action_{}", control.name, action.name);
+        let mut params = self.scope_parameters(control);
+
         let mut dump_fmt = Vec::new();
         for p in &action.parameters {
             dump_fmt.push(p.name.clone() + "={}");
@@ -328,9 +369,22 @@ impl<'a> ControlGenerator<'a> {
             let mut #table_name: #table_type = #table_type::new();
         };
 
-        if table.const_entries.is_empty() {
-            tokens.extend(quote! { #table_name });
-            return (table_type, tokens);
+        if table.default_action.name != "NoAction" {
+            let action = self.generate_const_action(
+                control,
+                &table.default_action,
+                control_param_types,
+            );
+            let action_id = &table.default_action.name;
+            tokens.extend(quote! {
+                #action
+                #table_name.default_action =
+                    Some(p4rs::table::DefaultAction {
+                        action,
+                        action_id: #action_id,
+                        parameter_data: Vec::new(),
+                    });
+            });
         }
 
         for entry in &table.const_entries {
@@ -338,7 +392,7 @@ impl<'a> ControlGenerator<'a> {
             for (i, k) in entry.keyset.iter().enumerate() {
                 match &k.value {
                     KeySetElementValue::Expression(e) => {
-                        let eg = ExpressionGenerator::new(self.hlir);
+                        let eg = ExpressionGenerator::new(self.ast, self.hlir);
                         let xpr = eg.generate_expression(e.as_ref());
                         let ks = match table.key[i].1 {
                             MatchKind::Exact => {
@@ -356,23 +410,17 @@ impl<'a> ControlGenerator<'a> {
                                 }
                             }
                             MatchKind::LongestPrefixMatch => {
-                                let len = match try_extract_prefix_len(e) {
-                                    Some(len) => len,
-                                    None => {
-                                        panic!(
-                                            "codegen: coult not determine prefix 
-                                        len for key {:#?}",
-                                        table.key[i].1,
-                                        );
-                                    }
+                                let width = self.key_width(table, i);
+                                // a value without a mask matches all of it
+                                let len = match &e.kind {
+                                    ExpressionKind::Binary(
+                                        _,
+                                        BinOp::Mask,
+                                        _,
+                                    ) => self.prefix_len(table, i, e, width),
+                                    _ => width as u8,
                                 };
-                                let k = format_ident!("{}", "Lpm");
-                                quote! {
-                                    p4rs::table::Key::#k(p4rs::table::Prefix{
-                                        addr: bitvec_to_ip6addr(&(#xpr)),
-                                        len: #len,
-                                    })
-                                }
+                                const_lpm_key(width, xpr, len)
                             }
                             MatchKind::Range => {
                                 let k = format_ident!("Range");
@@ -383,82 +431,42 @@ impl<'a> ControlGenerator<'a> {
                         };
                         keyset.push(ks);
                     }
-                    x => todo!("key set element {:?}", x),
-                }
-            }
-
-            let action = match control.get_action(&entry.action.name) {
-                Some(action) => action,
-                None => {
-                    panic!("codegen: action {} not found", entry.action.name);
-                }
-            };
-
-            let mut action_fn_args = Vec::new();
-            for arg in &control.parameters {
-                let a = format_ident!("{}", arg.name);
-                action_fn_args.push(quote! { #a });
-            }
-
-            let action_fn_name =
-                format_ident!("{}_action_{}", control.name, entry.action.name);
-            for (i, expr) in entry.action.parameters.iter().enumerate() {
-                match &expr.kind {
-                    ExpressionKind::IntegerLit(v) => {
-                        match &action.parameters[i].ty {
-                            Type::Bit(n) => {
-                                if *n <= 8 {
-                                    let v = *v as u8;
-                                    action_fn_args.push(quote! {
-                                        #v.view_bits::<Msb0>().to_bitvec()
-                                    });
-                                }
-                            }
-                            x => {
-                                todo!("action int lit expression type {:?}", x)
-                            }
-                        }
-                    }
-                    ExpressionKind::BitLit(width, v) => {
-                        match &action.parameters[i].ty {
-                            Type::Bit(n) => {
-                                let n = *n;
-                                if n != *width as usize {
-                                    panic!(
-                                        "{:?} not compatible with {:?}",
-                                        expr.kind, action.parameters[i],
-                                    );
-                                }
-                                let size = n;
-                                action_fn_args.push(quote! {{
-                                    let mut x = bitvec![mut u8, Msb0; 0; #size];
-                                    x.store_le(#v);
-                                    x
-                                }});
-                            }
-                            x => {
-                                todo!("action bit lit expression type {:?}", x)
+                    KeySetElementValue::Masked(val, mask) => {
+                        let eg = ExpressionGenerator::new(self.ast, self.hlir);
+                        let val = eg.generate_expression(val.as_ref());
+                        let mask_tks = eg.generate_expression(mask.as_ref());
+                        let ks = match &table.key[i].1 {
+                            MatchKind::LongestPrefixMatch => {
+                                let width = self.key_width(table, i);
+                                let len =
+                                    self.prefix_len(table, i, mask, width);
+                                const_lpm_key(
+                                    width,
+                                    quote! { #val & #mask_tks },
+                                    len,
+                                )
                             }
-                        }
+                            x => todo!("masked key set element for {:?}", x),
+                        };
+                        keyset.push(ks);
                     }
-                    x => todo!("action parameter type {:?}", x),
+                    x => todo!("key set element {:?}", x),
                 }
             }
 
-            let mut closure_params = Vec::new();
-            for x in &control.parameters {
-                let name = format_ident!("{}", x.name);
-                closure_params.push(quote! { #name });
-            }
+            let action_id = &entry.action.name;
+
+            let action = self.generate_const_action(
+                control,
+                &entry.action,
+                control_param_types,
+            );
 
             tokens.extend(quote! {
 
-                let action: std::sync::Arc<dyn Fn(#(#control_param_types),*)> =
-                    std::sync::Arc::new(|#(#closure_params),*| {
-                        #action_fn_name(#(#action_fn_args),*);
-                    });
+                #action
 
-                #table_name.entries.insert(
+                #table_name.insert(
                     p4rs::table::TableEntry::<
                         #n,
                         std::sync::Arc<dyn Fn(#(#control_param_types),*)>,
@@ -467,10 +475,10 @@ impl<'a> ControlGenerator<'a> {
                         priority: 0,
                         name: "your name here".into(),
                         action,
+                        action_id: #action_id,
 
                         //TODO actual data, does this actually matter for
                         //constant entries?
-                        action_id: String::new(),
                         parameter_data: Vec::new(),
                     });
             })
@@ -481,6 +489,121 @@ impl<'a> ControlGenerator<'a> {
         (table_type, tokens)
     }
 
+    /// The width in bits of the `i`th key of a table.
+    fn key_width(&self, table: &Table, i: usize) -> usize {
+        type_size(&self.hlir.lvalue_decls[&table.key[i].0].ty, self.ast)
+    }
+
+    /// The prefix length a const entry's mask gives the `i`th key of a table.
+    fn prefix_len(
+        &self,
+        table: &Table,
+        i: usize,
+        mask: &Expression,
+        width: usize,
+    ) -> u8 {
+        try_extract_prefix_len(mask, width).unwrap_or_else(|| {
+            panic!(
+                "codegen: could not determine prefix len for key {} of \
+                table {}",
+                i, table.name,
+            )
+        })
+    }
+
+    /// Generate a `let action = ...` binding for a closure that calls the
+    /// referenced action with its constant arguments.
+    fn generate_const_action(
+        &self,
+        control: &Control,
+        aref: &ActionRef,
+        control_param_types: &Vec<TokenStream>,
+    ) -> TokenStream {
+        let action = match control.get_action(&aref.name) {
+            Some(action) => action,
+            None => {
+                panic!("codegen: action {} not found", aref.name);
+            }
+        };
+
+        let mut closure_params = Vec::new();
+        for x in &control.parameters {
+            let name = format_ident!("{}", x.name);
+            closure_params.push(quote! { #name });
+        }
+        for var in &control.variables {
+            if let Type::UserDefined(typename) = &var.ty {
+                if self.ast.get_extern(typename).is_some() {
+                    let name = format_ident!("{}", var.name);
+                    closure_params.push(quote! { #name });
+                }
+            }
+        }
+        let mut action_fn_args = closure_params.clone();
+
+        let action_fn_name =
+            format_ident!("{}_action_{}", control.name, aref.name);
+        for (i, expr) in aref.parameters.iter().enumerate() {
+            match &expr.kind {
+                ExpressionKind::IntegerLit(v) => {
+                    match &action.parameters[i].ty {
+                        Type::Bit(n) | Type::Int(n) => {
+                            if *n <= 8 {
+                                let v = *v as u8;
+                                action_fn_args.push(quote! {
+                                    #v.view_bits::<Msb0>().to_bitvec()
+                                });
+                            } else if *n <= 128 {
+                                let v = *v as u128;
+                                let size = *n;
+                                action_fn_args.push(quote! {{
+                                    let mut x = bitvec![mut u8, Msb0; 0; #size];
+                                    x.store_le(#v);
+                                    x
+                                }});
+                            } else {
+                                todo!("action int lit for bit<{}>", n)
+                            }
+                        }
+                        x => {
+                            todo!("action int lit expression type {:?}", x)
+                        }
+                    }
+                }
+                ExpressionKind::BitLit(width, v) => {
+                    match &action.parameters[i].ty {
+                        Type::Bit(n) => {
+                            let n = *n;
+                            if n != *width as usize {
+                                panic!(
+                                    "{:?} not compatible with {:?}",
+                                    expr.kind, action.parameters[i],
+                                );
+                            }
+                            let size = n;
+                            action_fn_args.push(quote! {{
+                                let mut x = bitvec![mut u8, Msb0; 0; #size];
+                                x.store_le(#v);
+                                x
+                            }});
+                        }
+                        x => {
+                            todo!("action bit lit expression type {:?}", x)
+                        }
+                    }
+                }
+                x => todo!("action parameter type {:?}", x),
+            }
+        }
+
+        quote! {
+            let action: std::sync::Arc<dyn Fn(#(#control_param_types),*)> =
+                std::sync::Arc::new(|#(#closure_params),*| {
+                    #action_fn_name(#(#action_fn_args),*);
+                });
+        }
+    }
+
     fn generate_control_apply_body(
         &mut self,
         control: &Control,
@@ -519,3 +642,22 @@ impl<'a> ControlGenerator<'a> {
         control.parameters.iter().find(|&arg| arg.name == arg_name)
     }
 }
+
+/// Generate a longest prefix match key for a const entry, taking the address
+/// from `value` by the width of the key.
+fn const_lpm_key(width: usize, value: TokenStream, len: u8) -> TokenStream {
+    let addr = match width {
+        32 => quote! { bitvec_to_ip4addr(&(#value)) },
+        128 => quote! { bitvec_to_ip6addr(&(#value)) },
+        x => panic!(
+            "codegen: lpm keys must be 32 or 128 bits wide, found {} bits",
+            x,
+        ),
+    };
+    quote! {
+        p4rs::table::Key::Lpm(p4rs::table::Prefix {
+            addr: #addr,
+            len: #len,
+        })
+    }
+}
@@ -1,17 +1,38 @@
 // Copyright 2022 Oxide Computer Company
 
-use p4::ast::{BinOp, DeclarationInfo, Expression, ExpressionKind, Lvalue};
+use crate::{
+    function::function_name, header_stack_of, is_header, lvalue_path, rust_type,
+};
+use p4::ast::{
+    BinOp, Call, Control, DeclarationInfo, Direction, Expression,
+    ExpressionKind, Function, Lvalue, Type, UnOp, AST,
+};
 use p4::hlir::Hlir;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 pub(crate) struct ExpressionGenerator<'a> {
+    ast: &'a AST,
     hlir: &'a Hlir,
+
+    /// The control expressions are generated within, if any. Table apply
+    /// results and control local functions may only be referenced from
+    /// within a control.
+    control: Option<&'a Control>,
 }
 
 impl<'a> ExpressionGenerator<'a> {
-    pub fn new(hlir: &'a Hlir) -> Self {
-        Self { hlir }
+    pub fn new(ast: &'a AST, hlir: &'a Hlir) -> Self {
+        Self {
+            ast,
+            hlir,
+            control: None,
+        }
+    }
+
+    pub fn with_control(mut self, control: &'a Control) -> Self {
+        self.control = Some(control);
+        self
     }
 
     pub(crate) fn generate_expression(&self, xpr: &Expression) -> TokenStream {
@@ -25,26 +46,114 @@ impl<'a> ExpressionGenerator<'a> {
             ExpressionKind::BitLit(width, v) => {
                 self.generate_bit_literal(*width, *v)
             }
-            ExpressionKind::SignedLit(_width, _v) => {
-                todo!("generate expression signed lit");
+            ExpressionKind::SignedLit(width, v) => {
+                // signed values are carried as two's complement bit vectors
+                let mask = u128::MAX >> (128 - *width as u32);
+                self.generate_bit_literal(*width, (*v as u128) & mask)
             }
             ExpressionKind::Lvalue(v) => self.generate_lvalue(v),
             ExpressionKind::Binary(lhs, op, rhs) => {
-                let lhs_tks = self.generate_expression(lhs.as_ref());
+                let lhs_tks = self.generate_operand(lhs.as_ref(), rhs.as_ref());
                 let op_tks = self.generate_binop(*op);
-                let rhs_tks = self.generate_expression(rhs.as_ref());
+                let rhs_tks = self.generate_operand(rhs.as_ref(), lhs.as_ref());
                 let mut ts = TokenStream::new();
+                let literals =
+                    matches!(lhs.kind, ExpressionKind::IntegerLit(_))
+                        && matches!(rhs.kind, ExpressionKind::IntegerLit(_));
+                let signed =
+                    matches!(
+                        self.hlir.expression_types.get(lhs.as_ref()),
+                        Some(Type::Int(_))
+                    ) && !matches!(lhs.kind, ExpressionKind::IntegerLit(_));
                 match op {
+                    _ if literals => {
+                        ts.extend(quote! { (#lhs_tks) #op_tks (#rhs_tks) });
+                    }
+                    BinOp::Mul => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::mul_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::Div if signed => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::div_signed_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::Div => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::div_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::Shl | BinOp::Shr => {
+                        let amount = self.generate_shift_amount(rhs.as_ref());
+                        let shift = match (op, signed) {
+                            (BinOp::Shl, _) => quote! { shl_le },
+                            (_, true) => quote! { shr_signed_le },
+                            (_, false) => quote! { shr_le },
+                        };
+                        ts.extend(quote! {
+                            p4rs::bitmath::#shift(#lhs_tks.clone(), #amount)
+                        });
+                    }
+                    BinOp::Concat => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::concat_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::LogicalAnd | BinOp::LogicalOr => {
+                        ts.extend(quote! { (#lhs_tks) #op_tks (#rhs_tks) });
+                    }
                     BinOp::Add => {
                         ts.extend(quote!{
                             p4rs::bitmath::add_le(#lhs_tks.clone(), #rhs_tks.clone())
                         });
                     }
+                    BinOp::Subtract => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sub_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::SatAdd if signed => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::add_sat_signed_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::SatAdd => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::add_sat_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::SatSubtract if signed => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sub_sat_signed_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::SatSubtract => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::sub_sat_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
+                    BinOp::Mod if signed => {
+                        ts.extend(quote!{
+                            p4rs::bitmath::mod_signed_le(#lhs_tks.clone(), #rhs_tks.clone())
+                        });
+                    }
                     BinOp::Mod => {
                         ts.extend(quote!{
                             p4rs::bitmath::mod_le(#lhs_tks.clone(), #rhs_tks.clone())
                         });
                     }
+                    BinOp::Geq | BinOp::Gt | BinOp::Leq | BinOp::Lt => {
+                        let cmp = if signed {
+                            quote! { cmp_signed_le }
+                        } else {
+                            quote! { cmp_le }
+                        };
+                        ts.extend(quote! {
+                            p4rs::bitmath::#cmp(&#lhs_tks, &#rhs_tks)
+                                #op_tks std::cmp::Ordering::Equal
+                        });
+                    }
                     BinOp::Eq | BinOp::NotEq => {
                         let lhs_tks_ = match &lhs.as_ref().kind {
                             ExpressionKind::Lvalue(lval) => {
@@ -92,22 +201,96 @@ impl<'a> ExpressionGenerator<'a> {
                             }
                             _ => rhs_tks,
                         };
-                        ts.extend(lhs_tks_);
-                        ts.extend(op_tks);
-                        ts.extend(rhs_tks_);
+                        // serializable enums compare against their
+                        // underlying bit representation
+                        let lhs_ty = self.hlir.expression_types.get(lhs);
+                        let rhs_ty = self.hlir.expression_types.get(rhs);
+                        let (lhs_tks_, rhs_tks_) = match (lhs_ty, rhs_ty) {
+                            (
+                                Some(Type::UserDefined(_)),
+                                Some(Type::Bit(_)),
+                            ) => (
+                                quote! { BitVec::<u8, Msb0>::from(#lhs_tks_) },
+                                rhs_tks_,
+                            ),
+                            (
+                                Some(Type::Bit(_)),
+                                Some(Type::UserDefined(_)),
+                            ) => (
+                                lhs_tks_,
+                                quote! { BitVec::<u8, Msb0>::from(#rhs_tks_) },
+                            ),
+                            _ => (lhs_tks_, rhs_tks_),
+                        };
+                        ts.extend(quote! { (#lhs_tks_) #op_tks (#rhs_tks_) });
                     }
                     _ => {
-                        ts.extend(lhs_tks);
-                        ts.extend(op_tks);
-                        ts.extend(rhs_tks);
+                        ts.extend(quote! {
+                            (#lhs_tks.clone()) #op_tks (#rhs_tks.clone())
+                        });
+                    }
+                }
+                // P4 and Rust operator precedence differ, keep the structure
+                // of the P4 expression explicit
+                quote! { (#ts) }
+            }
+            ExpressionKind::Unary(op, operand) => {
+                let tks = self.generate_expression(operand.as_ref());
+                match (op, &operand.kind) {
+                    (UnOp::Not, _) => quote! { !(#tks) },
+                    (UnOp::Negate, ExpressionKind::IntegerLit(_)) => {
+                        quote! { -(#tks) }
+                    }
+                    (UnOp::Negate, _) => {
+                        quote! { p4rs::bitmath::neg_le(#tks.clone()) }
                     }
+                    (UnOp::Complement, _) => quote! { !(#tks.clone()) },
+                }
+            }
+            ExpressionKind::Cast(ty, operand) => {
+                let from = self
+                    .hlir
+                    .expression_types
+                    .get(operand.as_ref())
+                    .unwrap_or_else(|| panic!("type for {:#?}", operand));
+                let value = self.generate_expression(operand.as_ref());
+                self.generate_conversion(operand.as_ref(), value, from, ty)
+            }
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                let cond = self.generate_expression(cond.as_ref());
+                let then_tks =
+                    self.generate_operand(then.as_ref(), otherwise.as_ref());
+                let otherwise_tks =
+                    self.generate_operand(otherwise.as_ref(), then.as_ref());
+                quote! {
+                    if #cond { #then_tks.clone() } else { #otherwise_tks.clone() }
                 }
-                ts
             }
             ExpressionKind::Index(lval, xpr) => {
-                let mut ts = self.generate_lvalue(lval);
-                ts.extend(self.generate_expression(xpr.as_ref()));
-                ts
+                let ts = self.generate_lvalue(lval);
+                if let Some(Type::HeaderStack(..)) =
+                    self.hlir.lvalue_decls.get(lval).map(|info| &info.ty)
+                {
+                    let i = match &xpr.kind {
+                        ExpressionKind::IntegerLit(i) => {
+                            let i = *i as usize;
+                            quote! { #i }
+                        }
+                        _ => {
+                            let i = self.generate_expression(xpr.as_ref());
+                            quote! { (#i).load_le::<usize>() }
+                        }
+                    };
+                    return quote! { #ts[#i] };
+                }
+                match &xpr.kind {
+                    ExpressionKind::Slice(hi, lo) => {
+                        let (hi, lo) = self.slice_bounds(hi, lo);
+                        quote! { p4rs::bitmath::slice_le(&#ts, #hi, #lo) }
+                    }
+                    // hlir only allows slices of other values
+                    x => panic!("codegen: index {:?} of {}", x, lval.name),
+                }
             }
             ExpressionKind::Slice(begin, end) => {
                 let l = match &begin.kind {
@@ -123,24 +306,7 @@ impl<'a> ExpressionGenerator<'a> {
                     [#r..#l]
                 }
             }
-            ExpressionKind::Call(call) => {
-                let lv: Vec<TokenStream> = call
-                    .lval
-                    .name
-                    .split('.')
-                    .map(|x| format_ident!("{}", x))
-                    .map(|x| quote! { #x })
-                    .collect();
-
-                let lvalue = quote! { #(#lv).* };
-                let mut args = Vec::new();
-                for arg in &call.args {
-                    args.push(self.generate_expression(arg));
-                }
-                quote! {
-                    #lvalue(#(#args),*)
-                }
-            }
+            ExpressionKind::Call(call) => self.generate_call(call),
             ExpressionKind::List(elements) => {
                 let mut parts = Vec::new();
                 for e in elements {
@@ -150,7 +316,353 @@ impl<'a> ExpressionGenerator<'a> {
                     &[ #(&#parts),* ]
                 }
             }
+            ExpressionKind::Member(xpr, member) => {
+                let xpr = match (&xpr.kind, self.control) {
+                    (ExpressionKind::Call(call), Some(control))
+                        if control.get_table(call.lval.root()).is_some() =>
+                    {
+                        self.generate_table_apply(self.ast, control, call)
+                    }
+                    _ => self.generate_expression(xpr.as_ref()),
+                };
+                let member = format_ident!("{}", member);
+                quote! { (#xpr).#member }
+            }
+        }
+    }
+
+    /// Convert `value`, the generated code for `xpr`, from one type to
+    /// another. Unsigned values are zero extended and signed values are sign
+    /// extended when widening, both are truncated when narrowing.
+    pub(crate) fn generate_conversion(
+        &self,
+        xpr: &Expression,
+        value: TokenStream,
+        from: &Type,
+        to: &Type,
+    ) -> TokenStream {
+        match (&xpr.kind, from, to) {
+            (ExpressionKind::IntegerLit(v), _, Type::Bit(w))
+            | (ExpressionKind::IntegerLit(v), _, Type::Int(w)) => {
+                let mask = u128::MAX.checked_shr(128 - *w as u32).unwrap_or(0);
+                self.generate_bit_literal(*w as u16, (*v as u128) & mask)
+            }
+            (_, from, to) if from == to => value,
+            (_, Type::Bit(_), Type::Bit(w))
+            | (_, Type::Bit(_), Type::Int(w)) => {
+                quote! { p4rs::bitmath::resize_le(#value.clone(), #w) }
+            }
+            (_, Type::Int(_), Type::Int(w))
+            | (_, Type::Int(_), Type::Bit(w)) => {
+                quote! { p4rs::bitmath::sign_extend_le(#value.clone(), #w) }
+            }
+            (_, Type::Bool, Type::Bit(w)) => {
+                quote! {{
+                    let mut x = bitvec![mut u8, Msb0; 0; #w];
+                    x.store_le(#value as u8);
+                    x
+                }}
+            }
+            (_, Type::Bit(_), Type::Bool) => quote! { #value.any() },
+            (_, Type::UserDefined(_), Type::Bit(_) | Type::Int(_)) => {
+                quote! { BitVec::<u8, Msb0>::from(#value) }
+            }
+            (_, Type::Bit(_) | Type::Int(_), Type::UserDefined(name)) => {
+                // values that do not name a member are kept as they are
+                let name = format_ident!("{}", name);
+                quote! { #name::from(&#value) }
+            }
+            _ => todo!("type converter for {} to {}", from, to),
+        }
+    }
+
+    /// The constant bounds of a slice.
+    pub(crate) fn slice_bounds(
+        &self,
+        hi: &Expression,
+        lo: &Expression,
+    ) -> (usize, usize) {
+        let bound = |xpr: &Expression| match &xpr.kind {
+            ExpressionKind::IntegerLit(v) => *v as usize,
+            _ => panic!("slice ranges can only be integer literals"),
+        };
+        (bound(hi), bound(lo))
+    }
+
+    /// Generate an operand of a binary expression. Integer literals take on
+    /// the width of the other operand.
+    fn generate_operand(
+        &self,
+        xpr: &Expression,
+        other: &Expression,
+    ) -> TokenStream {
+        let v = match (&xpr.kind, &other.kind) {
+            (ExpressionKind::IntegerLit(_), ExpressionKind::IntegerLit(_)) => {
+                return self.generate_expression(xpr)
+            }
+            (ExpressionKind::IntegerLit(v), _) => *v,
+            _ => return self.generate_expression(xpr),
+        };
+        match self.hlir.expression_types.get(other) {
+            Some(Type::Bit(w)) | Some(Type::Int(w)) if *w <= 128 => {
+                let mask = u128::MAX.checked_shr(128 - *w as u32).unwrap_or(0);
+                self.generate_bit_literal(*w as u16, (v as u128) & mask)
+            }
+            _ => self.generate_expression(xpr),
+        }
+    }
+
+    fn generate_shift_amount(&self, xpr: &Expression) -> TokenStream {
+        match &xpr.kind {
+            ExpressionKind::IntegerLit(v) => {
+                let v = *v as usize;
+                quote! { #v }
+            }
+            _ => {
+                let tks = self.generate_expression(xpr);
+                quote! {{
+                    let n: u128 = #tks.load_le();
+                    n as usize
+                }}
+            }
+        }
+    }
+
+    pub(crate) fn generate_call(&self, call: &Call) -> TokenStream {
+        if let Some(DeclarationInfo::Function) =
+            self.hlir.lvalue_decls.get(&call.lval).map(|n| &n.decl)
+        {
+            return self.generate_function_call(call);
+        }
+        if let ("lookahead", [ty]) =
+            (call.lval.leaf(), call.type_args.as_slice())
+        {
+            return self.generate_lookahead(&call.lval.pop_right(), ty);
+        }
+        let lvalue = lvalue_path(&call.lval, self.hlir);
+        // header stack push and pop counts are plain integers
+        let stack_method = header_stack_of(&call.lval, self.hlir).is_some();
+        let mut args = Vec::new();
+        for arg in &call.args {
+            let arg_xpr = self.generate_expression(arg);
+            if stack_method {
+                args.push(quote! { (#arg_xpr) as usize });
+            } else {
+                args.push(arg_xpr);
+            }
+        }
+        quote! {
+            #lvalue(#(#args),*)
+        }
+    }
+
+    /// Generate a lookahead of a `ty` valued quantity in the packet `pkt`.
+    fn generate_lookahead(&self, pkt: &Lvalue, ty: &Type) -> TokenStream {
+        let pkt = lvalue_path(pkt, self.hlir);
+        match ty {
+            Type::Bit(n) | Type::Int(n) => quote! {
+                #pkt.lookahead_bits(#n)
+            },
+            Type::Bool => quote! {
+                #pkt.lookahead_bits(1)[0]
+            },
+            Type::UserDefined(_) => {
+                let ty = rust_type(ty);
+                quote! { #pkt.lookahead::<#ty>() }
+            }
+            x => todo!("lookahead of {}", x),
+        }
+    }
+
+    /// Generate a call to a P4 function. Functions local to a control are
+    /// passed the parameters of the control ahead of their own arguments.
+    /// Arguments are copied in before the call, and out arguments are copied
+    /// back out after it.
+    fn generate_function_call(&self, call: &Call) -> TokenStream {
+        let name = call.lval.root();
+        let (control, f): (Option<&Control>, &Function) =
+            match self.control.and_then(|c| Some((c, c.get_function(name)?))) {
+                Some((c, f)) => (Some(c), f),
+                None => match self.ast.get_function(name) {
+                    Some(f) => (None, f),
+                    None => panic!("codegen: function {} not found", name),
+                },
+            };
+
+        let mut args = Vec::new();
+        if let Some(control) = control {
+            for p in &control.parameters {
+                let name = format_ident!("{}", p.name);
+                args.push(quote! { #name });
+            }
+            for var in &control.variables {
+                if let Type::UserDefined(typename) = &var.ty {
+                    if self.ast.get_extern(typename).is_some() {
+                        let name = format_ident!("{}", var.name);
+                        args.push(quote! { &#name });
+                    }
+                }
+            }
         }
+
+        let mut locals = Vec::new();
+        let mut copy_out = Vec::new();
+        for (i, (arg, p)) in
+            call.args.iter().zip(f.parameters.iter()).enumerate()
+        {
+            let local = format_ident!("arg{}", i);
+            match (p.direction, &arg.kind) {
+                (
+                    Direction::Out | Direction::InOut,
+                    ExpressionKind::Lvalue(l),
+                ) => {
+                    let path = self.generate_lvalue(l);
+                    // out and inout parameters are already references
+                    let target =
+                        match self.hlir.lvalue_decls.get(l).map(|n| &n.decl) {
+                            Some(DeclarationInfo::Parameter(
+                                Direction::Out | Direction::InOut,
+                            )) if l.degree() == 1 => quote! { *#path },
+                            _ => path.clone(),
+                        };
+                    locals.push(quote! { let mut #local = #path.clone(); });
+                    copy_out.push(quote! { #target = #local; });
+                    args.push(quote! { &mut #local });
+                }
+                _ => {
+                    let ty = self.hlir.expression_types.get(arg.as_ref());
+                    let value = self.generate_expression(arg.as_ref());
+                    let value = match ty {
+                        Some(ty) if ty != &p.ty => self.generate_conversion(
+                            arg.as_ref(),
+                            value,
+                            ty,
+                            &p.ty,
+                        ),
+                        _ => value,
+                    };
+                    if p.ty == Type::Bool {
+                        locals.push(quote! { let #local = #value; });
+                    } else {
+                        locals.push(quote! { let #local = (#value).clone(); });
+                    }
+                    args.push(quote! { #local });
+                }
+            }
+        }
+
+        let name = function_name(control, f);
+        if f.return_type == Type::Void {
+            return quote! {{
+                #(#locals)*
+                #name(#(#args),*);
+                #(#copy_out)*
+            }};
+        }
+        quote! {{
+            #(#locals)*
+            let result = #name(#(#args),*);
+            #(#copy_out)*
+            result
+        }}
+    }
+
+    /// Generate an expression that applies a local table and evaluates to a
+    /// `p4rs::table::ApplyResult`.
+    pub(crate) fn generate_table_apply(
+        &self,
+        ast: &AST,
+        control: &Control,
+        c: &Call,
+    ) -> TokenStream {
+        let root = c.lval.root();
+        let table = match control.get_table(root) {
+            Some(table) => table,
+            None => {
+                panic!(
+                    "codegen: table {} not found in control {}",
+                    root, control.name,
+                );
+            }
+        };
+
+        //
+        // match an action based on the key material
+        //
+
+        let table_name = format_ident!("{}", c.lval.root());
+
+        let table_name_str = format!("{}_table_{}", control.name, table.name,);
+
+        let mut action_args = Vec::new();
+        for p in &control.parameters {
+            let name = format_ident!("{}", p.name);
+            action_args.push(quote! { #name });
+        }
+
+        for var in &control.variables {
+            let name = format_ident!("{}", var.name);
+            if let Type::UserDefined(typename) = &var.ty {
+                if ast.get_extern(typename).is_some() {
+                    action_args.push(quote! { &#name });
+                }
+            }
+        }
+
+        let mut selector_components = Vec::new();
+        for (lval, _match_kind) in &table.key {
+            let lvref = lvalue_path(lval, self.hlir);
+
+            // determine if this lvalue references a header or a struct,
+            // if it's a header there's a bit of extra unsrapping we
+            // need to do to match the selector against the value.
+
+            let names = control.names();
+
+            if lval.degree() > 1 && is_header(&lval.pop_right(), ast, &names) {
+                //TODO: to_biguint is bad here, copying on data path
+                selector_components.push(quote! {
+                    p4rs::bitvec_to_biguint(
+                        &#lvref
+                    ).value
+                });
+            } else {
+                selector_components.push(quote! {
+                    p4rs::bitvec_to_biguint(&#lvref).value
+                });
+            }
+        }
+        quote! {{
+            let matches = #table_name.match_selector(
+                &[#(#selector_components),*]
+            );
+            if matches.len() > 0 {
+                softnpu_provider::control_table_hit!(||#table_name_str);
+                (matches[0].action)(#(#action_args),*);
+                p4rs::table::ApplyResult {
+                    hit: true,
+                    miss: false,
+                    action_run: matches[0].action_id,
+                }
+            } else {
+                softnpu_provider::control_table_miss!(||#table_name_str);
+                match &#table_name.default_action {
+                    Some(default) => {
+                        (default.action)(#(#action_args),*);
+                        p4rs::table::ApplyResult {
+                            hit: false,
+                            miss: true,
+                            action_run: default.action_id,
+                        }
+                    }
+                    None => p4rs::table::ApplyResult {
+                        hit: false,
+                        miss: true,
+                        action_run: "NoAction",
+                    },
+                }
+            }
+        }}
     }
 
     pub(crate) fn generate_bit_literal(
@@ -175,6 +687,8 @@ impl<'a> ExpressionGenerator<'a> {
         match op {
             BinOp::Add => quote! { + },
             BinOp::Subtract => quote! { - },
+            BinOp::SatAdd => quote! { |+| },
+            BinOp::SatSubtract => quote! { |-| },
             BinOp::Mod => quote! { % },
             BinOp::Geq => quote! { >= },
             BinOp::Gt => quote! { > },
@@ -186,18 +700,35 @@ impl<'a> ExpressionGenerator<'a> {
             BinOp::BitAnd => quote! { & },
             BinOp::BitOr => quote! { | },
             BinOp::Xor => quote! { ^ },
+            BinOp::Shl => quote! { << },
+            BinOp::Shr => quote! { >> },
+            BinOp::Mul => quote! { * },
+            BinOp::Div => quote! { / },
+            BinOp::LogicalAnd => quote! { && },
+            BinOp::LogicalOr => quote! { || },
+            BinOp::Concat => quote! { ++ },
         }
     }
 
-    pub(crate) fn generate_lvalue(&self, lval: &Lvalue) -> TokenStream {
-        let lv: Vec<TokenStream> = lval
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+    /// Constants are substituted with their initializer, with integer
+    /// initializers taking the width of the constant.
+    fn generate_constant(&self, lval: &Lvalue) -> TokenStream {
+        let c = self
+            .ast
+            .get_constant(&lval.name)
+            .unwrap_or_else(|| panic!("constant {} not found", lval.name));
+        match (&c.initializer.kind, &c.ty) {
+            (ExpressionKind::IntegerLit(v), Type::Bit(w))
+            | (ExpressionKind::IntegerLit(v), Type::Int(w)) => {
+                let mask = u128::MAX.checked_shr(128 - *w as u32).unwrap_or(0);
+                self.generate_bit_literal(*w as u16, (*v as u128) & mask)
+            }
+            _ => self.generate_expression(c.initializer.as_ref()),
+        }
+    }
 
-        let lvalue = quote! { #(#lv).* };
+    pub(crate) fn generate_lvalue(&self, lval: &Lvalue) -> TokenStream {
+        let lvalue = lvalue_path(lval, self.hlir);
 
         let name_info = self
             .hlir
@@ -209,6 +740,19 @@ impl<'a> ExpressionGenerator<'a> {
             DeclarationInfo::HeaderMember => quote! {
                 #lvalue
             },
+            DeclarationInfo::EnumMember => {
+                let parts: Vec<_> = lval
+                    .name
+                    .split('.')
+                    .map(|x| format_ident!("{}", x))
+                    .collect();
+                quote! { #(#parts)::* }
+            }
+            DeclarationInfo::ErrorMember => {
+                let member = format_ident!("{}", lval.leaf());
+                quote! { error::#member }
+            }
+            DeclarationInfo::Constant => self.generate_constant(lval),
             /*
             DeclarationInfo::ActionParameter(_) => quote! {
                 &#lvalue
@@ -0,0 +1,97 @@
+// Copyright 2022 Oxide Computer Company
+
+use crate::{
+    rust_type,
+    statement::{StatementContext, StatementGenerator},
+    Context,
+};
+use p4::ast::{Control, Direction, Function, Type, AST};
+use p4::hlir::Hlir;
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+
+pub(crate) struct FunctionGenerator<'a> {
+    ast: &'a AST,
+    hlir: &'a Hlir,
+    ctx: &'a mut Context,
+}
+
+impl<'a> FunctionGenerator<'a> {
+    pub(crate) fn new(
+        ast: &'a AST,
+        hlir: &'a Hlir,
+        ctx: &'a mut Context,
+    ) -> Self {
+        Self { ast, hlir, ctx }
+    }
+
+    pub(crate) fn generate(&mut self) {
+        for f in &self.ast.functions {
+            self.generate_function(None, f, Vec::new());
+        }
+    }
+
+    /// Generate a rust function for a P4 function. Functions local to a
+    /// control take the parameters of the control, given by `params`, ahead
+    /// of their own.
+    pub(crate) fn generate_function(
+        &mut self,
+        control: Option<&Control>,
+        f: &Function,
+        mut params: Vec<TokenStream>,
+    ) {
+        for p in &f.parameters {
+            let name = format_ident!("{}", p.name);
+            let ty = rust_type(&p.ty);
+            match p.direction {
+                Direction::Out | Direction::InOut => {
+                    params.push(quote! { #name: &mut #ty });
+                }
+                _ => {
+                    params.push(quote! { mut #name: #ty });
+                }
+            }
+        }
+
+        let return_type = match &f.return_type {
+            Type::Void => TokenStream::new(),
+            ty => {
+                let ty = rust_type(ty);
+                quote! { -> #ty }
+            }
+        };
+
+        let mut names = self.ast.names();
+        if let Some(control) = control {
+            names.extend(control.names());
+        }
+        names.extend(f.names());
+        let sg = StatementGenerator::new(
+            self.ast,
+            self.hlir,
+            StatementContext::Function(control, f),
+        );
+        let body = sg.generate_block(&f.statement_block, &mut names);
+
+        let name = function_name(control, f);
+        self.ctx.functions.insert(
+            name.to_string(),
+            quote! {
+                #[allow(unused_mut)]
+                pub fn #name(#(#params),*) #return_type {
+                    #body
+                }
+            },
+        );
+    }
+}
+
+/// The name of the rust function generated for a P4 function.
+pub(crate) fn function_name(control: Option<&Control>, f: &Function) -> Ident {
+    match control {
+        Some(control) => {
+            format_ident!("{}_function_{}", control.name, f.name)
+        }
+        None => format_ident!("function_{}", f.name),
+    }
+}
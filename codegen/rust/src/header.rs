@@ -1,7 +1,8 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::{rust_type, type_size, Context};
-use p4::ast::{Header, AST};
+use p4::ast::{Header, HeaderUnion, Type, AST};
+use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 pub(crate) struct HeaderGenerator<'a> {
@@ -18,6 +19,9 @@ impl<'a> HeaderGenerator<'a> {
         for h in &self.ast.headers {
             self.generate_header(h);
         }
+        for u in &self.ast.header_unions {
+            self.generate_header_union(u);
+        }
     }
 
     fn generate_header(&mut self, h: &Header) {
@@ -56,56 +60,73 @@ impl<'a> HeaderGenerator<'a> {
         let fmt = "{} ".repeat(h.members.len() * 2);
         let fmt = fmt.trim();
         let mut offset = 0;
+        // the variable length field of the header, if any, whose current
+        // length shifts the offsets of the fields that follow it
+        let mut variable = None;
         for member in &h.members {
             let name = format_ident!("{}", member.name);
             let name_s = &member.name;
-            let size = type_size(&member.ty, self.ast);
-            member_values.push(quote! {
-                #name: BitVec::<u8, Msb0>::default()
+            // serializable enum fields are converted to and from their
+            // underlying bits on the wire
+            let enum_ty = match &member.ty {
+                Type::UserDefined(typename)
+                    if self.ast.get_enum(typename).is_some() =>
+                {
+                    Some(format_ident!("{}", typename))
+                }
+                _ => None,
+            };
+            member_values.push(match &enum_ty {
+                Some(ty) => quote! { #name: #ty::default() },
+                None => quote! { #name: BitVec::<u8, Msb0>::default() },
             });
+            if let Type::Varbit(width) = &member.ty {
+                // variable length fields are kept in wire order
+                set_statements.push(quote! {
+                    self.#name = buf.view_bits::<Msb0>()[#offset..#offset+var_len]
+                        .to_owned()
+                });
+                to_bitvec_statements.push(quote! {
+                    x[#offset..#offset+var_len] |= &self.#name;
+                });
+                checksum_statements.push(quote! {
+                    csum = p4rs::bitmath::add_le(csum.clone(), self.#name.csum())
+                });
+                dump_statements.push(quote! {
+                    #name_s.cyan(),
+                    format!("{:02x?}", self.#name.as_raw_slice())
+                });
+                variable = Some((name, *width));
+                continue;
+            }
+            let size = type_size(&member.ty, self.ast);
             let end = offset + size;
-            set_statements.push(quote! {
-                self.#name = {
-                    let mut b = buf.view_bits::<Msb0>()[#offset..#end].to_owned();
-                    // NOTE this barfing and then unbarfing a vec is to handle
-                    // the p4 confused-endian data model.
-                    let mut v = b.into_vec();
-                    v.reverse();
-                    let mut b = BitVec::<u8, Msb0>::from_vec(v);
-                    if (#end-#offset) < 8 {
-                        b.shift_left(#offset % 8);
-                    }
-                    b.resize(#end-#offset, false);
-                    b
+            let (start, stop) = match variable {
+                Some(_) => {
+                    (quote! { (#offset+var_len) }, quote! { (#end+var_len) })
                 }
+                None => (quote! { #offset }, quote! { #end }),
+            };
+            let bits = match &enum_ty {
+                Some(_) => quote! { BitVec::<u8, Msb0>::from(self.#name) },
+                None => quote! { self.#name },
+            };
+            let from_wire = quote! {
+                p4rs::field_from_wire(&buf.view_bits::<Msb0>()[#start..#stop])
+            };
+            set_statements.push(match &enum_ty {
+                Some(ty) => quote! { self.#name = #ty::from(&#from_wire) },
+                None => quote! { self.#name = #from_wire },
             });
             to_bitvec_statements.push(quote! {
-                // NOTE this barfing and then unbarfing a vec is to handle
-                // the p4 confused-endian data model.
-                let mut v = self.#name.clone().into_vec();
-                v.reverse();
-                let n = (#end-#offset);
-                let m = n%8;
-                if (n > 8) && m != 0 {
-                    let mut b = BitVec::<u8, Msb0>::from_vec(v);
-                    if b.len() > m {
-                        x[#offset..#end] |= &b[m..];
-                    } else {
-                        x[#offset..#end] |= &b;
-                    }
-                } else {
-                    let mut b = BitVec::<u8, Msb0>::from_vec(v);
-                    b.resize(#end-#offset, false);
-                    x[#offset..#end] |= &b;
-                }
-
+                p4rs::field_to_wire(&#bits, &mut x[#start..#stop])
             });
             checksum_statements.push(quote! {
-                csum = p4rs::bitmath::add_le(csum.clone(), self.#name.csum())
+                csum = p4rs::bitmath::add_le(csum.clone(), #bits.csum())
             });
             dump_statements.push(quote! {
                 #name_s.cyan(),
-                p4rs::dump_bv(&self.#name)
+                p4rs::dump_bv(&#bits)
             });
 
             offset += size;
@@ -114,6 +135,31 @@ impl<'a> HeaderGenerator<'a> {
             format!(#fmt, #(#dump_statements),*)
         };
 
+        let (var_len, size, set_variable_size) = match &variable {
+            Some((v, width)) => (
+                quote! { let var_len = self.#v.len(); },
+                quote! { #offset + self.#v.len() },
+                quote! {
+                    fn max_variable_size(&self) -> usize {
+                        #width
+                    }
+                    fn set_variable_size(&mut self, size: usize) {
+                        self.#v.resize(size, false);
+                    }
+                },
+            ),
+            None => (
+                TokenStream::new(),
+                quote! { #offset },
+                quote! {
+                    fn max_variable_size(&self) -> usize {
+                        0
+                    }
+                    fn set_variable_size(&mut self, _size: usize) {}
+                },
+            ),
+        };
+
         //TODO perhaps we should just keep the whole header as one bitvec so we
         //don't need to construct a consolidated bitvec like to_bitvec does?
         generated.extend(quote! {
@@ -128,12 +174,14 @@ impl<'a> HeaderGenerator<'a> {
                     &mut self,
                     buf: &[u8]
                 ) -> Result<(), TryFromSliceError> {
+                    #var_len
                     #(#set_statements);*;
                     Ok(())
                 }
-                fn size() -> usize {
-                    #offset
+                fn size(&self) -> usize {
+                    #size
                 }
+                #set_variable_size
                 fn set_valid(&mut self) {
                     self.valid = true;
                 }
@@ -144,7 +192,8 @@ impl<'a> HeaderGenerator<'a> {
                     self.valid
                 }
                 fn to_bitvec(&self) -> BitVec<u8, Msb0> {
-                    let mut x = bitvec![u8, Msb0; 0u8; Self::size()];
+                    #var_len
+                    let mut x = bitvec![u8, Msb0; 0u8; self.size()];
                     #(#to_bitvec_statements);*;
                     x
                 }
@@ -180,4 +229,75 @@ impl<'a> HeaderGenerator<'a> {
 
         self.ctx.structs.insert(h.name.clone(), generated);
     }
+
+    fn generate_header_union(&mut self, u: &HeaderUnion) {
+        let name = format_ident!("{}", u.name);
+
+        let mut members = Vec::new();
+        let mut valid_checks = Vec::new();
+        let mut valid_size_statements = Vec::new();
+        let mut to_bitvec_statements = Vec::new();
+        let mut dump_statements = Vec::new();
+        for member in &u.members {
+            let member_name = format_ident!("{}", member.name);
+            let member_name_s = &member.name;
+            let ty = match &member.ty {
+                Type::UserDefined(typename) => format_ident!("{}", typename),
+                x => panic!("header union member of type {}", x),
+            };
+            members.push(quote! { pub #member_name: #ty });
+            valid_checks.push(quote! { self.#member_name.is_valid() });
+            valid_size_statements.push(quote! {
+                if self.#member_name.is_valid() {
+                    return self.#member_name.size();
+                }
+            });
+            to_bitvec_statements.push(quote! {
+                if self.#member_name.is_valid() {
+                    return self.#member_name.to_bitvec();
+                }
+            });
+            dump_statements.push(quote! {
+                if self.#member_name.is_valid() {
+                    return format!(
+                        "{} {}",
+                        #member_name_s.cyan(),
+                        self.#member_name.dump(),
+                    );
+                }
+            });
+        }
+
+        // A header union holds each of its member headers, and keeps at most
+        // one of them valid at a time.
+        let generated = quote! {
+            #[derive(Debug, Default, Clone)]
+            pub struct #name {
+                #(#members),*
+            }
+
+            impl #name {
+                fn is_valid(&self) -> bool {
+                    false #(|| #valid_checks)*
+                }
+                fn isValid(&self) -> bool {
+                    self.is_valid()
+                }
+                fn valid_size(&self) -> usize {
+                    #(#valid_size_statements)*
+                    0
+                }
+                fn to_bitvec(&self) -> BitVec<u8, Msb0> {
+                    #(#to_bitvec_statements)*
+                    BitVec::new()
+                }
+                fn dump(&self) -> String {
+                    #(#dump_statements)*
+                    "∅".to_owned()
+                }
+            }
+        };
+
+        self.ctx.structs.insert(u.name.clone(), generated);
+    }
 }
@@ -8,22 +8,28 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
 use p4::ast::{
-    ActionParameter, Control, ControlParameter, DeclarationInfo, Direction,
-    Expression, ExpressionKind, HeaderMember, Lvalue, MutVisitor, NameInfo,
-    Parser, StructMember, Table, Type, UserDefinedType, AST,
+    ActionParameter, Call, Control, ControlParameter, DeclarationInfo,
+    Direction, Expression, ExpressionKind, HeaderMember, HeaderUnion, Lvalue,
+    MutVisitor, NameInfo, Parser, StructMember, Table, Type, UserDefinedType,
+    VisitorMut, AST,
 };
 use p4::hlir::Hlir;
-use p4::util::resolve_lvalue;
+use p4::util::{resolve_lvalue, stack_index_value};
 
 use control::ControlGenerator;
+use function::FunctionGenerator;
 use header::HeaderGenerator;
+use p4enum::EnumGenerator;
 use p4struct::StructGenerator;
 use parser::ParserGenerator;
 use pipeline::PipelineGenerator;
 
+pub mod architecture;
 mod control;
 mod expression;
+mod function;
 mod header;
+mod p4enum;
 mod p4struct;
 mod parser;
 mod pipeline;
@@ -45,6 +51,9 @@ struct Context {
 pub struct Settings {
     /// Name to give to the C-ABI constructor.
     pub pipeline_name: String,
+
+    /// Architecture descriptions for packages beyond the built in ones.
+    pub architectures: Vec<architecture::Architecture>,
 }
 
 pub struct Sanitizer {}
@@ -101,10 +110,10 @@ pub fn emit(
             // On failure write generated code to a tempfile
             println!("Code generation produced unparsable code");
             write_to_tempfile(&tokens)?;
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to parse generated code: {:?}", e),
-            ));
+            return Err(io::Error::other(format!(
+                "Failed to parse generated code: {:?}",
+                e
+            )));
         }
     };
     fs::write(filename, prettyplease::unparse(&f))?;
@@ -134,12 +143,18 @@ pub fn emit_tokens(ast: &AST, hlir: &Hlir, settings: Settings) -> TokenStream {
     let mut hg = HeaderGenerator::new(ast, &mut ctx);
     hg.generate();
 
+    let mut eg = EnumGenerator::new(ast, &mut ctx);
+    eg.generate();
+
     let mut sg = StructGenerator::new(ast, &mut ctx);
     sg.generate();
 
     let mut pg = ParserGenerator::new(ast, hlir, &mut ctx);
     pg.generate();
 
+    let mut fg = FunctionGenerator::new(ast, hlir, &mut ctx);
+    fg.generate();
+
     let mut cg = ControlGenerator::new(ast, hlir, &mut ctx);
     cg.generate();
 
@@ -185,6 +200,7 @@ fn dtrace_probes() -> TokenStream {
             fn parser_accepted(_: &str) {}
             fn parser_transition(_: &str) {}
             fn parser_dropped() {}
+            fn parser_rejected(_: &str) {}
             fn control_apply(_: &str) {}
             fn control_table_hit(_: &str) {}
             fn control_table_miss(_: &str) {}
@@ -211,12 +227,16 @@ fn get_parser_arg<'a>(
 fn rust_type(ty: &Type) -> TokenStream {
     match ty {
         Type::Bool => quote! { bool },
-        Type::Error => todo!("generate error type"),
+        Type::Error => quote! { error },
         Type::Bit(_size) => {
             quote! { BitVec::<u8, Msb0> }
         }
-        Type::Int(_size) => todo!("generate int type"),
-        Type::Varbit(_size) => todo!("generate varbit type"),
+        Type::Int(_size) => {
+            quote! { BitVec::<u8, Msb0> }
+        }
+        Type::Varbit(_size) => {
+            quote! { BitVec::<u8, Msb0> }
+        }
         Type::String => quote! { String },
         Type::UserDefined(name) => {
             let typename = format_ident!("{}", name);
@@ -235,6 +255,10 @@ fn rust_type(ty: &Type) -> TokenStream {
             quote! { () }
         }
         Type::List(_) => todo!("rust type for list"),
+        Type::HeaderStack(name, size) => {
+            let typename = format_ident!("{}", name);
+            quote! { p4rs::HeaderStack<#typename, #size> }
+        }
         Type::State => {
             todo!("rust type for state");
         }
@@ -247,7 +271,7 @@ fn rust_type(ty: &Type) -> TokenStream {
 fn type_size(ty: &Type, ast: &AST) -> usize {
     match ty {
         Type::Bool => 1,
-        Type::Error => todo!("generate error size"),
+        Type::Error => 16,
         Type::Bit(size) => *size,
         Type::Int(size) => *size,
         Type::Varbit(size) => *size,
@@ -271,6 +295,17 @@ fn type_size(ty: &Type, ast: &AST) -> usize {
                     }
                     sz
                 }
+                UserDefinedType::HeaderUnion(u) => u
+                    .members
+                    .iter()
+                    .map(|m| type_size(&m.ty, ast))
+                    .max()
+                    .unwrap_or(0),
+                UserDefinedType::Enum(e) => match &e.ty {
+                    Some(ty) => type_size(ty, ast),
+                    // the checker only allows these where no size is needed
+                    None => panic!("enum {} has no underlying type", e.name),
+                },
                 UserDefinedType::Extern(_) => {
                     todo!("size for extern?");
                 }
@@ -287,6 +322,9 @@ fn type_size(ty: &Type, ast: &AST) -> usize {
         }
         Type::Void => 0,
         Type::List(_) => todo!("type size for list"),
+        Type::HeaderStack(name, size) => {
+            size * type_size(&Type::UserDefined(name.clone()), ast)
+        }
         Type::State => {
             todo!("type size for state");
         }
@@ -299,7 +337,7 @@ fn type_size(ty: &Type, ast: &AST) -> usize {
 fn type_size_bytes(ty: &Type, ast: &AST) -> usize {
     let s = type_size(ty, ast);
     let mut b = s >> 3;
-    if s % 8 != 0 {
+    if !s.is_multiple_of(8) {
         b += 1
     }
     b
@@ -309,20 +347,23 @@ fn type_size_bytes(ty: &Type, ast: &AST) -> usize {
 //
 //   a &&& b
 //
-// where b is an integer literal interpret b as a prefix mask based on the
-// number of leading ones
-fn try_extract_prefix_len(expr: &Expression) -> Option<u8> {
-    match &expr.kind {
-        ExpressionKind::Binary(_lhs, _op, rhs) => match &rhs.kind {
-            ExpressionKind::IntegerLit(v) => Some(v.leading_ones() as u8),
-            ExpressionKind::BitLit(_width, v) => Some(v.leading_ones() as u8),
-            ExpressionKind::SignedLit(_width, v) => {
-                Some(v.trailing_ones() as u8)
-            }
-            _ => None,
-        },
-        _ => None,
+// where b is an integer literal interpret b as a prefix mask of a key `width`
+// bits wide based on the number of leading ones
+fn try_extract_prefix_len(expr: &Expression, width: usize) -> Option<u8> {
+    let mask = match &expr.kind {
+        ExpressionKind::Binary(_lhs, _op, rhs) => {
+            return try_extract_prefix_len(rhs, width)
+        }
+        ExpressionKind::IntegerLit(v) => *v as u128,
+        ExpressionKind::BitLit(_width, v) => *v,
+        ExpressionKind::SignedLit(_width, v) => *v as u128,
+        _ => return None,
+    };
+    if width == 0 || width > 128 {
+        return None;
     }
+    let len = (mask << (128 - width)).leading_ones() as usize;
+    Some(len.min(width) as u8)
 }
 
 fn is_header(
@@ -338,6 +379,109 @@ fn is_header(
     ast.get_header(&typename).is_some()
 }
 
+/// Return the header stack an lvalue refers to a member of, if any.
+fn header_stack_of(lval: &Lvalue, hlir: &Hlir) -> Option<Lvalue> {
+    if lval.degree() < 2 {
+        return None;
+    }
+    let parent = lval.pop_right();
+    match hlir.lvalue_decls.get(&parent) {
+        Some(NameInfo {
+            ty: Type::HeaderStack(..),
+            ..
+        }) => Some(parent),
+        _ => None,
+    }
+}
+
+/// Return the header union an lvalue refers to a member of, along with its
+/// declaration, if any.
+fn header_union_of<'a>(
+    lval: &Lvalue,
+    hlir: &Hlir,
+    ast: &'a AST,
+) -> Option<(Lvalue, &'a HeaderUnion)> {
+    if lval.degree() < 2 {
+        return None;
+    }
+    let parent = lval.pop_right();
+    match hlir.lvalue_decls.get(&parent) {
+        Some(NameInfo {
+            ty: Type::UserDefined(name),
+            ..
+        }) => ast.get_header_union(name).map(|u| (parent, u)),
+        _ => None,
+    }
+}
+
+/// Generate a rust path for an lvalue. Header stack elements referenced by
+/// index, `next` or `last` become accesses to the elements of the underlying
+/// `p4rs::HeaderStack`.
+fn lvalue_path(lval: &Lvalue, hlir: &Hlir) -> TokenStream {
+    let parts = lval.parts();
+    let mut path = TokenStream::new();
+    for (i, name) in parts.iter().enumerate() {
+        let ident = format_ident!("{}", name);
+        let prefix = lval.prefix(i + 1);
+        path = if i == 0 {
+            quote! { #ident }
+        } else if header_stack_of(&prefix, hlir).is_some() {
+            match *name {
+                "next" => quote! { #path[p4rs::StackNext] },
+                "last" => quote! { #path[p4rs::StackLast] },
+                "lastIndex" => quote! { #path.last_index_bits() },
+                "size" => quote! { #path.size_bits() },
+                _ => quote! { #path.#ident },
+            }
+        } else {
+            quote! { #path.#ident }
+        };
+        if let Some(index) = lval.stack_index(i) {
+            let index = stack_index_value(index).unwrap_or_else(|| {
+                panic!("codegen: header stack index {:?}", index.kind)
+            });
+            path = quote! { #path[#index] };
+        }
+    }
+    path
+}
+
+/// Whether the apply block of a control verifies checksums, directly or
+/// through the controls it applies. Such controls take a trailing
+/// `checksum_error` flag that they set when a checksum fails to verify.
+fn verifies_checksums(control: &Control, ast: &AST) -> bool {
+    struct ChecksumCalls<'a> {
+        control: &'a Control,
+        ast: &'a AST,
+        found: bool,
+    }
+    impl VisitorMut for ChecksumCalls<'_> {
+        fn call(&mut self, call: &Call) {
+            if call.lval.name == "verify_checksum" {
+                self.found = true;
+                return;
+            }
+            let applied = self.control.variables.iter().find(|v| {
+                call.lval.degree() == 2 && v.name == call.lval.root()
+            });
+            if let Some(Type::UserDefined(name)) = applied.map(|v| &v.ty) {
+                if let Some(c) = self.ast.get_control(name) {
+                    self.found |= verifies_checksums(c, self.ast);
+                }
+            }
+        }
+    }
+    let mut calls = ChecksumCalls {
+        control,
+        ast,
+        found: false,
+    };
+    for s in &control.apply.statements {
+        s.accept_mut(&mut calls);
+    }
+    calls.found
+}
+
 fn is_header_member(lval: &Lvalue, hlir: &Hlir) -> bool {
     if lval.degree() >= 1 {
         let name_info = hlir
@@ -367,11 +511,15 @@ fn is_rust_reference(lval: &Lvalue, names: &HashMap<String, NameInfo>) -> bool {
             DeclarationInfo::Method => false,
             DeclarationInfo::StructMember => false,
             DeclarationInfo::HeaderMember => false,
+            DeclarationInfo::EnumMember => false,
+            DeclarationInfo::ErrorMember => false,
             DeclarationInfo::ControlTable => false,
             DeclarationInfo::ControlMember => false,
             DeclarationInfo::State => false,
             DeclarationInfo::Action => false,
             DeclarationInfo::ActionParameter(_) => false,
+            DeclarationInfo::Function => false,
+            DeclarationInfo::Constant => false,
         }
     } else {
         false
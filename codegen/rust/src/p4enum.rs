@@ -0,0 +1,164 @@
+// Copyright 2022 Oxide Computer Company
+
+use crate::Context;
+use p4::ast::{Enum, ExpressionKind, Type, AST};
+use quote::{format_ident, quote};
+
+pub(crate) struct EnumGenerator<'a> {
+    ast: &'a AST,
+    ctx: &'a mut Context,
+}
+
+impl<'a> EnumGenerator<'a> {
+    pub(crate) fn new(ast: &'a AST, ctx: &'a mut Context) -> Self {
+        Self { ast, ctx }
+    }
+
+    pub(crate) fn generate(&mut self) {
+        for e in &self.ast.enums {
+            self.generate_enum(e);
+        }
+        self.generate_error();
+    }
+
+    /// Generate the error enum. Errors are recorded on a rejected packet by
+    /// name, and are carried as a 16 bit code when serialized.
+    fn generate_error(&mut self) {
+        let mut members = Vec::new();
+        let mut to_name = Vec::new();
+        let mut from_name = Vec::new();
+        let mut to_value = Vec::new();
+        for (i, name) in self.ast.error_names().into_iter().enumerate() {
+            let member = format_ident!("{}", name);
+            if i == 0 {
+                members.push(quote! { #[default] #member });
+            } else {
+                members.push(quote! { #member });
+            }
+            to_name.push(quote! { error::#member => #name });
+            from_name.push(quote! { #name => Some(error::#member) });
+            let value = i as u16;
+            to_value.push(quote! { error::#member => #value });
+        }
+
+        let tokens = quote! {
+            #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+            pub enum error {
+                #(#members),*
+            }
+
+            impl error {
+                pub fn name(&self) -> &'static str {
+                    match self {
+                        #(#to_name),*
+                    }
+                }
+
+                pub fn from_name(name: &str) -> Option<Self> {
+                    match name {
+                        #(#from_name),*,
+                        _ => None,
+                    }
+                }
+            }
+
+            impl From<error> for BitVec<u8, Msb0> {
+                fn from(x: error) -> Self {
+                    let value: u16 = match x {
+                        #(#to_value),*
+                    };
+                    let mut bv = bitvec![mut u8, Msb0; 0; 16];
+                    bv.store_le(value);
+                    bv
+                }
+            }
+        };
+
+        self.ctx.structs.insert("error".into(), tokens);
+    }
+
+    fn generate_enum(&mut self, e: &Enum) {
+        let name = format_ident!("{}", e.name);
+
+        let mut members = Vec::new();
+        for (i, m) in e.members.iter().enumerate() {
+            let member = format_ident!("{}", m.name);
+            if i == 0 {
+                members.push(quote! { #[default] #member });
+            } else {
+                members.push(quote! { #member });
+            }
+        }
+
+        // serializable enums convert to and from their underlying bit
+        // representation, and may hold values that do not name a member
+        let width = match &e.ty {
+            Some(Type::Bit(w)) | Some(Type::Int(w)) => *w,
+            Some(ty) => panic!("enum underlying type {}", ty),
+            None => {
+                let tokens = quote! {
+                    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+                    pub enum #name {
+                        #(#members),*
+                    }
+                };
+                self.ctx.structs.insert(e.name.clone(), tokens);
+                return;
+            }
+        };
+        if e.members.iter().any(|m| m.name == "Unknown") {
+            panic!(
+                "codegen: serializable enum {} may not have a member named \
+                Unknown",
+                e.name,
+            );
+        }
+        let mut tokens = quote! {
+            #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+            pub enum #name {
+                #(#members),*,
+                /// A value that does not name a member.
+                Unknown(u128),
+            }
+        };
+
+        let mut to_value = Vec::new();
+        let mut from_value = Vec::new();
+        for m in &e.members {
+            let member = format_ident!("{}", m.name);
+            let value = match m.value.as_ref().map(|v| &v.kind) {
+                Some(ExpressionKind::IntegerLit(v)) => *v as u128,
+                Some(ExpressionKind::BitLit(_, v)) => *v,
+                x => panic!("enum member value {:?}", x),
+            };
+            to_value.push(quote! { #name::#member => #value });
+            from_value.push(quote! { #value => #name::#member });
+        }
+
+        tokens.extend(quote! {
+            impl From<#name> for BitVec<u8, Msb0> {
+                fn from(x: #name) -> Self {
+                    let value: u128 = match x {
+                        #(#to_value),*,
+                        #name::Unknown(x) => x,
+                    };
+                    let mut bv = bitvec![mut u8, Msb0; 0; #width];
+                    bv.store_le(value);
+                    bv
+                }
+            }
+
+            impl From<&BitVec<u8, Msb0>> for #name {
+                fn from(bv: &BitVec<u8, Msb0>) -> Self {
+                    let value: u128 = bv.load_le();
+                    match value {
+                        #(#from_value),*,
+                        x => #name::Unknown(x),
+                    }
+                }
+            }
+        });
+
+        self.ctx.structs.insert(e.name.clone(), tokens);
+    }
+}
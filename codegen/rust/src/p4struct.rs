@@ -42,15 +42,16 @@ impl<'a> StructGenerator<'a> {
                         // valid header size statements
                         valid_member_size.push(quote! {
                             if self.#name.valid {
-                                x += #ty::size();
+                                x += self.#name.size();
                             }
                         });
 
                         // to bitvec statements
-                        to_bitvec_stmts.push(quote!{
+                        to_bitvec_stmts.push(quote! {
                             if self.#name.valid {
-                                x[off..off+#ty::size()] |= self.#name.to_bitvec();
-                                off += #ty::size();
+                                let n = self.#name.size();
+                                x[off..off+n] |= self.#name.to_bitvec();
+                                off += n;
                             }
                         });
 
@@ -58,6 +59,28 @@ impl<'a> StructGenerator<'a> {
                             #name_s.blue(),
                             self.#name.dump()
                         });
+                    } else if self.ast.get_header_union(typename).is_some() {
+                        let ty = format_ident!("{}", typename);
+                        members.push(quote! { pub #name: #ty });
+                        valid_member_size.push(quote! {
+                            x += self.#name.valid_size();
+                        });
+                        to_bitvec_stmts.push(quote! {
+                            let n = self.#name.valid_size();
+                            x[off..off+n] |= self.#name.to_bitvec();
+                            off += n;
+                        });
+                        dump_statements.push(quote! {
+                            #name_s.blue(),
+                            self.#name.dump()
+                        });
+                    } else if self.ast.get_enum(typename).is_some() {
+                        let ty = format_ident!("{}", typename);
+                        members.push(quote! { pub #name: #ty });
+                        dump_statements.push(quote! {
+                            #name_s.blue(),
+                            format!("{:?}", self.#name)
+                        });
                     } else {
                         panic!(
                             "Struct member {:#?} undefined in {:#?}",
@@ -65,7 +88,30 @@ impl<'a> StructGenerator<'a> {
                         );
                     }
                 }
-                Type::Bit(size) => {
+                Type::HeaderStack(typename, size) => {
+                    let ty = format_ident!("{}", typename);
+                    members.push(quote! {
+                        pub #name: p4rs::HeaderStack<#ty, #size>
+                    });
+                    valid_member_size.push(quote! {
+                        x += self.#name.valid_size();
+                    });
+                    to_bitvec_stmts.push(quote! {
+                        let n = self.#name.valid_size();
+                        x[off..off+n] |= self.#name.to_bitvec();
+                        off += n;
+                    });
+                    dump_statements.push(quote! {
+                        #name_s.blue(),
+                        self.#name
+                            .elements
+                            .iter()
+                            .map(|h| h.dump())
+                            .collect::<Vec<_>>()
+                            .join(" | ")
+                    });
+                }
+                Type::Bit(size) | Type::Int(size) => {
                     members.push(quote! { pub #name: BitVec::<u8, Msb0> });
                     dump_statements.push(quote! {
                         #name_s.blue(),
@@ -79,6 +125,13 @@ impl<'a> StructGenerator<'a> {
                         off += #size;
                     });
                 }
+                Type::Error => {
+                    members.push(quote! { pub #name: error });
+                    dump_statements.push(quote! {
+                        #name_s.blue(),
+                        self.#name.name()
+                    });
+                }
                 Type::Bool => {
                     members.push(quote! { pub #name: bool });
                     dump_statements.push(quote! {
@@ -1,14 +1,16 @@
 // Copyright 2022 Oxide Computer Company
 
+use crate::architecture::{Architecture, CloneRequest, MetadataMember};
 use crate::{
-    qualified_table_function_name, qualified_table_name, rust_type,
-    type_size_bytes, Context, Settings,
+    qualified_table_function_name, qualified_table_name, rust_type, type_size,
+    type_size_bytes, verifies_checksums, Context, Settings,
 };
 use p4::ast::{
-    Control, Direction, MatchKind, PackageInstance, Parser, Table, Type, AST,
+    Action, Control, Direction, MatchKind, Package, PackageInstance,
+    PackageParameter, Parser, Table, Type, AST,
 };
 use p4::hlir::Hlir;
-use proc_macro2::TokenStream;
+use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote};
 
 pub(crate) struct PipelineGenerator<'a> {
@@ -18,6 +20,88 @@ pub(crate) struct PipelineGenerator<'a> {
     settings: &'a Settings,
 }
 
+/// A stage of a package instance.
+#[derive(Clone, Copy)]
+enum Stage<'a> {
+    Parser(&'a Parser),
+    Control(&'a Control),
+    /// A control that emits headers to a `packet_out`.
+    Deparser(&'a Control),
+}
+
+impl<'a> Stage<'a> {
+    fn control(&self) -> Option<&'a Control> {
+        match self {
+            Stage::Parser(_) => None,
+            Stage::Control(c) | Stage::Deparser(c) => Some(c),
+        }
+    }
+
+    /// Parameters as (name, type, direction).
+    fn parameters(&self) -> Vec<(&'a str, &'a Type, &'a Direction)> {
+        match self {
+            Stage::Parser(p) => p
+                .parameters
+                .iter()
+                .map(|p| (p.name.as_str(), &p.ty, &p.direction))
+                .collect(),
+            Stage::Control(c) | Stage::Deparser(c) => c
+                .parameters
+                .iter()
+                .map(|p| (p.name.as_str(), &p.ty, &p.direction))
+                .collect(),
+        }
+    }
+}
+
+/// A stage along with the name of the pipeline member that holds it.
+struct PipelineStage<'a> {
+    member: Ident,
+    stage: Stage<'a>,
+}
+
+/// Locals that would collide with the values stages are handed.
+const RESERVED_LOCALS: &[&str] = &[
+    "pkt",
+    "port",
+    "out",
+    "data",
+    "payload",
+    "accept",
+    "ports",
+    "eport",
+    "result",
+    "dump",
+    "self",
+    "jobs",
+    "path",
+    "input",
+    "end",
+    "resubmit",
+    "carried",
+    "ingress_path",
+    "ingress_values",
+    "egress_clones",
+];
+
+/// Tracks the state of a packet as the statements for each stage are
+/// generated.
+#[derive(Clone)]
+struct Flow {
+    /// The headers the most recent parser extracted to.
+    headers: Option<Ident>,
+
+    /// Whether a deparser has emitted to `out` since the last parser ran.
+    deparsed: bool,
+
+    /// Whether a parser has run on a packet assembled from a previous
+    /// deparsing, rather than the packet the pipeline was handed.
+    reparsed: bool,
+
+    /// Statement that abandons the packet.
+    drop: TokenStream,
+}
+
 impl<'a> PipelineGenerator<'a> {
     pub(crate) fn new(
         ast: &'a AST,
@@ -39,90 +123,390 @@ impl<'a> PipelineGenerator<'a> {
         }
     }
 
-    pub(crate) fn generate_pipeline(&mut self, inst: &PackageInstance) {
-        //TODO check instance against package definition instead of hardcoding
-        //SoftNPU in below. This begs the question, will the Rust back end
-        //support something besides SoftNPU. Probably not for the forseeable
-        //future. However it could be interesting to support different package
-        //structures to emulate different types of hardware. For example the
-        //Tofino package instance has a relatively complex pipeline structure.
-        //And it could be interesting/useful to evaluate workloads running
-        //within SoftNPU that exploit that structure.
-
-        if inst.instance_type != "SoftNPU" {
-            //TODO check this in the checker for a nicer failure mode.
-            panic!("Only the SoftNPU package is supported");
+    /// Find the description of the architecture a package instance is for.
+    /// Descriptions passed in through settings take precedence over built in
+    /// ones. A package declared by the program that has no description gets
+    /// a conventional one, see [`Self::conventional_architecture`].
+    fn architecture(&self, inst: &PackageInstance) -> Architecture {
+        let arch = self
+            .settings
+            .architectures
+            .iter()
+            .cloned()
+            .chain(Architecture::builtin())
+            .find(|a| a.package == inst.instance_type);
+        if let Some(arch) = arch {
+            return arch;
+        }
+        match self.ast.get_package(&inst.instance_type) {
+            Some(pkg) => self.conventional_architecture(pkg),
+            None => {
+                panic!("package {} not declared", inst.instance_type);
+            }
         }
+    }
 
-        if inst.parameters.len() != 3 {
-            //TODO check this in the checker for a nicer failure mode.
-            panic!("SoftNPU instances take exactly 3 parameters");
+    /// A package without an architecture description takes its metadata
+    /// from the SoftNPU metadata structures, and runs ingress through its
+    /// first control stage.
+    fn conventional_architecture(&self, pkg: &Package) -> Architecture {
+        let first_control = pkg
+            .parameters
+            .iter()
+            .position(|p| self.ast.get_control(&p.type_name).is_some())
+            .unwrap_or_else(|| {
+                panic!("package {} has no control parameters", pkg.name)
+            });
+        Architecture {
+            package: pkg.name.clone(),
+            stage_names: Vec::new(),
+            ingress_stages: first_control + 1,
+            ..Architecture::softnpu()
         }
+    }
 
-        let parser = match self.ast.get_parser(&inst.parameters[0]) {
-            Some(p) => p,
-            None => {
-                //TODO check this in the checker for a nicer failure mode.
-                panic!("First argument to SoftNPU must be a parser");
+    /// Resolve the stages of a package instance. When the package is
+    /// declared, each argument must be the kind of block its parameter
+    /// calls for. Nested package instances contribute their stages in
+    /// place, externs are provided by the pipeline and are not stages.
+    fn stages(
+        &self,
+        inst: &PackageInstance,
+        arch: &Architecture,
+    ) -> Vec<PipelineStage<'a>> {
+        let mut stages = Vec::new();
+        for (i, (param, arg)) in self.stage_blocks(inst).into_iter().enumerate()
+        {
+            let name = match (arch.stage_names.get(i), param) {
+                (Some(name), _) => name.clone(),
+                (None, Some(param)) => param.name.clone(),
+                (None, None) => {
+                    panic!(
+                        "{} instances take at most {} parameters",
+                        inst.instance_type,
+                        arch.stage_names.len(),
+                    );
+                }
+            };
+            let expect_parser = param
+                .map(|param| self.ast.get_parser(&param.type_name).is_some());
+            let stage =
+                match (self.ast.get_parser(arg), self.ast.get_control(arg)) {
+                    (Some(p), _) if expect_parser != Some(false) => {
+                        Stage::Parser(p)
+                    }
+                    (_, Some(c)) if expect_parser != Some(true) => {
+                        let packet_out = Type::UserDefined("packet_out".into());
+                        if c.parameters.iter().any(|p| p.ty == packet_out) {
+                            Stage::Deparser(c)
+                        } else {
+                            Stage::Control(c)
+                        }
+                    }
+                    _ => {
+                        panic!(
+                            "argument {} to {} must be a {}",
+                            arg,
+                            inst.instance_type,
+                            match expect_parser {
+                                Some(true) => "parser",
+                                Some(false) => "control block",
+                                None => "parser or control block",
+                            }
+                        );
+                    }
+                };
+            stages.push(PipelineStage {
+                member: format_ident!("{}", name),
+                stage,
+            });
+        }
+
+        match stages.first() {
+            Some(PipelineStage {
+                stage: Stage::Parser(_),
+                ..
+            }) => {}
+            _ => {
+                panic!(
+                    "the first argument to {} must be a parser",
+                    inst.instance_type
+                );
             }
-        };
+        }
+        if arch.ingress_stages > stages.len() {
+            panic!(
+                "{} has {} ingress stages but only {} stages",
+                arch.package,
+                arch.ingress_stages,
+                stages.len(),
+            );
+        }
 
-        let ingress = match self.ast.get_control(&inst.parameters[1]) {
-            Some(c) => c,
-            None => {
-                //TODO check this in the checker for a nicer failure mode.
-                panic!("Second argument to SoftNPU must be a control block");
+        stages
+    }
+
+    /// The blocks a package instance is instantiated with along with the
+    /// package parameter each is passed as, if the package is declared.
+    fn stage_blocks<'b>(
+        &'b self,
+        inst: &'b PackageInstance,
+    ) -> Vec<(Option<&'a PackageParameter>, &'b str)> {
+        let pkg = self.ast.get_package(&inst.instance_type);
+        if let Some(pkg) = pkg {
+            if pkg.parameters.len() != inst.parameters.len() {
+                panic!(
+                    "{} instances take {} parameters",
+                    pkg.name,
+                    pkg.parameters.len(),
+                );
             }
-        };
+        }
+        let mut blocks = Vec::new();
+        for (i, arg) in inst.parameters.iter().enumerate() {
+            if let Some(nested) = self.ast.get_package_instance(arg) {
+                blocks.extend(self.stage_blocks(nested));
+            } else if self.ast.get_extern(arg).is_none() {
+                blocks.push((pkg.map(|pkg| &pkg.parameters[i]), arg.as_str()));
+            }
+        }
+        blocks
+    }
 
-        let egress = match self.ast.get_control(&inst.parameters[2]) {
-            Some(c) => c,
-            None => {
-                //TODO check this in the checker for a nicer failure mode.
-                panic!("Third argument to SoftNPU must be a control block");
+    /// The local each metadata and header structure handed to a stage is
+    /// held in, keyed by type name. Structures are named after the first
+    /// stage parameter of their type.
+    fn stage_values(
+        &self,
+        stages: &[PipelineStage<'_>],
+    ) -> Vec<(String, Ident)> {
+        let mut values: Vec<(String, Ident)> = Vec::new();
+        for s in stages {
+            for (name, ty, _) in s.stage.parameters() {
+                let typename = match ty {
+                    Type::UserDefined(typename) => typename,
+                    _ => continue,
+                };
+                if self.ast.get_extern(typename).is_some()
+                    || typename == "packet_in"
+                    || values.iter().any(|(t, _)| t == typename)
+                {
+                    continue;
+                }
+                let mut local = name.to_owned();
+                let mut n = 0;
+                while RESERVED_LOCALS.contains(&local.as_str())
+                    || values.iter().any(|(_, v)| *v == local)
+                {
+                    n += 1;
+                    local = format!("{}_{}", name, n);
+                }
+                values.push((typename.clone(), format_ident!("{}", local)));
             }
+        }
+        values
+    }
+
+    /// The local and member for a metadata member, and its width, if the
+    /// pipeline hands its structure to any stage.
+    fn metadata_member(
+        &self,
+        m: Option<&MetadataMember>,
+        values: &[(String, Ident)],
+    ) -> Option<(TokenStream, usize)> {
+        let m = m?;
+        let (_, local) = values.iter().find(|(t, _)| *t == m.ty)?;
+        let member = self
+            .ast
+            .get_struct(&m.ty)?
+            .members
+            .iter()
+            .find(|x| x.name == m.member)?;
+        let name = format_ident!("{}", m.member);
+        let width = match member.ty {
+            Type::Bool | Type::Error => 0,
+            _ => type_size(&member.ty, self.ast),
         };
+        Some((quote! { #local.#name }, width))
+    }
 
-        let pipeline_name = format_ident!("{}_pipeline", inst.name);
+    /// The metadata members the pipeline hands to any stage, see
+    /// [`Self::metadata_member`].
+    fn metadata_members(
+        &self,
+        members: &[MetadataMember],
+        values: &[(String, Ident)],
+    ) -> Vec<(TokenStream, usize)> {
+        members
+            .iter()
+            .filter_map(|m| self.metadata_member(Some(m), values))
+            .collect()
+    }
 
-        //
-        // get table members and initializers from both the ingress and egress
-        // controllers.
-        //
+    /// The local and member for each packet path member the pipeline hands
+    /// to a stage, along with the enum type of the member.
+    fn packet_path_members(
+        &self,
+        members: &[MetadataMember],
+        values: &[(String, Ident)],
+    ) -> Vec<(TokenStream, Ident)> {
+        members
+            .iter()
+            .filter_map(|m| {
+                let (_, local) = values.iter().find(|(t, _)| *t == m.ty)?;
+                let member = self
+                    .ast
+                    .get_struct(&m.ty)?
+                    .members
+                    .iter()
+                    .find(|x| x.name == m.member)?;
+                let ty = match &member.ty {
+                    Type::UserDefined(ty)
+                        if self.ast.get_enum(ty).is_some() =>
+                    {
+                        format_ident!("{}", ty)
+                    }
+                    ty => panic!(
+                        "{}.{} must be an enum to hold a packet path, not {}",
+                        m.ty, m.member, ty,
+                    ),
+                };
+                let name = format_ident!("{}", m.member);
+                Some((quote! { #local.#name }, ty))
+            })
+            .collect()
+    }
+
+    /// The flag and session of a clone request, if the pipeline hands the
+    /// structures they are members of to any stage.
+    fn clone_request(
+        &self,
+        request: Option<&CloneRequest>,
+        values: &[(String, Ident)],
+    ) -> Option<(TokenStream, TokenStream)> {
+        let request = request?;
+        let (flag, width) =
+            self.metadata_member(Some(&request.flag), values)?;
+        let (session, _) =
+            self.metadata_member(Some(&request.session), values)?;
+        Some((Self::flag_is_set(flag, width), session))
+    }
+
+    /// The names of the structure types the parameters of stages take, only
+    /// for `out` parameters when `outputs` is set.
+    fn stage_types<'b, 'c: 'b>(
+        stages: impl IntoIterator<Item = &'b PipelineStage<'c>>,
+        outputs: bool,
+    ) -> Vec<&'c String> {
+        stages
+            .into_iter()
+            .flat_map(|s| s.stage.parameters())
+            .filter(|(_, _, direction)| {
+                !outputs || **direction == Direction::Out
+            })
+            .filter_map(|(_, ty, _)| match ty {
+                Type::UserDefined(typename) => Some(typename),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the program refers to a metadata member anywhere.
+    fn referenced(&self, m: &MetadataMember) -> bool {
+        let ty = Type::UserDefined(m.ty.clone());
+        self.hlir.lvalue_decls.keys().any(|lval| {
+            lval.degree() > 1
+                && lval.leaf() == m.member
+                && self
+                    .hlir
+                    .lvalue_decls
+                    .get(&lval.pop_right())
+                    .is_some_and(|parent| parent.ty == ty)
+        })
+    }
 
-        let (mut table_members, mut table_initializers) =
-            self.table_members(ingress);
+    /// Whether a metadata flag of the given width is set, where a width of
+    /// zero is a bool.
+    fn flag_is_set(flag: TokenStream, width: usize) -> TokenStream {
+        if width == 0 {
+            return flag;
+        }
+        quote! { #flag.any() }
+    }
 
-        let (egress_table_members, egress_table_initializers) =
-            self.table_members(egress);
+    /// The value that sets a metadata flag of the given width, where a width
+    /// of zero is a bool.
+    fn flag_value(width: usize) -> TokenStream {
+        if width == 0 {
+            return quote! { true };
+        }
+        quote! {{
+            let mut x = bitvec![mut u8, Msb0; 0; #width];
+            x.store_le(1u8);
+            x
+        }}
+    }
 
-        table_members.extend_from_slice(&egress_table_members);
-        table_initializers.extend_from_slice(&egress_table_initializers);
+    pub(crate) fn generate_pipeline(&mut self, inst: &PackageInstance) {
+        let arch = self.architecture(inst);
+        let stages = self.stages(inst, &arch);
+        let controls: Vec<&Control> =
+            stages.iter().filter_map(|s| s.stage.control()).collect();
+
+        let pipeline_name = format_ident!("{}_pipeline", inst.name);
 
         //
-        // parser, ingress and egress function members
+        // get table members and initializers from each control
         //
 
-        let (parse_member, parser_initializer) = self.parse_entrypoint(parser);
+        let mut table_members = Vec::new();
+        let mut table_initializers = Vec::new();
+        for control in &controls {
+            let (members, initializers) = self.table_members(control);
+            table_members.extend_from_slice(&members);
+            table_initializers.extend_from_slice(&initializers);
+        }
 
-        let (ingress_member, ingress_initializer) =
-            self.control_entrypoint("ingress", ingress);
+        //
+        // stage function members
+        //
 
-        let (egress_member, egress_initializer) =
-            self.control_entrypoint("egress", egress);
+        let mut stage_members = Vec::new();
+        let mut stage_initializers = Vec::new();
+        for s in &stages {
+            let (member, initializer) = match s.stage {
+                Stage::Parser(p) => self.parse_entrypoint(&s.member, p),
+                Stage::Control(c) | Stage::Deparser(c) => {
+                    self.control_entrypoint(&s.member, c)
+                }
+            };
+            stage_members.push(member);
+            stage_initializers.push(initializer);
+        }
 
         let (pipeline_impl_process_packet, process_packet_headers) =
-            self.pipeline_impl_process_packet(parser, ingress, egress);
+            self.pipeline_impl_process_packet(&arch, &stages);
+
+        // multicast groups are configured through the pipeline's packet
+        // replication engine
+        let (pre_member, pre_initializer) = if arch.multicast_group.is_some() {
+            (
+                quote! { pub pre: p4rs::externs::PacketReplicationEngine, },
+                quote! { pre: p4rs::externs::PacketReplicationEngine::new(), },
+            )
+        } else {
+            (TokenStream::new(), TokenStream::new())
+        };
 
-        let add_table_entry_method =
-            self.add_table_entry_method(ingress, egress);
-        let remove_table_entry_method =
-            self.remove_table_entry_method(ingress, egress);
-        let get_table_entries_method =
-            self.get_table_entries_method(ingress, egress);
-        let get_table_ids_method = self.get_table_ids_method(ingress, egress);
+        let table_update_methods = self.table_update_methods(&controls);
+        let get_table_entries_method = self.get_table_entries_method(&controls);
+        let set_table_default_action_method =
+            self.set_table_default_action_method(&controls);
+        let get_table_ids_method = self.get_table_ids_method(&controls);
 
-        let table_modifiers = self.table_modifiers(ingress, egress);
+        let table_modifiers = self.table_modifiers(&controls);
+        let (entry_builders, entry_builder_methods) =
+            self.table_entry_builders(&controls, &pipeline_name);
 
         let c_create_fn =
             format_ident!("_{}_pipeline_create", self.settings.pipeline_name);
@@ -130,9 +514,8 @@ impl<'a> PipelineGenerator<'a> {
         let pipeline = quote! {
             pub struct #pipeline_name {
                 #(#table_members,)*
-                #parse_member,
-                #ingress_member,
-                #egress_member,
+                #(#stage_members,)*
+                #pre_member
                 radix: u16,
             }
 
@@ -141,21 +524,23 @@ impl<'a> PipelineGenerator<'a> {
                     usdt::register_probes().unwrap();
                     Self {
                         #(#table_initializers,)*
-                        #parser_initializer,
-                        #ingress_initializer,
-                        #egress_initializer,
+                        #(#stage_initializers,)*
+                        #pre_initializer
                         radix,
                     }
                 }
                 #process_packet_headers
                 #table_modifiers
+                #entry_builder_methods
             }
 
+            #entry_builders
+
             impl p4rs::Pipeline for #pipeline_name {
                 #pipeline_impl_process_packet
-                #add_table_entry_method
-                #remove_table_entry_method
+                #table_update_methods
                 #get_table_entries_method
+                #set_table_default_action_method
                 #get_table_ids_method
             }
 
@@ -164,7 +549,7 @@ impl<'a> PipelineGenerator<'a> {
             #[no_mangle]
             pub extern "C" fn #c_create_fn(radix: u16)
             -> *mut dyn p4rs::Pipeline{
-                let pipeline = main_pipeline::new(radix);
+                let pipeline = #pipeline_name::new(radix);
                 let boxpipe: Box<dyn p4rs::Pipeline> = Box::new(pipeline);
                 Box::into_raw(boxpipe)
             }
@@ -173,294 +558,768 @@ impl<'a> PipelineGenerator<'a> {
         self.ctx.pipelines.insert(inst.name.clone(), pipeline);
     }
 
-    fn pipeline_impl_process_packet(
-        &mut self,
+    /// Handle a packet a parser rejected. When the program reads the parser
+    /// error member of the architecture's metadata, the packet continues
+    /// with the error the parser recorded and no valid headers, so the whole
+    /// packet is carried as payload. If the architecture has drop flags the
+    /// first is set, so the packet is dropped unless a later stage clears
+    /// it. Programs that do not read the parser error have rejected packets
+    /// dropped outright.
+    fn parser_reject(
+        &self,
+        arch: &Architecture,
+        values: &[(String, Ident)],
         parser: &Parser,
-        ingress: &Control,
-        egress: &Control,
-    ) -> (TokenStream, TokenStream) {
-        let parsed_type = rust_type(&parser.parameters[1].ty);
-        // determine table arguments
-        let ingress_tables = ingress.tables(self.ast);
-        //TODO(dry)
-        let mut ingress_tbl_args = Vec::new();
-        for (cs, t) in ingress_tables {
-            let qtfn = qualified_table_function_name(Some(ingress), &cs, t);
-            let name = format_ident!("{}", qtfn);
-            ingress_tbl_args.push(quote! {
-                &self.#name
-            });
+        flow: &Flow,
+    ) -> TokenStream {
+        let parser_error =
+            self.metadata_member(arch.parser_error.as_ref(), values);
+        let drop = self.metadata_member(arch.drop.first(), values);
+        let read = arch.parser_error.as_ref().is_some_and(|m| {
+            self.ast
+                .get_struct(&m.ty)
+                .and_then(|s| s.members.iter().find(|x| x.name == m.member))
+                .is_some_and(|x| x.ty == Type::Error)
+                && self.referenced(m)
+        });
+        let (parser_error, _) = match parser_error {
+            Some(e) if read => e,
+            _ => {
+                let drop_packet = &flow.drop;
+                return quote! {
+                    // drop the packet
+                    softnpu_provider::parser_dropped!(||());
+                    #drop_packet
+                };
+            }
+        };
+        let mut reset = Vec::new();
+        for (typename, local) in self.parser_outputs(parser, values) {
+            let ty = format_ident!("{}", typename);
+            reset.push(quote! { #local = #ty::default(); });
         }
-        let egress_tables = egress.tables(self.ast);
-        let mut egress_tbl_args = Vec::new();
-        for (cs, t) in egress_tables {
-            let qtfn = qualified_table_function_name(Some(egress), &cs, t);
-            let name = format_ident!("{}", qtfn);
-            egress_tbl_args.push(quote! {
-                &self.#name
-            });
+        if let Some((drop, _)) = drop {
+            reset.push(quote! { #drop = true; });
         }
+        quote! {
+            softnpu_provider::parser_rejected!(||(
+                pkt.error.unwrap_or("NoError")
+            ));
+            #parser_error = pkt
+                .error
+                .and_then(error::from_name)
+                .unwrap_or_default();
+            #(#reset)*
+        }
+    }
 
-        let process_packet = quote! {
-            fn process_packet<'a>(
-                &mut self,
-                port: u16,
-                pkt: &mut packet_in<'a>,
-            ) -> Vec<(packet_out<'a>, u16)> {
-                //
-                // Instantiate the parser out type
-                //
-
-                let mut parsed = #parsed_type::default();
+    /// The structures a parser extracts to, as (type name, local).
+    fn parser_outputs(
+        &self,
+        parser: &Parser,
+        values: &[(String, Ident)],
+    ) -> Vec<(String, Ident)> {
+        parser
+            .parameters
+            .iter()
+            .filter(|p| p.direction == Direction::Out)
+            .filter_map(|p| match &p.ty {
+                Type::UserDefined(typename) => {
+                    values.iter().find(|(t, _)| t == typename).cloned()
+                }
+                _ => None,
+            })
+            .collect()
+    }
 
-                //
-                // Instantiate ingress/egress metadata
-                //
+    /// Arguments to pass to a stage, followed by the tables of a control.
+    fn stage_arguments(
+        &self,
+        stage: &Stage<'_>,
+        values: &[(String, Ident)],
+    ) -> Vec<TokenStream> {
+        let mut args = Vec::new();
+        let mut passed = Vec::new();
+        for (name, ty, direction) in stage.parameters() {
+            let typename = match ty {
+                Type::UserDefined(typename) => typename,
+                _ => panic!("stage parameter {} must be a structure", name),
+            };
+            if typename == "packet_in" {
+                args.push(quote! { pkt });
+                continue;
+            }
+            if typename == "packet_out" {
+                args.push(quote! { &mut out });
+                continue;
+            }
+            let (_, local) = values
+                .iter()
+                .find(|(t, _)| t == typename)
+                .unwrap_or_else(|| panic!("no value for {}", typename));
+            // Parsers take every parameter by mutable reference.
+            let mutable = matches!(stage, Stage::Parser(_))
+                || matches!(direction, Direction::Out | Direction::InOut);
+            // A stage that takes several parameters of the same type is
+            // handed the value once, and defaults for the others.
+            let value = if passed.contains(&local) {
+                let ty = format_ident!("{}", typename);
+                quote! { #ty::default() }
+            } else {
+                passed.push(local);
+                quote! { #local }
+            };
+            if mutable {
+                args.push(quote! { &mut #value });
+            } else {
+                args.push(quote! { &#value });
+            }
+        }
+        if let Some(control) = stage.control() {
+            for (cs, t) in control.tables(self.ast) {
+                let qtfn = qualified_table_function_name(Some(control), &cs, t);
+                let name = format_ident!("{}", qtfn);
+                args.push(quote! { &self.#name });
+            }
+        }
+        args
+    }
 
-                let mut ingress_metadata = ingress_metadata_t{
-                    port: {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(port);
-                        x
-                    },
-                    ..Default::default()
-                };
-                let mut egress_metadata = egress_metadata_t::default();
+    /// The packet as it stands after the stages run so far. Headers are
+    /// either what a deparser emitted, or the valid headers the last parser
+    /// extracted to in declaration order.
+    fn assembled_packet(&self, flow: &Flow) -> TokenStream {
+        let out = if flow.deparsed {
+            quote! { out.assemble(pkt.data, payload) }
+        } else {
+            let headers = &flow.headers;
+            quote! { packet_out::new(#headers.to_bitvec(), pkt.data, payload) }
+        };
+        if flow.reparsed {
+            quote! { #out.into_owned() }
+        } else {
+            out
+        }
+    }
 
-                //
-                // Run the parser block
-                //
+    /// Generate the statements that run a sequence of stages.
+    fn stage_statements(
+        &self,
+        arch: &Architecture,
+        stages: &[PipelineStage<'_>],
+        values: &[(String, Ident)],
+        flow: &mut Flow,
+    ) -> TokenStream {
+        let mut tokens = TokenStream::new();
+        for s in stages {
+            let member = &s.member;
+            let args = self.stage_arguments(&s.stage, values);
+            match s.stage {
+                Stage::Parser(parser) => {
+                    if flow.headers.is_some() {
+                        // parse the packet as assembled so far
+                        let packet = self.assembled_packet(flow);
+                        tokens.extend(quote! {
+                            let data = #packet.into_owned().header_data;
+                            let pkt = &mut packet_in::new(&data);
+                        });
+                        flow.reparsed = true;
+                        for (typename, local) in
+                            self.parser_outputs(parser, values)
+                        {
+                            let ty = format_ident!("{}", typename);
+                            tokens.extend(quote! {
+                                #local = #ty::default();
+                            });
+                        }
+                    }
+                    let outputs = self.parser_outputs(parser, values);
+                    let headers = match outputs.first() {
+                        Some((_, local)) => local.clone(),
+                        None => panic!(
+                            "parser {} has no out parameter",
+                            parser.name
+                        ),
+                    };
+                    let reject = self.parser_reject(arch, values, parser, flow);
+                    tokens.extend(quote! {
+                        let accept = (self.#member)(#(#args),*);
+                        if !accept {
+                            #reject
+                        } else {
+                            let dump = format!("\n{}", #headers.dump());
+                            softnpu_provider::parser_accepted!(||(&dump));
+                        }
 
-                let accept = (self.parse)(pkt, &mut parsed, &mut ingress_metadata);
-                if !accept {
-                    // drop the packet
-                    softnpu_provider::parser_dropped!(||());
-                    return Vec::new();
+                        // Everything the parser extracted or advanced past
+                        // precedes the payload.
+                        let payload = if accept { pkt.index } else { 0 };
+                    });
+                    flow.headers = Some(headers);
+                    flow.deparsed = false;
+                }
+                Stage::Control(c) if verifies_checksums(c, self.ast) => {
+                    tokens.extend(quote! {
+                        let mut checksum_error = false;
+                        (self.#member)(#(#args),*, &mut checksum_error);
+                    });
+                    if let Some((member, width)) = self
+                        .metadata_member(arch.checksum_error.as_ref(), values)
+                    {
+                        let set = Self::flag_value(width);
+                        tokens.extend(quote! {
+                            if checksum_error {
+                                #member = #set;
+                            }
+                        });
+                    }
+                }
+                Stage::Control(_) => {
+                    tokens.extend(quote! {
+                        (self.#member)(#(#args),*);
+                    });
                 }
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::parser_accepted!(||(&dump));
+                Stage::Deparser(_) => {
+                    tokens.extend(quote! {
+                        let mut out = packet_out::default();
+                        (self.#member)(#(#args),*);
+                    });
+                    flow.deparsed = true;
+                }
+            }
+        }
+        tokens
+    }
 
-                //
-                // Calculate parsed header size
-                //
+    fn pipeline_impl_process_packet(
+        &mut self,
+        arch: &Architecture,
+        stages: &[PipelineStage<'_>],
+    ) -> (TokenStream, TokenStream) {
+        let values = self.stage_values(stages);
 
-                let parsed_size = parsed.valid_header_size() >> 3;
+        //
+        // Instantiate the values handed to stages, and let them know the port
+        // the packet arrived on.
+        //
 
-                //
-                // Run the ingress block
-                //
+        let mut instantiate = Vec::new();
+        for (typename, local) in &values {
+            let ty = format_ident!("{}", typename);
+            instantiate.push(quote! {
+                let mut #local = #ty::default();
+            });
+        }
+        let ingress_ports = self.metadata_members(&arch.ingress_port, &values);
+        if ingress_ports.is_empty() {
+            panic!(
+                "{} stages do not take metadata for the ingress port",
+                arch.package,
+            );
+        }
+        for (ingress_port, width) in ingress_ports {
+            instantiate.push(quote! {
+                #ingress_port = {
+                    let mut x = bitvec![mut u8, Msb0; 0; #width];
+                    x.store_le(port as u128);
+                    x
+                };
+            });
+        }
+        if let Some((drop, width)) =
+            self.metadata_member(arch.default_drop.as_ref(), &values)
+        {
+            let set = Self::flag_value(width);
+            instantiate.push(quote! { #drop = #set; });
+        }
+        let ingress_paths =
+            self.packet_path_members(&arch.ingress_packet_path, &values);
+        let egress_paths =
+            self.packet_path_members(&arch.egress_packet_path, &values);
+
+        let egress_ports = self.metadata_members(&arch.egress_port, &values);
+        let egress_spec = match &arch.egress_spec {
+            Some(m) => self.metadata_member(Some(m), &values),
+            None => egress_ports.first().cloned(),
+        };
+        let (egress_spec, _) = egress_spec.unwrap_or_else(|| {
+            panic!(
+                "{} stages do not take metadata for the egress port",
+                arch.package,
+            )
+        });
 
-                (self.ingress)(
-                    &mut parsed,
-                    &mut ingress_metadata,
-                    &mut egress_metadata,
-                    #(#ingress_tbl_args),*
-                );
+        let (ingress, egress) = stages.split_at(arch.ingress_stages);
+
+        // Packets are dropped after ingress by any drop flag, and after
+        // egress by the drop flags of the metadata egress stages are handed.
+        let egress_types = Self::stage_types(egress, false);
+        let mut dropped = Vec::new();
+        let mut egress_dropped = Vec::new();
+        for m in &arch.drop {
+            if let Some((drop, _)) = self.metadata_member(Some(m), &values) {
+                if egress_types.contains(&&m.ty) {
+                    egress_dropped.push(drop.clone());
+                }
+                dropped.push(drop);
+            }
+        }
+        if let Some(drop_port) = arch.drop_port {
+            let dropped_port = quote! {
+                !#egress_spec.is_empty()
+                    && #egress_spec.load_le::<u128>() == #drop_port
+            };
+            dropped.push(dropped_port.clone());
+            egress_dropped.push(dropped_port);
+        }
+        let disjunction = |flags: Vec<TokenStream>| {
+            if flags.is_empty() {
+                quote! { false }
+            } else {
+                quote! { #(#flags)||* }
+            }
+        };
+        let drop = disjunction(dropped);
+        let egress_drop = disjunction(egress_dropped);
 
-                //
-                // Determine egress ports
-                //
+        let broadcast =
+            match self.metadata_member(arch.broadcast.as_ref(), &values) {
+                Some((broadcast, _)) => quote! { #broadcast },
+                None => quote! { false },
+            };
+        let multicast_group =
+            self.metadata_member(arch.multicast_group.as_ref(), &values);
+        let multicast = match &multicast_group {
+            Some((group, _)) => quote! {
+                else if #group.any() {
+                    self.pre.group_ports(#group.load_le::<u128>() as u32)
+                }
+            },
+            None => TokenStream::new(),
+        };
+        let resubmit = self.metadata_member(arch.resubmit.as_ref(), &values);
+        let (resubmit_flag, resubmitted) = match &resubmit {
+            Some((flag, width)) => {
+                let flag = Self::flag_is_set(flag.clone(), *width);
+                (
+                    quote! { let resubmit = !(#drop) && #flag; },
+                    quote! { || resubmit },
+                )
+            }
+            None => (TokenStream::new(), TokenStream::new()),
+        };
 
-                let ports = if egress_metadata.broadcast {
-                    let mut ports = Vec::new();
-                    for p in 0..self.radix {
-                        if p == port {
-                            continue;
-                        }
-                        ports.push(p);
+        let mut flow = Flow {
+            headers: None,
+            deparsed: false,
+            reparsed: false,
+            drop: quote! { return result; },
+        };
+        let ingress_statements =
+            self.stage_statements(arch, ingress, &values, &mut flow);
+        let headers = flow.headers.clone();
+
+        let determine_ports = quote! {
+            //
+            // Determine egress ports
+            //
+
+            #resubmit_flag
+            let ports = if #broadcast {
+                let mut ports = Vec::new();
+                for p in 0..self.radix {
+                    if p == port {
+                        continue;
                     }
-                    ports
+                    ports.push(p);
+                }
+                ports
+            } else {
+                if #drop #resubmitted {
+                    Vec::new()
+                } #multicast else if #egress_spec.is_empty() {
+                    Vec::new()
                 } else {
-                    if egress_metadata.port.is_empty() || egress_metadata.drop {
-                        Vec::new()
-                    } else {
-                        vec![egress_metadata.port.load_le()]
-                    }
-                };
-
-                let dump = parsed.dump();
-
-                if ports.is_empty() {
-                    softnpu_provider::ingress_dropped!(||(&dump));
-                    return Vec::new();
+                    vec![#egress_spec.load_le::<u128>() as u16]
                 }
+            };
 
-                let dump = format!("\n{}", parsed.dump());
+            if ports.is_empty() {
+                let dump = #headers.dump();
+                softnpu_provider::ingress_dropped!(||(&dump));
+            } else {
+                let dump = format!("\n{}", #headers.dump());
                 softnpu_provider::ingress_accepted!(||(&dump));
+            }
+        };
 
-                //
-                // Run output of ingress block through egress block on each
-                // egress port.
-                //
-                let mut result = Vec::new();
-                for eport in ports {
-
-                    let mut egm = egress_metadata.clone();
-                    let mut parsed_ = parsed.clone();
-
-                    //
-                    // Run the egress block
-                    //
-
-                    egm.port = {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(eport);
+        let set_egress_port: Vec<TokenStream> = egress_ports
+            .iter()
+            .map(|(egress_port, width)| {
+                quote! {
+                    #egress_port = {
+                        let mut x = bitvec![mut u8, Msb0; 0; #width];
+                        x.store_le(eport as u128);
                         x
                     };
+                }
+            })
+            .collect();
 
-                    (self.egress)(
-                        &mut parsed_,
-                        &mut ingress_metadata,
-                        &mut egm,
-                        #(#egress_tbl_args),*
-                    );
-
-                    if egm.drop {
-                        continue;
-                    }
+        //
+        // Egress runs once for each job, a port along with the packet and
+        // values to run it with. Jobs carry the path the packet took into
+        // egress, and the output of the ingress deparser when there is one.
+        //
 
-                    //
-                    // Create the packet output.
-                    //
+        let locals: Vec<&Ident> =
+            values.iter().map(|(_, local)| local).collect();
+        let path_type = egress_paths.first().map(|(_, ty)| ty);
+        let egress_path = |variant: &str| match path_type {
+            Some(ty) => {
+                let variant = format_ident!("{}", variant);
+                quote! { #ty::#variant, }
+            }
+            None => TokenStream::new(),
+        };
+        let job_path = path_type.map(|_| quote! { path, });
+        let set_egress_path: Vec<TokenStream> = egress_paths
+            .iter()
+            .map(|(member, _)| quote! { #member = path; })
+            .collect();
+        let job_out = flow.deparsed.then(|| quote! { out, });
+        let normal_out = flow.deparsed.then(|| quote! { out.clone(), });
+        let normal_path = match (path_type, &multicast_group) {
+            (Some(ty), Some((group, _))) => quote! {
+                let path = if #group.any() {
+                    #ty::NORMAL_MULTICAST
+                } else {
+                    #ty::NORMAL_UNICAST
+                };
+            },
+            (Some(ty), None) => quote! { let path = #ty::NORMAL_UNICAST; },
+            (None, _) => TokenStream::new(),
+        };
 
-                    let bv = parsed_.to_bitvec();
-                    let buf = bv.as_raw_slice();
-                    let out = packet_out{
-                        header_data: buf.to_owned(),
-                        payload_data: &pkt.data[parsed_size..],
-                    };
-                    result.push((out, eport))
+        let ingress_clone =
+            self.clone_request(arch.ingress_clone.as_ref(), &values);
+        let egress_clone =
+            self.clone_request(arch.egress_clone.as_ref(), &values);
+        if (ingress_clone.is_some() || egress_clone.is_some()) && !flow.deparsed
+        {
+            panic!(
+                "{} clones packets, which requires ingress to end with a \
+                deparser",
+                arch.package,
+            );
+        }
 
+        // A clone from ingress is of the packet as it arrived.
+        let clone_from_ingress = match &ingress_clone {
+            Some((flag, session)) => {
+                let path = egress_path("CLONE_I2E");
+                quote! {
+                    if #flag {
+                        let session = #session.load_le::<u128>() as u32;
+                        if let Some(eport) = self.pre.clone_session_port(session)
+                        {
+                            jobs.push_back((
+                                eport,
+                                #path
+                                packet_out::default(),
+                                0,
+                                (#(#locals.clone(),)*),
+                            ));
+                        }
+                    }
                 }
-                result
             }
+            None => TokenStream::new(),
         };
 
-        //TODO factor out commonalities with process_packet
-        let process_packet_headers = quote! {
-            fn process_packet_headers<'a>(
-                &mut self,
-                port: u16,
-                pkt: &mut packet_in<'a>,
-            ) -> Vec<(#parsed_type, u16)> {
-                //
-                // Instantiate the parser out type
-                //
-
-                let mut parsed = #parsed_type::default();
-
-                //
-                // Instantiate ingress/egress metadata
-                //
-
-                let mut ingress_metadata = ingress_metadata_t{
-                    port: {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(port);
-                        x
+        let mut egress_flow = Flow {
+            drop: quote! { continue; },
+            ..flow.clone()
+        };
+        let egress_statements =
+            self.stage_statements(arch, egress, &values, &mut egress_flow);
+        let emit = self.assembled_packet(&egress_flow);
+
+        // A clone from egress is of the packet egress put out, and is handed
+        // the values ingress finished with apart from those the egress
+        // deparsers put out.
+        let (keep_ingress_values, clone_from_egress) = match &egress_clone {
+            Some((flag, session)) => {
+                let deparser_outputs = Self::stage_types(
+                    egress
+                        .iter()
+                        .filter(|s| matches!(s.stage, Stage::Deparser(_))),
+                    true,
+                );
+                let clone_values: Vec<TokenStream> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (typename, local))| {
+                        if deparser_outputs.contains(&typename) {
+                            quote! { #local.clone() }
+                        } else {
+                            let i = syn::Index::from(i);
+                            quote! { ingress_values.#i.clone() }
+                        }
+                    })
+                    .collect();
+                let path = egress_path("CLONE_E2E");
+                (
+                    quote! {
+                        let ingress_values = (#(#locals.clone(),)*);
+                        let end = pkt.data.len() << 3;
+                        let mut egress_clones = 0;
                     },
-                    ..Default::default()
-                };
-                let mut egress_metadata = egress_metadata_t::default();
-
-                //
-                // Run the parser block
-                //
+                    quote! {
+                        if #flag
+                            && egress_clones < p4rs::externs::MAX_EGRESS_CLONES
+                        {
+                            let session = #session.load_le::<u128>() as u32;
+                            if let Some(eport) =
+                                self.pre.clone_session_port(session)
+                            {
+                                egress_clones += 1;
+                                let mut input = packet_out::default();
+                                input.emit(BitVec::from_slice(
+                                    &[&out.header_data[..], out.payload_data]
+                                        .concat(),
+                                ));
+                                jobs.push_back((
+                                    eport,
+                                    #path
+                                    input,
+                                    end,
+                                    (#(#clone_values,)*),
+                                ));
+                            }
+                        }
+                    },
+                )
+            }
+            None => (TokenStream::new(), TokenStream::new()),
+        };
 
-                let accept = (self.parse)(pkt, &mut parsed, &mut ingress_metadata);
-                if !accept {
-                    // drop the packet
-                    softnpu_provider::parser_dropped!(||());
-                    return Vec::new();
-                }
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::parser_accepted!(||(&dump));
+        // Resubmitted packets run through ingress again, handed the values
+        // the ingress deparsers put out that the ingress parsers take.
+        let deparser_outputs = Self::stage_types(
+            ingress
+                .iter()
+                .filter(|s| matches!(s.stage, Stage::Deparser(_))),
+            true,
+        );
+        let parser_inputs = Self::stage_types(
+            ingress
+                .iter()
+                .filter(|s| matches!(s.stage, Stage::Parser(_))),
+            false,
+        );
+        let (carried_types, carried_locals): (Vec<Ident>, Vec<&Ident>) = values
+            .iter()
+            .filter(|(typename, _)| {
+                deparser_outputs.contains(&typename)
+                    && parser_inputs.contains(&typename)
+            })
+            .map(|(typename, local)| (format_ident!("{}", typename), local))
+            .unzip();
+        let ingress_path_type = ingress_paths.first().map(|(_, ty)| ty);
+        let mut carried_initial = TokenStream::new();
+        let mut carried = TokenStream::new();
+        let mut carry = TokenStream::new();
+        if resubmit.is_some() && !carried_locals.is_empty() {
+            carried_initial.extend(quote! {
+                let mut carried = (#(#carried_types::default(),)*);
+            });
+            carried.extend(quote! {
+                (#(#carried_locals,)*) = carried;
+            });
+            carry.extend(quote! {
+                carried = (#(#carried_locals,)*);
+            });
+        }
+        let ingress_path = match ingress_path_type {
+            Some(ty) if resubmit.is_some() => {
+                carried_initial.extend(quote! {
+                    let mut ingress_path = #ty::NORMAL;
+                });
+                carry.extend(quote! {
+                    ingress_path = #ty::RESUBMIT;
+                });
+                quote! { ingress_path }
+            }
+            Some(ty) => quote! { #ty::NORMAL },
+            None => TokenStream::new(),
+        };
+        let set_ingress_path: Vec<TokenStream> = ingress_paths
+            .iter()
+            .map(|(member, _)| quote! { #member = #ingress_path; })
+            .collect();
+
+        let process_ingress = quote! {
+            #(#instantiate)*
+            #carried
+            #(#set_ingress_path)*
+
+            #ingress_statements
+
+            #determine_ports
+
+            //
+            // Run the output of ingress through egress on each egress port,
+            // and for each clone.
+            //
+
+            let mut jobs = std::collections::VecDeque::new();
+            #clone_from_ingress
+            #normal_path
+            for eport in ports {
+                jobs.push_back((
+                    eport,
+                    #job_path
+                    #normal_out
+                    payload,
+                    (#(#locals.clone(),)*),
+                ));
+            }
 
-                //
-                // Calculate parsed header size
-                //
+            #keep_ingress_values
+            while let Some((eport, #job_path #job_out payload, (#(mut #locals,)*))) =
+                jobs.pop_front()
+            {
+                #(#set_egress_port)*
+                #(#set_egress_path)*
 
-                let parsed_size = parsed.valid_header_size() >> 3;
+                #egress_statements
 
                 //
-                // Run the ingress block
+                // Create the packet output.
                 //
 
-                (self.ingress)(
-                    &mut parsed,
-                    &mut ingress_metadata,
-                    &mut egress_metadata,
-                    #(#ingress_tbl_args),*
-                );
+                let out = #emit;
+                #clone_from_egress
 
-                //
-                // Determine egress ports
-                //
-
-                let ports = if egress_metadata.broadcast {
-                    let mut ports = Vec::new();
-                    for p in 0..self.radix {
-                        if p == port {
-                            continue;
-                        }
-                        ports.push(p);
-                    }
-                    ports
-                } else {
-                    if egress_metadata.port.is_empty() || egress_metadata.drop {
-                        Vec::new()
-                    } else {
-                        vec![egress_metadata.port.load_le()]
+                if #egress_drop {
+                    continue;
+                }
+                result.push((out, eport))
+            }
+        };
+        let process_ingress = if resubmit.is_some() {
+            quote! {
+                #carried_initial
+                for _ in 0..=p4rs::externs::MAX_RESUBMISSIONS {
+                    #process_ingress
+
+                    if !resubmit {
+                        return result;
                     }
-                };
+                    pkt.index = 0;
+                    pkt.error = None;
+                    #carry
+                }
+                result
+            }
+        } else {
+            quote! {
+                #process_ingress
+                result
+            }
+        };
 
-                let dump = parsed.dump();
+        let process_packet = quote! {
+            fn process_packet<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+            ) -> Vec<(packet_out<'a>, u16)> {
+                let mut result = Vec::new();
+                #process_ingress
+            }
+        };
 
-                if ports.is_empty() {
-                    softnpu_provider::ingress_dropped!(||(&dump));
-                    return Vec::new();
-                }
+        //
+        // Headers are reported as the first parser extracted them, after
+        // each stage up to the first deparser or parser after that has run.
+        // Only the first pass through ingress is followed, clones are not.
+        //
 
-                let dump = format!("\n{}", parsed.dump());
-                softnpu_provider::ingress_accepted!(||(&dump));
+        let end = stages
+            .iter()
+            .skip(1)
+            .position(|s| !matches!(s.stage, Stage::Control(_)))
+            .map(|n| n + 1)
+            .unwrap_or(stages.len());
+        let parsed_type = match stages[0].stage {
+            Stage::Parser(p) => {
+                let (typename, _) = &self.parser_outputs(p, &values)[0];
+                format_ident!("{}", typename)
+            }
+            _ => unreachable!(),
+        };
+        let mut header_flow = Flow {
+            headers: None,
+            deparsed: false,
+            reparsed: false,
+            drop: quote! { return Vec::new(); },
+        };
+        let ingress_end = usize::min(arch.ingress_stages, end);
+        let ingress_statements = self.stage_statements(
+            arch,
+            &stages[..ingress_end],
+            &values,
+            &mut header_flow,
+        );
+        let mut header_flow = Flow {
+            drop: quote! { continue; },
+            ..header_flow
+        };
+        let egress_statements = self.stage_statements(
+            arch,
+            &stages[ingress_end..usize::max(ingress_end, end)],
+            &values,
+            &mut header_flow,
+        );
+        let copies: Vec<TokenStream> = values
+            .iter()
+            .map(|(_, local)| quote! { let mut #local = #local.clone(); })
+            .collect();
+
+        let set_normal_ingress_path: Vec<TokenStream> = ingress_paths
+            .iter()
+            .map(|(member, ty)| quote! { #member = #ty::NORMAL; })
+            .collect();
 
-                //
-                // Run output of ingress block through egress block on each
-                // egress port.
-                //
-                let mut result = Vec::new();
-                for eport in ports {
+        let process_packet_headers = quote! {
+            fn process_packet_headers<'a>(
+                &mut self,
+                port: u16,
+                pkt: &mut packet_in<'a>,
+            ) -> Vec<(#parsed_type, u16)> {
+                #(#instantiate)*
+                #(#set_normal_ingress_path)*
 
-                    let mut egm = egress_metadata.clone();
-                    let mut parsed_ = parsed.clone();
+                #ingress_statements
 
-                    //
-                    // Run the egress block
-                    //
+                #determine_ports
 
-                    egm.port = {
-                        let mut x = bitvec![mut u8, Msb0; 0; 16];
-                        x.store_le(eport);
-                        x
-                    };
+                let mut result = Vec::new();
+                #normal_path
+                for eport in ports {
+                    #(#copies)*
+                    #(#set_egress_port)*
+                    #(#set_egress_path)*
 
-                    (self.egress)(
-                        &mut parsed_,
-                        &mut ingress_metadata,
-                        &mut egm,
-                        #(#egress_tbl_args),*
-                    );
+                    #egress_statements
 
-                    if egm.drop {
+                    if #egress_drop {
                         continue;
                     }
 
-                    //
-                    // Create the packet output.
-                    //
-
-                    result.push((parsed_, eport))
-
+                    result.push((#headers, eport))
                 }
                 result
             }
@@ -516,33 +1375,24 @@ impl<'a> PipelineGenerator<'a> {
         (members, initializers)
     }
 
-    fn add_table_entry_method(
-        &mut self,
-        ingress: &Control,
-        egress: &Control,
-    ) -> TokenStream {
+    fn table_update_methods(&mut self, controls: &[&Control]) -> TokenStream {
         let mut body = TokenStream::new();
 
-        for control in &[ingress, egress] {
+        for control in controls {
             let tables = control.tables(self.ast);
             for (cs, table) in tables.iter() {
                 let qtn = qualified_table_name(Some(control), cs, table);
                 let qtfn =
                     qualified_table_function_name(Some(control), cs, table);
-                let call = format_ident!("add_{}_entry", qtfn);
+                let call = format_ident!("apply_{}_update", qtfn);
                 body.extend(quote! {
-                    #qtn => self.#call(
-                        action_id,
-                        keyset_data,
-                        parameter_data,
-                        priority,
-                    ),
+                    #qtn => self.#call(update),
                 });
             }
         }
 
         body.extend(quote! {
-            x => println!("add table entry: unknown table id {}, ignoring", x),
+            x => Err(p4rs::TableUpdateError::UnknownTable(x.to_owned())),
         });
 
         quote! {
@@ -553,44 +1403,106 @@ impl<'a> PipelineGenerator<'a> {
                 keyset_data: &[u8],
                 parameter_data: &[u8],
                 priority: u32,
-            ) {
-                match table_id {
-                    #body
+            ) -> Result<(), p4rs::TableUpdateError> {
+                self.apply_table_updates(&[p4rs::TableUpdate::Add {
+                    table_id: table_id.to_owned(),
+                    action_id: action_id.to_owned(),
+                    keyset_data: keyset_data.to_owned(),
+                    parameter_data: parameter_data.to_owned(),
+                    priority,
+                }])
+            }
+
+            fn remove_table_entry(
+                &mut self,
+                table_id: &str,
+                keyset_data: &[u8],
+            ) -> Result<(), p4rs::TableUpdateError> {
+                self.apply_table_updates(&[p4rs::TableUpdate::Remove {
+                    table_id: table_id.to_owned(),
+                    keyset_data: keyset_data.to_owned(),
+                }])
+            }
+
+            fn modify_table_entry(
+                &mut self,
+                table_id: &str,
+                action_id: &str,
+                keyset_data: &[u8],
+                parameter_data: &[u8],
+            ) -> Result<(), p4rs::TableUpdateError> {
+                self.apply_table_updates(&[p4rs::TableUpdate::Modify {
+                    table_id: table_id.to_owned(),
+                    action_id: action_id.to_owned(),
+                    keyset_data: keyset_data.to_owned(),
+                    parameter_data: parameter_data.to_owned(),
+                }])
+            }
+
+            fn apply_table_updates(
+                &mut self,
+                updates: &[p4rs::TableUpdate],
+            ) -> Result<(), p4rs::TableUpdateError> {
+                // each applied update leaves behind a closure that reverts
+                // it, run in reverse order if a later update fails
+                let mut undo: Vec<Box<dyn FnOnce(&mut Self)>> = Vec::new();
+                for update in updates {
+                    let result = match update.table_id() {
+                        #body
+                    };
+                    match result {
+                        Ok(u) => undo.push(u),
+                        Err(e) => {
+                            for u in undo.into_iter().rev() {
+                                u(self);
+                            }
+                            return Err(e);
+                        }
+                    }
                 }
+                Ok(())
             }
         }
     }
 
-    fn remove_table_entry_method(
+    fn set_table_default_action_method(
         &mut self,
-        ingress: &Control,
-        egress: &Control,
+        controls: &[&Control],
     ) -> TokenStream {
         let mut body = TokenStream::new();
 
-        for control in &[ingress, egress] {
+        for control in controls {
             let tables = control.tables(self.ast);
             for (cs, table) in tables.iter() {
                 let qtn = qualified_table_name(Some(control), cs, table);
-                let qftn =
+                if table.const_default_action {
+                    body.extend(quote! {
+                        #qtn => Err(p4rs::TableUpdateError::ConstDefaultAction {
+                            table_id: #qtn.to_owned(),
+                        }),
+                    });
+                    continue;
+                }
+                let qtfn =
                     qualified_table_function_name(Some(control), cs, table);
-                let call = format_ident!("remove_{}_entry", qftn);
+                let call = format_ident!("set_{}_default_action", qtfn);
                 body.extend(quote! {
-                    #qtn => self.#call(keyset_data),
+                    #qtn => self.#call(action_id, parameter_data),
                 });
             }
         }
 
-        body.extend(quote!{
-            x => println!("remove table entry: unknown table id {}, ignoring", x),
+        body.extend(quote! {
+            x => Err(p4rs::TableUpdateError::UnknownTable(x.to_owned())),
         });
 
         quote! {
-            fn remove_table_entry(
+            fn set_table_default_action(
                 &mut self,
                 table_id: &str,
-                keyset_data: &[u8],
-            ) {
+                action_id: &str,
+                parameter_data: &[u8],
+            ) -> Result<(), p4rs::TableUpdateError> {
                 match table_id {
                     #body
                 }
@@ -598,14 +1510,10 @@ impl<'a> PipelineGenerator<'a> {
         }
     }
 
-    fn get_table_ids_method(
-        &mut self,
-        ingress: &Control,
-        egress: &Control,
-    ) -> TokenStream {
+    fn get_table_ids_method(&mut self, controls: &[&Control]) -> TokenStream {
         let mut names = Vec::new();
 
-        for control in &[ingress, egress] {
+        for control in controls {
             let tables = control.tables(self.ast);
             for (cs, table) in &tables {
                 names.push(qualified_table_name(Some(control), cs, table));
@@ -620,79 +1528,400 @@ impl<'a> PipelineGenerator<'a> {
 
     fn get_table_entries_method(
         &mut self,
-        ingress: &Control,
-        egress: &Control,
+        controls: &[&Control],
     ) -> TokenStream {
         let mut body = TokenStream::new();
 
-        for control in &[ingress, egress] {
-            let tables = control.tables(self.ast);
-            for (cs, table) in tables.iter() {
-                let qtn = qualified_table_name(Some(control), cs, table);
-                let qtfn =
-                    qualified_table_function_name(Some(control), cs, table);
-                let call = format_ident!("get_{}_entries", qtfn);
-                body.extend(quote! {
-                    #qtn => Some(self.#call()),
-                });
-            }
-        }
+        for control in controls {
+            let tables = control.tables(self.ast);
+            for (cs, table) in tables.iter() {
+                let qtn = qualified_table_name(Some(control), cs, table);
+                let qtfn =
+                    qualified_table_function_name(Some(control), cs, table);
+                let call = format_ident!("get_{}_entries", qtfn);
+                body.extend(quote! {
+                    #qtn => Some(self.#call()),
+                });
+            }
+        }
+
+        body.extend(quote! {
+            x => None,
+        });
+
+        quote! {
+            fn get_table_entries(
+                &self,
+                table_id: &str,
+            ) -> Option<Vec<p4rs::TableEntry>> {
+                match table_id {
+                    #body
+                }
+            }
+        }
+    }
+
+    fn table_modifiers(&mut self, controls: &[&Control]) -> TokenStream {
+        let mut tokens = TokenStream::new();
+        for control in controls {
+            self.table_modifiers_for_control(&mut tokens, control);
+        }
+        tokens
+    }
+
+    fn table_modifiers_for_control(
+        &mut self,
+        tokens: &mut TokenStream,
+        control: &Control,
+    ) {
+        let tables = control.tables(self.ast);
+        for (cs, table) in tables {
+            let table_control = cs.last().unwrap().1;
+            let qtn = qualified_table_name(Some(control), &cs, table);
+            let qtfn = qualified_table_function_name(Some(control), &cs, table);
+            tokens.extend(self.table_update_function(
+                table,
+                table_control,
+                &qtn,
+                &qtfn,
+            ));
+            tokens.extend(self.get_table_entries_function(
+                table,
+                table_control,
+                &qtfn,
+            ));
+            if !table.const_default_action {
+                tokens.extend(self.set_table_default_action_function(
+                    table,
+                    table_control,
+                    &qtn,
+                    &qtfn,
+                ));
+            }
+        }
+    }
+
+    /// Generate a module for each table with typed keys and actions, along
+    /// with a pipeline method returning a handle that adds and removes
+    /// entries in terms of them. Returns the modules and the methods. Tables
+    /// with longest prefix match keys that are not IPv4 or IPv6 addresses
+    /// cannot hold entries, and get no builder.
+    fn table_entry_builders(
+        &mut self,
+        controls: &[&Control],
+        pipeline_name: &Ident,
+    ) -> (TokenStream, TokenStream) {
+        let mut modules = TokenStream::new();
+        let mut methods = TokenStream::new();
+        for control in controls {
+            for (cs, table) in control.tables(self.ast) {
+                let lpm_widths_supported =
+                    table.key.iter().all(|(lval, match_kind)| {
+                        !matches!(match_kind, MatchKind::LongestPrefixMatch)
+                            || matches!(
+                                type_size(
+                                    &self.hlir.lvalue_decls[lval].ty,
+                                    self.ast,
+                                ),
+                                32 | 128
+                            )
+                    });
+                if !lpm_widths_supported {
+                    continue;
+                }
+                let table_control = cs.last().unwrap().1;
+                let qtn = qualified_table_name(Some(control), &cs, table);
+                let qtfn =
+                    qualified_table_function_name(Some(control), &cs, table);
+                modules.extend(self.table_entry_builder(
+                    table,
+                    table_control,
+                    &qtn,
+                    &qtfn,
+                    pipeline_name,
+                ));
+                let name = format_ident!("{}", qtfn);
+                let doc = format!("Typed entries for the `{}` table.", qtn);
+                methods.extend(quote! {
+                    #[doc = #doc]
+                    pub fn #name(&mut self) -> #name::Handle<'_> {
+                        #name::Handle { pipeline: self }
+                    }
+                });
+            }
+        }
+        (modules, methods)
+    }
+
+    fn table_entry_builder(
+        &mut self,
+        table: &Table,
+        control: &Control,
+        qtn: &str,
+        qtfn: &str,
+        pipeline_name: &Ident,
+    ) -> TokenStream {
+        //
+        // key, a single match key or a tuple of them
+        //
+
+        let mut key_types = Vec::new();
+        let mut key_writes = Vec::new();
+        for (i, (lval, match_kind)) in table.key.iter().enumerate() {
+            let ty = &self.hlir.lvalue_decls[lval].ty;
+            let sz = type_size_bytes(ty, self.ast);
+            let bits = type_size(ty, self.ast);
+            let value = entry_value_type(ty, sz);
+            key_types.push(match match_kind {
+                MatchKind::Exact => quote! { p4rs::key::Exact<#value> },
+                MatchKind::Ternary => quote! { p4rs::key::Ternary<#value> },
+                MatchKind::Range => quote! { p4rs::key::Range<#value> },
+                MatchKind::LongestPrefixMatch => {
+                    let address = if bits == 32 {
+                        quote! { std::net::Ipv4Addr }
+                    } else {
+                        quote! { std::net::Ipv6Addr }
+                    };
+                    quote! { p4rs::key::Lpm<#address> }
+                }
+            });
+            let field = if table.key.len() == 1 {
+                quote! { key }
+            } else {
+                let i = syn::Index::from(i);
+                quote! { key.#i }
+            };
+            key_writes.push(quote! {
+                #field
+                    .write(#bits, &mut keyset_data)
+                    .map_err(|e| e.table_update_error(#qtn))?;
+            });
+        }
+        let key_type = match key_types.as_slice() {
+            [ty] => ty.clone(),
+            tys => quote! { (#(#tys),*) },
+        };
+
+        //
+        // actions, a struct per action and an enum over them
+        //
+
+        let mut action_structs = Vec::new();
+        let mut action_variants = Vec::new();
+        for action in &table.actions {
+            // NoAction comes from core.p4 rather than the control, and takes
+            // no parameters
+            let parameters = if action.name == "NoAction" {
+                Vec::new()
+            } else {
+                control
+                    .get_action(&action.name)
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "control {} must have action {}",
+                            control.name, &action.name,
+                        )
+                    })
+                    .parameters
+                    .clone()
+            };
+            let aname = format_ident!("{}", action.name);
+            let mut fields = Vec::new();
+            let mut writes = Vec::new();
+            for p in &parameters {
+                let pname = format_ident!("{}", p.name);
+                let sz = type_size_bytes(&p.ty, self.ast);
+                let bits = type_size(&p.ty, self.ast);
+                let ty = entry_value_type(&p.ty, sz);
+                fields.push(quote! { pub #pname: #ty });
+                writes.push(quote! {
+                    p4rs::key::Value::write(&self.#pname, #bits, &mut data)
+                        .map_err(|e| e.table_update_error(#qtn))?;
+                });
+            }
+            let (def, data) = if fields.is_empty() {
+                (quote! { pub struct #aname; }, quote! { Ok(Vec::new()) })
+            } else {
+                (
+                    quote! { pub struct #aname { #(#fields),* } },
+                    quote! {
+                        let mut data = Vec::new();
+                        #(#writes)*
+                        Ok(data)
+                    },
+                )
+            };
+            action_structs.push(quote! {
+                #def
+
+                impl #aname {
+                    pub fn parameter_data(
+                        &self,
+                    ) -> Result<Vec<u8>, p4rs::TableUpdateError> {
+                        #data
+                    }
+                }
+
+                impl From<#aname> for super::Action {
+                    fn from(a: #aname) -> Self {
+                        Self::#aname(a)
+                    }
+                }
+            });
+            action_variants.push((aname, action.name.clone()));
+        }
+
+        let variants = action_variants
+            .iter()
+            .map(|(aname, _)| quote! { #aname(action::#aname) });
+        let (action_ids, parameter_data) = if action_variants.is_empty() {
+            (quote! { match *self {} }, quote! { match *self {} })
+        } else {
+            let ids = action_variants.iter().map(|(aname, id)| {
+                quote! { Self::#aname(_) => #id, }
+            });
+            let data = action_variants.iter().map(|(aname, _)| {
+                quote! { Self::#aname(a) => a.parameter_data(), }
+            });
+            (
+                quote! { match self { #(#ids)* } },
+                quote! { match self { #(#data)* } },
+            )
+        };
+
+        //
+        // handle
+        //
+
+        let default_action = if table.const_default_action {
+            TokenStream::new()
+        } else {
+            let set = format_ident!("set_{}_default_action", qtfn);
+            quote! {
+                pub fn set_default_action(
+                    &mut self,
+                    action: impl Into<Action>,
+                ) -> Result<(), p4rs::TableUpdateError> {
+                    let action = action.into();
+                    self.pipeline.#set(
+                        action.action_id(),
+                        &action.parameter_data()?,
+                    )
+                }
+
+                pub fn clear_default_action(
+                    &mut self,
+                ) -> Result<(), p4rs::TableUpdateError> {
+                    self.pipeline.#set("NoAction", &[])
+                }
+            }
+        };
+
+        let name = format_ident!("{}", qtfn);
+        let doc = format!("Typed entries for the `{}` table.", qtn);
+        quote! {
+            #[doc = #doc]
+            pub mod #name {
+                pub type Key = #key_type;
+
+                pub fn keyset_data(
+                    key: &Key,
+                ) -> Result<Vec<u8>, p4rs::TableUpdateError> {
+                    let mut keyset_data = Vec::new();
+                    #(#key_writes)*
+                    Ok(keyset_data)
+                }
+
+                pub mod action {
+                    #(#action_structs)*
+                }
+
+                pub enum Action {
+                    #(#variants),*
+                }
+
+                impl Action {
+                    pub fn action_id(&self) -> &'static str {
+                        #action_ids
+                    }
+
+                    pub fn parameter_data(
+                        &self,
+                    ) -> Result<Vec<u8>, p4rs::TableUpdateError> {
+                        #parameter_data
+                    }
+                }
+
+                pub struct Handle<'a> {
+                    pub(super) pipeline: &'a mut super::#pipeline_name,
+                }
+
+                impl Handle<'_> {
+                    pub fn add(
+                        &mut self,
+                        key: Key,
+                        action: impl Into<Action>,
+                    ) -> Result<(), p4rs::TableUpdateError> {
+                        self.add_with_priority(key, action, 0)
+                    }
 
-        body.extend(quote! {
-            x => None,
-        });
+                    pub fn add_with_priority(
+                        &mut self,
+                        key: Key,
+                        action: impl Into<Action>,
+                        priority: u32,
+                    ) -> Result<(), p4rs::TableUpdateError> {
+                        let action = action.into();
+                        p4rs::Pipeline::add_table_entry(
+                            self.pipeline,
+                            #qtn,
+                            action.action_id(),
+                            &keyset_data(&key)?,
+                            &action.parameter_data()?,
+                            priority,
+                        )
+                    }
 
-        quote! {
-            fn get_table_entries(
-                &self,
-                table_id: &str,
-            ) -> Option<Vec<p4rs::TableEntry>> {
-                match table_id {
-                    #body
+                    pub fn remove(
+                        &mut self,
+                        key: Key,
+                    ) -> Result<(), p4rs::TableUpdateError> {
+                        p4rs::Pipeline::remove_table_entry(
+                            self.pipeline,
+                            #qtn,
+                            &keyset_data(&key)?,
+                        )
+                    }
+
+                    pub fn modify(
+                        &mut self,
+                        key: Key,
+                        action: impl Into<Action>,
+                    ) -> Result<(), p4rs::TableUpdateError> {
+                        let action = action.into();
+                        p4rs::Pipeline::modify_table_entry(
+                            self.pipeline,
+                            #qtn,
+                            action.action_id(),
+                            &keyset_data(&key)?,
+                            &action.parameter_data()?,
+                        )
+                    }
+
+                    #default_action
                 }
             }
         }
     }
 
-    fn table_modifiers(
-        &mut self,
-        ingress: &Control,
-        egress: &Control,
-    ) -> TokenStream {
-        let mut tokens = TokenStream::new();
-        self.table_modifiers_for_control(&mut tokens, ingress);
-        self.table_modifiers_for_control(&mut tokens, egress);
-        tokens
-    }
-
-    fn table_modifiers_for_control(
+    /// Generate the key extractions for a table's keyset data, along with the
+    /// length of the keyset data they read. Extractions return from the
+    /// enclosing function with an error for keys the table cannot hold.
+    fn table_entry_keys(
         &mut self,
-        tokens: &mut TokenStream,
-        control: &Control,
-    ) {
-        let tables = control.tables(self.ast);
-        for (cs, table) in tables {
-            let table_control = cs.last().unwrap().1;
-            let qtfn = qualified_table_function_name(Some(control), &cs, table);
-            tokens.extend(self.add_table_entry_function(
-                table,
-                table_control,
-                &qtfn,
-            ));
-            tokens.extend(self.remove_table_entry_function(
-                table,
-                table_control,
-                &qtfn,
-            ));
-            tokens.extend(self.get_table_entries_function(
-                table,
-                table_control,
-                &qtfn,
-            ));
-        }
-    }
-
-    fn table_entry_keys(&mut self, table: &Table) -> Vec<TokenStream> {
+        table: &Table,
+        qtn: &str,
+    ) -> (Vec<TokenStream>, usize) {
         let mut keys = Vec::new();
         let mut offset: usize = 0;
         for (lval, match_kind) in &table.key {
@@ -701,58 +1930,101 @@ impl<'a> PipelineGenerator<'a> {
                     panic!("declaration info for {:#?}", lval,)
                 });
             let sz = type_size_bytes(&name_info.ty, self.ast);
+            let bits = type_size(&name_info.ty, self.ast);
+            // keys a whole number of bytes wide always fit
+            let fits = |offset: usize| {
+                if bits.is_multiple_of(8) {
+                    return TokenStream::new();
+                }
+                quote! {
+                    if !p4rs::key::fits(
+                        &keyset_data[#offset..#offset + #sz],
+                        #bits,
+                    ) {
+                        return Err(p4rs::TableUpdateError::ValueTooWide {
+                            table_id: #qtn.to_owned(),
+                            width: #bits,
+                        });
+                    }
+                }
+            };
             match match_kind {
-                MatchKind::Exact => keys.push(quote! {
-                    p4rs::extract_exact_key(
-                        keyset_data,
-                        #offset,
-                        #sz,
-                    )
-                }),
+                MatchKind::Exact => {
+                    let fits = fits(offset);
+                    keys.push(quote! {{
+                        #fits
+                        p4rs::extract_exact_key(
+                            keyset_data,
+                            #offset,
+                            #sz,
+                        )
+                    }})
+                }
                 MatchKind::Ternary => {
-                    keys.push(quote! {
+                    // the value follows the care/dontcare indicator
+                    let fits = fits(offset + 1);
+                    keys.push(quote! {{
+                        #fits
                         p4rs::extract_ternary_key(
                             keyset_data,
                             #offset,
                             #sz,
                         )
-                    });
+                    }});
                     offset += 1; // for care/dontcare indicator
                 }
-                MatchKind::LongestPrefixMatch => keys.push(quote! {
-                    p4rs::extract_lpm_key(
-                        keyset_data,
-                        #offset,
-                        #sz,
-                    )
-                }),
-                MatchKind::Range => keys.push(quote! {
-                    p4rs::extract_range_key(
-                        keyset_data,
-                        #offset,
-                        #sz,
-                    )
-                }),
+                MatchKind::LongestPrefixMatch => {
+                    let width = sz << 3;
+                    keys.push(quote! {
+                        match p4rs::extract_lpm_key(keyset_data, #offset, #sz) {
+                            Some(key) => key,
+                            None => {
+                                return Err(
+                                    p4rs::TableUpdateError::UnsupportedPrefix {
+                                        table_id: #qtn.to_owned(),
+                                        width: #width,
+                                        len: keyset_data[#offset + #sz],
+                                    },
+                                )
+                            }
+                        }
+                    });
+                    offset += 1; // for prefix length
+                }
+                MatchKind::Range => {
+                    let (low, high) = (fits(offset), fits(offset + sz));
+                    keys.push(quote! {{
+                        #low
+                        #high
+                        p4rs::extract_range_key(
+                            keyset_data,
+                            #offset,
+                            #sz,
+                        )
+                    }});
+                    offset += sz; // for the high end of the range
+                }
             }
             offset += sz;
         }
 
-        keys
+        (keys, offset)
     }
 
-    fn add_table_entry_function(
+    /// Generate a function that validates and applies a single update to a
+    /// table, returning a closure that reverts it.
+    fn table_update_function(
         &mut self,
         table: &Table,
         control: &Control,
+        qtn: &str,
         qtfn: &str,
     ) -> TokenStream {
-        let keys = self.table_entry_keys(table);
+        let (keys, keyset_len) = self.table_entry_keys(table, qtn);
+        let tname = format_ident!("{}", qtfn);
 
         let mut action_match_body = TokenStream::new();
         for action in table.actions.iter() {
-            let call =
-                format_ident!("{}_action_{}", control.name, &action.name);
-            let n = table.key.len();
             //XXX hack
             if &action.name == "NoAction" {
                 continue;
@@ -763,185 +2035,248 @@ impl<'a> PipelineGenerator<'a> {
                     control.name, &action.name,
                 )
             });
-            let mut parameter_tokens = Vec::new();
-            let mut parameter_refs = Vec::new();
-            let mut offset: usize = 0;
-            for p in &a.parameters {
-                let pname = format_ident!("{}", p.name);
-                match &p.ty {
-                    Type::Bool => {
-                        parameter_tokens.push(quote! {
-                            let #pname = p4rs::extract_bool_action_parameter(
-                                parameter_data,
-                                #offset,
-                            );
-                        });
-                        offset += 1;
-                    }
-                    Type::Error => {
-                        todo!();
-                    }
-                    Type::State => {
-                        todo!();
-                    }
-                    Type::Action => {
-                        todo!();
-                    }
-                    Type::Bit(n) => {
-                        parameter_tokens.push(quote! {
-                            let #pname = p4rs::extract_bit_action_parameter(
-                                parameter_data,
-                                #offset,
-                                #n,
-                            );
-                        });
-                        parameter_refs.push(quote! { #pname.clone() });
-                        offset += n >> 3;
-                    }
-                    Type::Varbit(_n) => {
-                        todo!();
-                    }
-                    Type::Int(_n) => {
-                        todo!();
-                    }
-                    Type::String => {
-                        todo!();
-                    }
-                    Type::UserDefined(_s) => {
-                        todo!();
-                    }
-                    Type::ExternFunction => {
-                        todo!();
-                    }
-                    Type::HeaderMethod => {
-                        todo!();
-                    }
-                    Type::Table => {
-                        todo!();
-                    }
-                    Type::Void => {
-                        todo!();
-                    }
-                    Type::List(_) => {
-                        todo!();
-                    }
-                }
-            }
-            let mut control_params = Vec::new();
-            let mut control_param_types = Vec::new();
-            let mut action_params = Vec::new();
-            let mut action_param_types = Vec::new();
-            for p in &control.parameters {
-                let name = format_ident!("{}", p.name);
-                control_params.push(quote! { #name });
-                let ty = rust_type(&p.ty);
-                match p.direction {
-                    Direction::Out | Direction::InOut => {
-                        control_param_types.push(quote! { &mut #ty });
-                    }
-                    _ => {
-                        if p.ty == Type::Bool {
-                            control_param_types.push(quote! { #ty });
-                        } else {
-                            control_param_types.push(quote! { &#ty });
-                        }
-                    }
-                }
-            }
-
-            for p in &a.parameters {
-                let name = format_ident!("{}", p.name);
-                action_params.push(quote! { #name });
-                let ty = rust_type(&p.ty);
-                action_param_types.push(quote! { #ty });
-            }
-
-            for var in &control.variables {
-                let name = format_ident!("{}", var.name);
-                if let Type::UserDefined(typename) = &var.ty {
-                    if self.ast.get_extern(typename).is_some() {
-                        control_params.push(quote! { #name });
-                        let extern_type = format_ident!("{}", typename);
-                        control_param_types.push(quote! {
-                            &p4rs::externs::#extern_type
-                        });
-                    }
-                }
-            }
+            let closure = self.table_action_closure(control, a);
+            let parameter_len: usize = a
+                .parameters
+                .iter()
+                .map(|p| type_size_bytes(&p.ty, self.ast))
+                .sum();
 
             let aname = &action.name;
-            let tname = format_ident!("{}", qtfn);
             action_match_body.extend(quote! {
                 #aname => {
-                    #(#parameter_tokens)*
-                    let action: std::sync::Arc<dyn Fn(
-                        #(#control_param_types),*
-                    )>
-                    = std::sync::Arc::new(move |
-                        #(#control_params),*
-                    | {
-                        #call(
-                            #(#control_params),*,
-                            #(#parameter_refs),*
-                        )
-                    });
-                    self.#tname
-                        .entries
-                        .insert(p4rs::table::TableEntry::<
-                            #n,
-                            std::sync::Arc<dyn Fn(
-                                #(#control_param_types),*
-                            )>,
-                        > {
-                            key,
-                            priority,
-                            name: "your name here".into(), //TODO
-                            action,
+                    if parameter_data.len() != #parameter_len {
+                        return Err(p4rs::TableUpdateError::BadParameterData {
+                            table_id: #qtn.to_owned(),
                             action_id: #aname.to_owned(),
-                            parameter_data: parameter_data.to_owned(),
+                            expected: #parameter_len,
+                            found: parameter_data.len(),
+                        });
+                    }
+                    #closure
+                    (#aname, action)
+                }
+            });
+        }
+        if table.actions.iter().any(|a| a.name == "NoAction") {
+            let closure = self.table_no_action_closure(control);
+            action_match_body.extend(quote! {
+                "NoAction" => {
+                    if !parameter_data.is_empty() {
+                        return Err(p4rs::TableUpdateError::BadParameterData {
+                            table_id: #qtn.to_owned(),
+                            action_id: "NoAction".to_owned(),
+                            expected: 0,
+                            found: parameter_data.len(),
                         });
+                    }
+                    #closure
+                    ("NoAction", action)
                 }
             });
         }
-        let name = &control.name;
         action_match_body.extend(quote! {
-            x => panic!("unknown {} action id {}", #name, x),
+            x => return Err(p4rs::TableUpdateError::UnknownAction {
+                table_id: #qtn.to_owned(),
+                action_id: x.to_owned(),
+            }),
         });
 
-        let name = format_ident!("add_{}_entry", qtfn);
+        let name = format_ident!("apply_{}_update", qtfn);
         quote! {
-            // lifetime is due to
-            // https://github.com/rust-lang/rust/issues/96771#issuecomment-1119886703
-            pub fn #name<'a>(
+            #[allow(clippy::type_complexity)]
+            fn #name(
                 &mut self,
-                action_id: &str,
-                keyset_data: &'a [u8],
-                parameter_data: &'a [u8],
-                priority: u32,
-            ) {
-
+                update: &p4rs::TableUpdate,
+            ) -> Result<Box<dyn FnOnce(&mut Self)>, p4rs::TableUpdateError> {
+                let keyset_data = update.keyset_data();
+                if keyset_data.len() != #keyset_len {
+                    return Err(p4rs::TableUpdateError::BadKeysetData {
+                        table_id: #qtn.to_owned(),
+                        expected: #keyset_len,
+                        found: keyset_data.len(),
+                    });
+                }
                 let key = [#(#keys),*];
 
-                match action_id {
+                let (action_id, parameter_data, priority) = match update {
+                    p4rs::TableUpdate::Add {
+                        action_id,
+                        parameter_data,
+                        priority,
+                        ..
+                    } => (action_id, parameter_data, *priority),
+                    p4rs::TableUpdate::Modify {
+                        action_id,
+                        parameter_data,
+                        ..
+                    } => (action_id, parameter_data, 0),
+                    p4rs::TableUpdate::Remove { .. } => {
+                        let old = self.#tname.remove(&key).ok_or_else(|| {
+                            p4rs::TableUpdateError::NoSuchEntry {
+                                table_id: #qtn.to_owned(),
+                            }
+                        })?;
+                        return Ok(Box::new(move |p: &mut Self| {
+                            p.#tname.insert(old);
+                        }));
+                    }
+                };
+
+                let (action_id, action) = match action_id.as_str() {
                     #action_match_body
-                }
+                };
+                let entry = p4rs::table::TableEntry {
+                    key: key.clone(),
+                    priority,
+                    name: "your name here".into(), //TODO
+                    action,
+                    action_id,
+                    parameter_data: parameter_data.to_owned(),
+                };
 
+                if let p4rs::TableUpdate::Add { .. } = update {
+                    if !self.#tname.insert(entry) {
+                        return Err(p4rs::TableUpdateError::EntryExists {
+                            table_id: #qtn.to_owned(),
+                        });
+                    }
+                    Ok(Box::new(move |p: &mut Self| {
+                        p.#tname.remove(&key);
+                    }))
+                } else {
+                    let old = self.#tname.modify(entry).ok_or_else(|| {
+                        p4rs::TableUpdateError::NoSuchEntry {
+                            table_id: #qtn.to_owned(),
+                        }
+                    })?;
+                    Ok(Box::new(move |p: &mut Self| {
+                        p.#tname.modify(old);
+                    }))
+                }
             }
         }
     }
 
-    fn remove_table_entry_function(
+    /// Generate a `let action = ...` binding for a closure that calls the
+    /// action `a` with parameters extracted from runtime `parameter_data`.
+    fn table_action_closure(
         &mut self,
-        table: &Table,
         control: &Control,
-        qtfn: &str,
+        a: &Action,
     ) -> TokenStream {
-        let keys = self.table_entry_keys(table);
-        let n = table.key.len();
+        let call = format_ident!("{}_action_{}", control.name, &a.name);
+        let mut parameter_tokens = Vec::new();
+        let mut parameter_refs = Vec::new();
+        let mut offset: usize = 0;
+        for p in &a.parameters {
+            let pname = format_ident!("{}", p.name);
+            match &p.ty {
+                Type::Bool => {
+                    parameter_tokens.push(quote! {
+                        let #pname = p4rs::extract_bool_action_parameter(
+                            parameter_data,
+                            #offset,
+                        );
+                    });
+                    offset += 1;
+                }
+                Type::Error => {
+                    todo!();
+                }
+                Type::State => {
+                    todo!();
+                }
+                Type::Action => {
+                    todo!();
+                }
+                Type::Bit(n) | Type::Int(n) => {
+                    parameter_tokens.push(quote! {
+                        let #pname = p4rs::extract_bit_action_parameter(
+                            parameter_data,
+                            #offset,
+                            #n,
+                        );
+                    });
+                    parameter_refs.push(quote! { #pname.clone() });
+                    offset += type_size_bytes(&p.ty, self.ast);
+                }
+                Type::Varbit(_n) => {
+                    todo!();
+                }
+                Type::String => {
+                    todo!();
+                }
+                Type::UserDefined(_s) => {
+                    todo!();
+                }
+                Type::ExternFunction => {
+                    todo!();
+                }
+                Type::HeaderMethod => {
+                    todo!();
+                }
+                Type::Table => {
+                    todo!();
+                }
+                Type::Void => {
+                    todo!();
+                }
+                Type::List(_) => {
+                    todo!();
+                }
+                Type::HeaderStack(..) => {
+                    todo!();
+                }
+            }
+        }
+        let (control_params, control_param_types) =
+            self.table_action_signature(control);
+        let mut action_params = Vec::new();
+        let mut action_param_types = Vec::new();
 
-        let tname = format_ident!("{}", qtfn);
-        let name = format_ident!("remove_{}_entry", qtfn);
+        for p in &a.parameters {
+            let name = format_ident!("{}", p.name);
+            action_params.push(quote! { #name });
+            let ty = rust_type(&p.ty);
+            action_param_types.push(quote! { #ty });
+        }
+
+        quote! {
+            #(#parameter_tokens)*
+            let action: std::sync::Arc<dyn Fn(
+                #(#control_param_types),*
+            )>
+            = std::sync::Arc::new(move |
+                #(#control_params),*
+            | {
+                #call(
+                    #(#control_params),*,
+                    #(#parameter_refs),*
+                )
+            });
+        }
+    }
+
+    /// Generate a `let action = ...` binding for a closure that does nothing,
+    /// for table entries that run `NoAction`.
+    fn table_no_action_closure(&self, control: &Control) -> TokenStream {
+        let (_, control_param_types) = self.table_action_signature(control);
+        quote! {
+            let action: std::sync::Arc<dyn Fn(
+                #(#control_param_types),*
+            )>
+            = std::sync::Arc::new(|#(_: #control_param_types),*| {});
+        }
+    }
 
+    /// The names and types of the values handed to the action closures of a
+    /// control's tables, the control's parameters followed by its extern
+    /// instances.
+    fn table_action_signature(
+        &self,
+        control: &Control,
+    ) -> (Vec<TokenStream>, Vec<TokenStream>) {
         let mut control_params = Vec::new();
         let mut control_param_types = Vec::new();
         for p in &control.parameters {
@@ -975,41 +2310,85 @@ impl<'a> PipelineGenerator<'a> {
             }
         }
 
-        quote! {
-            // lifetime is due to
-            // https://github.com/rust-lang/rust/issues/96771#issuecomment-1119886703
-            pub fn #name<'a>(
-                &mut self,
-                keyset_data: &'a [u8],
-            ) {
+        (control_params, control_param_types)
+    }
 
-                let key = [#(#keys),*];
+    fn set_table_default_action_function(
+        &mut self,
+        table: &Table,
+        control: &Control,
+        qtn: &str,
+        qtfn: &str,
+    ) -> TokenStream {
+        let tname = format_ident!("{}", qtfn);
 
-                let action: std::sync::Arc<dyn Fn(
-                    #(#control_param_types),*
-                )>
-                = std::sync::Arc::new(move |
-                    #(#control_params),*
-                | { });
-
-                self.#tname
-                    .entries
-                    .remove(
-                        &p4rs::table::TableEntry::<
-                            #n,
-                            std::sync::Arc<dyn Fn(
-                                #(#control_param_types),*
-                            )>,
-                        > {
-                            key,
-                            priority: 0, //TODO
-                            name: "your name here".into(), //TODO
+        let mut action_match_body = TokenStream::new();
+        for action in table.actions.iter() {
+            if &action.name == "NoAction" {
+                continue;
+            }
+            let a = control.get_action(&action.name).unwrap_or_else(|| {
+                panic!(
+                    "control {} must have action {}",
+                    control.name, &action.name,
+                )
+            });
+            let closure = self.table_action_closure(control, a);
+            let parameter_len: usize = a
+                .parameters
+                .iter()
+                .map(|p| type_size_bytes(&p.ty, self.ast))
+                .sum();
+            let aname = &action.name;
+            action_match_body.extend(quote! {
+                #aname => {
+                    if parameter_data.len() != #parameter_len {
+                        return Err(p4rs::TableUpdateError::BadParameterData {
+                            table_id: #qtn.to_owned(),
+                            action_id: #aname.to_owned(),
+                            expected: #parameter_len,
+                            found: parameter_data.len(),
+                        });
+                    }
+                    #closure
+                    self.#tname.default_action =
+                        Some(p4rs::table::DefaultAction {
                             action,
-                            action_id: String::new(),
-                            parameter_data: Vec::new(),
-                        }
-                    );
+                            action_id: #aname,
+                            parameter_data: parameter_data.to_owned(),
+                        });
+                }
+            });
+        }
+        action_match_body.extend(quote! {
+            "NoAction" => {
+                if !parameter_data.is_empty() {
+                    return Err(p4rs::TableUpdateError::BadParameterData {
+                        table_id: #qtn.to_owned(),
+                        action_id: "NoAction".to_owned(),
+                        expected: 0,
+                        found: parameter_data.len(),
+                    });
+                }
+                self.#tname.default_action = None;
+            }
+            x => return Err(p4rs::TableUpdateError::UnknownAction {
+                table_id: #qtn.to_owned(),
+                action_id: x.to_owned(),
+            }),
+        });
 
+        let name = format_ident!("set_{}_default_action", qtfn);
+        quote! {
+            pub fn #name(
+                &mut self,
+                action_id: &str,
+                parameter_data: &[u8],
+            ) -> Result<(), p4rs::TableUpdateError> {
+                match action_id {
+                    #action_match_body
+                }
+                Ok(())
             }
         }
     }
@@ -1027,7 +2406,7 @@ impl<'a> PipelineGenerator<'a> {
             pub fn #name(&self) -> Vec<p4rs::TableEntry> {
                 let mut result = Vec::new();
 
-                for e in &self.#tname.entries{
+                for e in self.#tname.entries() {
 
                     let mut keyset_data = Vec::new();
                     for k in &e.key {
@@ -1040,7 +2419,7 @@ impl<'a> PipelineGenerator<'a> {
                     }
 
                     let x = p4rs::TableEntry{
-                        action_id: e.action_id.clone(),
+                        action_id: e.action_id.to_owned(),
                         keyset_data,
                         parameter_data: e.parameter_data.clone(),
                     };
@@ -1056,6 +2435,7 @@ impl<'a> PipelineGenerator<'a> {
 
     pub(crate) fn parse_entrypoint(
         &mut self,
+        name: &Ident,
         parser: &Parser,
     ) -> (TokenStream, TokenStream) {
         // this should never happen here, if it does it's a bug in the checker.
@@ -1067,24 +2447,22 @@ impl<'a> PipelineGenerator<'a> {
         let (sig, _) = pg.generate_state_function(parser, start_state);
 
         let member = quote! {
-            pub parse: fn #sig
+            pub #name: fn #sig
         };
 
         let initializer = format_ident!("{}_start", parser.name);
-        (member, quote! { parse: #initializer })
+        (member, quote! { #name: #initializer })
     }
 
     pub(crate) fn control_entrypoint(
         &mut self,
-        name: &str,
+        name: &Ident,
         control: &Control,
     ) -> (TokenStream, TokenStream) {
         let mut cg =
             crate::ControlGenerator::new(self.ast, self.hlir, self.ctx);
         let (sig, _) = cg.generate_control(control);
 
-        let name = format_ident!("{}", name);
-
         let member = quote! {
             pub #name: fn #sig
         };
@@ -1093,3 +2471,18 @@ impl<'a> PipelineGenerator<'a> {
         (member, quote! { #name: #initializer })
     }
 }
+
+/// The Rust type table entry builders take for a key or action parameter of
+/// type `ty` that is `sz` bytes wide, an integer where one is wide enough and
+/// a byte array otherwise.
+fn entry_value_type(ty: &Type, sz: usize) -> TokenStream {
+    match (ty, sz) {
+        (Type::Bool, _) => quote! { bool },
+        (_, 0..=1) => quote! { u8 },
+        (_, 2) => quote! { u16 },
+        (_, 3..=4) => quote! { u32 },
+        (_, 5..=8) => quote! { u64 },
+        (_, 9..=16) => quote! { u128 },
+        (_, sz) => quote! { [u8; #sz] },
+    }
+}
@@ -1,12 +1,15 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::{
-    expression::ExpressionGenerator, is_header, is_header_member,
-    is_rust_reference, rust_type,
+    expression::ExpressionGenerator, header_stack_of, header_union_of,
+    is_header_member, is_rust_reference, lvalue_path, rust_type,
+    verifies_checksums,
 };
 use p4::ast::{
-    Call, Control, DeclarationInfo, Direction, ExpressionKind, NameInfo,
-    Parser, Statement, StatementBlock, Transition, Type, AST,
+    Action, Call, Control, DeclarationInfo, Direction, Expression,
+    ExpressionKind, Function, KeySetElementValue, Lvalue, NameInfo, Parser,
+    Select, Statement, StatementBlock, Switch, SwitchLabel, Transition, Type,
+    VisitorMut, AST,
 };
 use p4::hlir::Hlir;
 use proc_macro2::TokenStream;
@@ -18,6 +21,8 @@ pub(crate) enum StatementContext<'a> {
     Control(&'a Control),
     #[allow(dead_code)]
     Parser(&'a Parser),
+    /// The body of a function, which may be local to a control.
+    Function(Option<&'a Control>, &'a Function),
 }
 
 pub(crate) struct StatementGenerator<'a> {
@@ -35,6 +40,18 @@ impl<'a> StatementGenerator<'a> {
         Self { ast, hlir, context }
     }
 
+    fn expression_generator(&self) -> ExpressionGenerator<'a> {
+        let eg = ExpressionGenerator::new(self.ast, self.hlir);
+        match self.context {
+            StatementContext::Control(control)
+            | StatementContext::Function(Some(control), _) => {
+                eg.with_control(control)
+            }
+            StatementContext::Parser(_)
+            | StatementContext::Function(None, _) => eg,
+        }
+    }
+
     pub(crate) fn generate_block(
         &self,
         sb: &StatementBlock,
@@ -51,11 +68,88 @@ impl<'a> StatementGenerator<'a> {
         &self,
         stmt: &Statement,
         names: &mut HashMap<String, NameInfo>,
+    ) -> TokenStream {
+        let mut ts = match self.context {
+            StatementContext::Parser(parser) => {
+                self.generate_stack_bounds_checks(parser, stmt)
+            }
+            _ => TokenStream::new(),
+        };
+        ts.extend(self.generate_statement_body(stmt, names));
+        ts
+    }
+
+    /// In parsers, evaluating `next` of a full header stack or `last` of an
+    /// empty one rejects the packet with StackOutOfBounds. Generate those
+    /// checks for the expressions a statement evaluates, ahead of the
+    /// statement itself. Nested blocks get their own checks.
+    fn generate_stack_bounds_checks(
+        &self,
+        parser: &Parser,
+        stmt: &Statement,
+    ) -> TokenStream {
+        let mut refs = StackElementRefs {
+            hlir: self.hlir,
+            refs: Vec::new(),
+        };
+        match stmt {
+            Statement::Assignment(lval, xpr) => {
+                refs.lvalue(lval);
+                xpr.accept_mut(&mut refs);
+            }
+            Statement::Call(c) => {
+                refs.lvalue(&c.lval);
+                for arg in &c.args {
+                    // extracting into next checks the stack itself
+                    if let ExpressionKind::Lvalue(lval) = &arg.kind {
+                        if c.lval.leaf() == "extract" && lval.leaf() == "next" {
+                            continue;
+                        }
+                    }
+                    arg.accept_mut(&mut refs);
+                }
+            }
+            Statement::If(ifb) => ifb.predicate.accept_mut(&mut refs),
+            Statement::Variable(v) => {
+                if let Some(xpr) = &v.initializer {
+                    xpr.accept_mut(&mut refs);
+                }
+            }
+            Statement::Constant(c) => c.initializer.accept_mut(&mut refs),
+            Statement::Transition(Transition::Select(sel)) => {
+                for p in &sel.parameters {
+                    p.accept_mut(&mut refs);
+                }
+            }
+            Statement::Return(Some(xpr)) => xpr.accept_mut(&mut refs),
+            _ => {}
+        }
+
+        let mut ts = TokenStream::new();
+        for (stack, element) in refs.refs {
+            let stack = lvalue_path(&stack, self.hlir);
+            let check = match element {
+                "next" => quote! { #stack.has_next() },
+                _ => quote! { #stack.has_last() },
+            };
+            let reject =
+                self.generate_reject(parser, quote! { "StackOutOfBounds" });
+            ts.extend(quote! {
+                if !#check { #reject }
+            });
+        }
+        ts
+    }
+
+    fn generate_statement_body(
+        &self,
+        stmt: &Statement,
+        names: &mut HashMap<String, NameInfo>,
     ) -> TokenStream {
         match stmt {
             Statement::Empty => TokenStream::new(),
             Statement::Assignment(lval, xpr) => {
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
 
                 let lhs = eg.generate_lvalue(lval);
 
@@ -73,18 +167,46 @@ impl<'a> StatementGenerator<'a> {
                         panic!("codegen name not resolved for {:#?}", lval)
                     });
 
-                if is_header_member(lval, self.hlir) {
-                    return quote! { #lhs = #rhs.clone(); };
+                if let Some((hi, lo)) = &lval.slice {
+                    let (hi, lo) = eg.slice_bounds(hi, lo);
+                    let ty = Type::Bit(hi - lo + 1);
+                    let rhs = self.converter(xpr, rhs, rhs_ty, &ty);
+                    let target = if is_rust_reference(lval, names) {
+                        quote! { *#lhs }
+                    } else {
+                        lhs
+                    };
+                    return quote! {
+                        p4rs::bitmath::set_slice_le(
+                            &mut #target, #hi, #lo, #rhs.clone(),
+                        );
+                    };
                 }
 
+                // serializable enums are assigned to bit fields through their
+                // underlying representation
+                let rhs = match (rhs_ty, &name_info.ty) {
+                    (Type::UserDefined(_), Type::Bit(_)) => {
+                        quote! { BitVec::<u8, Msb0>::from(#rhs) }
+                    }
+                    _ => rhs,
+                };
+                let rhs_ty = match (rhs_ty, &name_info.ty) {
+                    (Type::UserDefined(_), Type::Bit(_)) => &name_info.ty,
+                    _ => rhs_ty,
+                };
+
                 let rhs = if rhs_ty != &name_info.ty {
-                    let converter = self.converter(rhs_ty, &name_info.ty);
-                    quote!( #converter(#rhs) )
+                    self.converter(xpr, rhs, rhs_ty, &name_info.ty)
                 } else {
                     rhs
                 };
 
-                let rhs = if let Type::Bit(_) = rhs_ty {
+                if is_header_member(lval, self.hlir) {
+                    return quote! { #lhs = #rhs.clone(); };
+                }
+
+                let rhs = if let Type::Bit(_) | Type::Int(_) = rhs_ty {
                     // TODO eww, to better to figure out precisely when to_owned
                     // and clone are needed
                     quote! { #rhs.to_owned().clone() }
@@ -101,9 +223,22 @@ impl<'a> StatementGenerator<'a> {
                 }
             }
             Statement::Call(c) => match &self.context {
+                _ if matches!(
+                    self.hlir.lvalue_decls.get(&c.lval).map(|n| &n.decl),
+                    Some(DeclarationInfo::Function)
+                ) =>
+                {
+                    let call = self.expression_generator().generate_call(c);
+                    quote! { #call; }
+                }
                 StatementContext::Control(control) => {
                     let mut ts = TokenStream::new();
-                    self.generate_control_body_call(control, c, &mut ts);
+                    self.generate_control_body_call(Some(control), c, &mut ts);
+                    ts
+                }
+                StatementContext::Function(control, _) => {
+                    let mut ts = TokenStream::new();
+                    self.generate_control_body_call(*control, c, &mut ts);
                     ts
                 }
                 StatementContext::Parser(parser) => {
@@ -113,7 +248,7 @@ impl<'a> StatementGenerator<'a> {
                 }
             },
             Statement::If(ifb) => {
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
                 let predicate = eg.generate_expression(ifb.predicate.as_ref());
                 let block = self.generate_block(&ifb.block, names);
                 let mut ts = quote! {
@@ -136,15 +271,20 @@ impl<'a> StatementGenerator<'a> {
                 let ty = rust_type(&v.ty);
                 let initializer = match &v.initializer {
                     Some(xpr) => {
-                        let eg = ExpressionGenerator::new(self.hlir);
+                        let eg = self.expression_generator();
                         let ini = eg.generate_expression(xpr.as_ref());
                         let ini_ty =
                             self.hlir.expression_types.get(xpr).unwrap_or_else(
                                 || panic!("type for expression {:#?}", xpr),
                             );
                         if ini_ty != &v.ty {
-                            let converter = self.converter(ini_ty, &v.ty);
-                            quote! { #converter(#ini) }
+                            self.converter(xpr, ini, ini_ty, &v.ty)
+                        } else if let ExpressionKind::Lvalue(_)
+                        | ExpressionKind::Index(..) = &xpr.kind
+                        {
+                            // the named value may be a reference, or an
+                            // element of a header stack
+                            quote! { #ini.clone() }
                         } else {
                             ini
                         }
@@ -175,13 +315,14 @@ impl<'a> StatementGenerator<'a> {
             Statement::Constant(c) => {
                 let name = format_ident!("{}", c.name);
                 let ty = rust_type(&c.ty);
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
                 let initializer =
                     eg.generate_expression(c.initializer.as_ref());
                 quote! {
                     let #name: #ty = #initializer;
                 }
             }
+            Statement::Switch(sw) => self.generate_switch(sw, names),
             Statement::Transition(transition) => {
                 let parser = match self.context {
                     StatementContext::Parser(p) => p,
@@ -194,34 +335,31 @@ impl<'a> StatementGenerator<'a> {
                 };
                 match transition {
                     Transition::Reference(next_state) => {
-                        match next_state.name.as_str() {
-                            "accept" => quote! { return true; },
-                            "reject" => quote! { return false; },
-                            state_ref => {
-                                let state_name = format_ident!(
-                                    "{}_{}",
-                                    parser.name,
-                                    state_ref
-                                );
-                                let mut args = Vec::new();
-                                for arg in &parser.parameters {
-                                    let name = format_ident!("{}", arg.name);
-                                    args.push(quote! { #name });
-                                }
-                                quote! {
-                                    softnpu_provider::parser_transition!(||(#state_ref));
-                                    return #state_name( #(#args),* );
-                                }
-                            }
-                        }
+                        self.generate_transition(parser, &next_state.name)
                     }
-                    Transition::Select(_) => {
-                        todo!();
+                    Transition::Select(sel) => {
+                        self.generate_select(parser, sel)
                     }
                 }
             }
             Statement::Return(xpr) => {
-                let eg = ExpressionGenerator::new(self.hlir);
+                let eg = self.expression_generator();
+                // function return values are converted to the return type
+                if let (Some(xpr), StatementContext::Function(_, f)) =
+                    (xpr, &self.context)
+                {
+                    let value = eg.generate_expression(xpr.as_ref());
+                    let value = match self.hlir.expression_types.get(xpr) {
+                        Some(ty) if ty != &f.return_type => {
+                            self.converter(xpr, value, ty, &f.return_type)
+                        }
+                        _ => value,
+                    };
+                    return match f.return_type {
+                        Type::Bool => quote! { return #value; },
+                        _ => quote! { return (#value).clone(); },
+                    };
+                }
                 if let Some(xpr) = xpr {
                     let xp = eg.generate_expression(xpr.as_ref());
                     quote! { return #xp; }
@@ -232,19 +370,192 @@ impl<'a> StatementGenerator<'a> {
         }
     }
 
+    fn generate_transition(
+        &self,
+        parser: &Parser,
+        next_state: &str,
+    ) -> TokenStream {
+        match next_state {
+            "accept" => quote! { return true; },
+            "reject" => quote! { return false; },
+            state_ref => {
+                let state_name = format_ident!("{}_{}", parser.name, state_ref);
+                let mut args = Vec::new();
+                for arg in &parser.parameters {
+                    let name = format_ident!("{}", arg.name);
+                    args.push(quote! { #name });
+                }
+                quote! {
+                    softnpu_provider::parser_transition!(||(#state_ref));
+                    return #state_name( #(#args),* );
+                }
+            }
+        }
+    }
+
+    fn generate_select(&self, parser: &Parser, sel: &Select) -> TokenStream {
+        let eg = self.expression_generator();
+        let mut ts = TokenStream::new();
+
+        // Evaluate each select parameter once up front. Bit-typed parameters
+        // are converted to integers so masks and ranges can be evaluated
+        // numerically.
+        let mut selectors = Vec::new();
+        for (i, p) in sel.parameters.iter().enumerate() {
+            let ty = self.hlir.expression_types.get(p).unwrap_or_else(|| {
+                panic!("codegen type not found for {:#?}", p)
+            });
+            let name = format_ident!("selector_{}", i);
+            let xpr = eg.generate_expression(p.as_ref());
+            match ty {
+                Type::Bool => ts.extend(quote! {
+                    let #name: bool = #xpr;
+                }),
+                Type::Bit(_) | Type::Varbit(_) | Type::Int(_) => {
+                    ts.extend(quote! {
+                        let #name = p4rs::bitvec_to_biguint(&#xpr).value;
+                    })
+                }
+                x => todo!("select parameter type {}", x),
+            }
+            selectors.push((name, ty));
+        }
+
+        for e in &sel.elements {
+            let mut conditions = Vec::new();
+            for (k, (name, ty)) in e.keyset.iter().zip(selectors.iter()) {
+                match &k.value {
+                    KeySetElementValue::Default
+                    | KeySetElementValue::DontCare => {}
+                    KeySetElementValue::Expression(xpr) => {
+                        let v = self.generate_select_key(xpr, ty);
+                        conditions.push(quote! { #name == #v });
+                    }
+                    KeySetElementValue::Masked(val, mask) => {
+                        let v = self.generate_select_key(val, ty);
+                        let m = self.generate_select_key(mask, ty);
+                        conditions.push(quote! {
+                            (&#name & &#m) == (&#v & &#m)
+                        });
+                    }
+                    KeySetElementValue::Ranged(begin, end) => {
+                        let b = self.generate_select_key(begin, ty);
+                        let e = self.generate_select_key(end, ty);
+                        conditions.push(quote! {
+                            (#b..=#e).contains(&#name)
+                        });
+                    }
+                }
+            }
+            let transition = self.generate_transition(parser, &e.name);
+            if conditions.is_empty() {
+                // default case, nothing after this can match
+                ts.extend(transition);
+                return ts;
+            }
+            ts.extend(quote! {
+                if #(#conditions)&&* {
+                    #transition
+                }
+            });
+        }
+
+        // no keyset matched, the parser rejects the packet
+        ts.extend(self.generate_reject(parser, quote! { "NoMatch" }));
+        ts
+    }
+
+    fn generate_select_key(&self, xpr: &Expression, ty: &Type) -> TokenStream {
+        let eg = self.expression_generator();
+        match (&xpr.kind, ty) {
+            (ExpressionKind::IntegerLit(v), Type::Bit(w))
+            | (ExpressionKind::IntegerLit(v), Type::Varbit(w))
+            | (ExpressionKind::IntegerLit(v), Type::Int(w)) => {
+                let width = usize::min(*w, 128) as u16;
+                let lit = eg.generate_bit_literal(width, *v as u128);
+                quote! { p4rs::bitvec_to_biguint(&#lit).value }
+            }
+            (_, Type::Bool) => eg.generate_expression(xpr),
+            _ => {
+                let v = eg.generate_expression(xpr);
+                quote! { p4rs::bitvec_to_biguint(&#v).value }
+            }
+        }
+    }
+
     fn generate_parser_body_call(
         &self,
         parser: &Parser,
         c: &Call,
         tokens: &mut TokenStream,
     ) {
-        let lval: Vec<TokenStream> = c
-            .lval
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lval = lvalue_path(&c.lval, self.hlir);
+
+        // extracting into the next element of a header stack advances the
+        // stack, and rejects the packet once the stack is full
+        if let [xpr] = c.args.as_slice() {
+            if let ExpressionKind::Lvalue(lvarg) = &xpr.kind {
+                if let (Some(stack), "next") =
+                    (header_stack_of(lvarg, self.hlir), lvarg.leaf())
+                {
+                    let stack = lvalue_path(&stack, self.hlir);
+                    let reject = self
+                        .generate_reject(parser, quote! { "StackOutOfBounds" });
+                    let extract = self.generate_checked_extraction(
+                        parser,
+                        quote! { #lval(h) },
+                    );
+                    tokens.extend(quote! {
+                        match #stack.next() {
+                            Some(h) => { #extract }
+                            None => { #reject }
+                        };
+                    });
+                    return;
+                }
+            }
+        }
+
+        if let ("verify", [cond, error]) =
+            (c.lval.name.as_str(), c.args.as_slice())
+        {
+            tokens.extend(self.generate_verify(parser, cond, error));
+            return;
+        }
+
+        if let ("advance", [bits]) = (c.lval.leaf(), c.args.as_slice()) {
+            let target = lvalue_path(&c.lval.pop_right(), self.hlir);
+            let bits = self.generate_size_argument(bits);
+            tokens.extend(self.generate_checked_extraction(
+                parser,
+                quote! { #target.advance(#bits) },
+            ));
+            return;
+        }
+
+        // the two argument form of extract provides the size in bits of the
+        // variable length field of the header being extracted
+        if let ("extract", [hdr, size]) = (c.lval.leaf(), c.args.as_slice()) {
+            let target = lvalue_path(&c.lval.pop_right(), self.hlir);
+            let size = self.generate_size_argument(size);
+            match &hdr.kind {
+                ExpressionKind::Lvalue(lval) => {
+                    let path = lvalue_path(lval, self.hlir);
+                    let reject = self
+                        .generate_reject(parser, quote! { e.parser_error() });
+                    tokens.extend(quote! {
+                        if let Err(e) =
+                            #target.extract_variable(&mut #path, #size)
+                        {
+                            #reject
+                        }
+                    });
+                }
+                x => todo!("extract into {:?}", x),
+            }
+            tokens.extend(self.validity_side_effects(c));
+            return;
+        }
 
         let mut args = Vec::new();
         for a in &c.args {
@@ -263,34 +574,160 @@ impl<'a> StatementGenerator<'a> {
                             }
                         }
                     }
-                    let lvref: Vec<TokenStream> = parts
-                        .iter()
-                        .map(|x| format_ident!("{}", x))
-                        .map(|x| quote! { #x })
-                        .collect();
+                    let lvref = lvalue_path(lvarg, self.hlir);
                     if mut_arg {
-                        args.push(quote! { &mut #(#lvref).* });
+                        args.push(quote! { &mut #lvref });
                     } else {
-                        args.push(quote! { #(#lvref).* });
+                        args.push(quote! { #lvref });
                     }
                 }
                 x => todo!("extern arg {:?}", x),
             }
         }
-        tokens.extend(quote! {
-            #(#lval).* ( #(#args),* );
-        });
+        if c.lval.leaf() == "extract" {
+            tokens.extend(self.generate_checked_extraction(
+                parser,
+                quote! { #lval ( #(#args),* ) },
+            ));
+        } else {
+            tokens.extend(quote! {
+                #lval ( #(#args),* );
+            });
+        }
+
+        tokens.extend(self.validity_side_effects(c));
+    }
+
+    /// Headers made valid through extraction, in either of its forms, or
+    /// setValid may be members of a header union, whose other members become
+    /// invalid.
+    fn validity_side_effects(&self, c: &Call) -> TokenStream {
+        match (c.lval.leaf(), c.args.as_slice()) {
+            ("setValid", _) => {
+                self.header_union_invalidation(&c.lval.pop_right())
+            }
+            ("extract", [hdr] | [hdr, _]) => match &hdr.kind {
+                ExpressionKind::Lvalue(lvarg) => {
+                    self.header_union_invalidation(lvarg)
+                }
+                _ => TokenStream::new(),
+            },
+            _ => TokenStream::new(),
+        }
+    }
+
+    /// Reject the packet with `error` recorded on the packet being parsed if
+    /// `cond` does not hold.
+    fn generate_verify(
+        &self,
+        parser: &Parser,
+        cond: &Expression,
+        error: &Expression,
+    ) -> TokenStream {
+        let eg = self.expression_generator();
+        let cond = eg.generate_expression(cond);
+        let error = eg.generate_expression(error);
+        let reject = self.generate_reject(parser, quote! { (#error).name() });
+        quote! {
+            if !(#cond) {
+                #reject
+            }
+        }
+    }
+
+    /// Reject the packet being parsed if the packet ends before the data
+    /// `extraction` consumes from it.
+    fn generate_checked_extraction(
+        &self,
+        parser: &Parser,
+        extraction: TokenStream,
+    ) -> TokenStream {
+        let reject = self.generate_reject(parser, quote! { "PacketTooShort" });
+        quote! {
+            if #extraction.is_err() {
+                #reject
+            }
+        }
+    }
+
+    /// Reject the packet being parsed, recording the name of the error given
+    /// by `error` on the packet.
+    fn generate_reject(
+        &self,
+        parser: &Parser,
+        error: TokenStream,
+    ) -> TokenStream {
+        let pkt = parser
+            .parameters
+            .iter()
+            .find(|p| p.ty == Type::UserDefined("packet_in".into()))
+            .unwrap_or_else(|| {
+                panic!("parser {} has no packet_in parameter", parser.name)
+            });
+        let pkt = format_ident!("{}", pkt.name);
+        quote! {
+            #pkt.error = Some(#error);
+            return false;
+        }
+    }
+
+    /// Generate a `usize` from a `bit<N>` valued size argument.
+    fn generate_size_argument(&self, xpr: &Expression) -> TokenStream {
+        match &xpr.kind {
+            ExpressionKind::IntegerLit(v) => {
+                let v = *v as usize;
+                quote! { #v }
+            }
+            _ => {
+                let size = self.expression_generator().generate_expression(xpr);
+                quote! {{
+                    let size: u32 = (#size).load_le();
+                    size as usize
+                }}
+            }
+        }
+    }
+
+    /// Invalidate the other members of the header union `header` belongs to,
+    /// if it belongs to one.
+    fn header_union_invalidation(&self, header: &Lvalue) -> TokenStream {
+        let (union, decl) = match header_union_of(header, self.hlir, self.ast) {
+            Some(union) => union,
+            None => return TokenStream::new(),
+        };
+        let union = lvalue_path(&union, self.hlir);
+        let mut ts = TokenStream::new();
+        for m in decl.members.iter().filter(|m| m.name != header.leaf()) {
+            let member = format_ident!("{}", m.name);
+            ts.extend(quote! { #union.#member.set_invalid(); });
+        }
+        ts
     }
 
     fn generate_control_body_call(
         &self,
-        control: &Control,
+        control: Option<&Control>,
         c: &Call,
         tokens: &mut TokenStream,
     ) {
         //
         // get the lval reference to the thing being called
         //
+        if matches!(
+            self.hlir.lvalue_decls.get(&c.lval).map(|n| &n.ty),
+            Some(Type::ExternFunction)
+        ) {
+            self.generate_extern_function_call(c, tokens);
+            return;
+        }
+        if let (StatementContext::Control(control), 1) =
+            (&self.context, c.lval.degree())
+        {
+            if let Some(action) = control.get_action(&c.lval.name) {
+                self.generate_action_call(control, action, c, tokens);
+                return;
+            }
+        }
         if c.lval.name.split('.').count() < 2 {
             panic!(
                 "codegen: bare calls not supported, \
@@ -299,9 +736,12 @@ impl<'a> StatementGenerator<'a> {
             );
         }
         match c.lval.leaf() {
-            "apply" => {
-                self.generate_control_apply_body_call(control, c, tokens);
-            }
+            "apply" => match control {
+                Some(control) => {
+                    self.generate_control_apply_body_call(control, c, tokens);
+                }
+                None => panic!("codegen: apply outside of a control: {:#?}", c),
+            },
             "setValid" => {
                 self.generate_header_set_validity(c, tokens, true);
             }
@@ -311,24 +751,167 @@ impl<'a> StatementGenerator<'a> {
             "isValid" => {
                 self.generate_header_get_validity(c, tokens);
             }
+            "push_front" | "pop_front"
+                if header_stack_of(&c.lval, self.hlir).is_some() =>
+            {
+                let call = self.expression_generator().generate_call(c);
+                tokens.extend(quote! { #call; });
+            }
+            "emit" if self.is_packet_out(&c.lval.pop_right()) => {
+                self.generate_emit(c, tokens);
+            }
             _ => {
                 // assume we are at an extern call
 
                 // TODO check the extern call against defined externs in checker
                 // before we get here
 
-                self.generate_control_extern_call(control, c, tokens);
+                self.generate_control_extern_call(c, tokens);
             }
         }
     }
 
-    fn generate_control_extern_call(
+    /// Whether an lvalue names a packet_out parameter of the enclosing
+    /// control.
+    fn is_packet_out(&self, lval: &Lvalue) -> bool {
+        let control = match self.context {
+            StatementContext::Control(c) => c,
+            StatementContext::Function(Some(c), _) => c,
+            _ => return false,
+        };
+        lval.degree() == 1
+            && control.parameters.iter().any(|p| {
+                p.name == lval.name
+                    && p.ty == Type::UserDefined("packet_out".into())
+            })
+    }
+
+    /// Emit a header, or the valid headers of a structure, union or stack,
+    /// to a packet_out. Invalid headers are not emitted.
+    fn generate_emit(&self, c: &Call, tokens: &mut TokenStream) {
+        let pkt = lvalue_path(&c.lval.pop_right(), self.hlir);
+        let arg = match c.args.first() {
+            Some(arg) => arg.as_ref(),
+            None => panic!("codegen: emit without a header: {:#?}", c),
+        };
+        let header = self.expression_generator().generate_expression(arg);
+        let emit = quote! { #pkt.emit(#header.to_bitvec()); };
+        match self.hlir.expression_types.get(arg) {
+            Some(Type::UserDefined(name))
+                if self.ast.get_header(name).is_some() =>
+            {
+                tokens.extend(quote! {
+                    if #header.is_valid() {
+                        #emit
+                    }
+                });
+            }
+            Some(_) => tokens.extend(emit),
+            None => panic!("codegen: emit of untyped expression {:#?}", arg),
+        }
+    }
+
+    /// Call an action directly from the body of the control that declares it.
+    /// The action is handed the control's parameters and extern instances as
+    /// it is from a table.
+    fn generate_action_call(
+        &self,
+        control: &Control,
+        action: &Action,
+        c: &Call,
+        tokens: &mut TokenStream,
+    ) {
+        let mut args = Vec::new();
+        for p in &control.parameters {
+            let name = format_ident!("{}", p.name);
+            args.push(quote! { #name });
+        }
+        for var in &control.variables {
+            if let Type::UserDefined(typename) = &var.ty {
+                if self.ast.get_extern(typename).is_some() {
+                    let name = format_ident!("{}", var.name);
+                    args.push(quote! { #name });
+                }
+            }
+        }
+        let eg = self.expression_generator();
+        for (a, p) in c.args.iter().zip(action.parameters.iter()) {
+            match (&a.kind, &p.ty) {
+                (
+                    ExpressionKind::IntegerLit(v),
+                    Type::Bit(w) | Type::Int(w),
+                ) => {
+                    args.push(eg.generate_bit_literal(*w as u16, *v as u128));
+                }
+                _ => {
+                    let arg = eg.generate_expression(a.as_ref());
+                    args.push(quote! { (#arg).clone() });
+                }
+            }
+        }
+        let name = format_ident!("{}_action_{}", control.name, action.name);
+        tokens.extend(quote! {
+            #name(#(#args),*);
+        });
+    }
+
+    /// Calls to the extern functions of the v1model architecture.
+    fn generate_extern_function_call(
         &self,
-        _control: &Control,
         c: &Call,
         tokens: &mut TokenStream,
     ) {
-        let eg = ExpressionGenerator::new(self.hlir);
+        let eg = self.expression_generator();
+        let args: Vec<TokenStream> = c
+            .args
+            .iter()
+            .map(|a| eg.generate_expression(a.as_ref()))
+            .collect();
+        match (c.lval.name.as_str(), args.as_slice()) {
+            ("mark_to_drop", [standard_metadata]) => {
+                let drop_port = eg.generate_bit_literal(9, 511);
+                tokens.extend(quote! {
+                    #standard_metadata.egress_spec = #drop_port;
+                });
+            }
+            ("verify_checksum", [condition, data, checksum, _]) => {
+                self.check_hash_algorithm(c);
+                tokens.extend(quote! {
+                    if #condition
+                        && p4rs::checksum::csum16(#data) != #checksum
+                    {
+                        *checksum_error = true;
+                    }
+                });
+            }
+            ("update_checksum", [condition, data, checksum, _]) => {
+                self.check_hash_algorithm(c);
+                tokens.extend(quote! {
+                    if #condition {
+                        #checksum = p4rs::checksum::csum16(#data);
+                    }
+                });
+            }
+            _ => {
+                panic!("codegen: extern function {} not supported", c.lval.name)
+            }
+        }
+    }
+
+    /// Checksums are only computed with the csum16 algorithm, given as the
+    /// last argument of a checksum call.
+    fn check_hash_algorithm(&self, c: &Call) {
+        match c.args.last().map(|a| &a.kind) {
+            Some(ExpressionKind::Lvalue(algo)) if algo.leaf() == "csum16" => {}
+            _ => panic!(
+                "codegen: {} only supports HashAlgorithm.csum16",
+                c.lval.name
+            ),
+        }
+    }
+
+    fn generate_control_extern_call(&self, c: &Call, tokens: &mut TokenStream) {
+        let eg = self.expression_generator();
         let mut args = Vec::new();
 
         for a in &c.args {
@@ -336,16 +919,10 @@ impl<'a> StatementGenerator<'a> {
             args.push(arg_xpr);
         }
 
-        let lvref: Vec<TokenStream> = c
-            .lval
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lvref = lvalue_path(&c.lval, self.hlir);
 
         tokens.extend(quote! {
-            #(#lvref).*(#(#args),*);
+            #lvref(#(#args),*);
         })
     }
 
@@ -373,11 +950,9 @@ impl<'a> StatementGenerator<'a> {
             t => panic!("call references non-user-defined type {:#?}", t),
         };
 
-        let root = c.lval.root();
-
         // This is a call to another control instance
         if control_instance.name != control.name {
-            let eg = ExpressionGenerator::new(self.hlir);
+            let eg = self.expression_generator();
             let mut locals = Vec::new();
             let mut args = Vec::new();
             for (i, a) in c.args.iter().enumerate() {
@@ -451,6 +1026,9 @@ impl<'a> StatementGenerator<'a> {
                 let name = format_ident!("{}_{}", c.lval.root(), qtn);
                 args.push(quote! { #name });
             }
+            if verifies_checksums(control_instance, self.ast) {
+                args.push(quote! { &mut *checksum_error });
+            }
 
             let cname = &control_instance.name;
             let call = format_ident!("{}_apply", control_instance.name);
@@ -465,95 +1043,131 @@ impl<'a> StatementGenerator<'a> {
         }
 
         // this is a local table
+        let apply = self
+            .expression_generator()
+            .generate_table_apply(self.ast, control, c);
+        tokens.extend(quote! { #apply; });
+    }
 
-        let table = match control_instance.get_table(root) {
-            Some(table) => table,
-            None => {
-                panic!(
-                    "codegen: table {} not found in control {} decl: {:#?}",
-                    root, control.name, name_info,
-                );
-            }
-        };
-
-        //
-        // match an action based on the key material
-        //
-
-        let table_name = format_ident!("{}", c.lval.root());
+    fn generate_switch(
+        &self,
+        sw: &Switch,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> TokenStream {
+        let eg = self.expression_generator();
+        let ty = self
+            .hlir
+            .expression_types
+            .get(sw.expression.as_ref())
+            .unwrap_or_else(|| {
+                panic!("codegen type not found for {:#?}", sw.expression)
+            });
 
-        let table_name_str =
-            format!("{}_table_{}", control_instance.name, table.name,);
+        let mut arms = Vec::new();
+        let mut has_default = false;
 
-        let mut action_args = Vec::new();
-        for p in &control.parameters {
-            let name = format_ident!("{}", p.name);
-            action_args.push(quote! { #name });
+        // a switch on the action a table ran matches on the action name
+        if let Type::Action = ty {
+            let apply = match (&sw.expression.kind, &self.context) {
+                (
+                    ExpressionKind::Member(xpr, _),
+                    StatementContext::Control(control),
+                ) => match &xpr.kind {
+                    ExpressionKind::Call(call) => self
+                        .expression_generator()
+                        .generate_table_apply(self.ast, control, call),
+                    x => panic!("codegen: switch on action from {:?}", x),
+                },
+                x => panic!("codegen: switch on action from {:?}", x),
+            };
+            for case in &sw.cases {
+                let mut patterns = Vec::new();
+                for label in &case.labels {
+                    match label {
+                        SwitchLabel::Default => {
+                            has_default = true;
+                            patterns.push(quote! { _ });
+                        }
+                        SwitchLabel::Expression(xpr) => match &xpr.kind {
+                            ExpressionKind::Lvalue(lval) => {
+                                let name = &lval.name;
+                                patterns.push(quote! { #name });
+                            }
+                            x => panic!("codegen: action switch label {:?}", x),
+                        },
+                    }
+                }
+                let block = self.generate_block(&case.block, names);
+                arms.push(quote! { #(#patterns)|* => { #block } });
+            }
+            if !has_default {
+                arms.push(quote! { _ => {} });
+            }
+            return quote! {
+                match (#apply).action_run {
+                    #(#arms),*
+                }
+            };
         }
 
-        for var in &control.variables {
-            let name = format_ident!("{}", var.name);
-            if let Type::UserDefined(typename) = &var.ty {
-                if self.ast.get_extern(typename).is_some() {
-                    action_args.push(quote! { &#name });
+        // enums match directly on their members
+        if let Type::UserDefined(_) = ty {
+            let selector = eg.generate_expression(sw.expression.as_ref());
+            for case in &sw.cases {
+                let mut patterns = Vec::new();
+                for label in &case.labels {
+                    match label {
+                        SwitchLabel::Default => {
+                            has_default = true;
+                            patterns.push(quote! { _ });
+                        }
+                        SwitchLabel::Expression(xpr) => {
+                            patterns.push(eg.generate_expression(xpr));
+                        }
+                    }
                 }
+                let block = self.generate_block(&case.block, names);
+                arms.push(quote! { #(#patterns)|* => { #block } });
             }
+            if !has_default {
+                arms.push(quote! { _ => {} });
+            }
+            return quote! {
+                match #selector {
+                    #(#arms),*
+                }
+            };
         }
 
-        let mut selector_components = Vec::new();
-        for (lval, _match_kind) in &table.key {
-            let lvref: Vec<TokenStream> = lval
-                .name
-                .split('.')
-                .map(|x| format_ident!("{}", x))
-                .map(|x| quote! { #x })
-                .collect();
-
-            // determine if this lvalue references a header or a struct,
-            // if it's a header there's a bit of extra unsrapping we
-            // need to do to match the selector against the value.
-
-            let names = control.names();
-
-            if lval.degree() > 1
-                && is_header(&lval.pop_right(), self.ast, &names)
+        // everything else is compared by value
+        let selector = self.generate_select_key(sw.expression.as_ref(), ty);
+        for case in &sw.cases {
+            let block = self.generate_block(&case.block, names);
+            if case
+                .labels
+                .iter()
+                .any(|l| matches!(l, SwitchLabel::Default))
             {
-                //TODO: to_biguint is bad here, copying on data path
-                selector_components.push(quote! {
-                    p4rs::bitvec_to_biguint(
-                        &#(#lvref).*
-                    ).value
-                });
-            } else {
-                selector_components.push(quote! {
-                    p4rs::bitvec_to_biguint(&#(#lvref).*).value
-                });
+                has_default = true;
+                arms.push(quote! { _ => { #block } });
+                continue;
+            }
+            let mut conditions = Vec::new();
+            for label in &case.labels {
+                if let SwitchLabel::Expression(xpr) = label {
+                    let key = self.generate_select_key(xpr, ty);
+                    conditions.push(quote! { x == #key });
+                }
             }
+            arms.push(quote! { x if #(#conditions)||* => { #block } });
         }
-        let default_action =
-            format_ident!("{}_action_{}", control.name, table.default_action);
-        tokens.extend(quote! {
-            let matches = #table_name.match_selector(
-                &[#(#selector_components),*]
-            );
-            if matches.len() > 0 {
-                softnpu_provider::control_table_hit!(||#table_name_str);
-                (matches[0].action)(#(#action_args),*)
+        if !has_default {
+            arms.push(quote! { _ => {} });
+        }
+        quote! {
+            match #selector {
+                #(#arms),*
             }
-        });
-        if table.default_action != "NoAction" {
-            tokens.extend(quote! {
-                else {
-                    softnpu_provider::control_table_miss!(||#table_name_str);
-                    #default_action(#(#action_args),*);
-                }
-            });
-        } else {
-            tokens.extend(quote! {
-                else {
-                    softnpu_provider::control_table_miss!(||#table_name_str);
-                }
-            });
         }
     }
 
@@ -563,48 +1177,60 @@ impl<'a> StatementGenerator<'a> {
         tokens: &mut TokenStream,
         valid: bool,
     ) {
-        let lhs: Vec<TokenStream> = c
-            .lval
-            .pop_right()
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lhs = lvalue_path(&c.lval.pop_right(), self.hlir);
         if valid {
             tokens.extend(quote! {
-                #(#lhs).*.set_valid();
+                #lhs.set_valid();
             });
+            tokens.extend(self.header_union_invalidation(&c.lval.pop_right()));
         } else {
             tokens.extend(quote! {
-                #(#lhs).*.set_invalid();
+                #lhs.set_invalid();
             });
         }
     }
 
     fn generate_header_get_validity(&self, c: &Call, tokens: &mut TokenStream) {
-        let lhs: Vec<TokenStream> = c
-            .lval
-            .pop_right()
-            .name
-            .split('.')
-            .map(|x| format_ident!("{}", x))
-            .map(|x| quote! { #x })
-            .collect();
+        let lhs = lvalue_path(&c.lval.pop_right(), self.hlir);
         tokens.extend(quote! {
-            #(#lhs).*.is_valid()
+            #lhs.is_valid()
         });
     }
 
-    fn converter(&self, from: &Type, to: &Type) -> TokenStream {
-        match (from, to) {
-            (Type::Int(_), Type::Bit(_)) => {
-                quote! { p4rs::int_to_bitvec }
-            }
-            (Type::Bit(x), Type::Bit(16)) if *x <= 16 => {
-                quote! { p4rs::bitvec_to_bitvec16 }
+    fn converter(
+        &self,
+        xpr: &Expression,
+        value: TokenStream,
+        from: &Type,
+        to: &Type,
+    ) -> TokenStream {
+        self.expression_generator()
+            .generate_conversion(xpr, value, from, to)
+    }
+}
+
+/// Collects the header stacks whose `next` or `last` elements are referred
+/// to by the lvalues visited.
+struct StackElementRefs<'a> {
+    hlir: &'a Hlir,
+    refs: Vec<(Lvalue, &'static str)>,
+}
+
+impl VisitorMut for StackElementRefs<'_> {
+    fn lvalue(&mut self, lval: &Lvalue) {
+        for (i, part) in lval.parts().into_iter().enumerate().skip(1) {
+            let element = match part {
+                "next" => "next",
+                "last" => "last",
+                _ => continue,
+            };
+            if let Some(stack) = header_stack_of(&lval.prefix(i + 1), self.hlir)
+            {
+                let r = (stack, element);
+                if !self.refs.contains(&r) {
+                    self.refs.push(r);
+                }
             }
-            _ => todo!("type converter for {} to {}", from, to),
         }
     }
 }
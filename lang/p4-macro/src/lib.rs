@@ -103,6 +103,7 @@ fn generate_rs(
         &hlir,
         p4_rust::Settings {
             pipeline_name: settings.pipeline_name.clone(),
+            architectures: Vec::new(),
         },
     )
     .into();
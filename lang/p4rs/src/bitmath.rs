@@ -90,8 +90,325 @@ pub fn mod_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
     c
 }
 
+pub fn sub_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_be();
+    let y: u128 = b.load_be();
+    let z = x.wrapping_sub(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_be(z);
+    c
+}
+
+pub fn sub_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.wrapping_sub(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+pub fn mul_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_be();
+    let y: u128 = b.load_be();
+    let z = x.wrapping_mul(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_be(z);
+    c
+}
+
+pub fn mul_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.wrapping_mul(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+pub fn div_be(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_be();
+    let y: u128 = b.load_be();
+    let z = x / y;
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_be(z);
+    c
+}
+
+pub fn div_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x / y;
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+/// Shift left by `n` bits, discarding the bits shifted out of the value.
+pub fn shl_le(a: BitVec<u8, Msb0>, n: usize) -> BitVec<u8, Msb0> {
+    let len = a.len();
+    let x: u128 = a.load_le();
+    let z = x.checked_shl(n as u32).unwrap_or(0);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+/// Logical shift right by `n` bits.
+pub fn shr_le(a: BitVec<u8, Msb0>, n: usize) -> BitVec<u8, Msb0> {
+    let len = a.len();
+    let x: u128 = a.load_le();
+    let z = x.checked_shr(n as u32).unwrap_or(0);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+/// Concatenate two values, `a` becomes the most significant part of the
+/// result.
+pub fn concat_le(a: BitVec<u8, Msb0>, b: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = a.len() + b.len();
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.checked_shl(b.len() as u32).unwrap_or(0) | y;
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+/// Convert an unsigned value to `width` bits, zero extending when widening and
+/// truncating when narrowing.
+pub fn resize_le(a: BitVec<u8, Msb0>, width: usize) -> BitVec<u8, Msb0> {
+    let x: u128 = a.load_le();
+    let mut c = BitVec::new();
+    c.resize(width, false);
+    c.store_le(x);
+    c
+}
+
+/// Read bits `hi` down to `lo` of a value.
+pub fn slice_le(
+    a: &BitVec<u8, Msb0>,
+    hi: usize,
+    lo: usize,
+) -> BitVec<u8, Msb0> {
+    let x: u128 = a.load_le();
+    let mut c = BitVec::new();
+    c.resize(hi - lo + 1, false);
+    c.store_le(x >> lo);
+    c
+}
+
+/// Overwrite bits `hi` down to `lo` of a value with `b`.
+pub fn set_slice_le(
+    a: &mut BitVec<u8, Msb0>,
+    hi: usize,
+    lo: usize,
+    b: BitVec<u8, Msb0>,
+) {
+    let mask = (u128::MAX >> (127 - (hi - lo))) << lo;
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    a.store_le((x & !mask) | ((y << lo) & mask));
+}
+
+/// Two's complement negation.
+pub fn neg_le(a: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let len = a.len();
+    let x: u128 = a.load_le();
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(x.wrapping_neg());
+    c
+}
+
+/// Compare two unsigned values.
+pub fn cmp_le(
+    a: &BitVec<u8, Msb0>,
+    b: &BitVec<u8, Msb0>,
+) -> std::cmp::Ordering {
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    x.cmp(&y)
+}
+
+// Signed values are two's complement bit vectors. Addition and subtraction
+// are the same for signed and unsigned values, everything else needs to take
+// the sign of the operands into account.
+
+fn sign_extend(x: u128, width: usize) -> i128 {
+    if width == 0 || width >= 128 {
+        return x as i128;
+    }
+    let shift = 128 - width;
+    ((x << shift) as i128) >> shift
+}
+
+fn load_signed_be(a: &BitVec<u8, Msb0>) -> i128 {
+    sign_extend(a.load_be(), a.len())
+}
+
+fn load_signed_le(a: &BitVec<u8, Msb0>) -> i128 {
+    sign_extend(a.load_le(), a.len())
+}
+
+fn store_signed_be(z: i128, len: usize) -> BitVec<u8, Msb0> {
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_be(z as u128);
+    c
+}
+
+fn store_signed_le(z: i128, len: usize) -> BitVec<u8, Msb0> {
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z as u128);
+    c
+}
+
+pub fn add_signed_be(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let z = load_signed_be(&a).wrapping_add(load_signed_be(&b));
+    store_signed_be(z, len)
+}
+
+pub fn add_signed_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let z = load_signed_le(&a).wrapping_add(load_signed_le(&b));
+    store_signed_le(z, len)
+}
+
+pub fn mod_signed_be(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let z = load_signed_be(&a).rem_euclid(load_signed_be(&b));
+    store_signed_be(z, len)
+}
+
+pub fn mod_signed_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let z = load_signed_le(&a).rem_euclid(load_signed_le(&b));
+    store_signed_le(z, len)
+}
+
+pub fn div_signed_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let z = load_signed_le(&a).wrapping_div(load_signed_le(&b));
+    store_signed_le(z, len)
+}
+
+/// Arithmetic shift right by `n` bits, the sign bit is shifted in.
+pub fn shr_signed_le(a: BitVec<u8, Msb0>, n: usize) -> BitVec<u8, Msb0> {
+    let len = a.len();
+    let z = load_signed_le(&a) >> usize::min(n, 127);
+    store_signed_le(z, len)
+}
+
+/// Compare two signed values.
+pub fn cmp_signed_le(
+    a: &BitVec<u8, Msb0>,
+    b: &BitVec<u8, Msb0>,
+) -> std::cmp::Ordering {
+    load_signed_le(a).cmp(&load_signed_le(b))
+}
+
+/// Convert a signed value to `width` bits, extending the sign of the value
+/// when widening and truncating when narrowing.
+pub fn sign_extend_le(a: BitVec<u8, Msb0>, width: usize) -> BitVec<u8, Msb0> {
+    store_signed_le(load_signed_le(&a), width)
+}
+
+/// Unsigned addition that saturates at the maximum value of the result width.
+pub fn add_sat_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let max = u128::MAX >> (128 - len);
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.saturating_add(y).min(max);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+/// Unsigned subtraction that saturates at zero.
+pub fn sub_sat_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let x: u128 = a.load_le();
+    let y: u128 = b.load_le();
+    let z = x.saturating_sub(y);
+    let mut c = BitVec::new();
+    c.resize(len, false);
+    c.store_le(z);
+    c
+}
+
+fn signed_bounds(len: usize) -> (i128, i128) {
+    let max = i128::MAX >> (128 - len);
+    (-max - 1, max)
+}
+
+/// Signed addition that saturates at the bounds of the result width.
+pub fn add_sat_signed_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let (min, max) = signed_bounds(len);
+    let z = load_signed_le(&a).saturating_add(load_signed_le(&b));
+    store_signed_le(z.clamp(min, max), len)
+}
+
+/// Signed subtraction that saturates at the bounds of the result width.
+pub fn sub_sat_signed_le(
+    a: BitVec<u8, Msb0>,
+    b: BitVec<u8, Msb0>,
+) -> BitVec<u8, Msb0> {
+    let len = usize::max(a.len(), b.len());
+    let (min, max) = signed_bounds(len);
+    let z = load_signed_le(&a).saturating_sub(load_signed_le(&b));
+    store_signed_le(z.clamp(min, max), len)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{load_signed_le, store_signed_le};
+    use bitvec::prelude::*;
 
     #[test]
     fn bitmath_add() {
@@ -193,4 +510,109 @@ mod tests {
         let cc: u128 = c.load_be();
         assert_eq!(cc, 47u128 % 7u128);
     }
+
+    fn signed(value: i128, width: usize) -> BitVec<u8, Msb0> {
+        store_signed_le(value, width)
+    }
+
+    fn value(x: &BitVec<u8, Msb0>) -> i128 {
+        load_signed_le(x)
+    }
+
+    #[test]
+    fn bitmath_signed() {
+        use super::*;
+        assert_eq!(value(&add_signed_le(signed(-3, 8), signed(1, 8))), -2);
+        assert_eq!(value(&add_signed_le(signed(127, 8), signed(1, 8))), -128);
+        assert_eq!(value(&sub_le(signed(-3, 16), signed(300, 16))), -303);
+        assert_eq!(value(&mod_signed_le(signed(-7, 8), signed(3, 8))), 2);
+        assert_eq!(
+            cmp_signed_le(&signed(-1, 16), &signed(1, 16)),
+            std::cmp::Ordering::Less,
+        );
+
+        let x = sign_extend_le(signed(-2, 8), 32);
+        assert_eq!(x.len(), 32);
+        assert_eq!(value(&x), -2);
+        let y: u32 = x.load_le();
+        assert_eq!(y, 0xfffffffe);
+
+        let x = sign_extend_le(signed(-2, 32), 8);
+        assert_eq!(x.len(), 8);
+        assert_eq!(value(&x), -2);
+    }
+
+    #[test]
+    fn bitmath_saturating() {
+        use super::*;
+        let sat = add_sat_signed_le(signed(100, 8), signed(100, 8));
+        assert_eq!(value(&sat), 127);
+        let sat = sub_sat_signed_le(signed(-100, 8), signed(100, 8));
+        assert_eq!(value(&sat), -128);
+        let sat = add_sat_signed_le(signed(-100, 8), signed(50, 8));
+        assert_eq!(value(&sat), -50);
+
+        let mut a = bitvec![mut u8, Msb0; 0; 8];
+        a.store_le(200u8);
+        let mut b = bitvec![mut u8, Msb0; 0; 8];
+        b.store_le(100u8);
+        let c: u8 = add_sat_le(a.clone(), b.clone()).load_le();
+        assert_eq!(c, 255);
+        let c: u8 = sub_sat_le(b, a).load_le();
+        assert_eq!(c, 0);
+    }
+
+    fn unsigned(value: u128, width: usize) -> BitVec<u8, Msb0> {
+        let mut x = bitvec![u8, Msb0; 0; width];
+        x.store_le(value);
+        x
+    }
+
+    #[test]
+    fn bitmath_operators() {
+        use super::*;
+        let c: u16 = mul_le(unsigned(300, 16), unsigned(300, 16)).load_le();
+        assert_eq!(c, (300u32 * 300 % 65536) as u16);
+        let c: u16 = div_le(unsigned(1000, 16), unsigned(7, 16)).load_le();
+        assert_eq!(c, 142);
+        assert_eq!(value(&div_signed_le(signed(-9, 8), signed(2, 8))), -4);
+
+        let c: u8 = shl_le(unsigned(0x81, 8), 1).load_le();
+        assert_eq!(c, 0x02);
+        let c: u8 = shr_le(unsigned(0x81, 8), 4).load_le();
+        assert_eq!(c, 0x08);
+        let c: u8 = shl_le(unsigned(0xff, 8), 200).load_le();
+        assert_eq!(c, 0);
+        assert_eq!(value(&shr_signed_le(signed(-16, 8), 2)), -4);
+
+        let c = concat_le(unsigned(0xab, 8), unsigned(0x1234, 16));
+        assert_eq!(c.len(), 24);
+        let c: u32 = c.load_le();
+        assert_eq!(c, 0xab1234);
+
+        let c = resize_le(unsigned(0x1234, 16), 32);
+        assert_eq!(c.len(), 32);
+        let c: u32 = c.load_le();
+        assert_eq!(c, 0x1234);
+        let c: u8 = resize_le(unsigned(0x1234, 16), 8).load_le();
+        assert_eq!(c, 0x34);
+
+        let c: u8 = slice_le(&unsigned(0xabcd, 16), 11, 4).load_le();
+        assert_eq!(c, 0xbc);
+        let mut c = unsigned(0xabcd, 16);
+        set_slice_le(&mut c, 5, 3, unsigned(0b010, 3));
+        let c: u16 = c.load_le();
+        assert_eq!(c, 0xabd5);
+        let mut c = unsigned(0, 128);
+        set_slice_le(&mut c, 127, 96, unsigned(0x0064ff9b, 32));
+        let c: u128 = c.load_le();
+        assert_eq!(c, 0x0064ff9b << 96);
+
+        let c: u8 = neg_le(unsigned(1, 8)).load_le();
+        assert_eq!(c, 0xff);
+        assert_eq!(
+            cmp_le(&unsigned(0x0100, 16), &unsigned(0x00ff, 16)),
+            std::cmp::Ordering::Greater,
+        );
+    }
 }
@@ -62,7 +62,7 @@ pub fn udp6_checksum(data: &[u8]) -> u16 {
     csum.add(payload_len[0], payload_len[1]);
 
     let len = payload.len();
-    let (odd, len) = if len % 2 == 0 {
+    let (odd, len) = if len.is_multiple_of(2) {
         (false, len)
     } else {
         (true, len - 1)
@@ -106,6 +106,27 @@ impl Checksum for &BitVec<u8, Msb0> {
     }
 }
 
+/// The ones' complement sum of a list of fields, as they are laid out on the
+/// wire one after another. The fields are padded with zeros to a multiple of
+/// 16 bits.
+pub fn csum16(fields: &[&BitVec<u8, Msb0>]) -> BitVec<u8, Msb0> {
+    let mut wire = BitVec::<u8, Msb0>::new();
+    for f in fields {
+        let mut x = bitvec![u8, Msb0; 0; f.len()];
+        crate::field_to_wire(f, &mut x);
+        wire.extend_from_bitslice(&x);
+    }
+    wire.resize(wire.len().div_ceil(16) * 16, false);
+
+    let mut csum = Csum::default();
+    for pair in wire.chunks(16) {
+        csum.add(pair[..8].load_be(), pair[8..].load_be());
+    }
+    let mut result = bitvec![u8, Msb0; 0; 16];
+    result.store_le(csum.result());
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,4 +175,31 @@ mod tests {
 
         assert_eq!(x, y);
     }
+
+    fn field(value: u128, width: usize) -> BitVec<u8, Msb0> {
+        let mut x = bitvec![u8, Msb0; 0; width];
+        x.store_le(value);
+        x
+    }
+
+    #[test]
+    fn ipv4_csum16() {
+        // 4500 0073 0000 4000 4011 b861 c0a8 0001 c0a8 00c7
+        let fields = [
+            field(4, 4),
+            field(5, 4),
+            field(0, 8),
+            field(0x73, 16),
+            field(0, 16),
+            field(2, 3),
+            field(0, 13),
+            field(0x40, 8),
+            field(0x11, 8),
+            field(0xc0a80001, 32),
+            field(0xc0a800c7, 32),
+        ];
+        let fields: Vec<&BitVec<u8, Msb0>> = fields.iter().collect();
+        let c: u16 = csum16(&fields).load_le();
+        assert_eq!(c, 0xb861);
+    }
 }
@@ -13,3 +13,220 @@ impl fmt::Display for TryFromSliceError {
 }
 
 impl Error for TryFromSliceError {}
+
+/// The packet ended before the data being extracted from it did.
+#[derive(Debug)]
+pub struct PacketTooShort {
+    /// The number of bits the extraction required.
+    pub needed: usize,
+    /// The number of bits left in the packet.
+    pub available: usize,
+}
+
+impl fmt::Display for PacketTooShort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "packet too short: {} bits needed, {} available",
+            self.needed, self.available
+        )
+    }
+}
+
+impl Error for PacketTooShort {}
+
+/// Why a header with a variable length field could not be extracted.
+#[derive(Debug)]
+pub enum ExtractError {
+    PacketTooShort(PacketTooShort),
+    /// The length given for the variable length field is larger than the
+    /// maximum width of the field.
+    InvalidArgument {
+        size: usize,
+        max: usize,
+    },
+}
+
+impl ExtractError {
+    /// The name of the P4 parser error this error corresponds to.
+    pub fn parser_error(&self) -> &'static str {
+        match self {
+            Self::PacketTooShort(_) => "PacketTooShort",
+            Self::InvalidArgument { .. } => "ParserInvalidArgument",
+        }
+    }
+}
+
+impl From<PacketTooShort> for ExtractError {
+    fn from(e: PacketTooShort) -> Self {
+        Self::PacketTooShort(e)
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PacketTooShort(e) => e.fmt(f),
+            Self::InvalidArgument { size, max } => write!(
+                f,
+                "variable length field of {} bits exceeds maximum of {}",
+                size, max
+            ),
+        }
+    }
+}
+
+impl Error for ExtractError {}
+
+/// Why a typed key or parameter value could not be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueError {
+    /// The value does not fit in a field of `width` bits.
+    TooWide { width: usize },
+    /// The prefix length of a longest prefix match key is longer than the
+    /// `width` bits of its address.
+    PrefixTooLong { width: usize, len: u8 },
+}
+
+impl ValueError {
+    /// The error updating the table `table_id` with this value results in.
+    pub fn table_update_error(self, table_id: &str) -> TableUpdateError {
+        let table_id = table_id.to_owned();
+        match self {
+            Self::TooWide { width } => {
+                TableUpdateError::ValueTooWide { table_id, width }
+            }
+            Self::PrefixTooLong { width, len } => {
+                TableUpdateError::UnsupportedPrefix {
+                    table_id,
+                    width,
+                    len,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooWide { width } => {
+                write!(f, "value does not fit in {} bits", width)
+            }
+            Self::PrefixTooLong { width, len } => {
+                write!(f, "/{} prefix of a {} bit address", len, width)
+            }
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+/// Why a table update could not be applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableUpdateError {
+    UnknownTable(String),
+    UnknownAction {
+        table_id: String,
+        action_id: String,
+    },
+    /// The keyset data is not the length the table's key requires.
+    BadKeysetData {
+        table_id: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The parameter data is not the length the action's parameters require.
+    BadParameterData {
+        table_id: String,
+        action_id: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An entry with the same key is already in the table.
+    EntryExists {
+        table_id: String,
+    },
+    /// No entry with the key is in the table.
+    NoSuchEntry {
+        table_id: String,
+    },
+    /// The table's default action is declared const and cannot be changed.
+    ConstDefaultAction {
+        table_id: String,
+    },
+    /// A key or parameter value does not fit in its field of `width` bits.
+    ValueTooWide {
+        table_id: String,
+        width: usize,
+    },
+    /// The prefix length of a longest prefix match key is longer than the
+    /// `width` bits of its address, or the table cannot hold addresses of
+    /// that width.
+    UnsupportedPrefix {
+        table_id: String,
+        width: usize,
+        len: u8,
+    },
+}
+
+impl fmt::Display for TableUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownTable(table_id) => {
+                write!(f, "unknown table id {}", table_id)
+            }
+            Self::UnknownAction {
+                table_id,
+                action_id,
+            } => {
+                write!(f, "table {}: unknown action id {}", table_id, action_id)
+            }
+            Self::BadKeysetData {
+                table_id,
+                expected,
+                found,
+            } => write!(
+                f,
+                "table {}: expected {} bytes of keyset data, found {}",
+                table_id, expected, found
+            ),
+            Self::BadParameterData {
+                table_id,
+                action_id,
+                expected,
+                found,
+            } => write!(
+                f,
+                "table {}: action {} expects {} bytes of parameter data, \
+                found {}",
+                table_id, action_id, expected, found
+            ),
+            Self::EntryExists { table_id } => {
+                write!(f, "table {}: entry already exists", table_id)
+            }
+            Self::NoSuchEntry { table_id } => {
+                write!(f, "table {}: no such entry", table_id)
+            }
+            Self::ConstDefaultAction { table_id } => {
+                write!(f, "table {}: default action is const", table_id)
+            }
+            Self::ValueTooWide { table_id, width } => write!(
+                f,
+                "table {}: value does not fit in {} bits",
+                table_id, width
+            ),
+            Self::UnsupportedPrefix {
+                table_id,
+                width,
+                len,
+            } => write!(
+                f,
+                "table {}: unsupported /{} prefix of a {} bit address",
+                table_id, len, width
+            ),
+        }
+    }
+}
+
+impl Error for TableUpdateError {}
@@ -1,6 +1,7 @@
 // Copyright 2022 Oxide Computer Company
 
 use bitvec::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub struct Checksum {}
 
@@ -29,3 +30,66 @@ impl Default for Checksum {
         Self::new()
     }
 }
+
+/// The most times a packet is resubmitted to ingress. A packet ingress
+/// resubmits once more is dropped.
+pub const MAX_RESUBMISSIONS: usize = 8;
+
+/// The most clones egress makes of the packets it puts out for a packet the
+/// pipeline was handed. Further clones are not made.
+pub const MAX_EGRESS_CLONES: usize = 8;
+
+/// The multicast groups of a pipeline, as a set of ports for each group, and
+/// its clone sessions, as the port each session sends clones to.
+#[derive(Default)]
+pub struct PacketReplicationEngine {
+    groups: BTreeMap<u32, BTreeSet<u16>>,
+    clone_sessions: BTreeMap<u32, u16>,
+}
+
+impl PacketReplicationEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a port to a multicast group, creating the group if need be.
+    pub fn add_group_member(&mut self, group: u32, port: u16) {
+        self.groups.entry(group).or_default().insert(port);
+    }
+
+    /// Remove a port from a multicast group, the group is removed with its
+    /// last port.
+    pub fn remove_group_member(&mut self, group: u32, port: u16) {
+        if let Some(ports) = self.groups.get_mut(&group) {
+            ports.remove(&port);
+            if ports.is_empty() {
+                self.groups.remove(&group);
+            }
+        }
+    }
+
+    /// The ports of a multicast group, in order.
+    pub fn group_ports(&self, group: u32) -> Vec<u16> {
+        self.groups
+            .get(&group)
+            .map(|ports| ports.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Send the clones of a clone session to a port, replacing the port the
+    /// session sent clones to.
+    pub fn set_clone_session(&mut self, session: u32, port: u16) {
+        self.clone_sessions.insert(session, port);
+    }
+
+    /// Remove a clone session, packets cloned through it are not sent
+    /// anywhere.
+    pub fn remove_clone_session(&mut self, session: u32) {
+        self.clone_sessions.remove(&session);
+    }
+
+    /// The port a clone session sends clones to.
+    pub fn clone_session_port(&self, session: u32) -> Option<u16> {
+        self.clone_sessions.get(&session).copied()
+    }
+}
@@ -0,0 +1,698 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Lookup indexes for table entries.
+//!
+//! Every key in a P4 table has the same structure: the match kind of each
+//! dimension is fixed by the table definition. A [`TableIndex`] takes
+//! advantage of that and picks a lookup structure based on the match kinds of
+//! the first key inserted into a table.
+//!
+//! - All dimensions exact: a hash map keyed on the exact values.
+//! - A single lpm dimension: a binary trie per address family.
+//! - A single range dimension: an interval tree.
+//! - Anything else: a tuple space classifier. Keys are grouped by the mask
+//!   they apply in each dimension, and each group is a hash map keyed on the
+//!   masked values. Range dimensions are wildcarded in the grouping and
+//!   checked against the candidates of each group.
+//!
+//! Tables may instead opt into a [`Rice`] decision tree for every dimension,
+//! see [`Backend`].
+//!
+//! Indexes only track keys. The entries themselves live in the table, which
+//! looks them up by key once the index has produced a set of matching keys.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use num::bigint::BigUint;
+use num::ToPrimitive;
+
+use crate::rice::Rice;
+use crate::table::{keyset_matches, Backend, Key, Prefix, Ternary};
+
+pub enum TableIndex<const D: usize> {
+    Exact(ExactIndex<D>),
+    Lpm(LpmIndex<D>),
+    Range(RangeIndex<D>),
+    Classifier(Classifier<D>),
+    Rice(Rice<D>),
+}
+
+impl<const D: usize> Default for TableIndex<D> {
+    fn default() -> Self {
+        Self::Classifier(Classifier::default())
+    }
+}
+
+impl<const D: usize> TableIndex<D> {
+    /// Create an empty index for `backend` suited to keys with the same
+    /// structure as `key`.
+    pub fn new(backend: Backend, key: &[Key; D]) -> Self {
+        if backend == Backend::Rice {
+            return Self::Rice(Rice::default());
+        }
+        if key.iter().all(|k| matches!(k, Key::Exact(_))) {
+            return Self::Exact(ExactIndex::default());
+        }
+        match key.first() {
+            Some(Key::Lpm(_)) if D == 1 => Self::Lpm(LpmIndex::default()),
+            Some(Key::Range(..)) if D == 1 => {
+                Self::Range(RangeIndex::default())
+            }
+            _ => Self::Classifier(Classifier::default()),
+        }
+    }
+
+    /// Create a classifier index holding all of the provided keys. This is
+    /// the fallback for tables whose keys do not share a common structure.
+    pub fn classifier<'a>(keys: impl Iterator<Item = &'a [Key; D]>) -> Self {
+        let mut index = Classifier::default();
+        for key in keys {
+            index.insert(key.clone());
+        }
+        Self::Classifier(index)
+    }
+
+    /// Returns true if `key` can be held by this index.
+    pub fn accepts(&self, key: &[Key; D]) -> bool {
+        match self {
+            Self::Exact(_) => key.iter().all(|k| matches!(k, Key::Exact(_))),
+            Self::Lpm(_) => matches!(key[0], Key::Lpm(_)),
+            Self::Range(_) => matches!(key[0], Key::Range(..)),
+            Self::Classifier(_) | Self::Rice(_) => true,
+        }
+    }
+
+    /// Add a key to the index. The key must be accepted by the index and not
+    /// already be present.
+    pub fn insert(&mut self, key: [Key; D]) {
+        match self {
+            Self::Exact(x) => x.insert(key),
+            Self::Lpm(x) => x.insert(key),
+            Self::Range(x) => x.insert(key),
+            Self::Classifier(x) => x.insert(key),
+            Self::Rice(x) => x.insert(key),
+        }
+    }
+
+    /// Remove a key from the index, returns true if the key was present.
+    pub fn remove(&mut self, key: &[Key; D]) -> bool {
+        match self {
+            Self::Exact(x) => x.remove(key),
+            Self::Lpm(x) => x.remove(key),
+            Self::Range(x) => x.remove(key),
+            Self::Classifier(x) => x.remove(key),
+            Self::Rice(x) => x.remove(key),
+        }
+    }
+
+    /// Find the keys that match a selector. For lpm indexes only the longest
+    /// matching prefixes are returned, as these are the only entries that
+    /// survive match result sorting.
+    pub fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        match self {
+            Self::Exact(x) => x.lookup(selector),
+            Self::Lpm(x) => x.lookup(selector),
+            Self::Range(x) => x.lookup(selector),
+            Self::Classifier(x) => x.lookup(selector),
+            Self::Rice(x) => x.lookup(selector),
+        }
+    }
+}
+
+fn remove_key<const D: usize>(
+    keys: &mut Vec<[Key; D]>,
+    key: &[Key; D],
+) -> bool {
+    match keys.iter().position(|k| k == key) {
+        Some(i) => {
+            keys.swap_remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// An index over tables whose keys are all exact matches.
+pub struct ExactIndex<const D: usize> {
+    map: HashMap<[BigUint; D], Vec<[Key; D]>>,
+}
+
+impl<const D: usize> Default for ExactIndex<D> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+}
+
+impl<const D: usize> ExactIndex<D> {
+    // Keys that differ only in width share a value, so each value maps to a
+    // list of keys.
+    fn values(key: &[Key; D]) -> [BigUint; D] {
+        std::array::from_fn(|i| match &key[i] {
+            Key::Exact(x) => x.value.clone(),
+            k => panic!("non-exact key {:?} in exact index", k),
+        })
+    }
+
+    fn insert(&mut self, key: [Key; D]) {
+        self.map.entry(Self::values(&key)).or_default().push(key);
+    }
+
+    fn remove(&mut self, key: &[Key; D]) -> bool {
+        let values = Self::values(key);
+        let Some(keys) = self.map.get_mut(&values) else {
+            return false;
+        };
+        let removed = remove_key(keys, key);
+        if keys.is_empty() {
+            self.map.remove(&values);
+        }
+        removed
+    }
+
+    fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        match self.map.get(selector) {
+            Some(keys) => keys.iter().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Returns a prefix as an address in the low `width` bits of a `u128`.
+fn prefix_bits(prefix: &Prefix) -> (u128, u32) {
+    match prefix.addr {
+        IpAddr::V4(a) => (u32::from(a) as u128, 32),
+        IpAddr::V6(a) => (u128::from(a), 128),
+    }
+}
+
+fn prefix_mask(len: u8, width: u32) -> u128 {
+    match len {
+        0 => 0,
+        len => (u128::MAX << (128 - len as u32)) >> (128 - width),
+    }
+}
+
+/// Returns the bit of a `width` bit address at `depth`, counting from the
+/// most significant bit.
+fn prefix_bit(addr: u128, width: u32, depth: u32) -> usize {
+    ((addr >> (width - 1 - depth)) & 1) as usize
+}
+
+struct TrieNode<const D: usize> {
+    keys: Vec<[Key; D]>,
+    children: [Option<Box<TrieNode<D>>>; 2],
+}
+
+impl<const D: usize> Default for TrieNode<D> {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            children: [None, None],
+        }
+    }
+}
+
+impl<const D: usize> TrieNode<D> {
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.children.iter().all(Option::is_none)
+    }
+}
+
+/// A binary trie over the prefixes of one address family. Each node is one
+/// bit deeper than its parent, and keys live at the node whose depth is
+/// their prefix length.
+struct PrefixTrie<const D: usize> {
+    root: TrieNode<D>,
+    width: u32,
+}
+
+impl<const D: usize> PrefixTrie<D> {
+    fn new(width: u32) -> Self {
+        Self {
+            root: TrieNode::default(),
+            width,
+        }
+    }
+
+    fn insert(&mut self, addr: u128, len: u8, key: [Key; D]) {
+        let width = self.width;
+        let mut node = &mut self.root;
+        for depth in 0..len as u32 {
+            let bit = prefix_bit(addr, width, depth);
+            node = node.children[bit].get_or_insert_with(Default::default);
+        }
+        node.keys.push(key);
+    }
+
+    fn remove(&mut self, addr: u128, len: u8, key: &[Key; D]) -> bool {
+        let width = self.width;
+        Self::remove_at(&mut self.root, width, addr, 0, len as u32, key)
+    }
+
+    fn remove_at(
+        node: &mut TrieNode<D>,
+        width: u32,
+        addr: u128,
+        depth: u32,
+        len: u32,
+        key: &[Key; D],
+    ) -> bool {
+        if depth == len {
+            return remove_key(&mut node.keys, key);
+        }
+        let bit = prefix_bit(addr, width, depth);
+        let Some(child) = node.children[bit].as_mut() else {
+            return false;
+        };
+        let removed = Self::remove_at(child, width, addr, depth + 1, len, key);
+        if child.is_empty() {
+            node.children[bit] = None;
+        }
+        removed
+    }
+
+    /// Returns the keys with the longest prefix covering `addr`.
+    fn lookup(&self, addr: u128) -> &[[Key; D]] {
+        let mut node = &self.root;
+        let mut longest = &node.keys[..];
+        for depth in 0..self.width {
+            match &node.children[prefix_bit(addr, self.width, depth)] {
+                Some(child) => node = child,
+                None => break,
+            }
+            if !node.keys.is_empty() {
+                longest = &node.keys[..];
+            }
+        }
+        longest
+    }
+}
+
+/// An index over tables keyed by a single lpm dimension.
+pub struct LpmIndex<const D: usize> {
+    v4: PrefixTrie<D>,
+    v6: PrefixTrie<D>,
+}
+
+impl<const D: usize> Default for LpmIndex<D> {
+    fn default() -> Self {
+        Self {
+            v4: PrefixTrie::new(32),
+            v6: PrefixTrie::new(128),
+        }
+    }
+}
+
+impl<const D: usize> LpmIndex<D> {
+    fn prefix(key: &[Key; D]) -> &Prefix {
+        match &key[0] {
+            Key::Lpm(p) => p,
+            k => panic!("non-lpm key {:?} in lpm index", k),
+        }
+    }
+
+    fn trie(&mut self, prefix: &Prefix) -> &mut PrefixTrie<D> {
+        match prefix.addr {
+            IpAddr::V4(_) => &mut self.v4,
+            IpAddr::V6(_) => &mut self.v6,
+        }
+    }
+
+    fn insert(&mut self, key: [Key; D]) {
+        let prefix = Self::prefix(&key).clone();
+        let (addr, _) = prefix_bits(&prefix);
+        self.trie(&prefix).insert(addr, prefix.len, key);
+    }
+
+    fn remove(&mut self, key: &[Key; D]) -> bool {
+        let prefix = Self::prefix(key);
+        let (addr, _) = prefix_bits(prefix);
+        self.trie(prefix).remove(addr, prefix.len, key)
+    }
+
+    fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        let mut result = Vec::new();
+        // A selector may be covered by prefixes of both families, e.g. the
+        // default routes 0.0.0.0/0 and ::/0. Results from both tries are
+        // returned and pruned to the longest prefix by match sorting.
+        if let Some(addr) = selector[0].to_u32() {
+            result.extend(self.v4.lookup(addr as u128));
+        }
+        if let Some(addr) = selector[0].to_u128() {
+            result.extend(self.v6.lookup(addr));
+        }
+        result
+    }
+}
+
+type IntervalLink<const D: usize> = Option<Box<IntervalNode<D>>>;
+
+/// A node in an AVL tree of intervals ordered by `(low, high)`. Each node
+/// tracks the largest upper bound in its subtree so stabbing queries can skip
+/// subtrees that end before the selector.
+struct IntervalNode<const D: usize> {
+    low: BigUint,
+    high: BigUint,
+    max: BigUint,
+    height: i32,
+    keys: Vec<[Key; D]>,
+    left: IntervalLink<D>,
+    right: IntervalLink<D>,
+}
+
+fn height<const D: usize>(link: &IntervalLink<D>) -> i32 {
+    link.as_ref().map(|n| n.height).unwrap_or(0)
+}
+
+impl<const D: usize> IntervalNode<D> {
+    fn new(low: BigUint, high: BigUint, key: [Key; D]) -> Box<Self> {
+        Box::new(Self {
+            max: high.clone(),
+            low,
+            high,
+            height: 1,
+            keys: vec![key],
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.height = 1 + height(&self.left).max(height(&self.right));
+        let mut max = &self.high;
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            if &child.max > max {
+                max = &child.max;
+            }
+        }
+        self.max = max.clone();
+    }
+
+    fn rotate_right(mut self: Box<Self>) -> Box<Self> {
+        let mut left = self.left.take().unwrap();
+        self.left = left.right.take();
+        self.update();
+        left.right = Some(self);
+        left.update();
+        left
+    }
+
+    fn rotate_left(mut self: Box<Self>) -> Box<Self> {
+        let mut right = self.right.take().unwrap();
+        self.right = right.left.take();
+        self.update();
+        right.left = Some(self);
+        right.update();
+        right
+    }
+
+    fn balance(mut self: Box<Self>) -> Box<Self> {
+        self.update();
+        let factor = height(&self.left) - height(&self.right);
+        if factor > 1 {
+            let left = self.left.take().unwrap();
+            self.left = Some(if height(&left.left) < height(&left.right) {
+                left.rotate_left()
+            } else {
+                left
+            });
+            self.rotate_right()
+        } else if factor < -1 {
+            let right = self.right.take().unwrap();
+            self.right = Some(if height(&right.right) < height(&right.left) {
+                right.rotate_right()
+            } else {
+                right
+            });
+            self.rotate_left()
+        } else {
+            self
+        }
+    }
+
+    fn cmp(&self, low: &BigUint, high: &BigUint) -> Ordering {
+        (low, high).cmp(&(&self.low, &self.high))
+    }
+
+    fn insert(
+        link: IntervalLink<D>,
+        low: BigUint,
+        high: BigUint,
+        key: [Key; D],
+    ) -> Box<Self> {
+        let Some(mut node) = link else {
+            return Self::new(low, high, key);
+        };
+        match node.cmp(&low, &high) {
+            Ordering::Less => {
+                node.left = Some(Self::insert(node.left.take(), low, high, key))
+            }
+            Ordering::Greater => {
+                node.right =
+                    Some(Self::insert(node.right.take(), low, high, key))
+            }
+            Ordering::Equal => {
+                node.keys.push(key);
+                return node;
+            }
+        }
+        node.balance()
+    }
+
+    /// Detach the leftmost node of a subtree, returning the rebalanced
+    /// remainder and the detached node.
+    fn take_min(mut self: Box<Self>) -> (IntervalLink<D>, Box<Self>) {
+        match self.left.take() {
+            None => (self.right.take(), self),
+            Some(left) => {
+                let (rest, min) = left.take_min();
+                self.left = rest;
+                (Some(self.balance()), min)
+            }
+        }
+    }
+
+    fn remove(
+        link: IntervalLink<D>,
+        low: &BigUint,
+        high: &BigUint,
+        key: &[Key; D],
+    ) -> (IntervalLink<D>, bool) {
+        let Some(mut node) = link else {
+            return (None, false);
+        };
+        let removed = match node.cmp(low, high) {
+            Ordering::Less => {
+                let (left, removed) =
+                    Self::remove(node.left.take(), low, high, key);
+                node.left = left;
+                removed
+            }
+            Ordering::Greater => {
+                let (right, removed) =
+                    Self::remove(node.right.take(), low, high, key);
+                node.right = right;
+                removed
+            }
+            Ordering::Equal => {
+                let removed = remove_key(&mut node.keys, key);
+                if !node.keys.is_empty() {
+                    return (Some(node), removed);
+                }
+                return match (node.left.take(), node.right.take()) {
+                    (None, child) | (child, None) => (child, removed),
+                    (left, Some(right)) => {
+                        let (rest, mut min) = right.take_min();
+                        min.left = left;
+                        min.right = rest;
+                        (Some(min.balance()), removed)
+                    }
+                };
+            }
+        };
+        (Some(node.balance()), removed)
+    }
+
+    fn stab<'a>(
+        link: &'a IntervalLink<D>,
+        x: &BigUint,
+        out: &mut Vec<&'a [Key; D]>,
+    ) {
+        let Some(node) = link else {
+            return;
+        };
+        if &node.max < x {
+            return;
+        }
+        Self::stab(&node.left, x, out);
+        if &node.low <= x {
+            if x <= &node.high {
+                out.extend(node.keys.iter());
+            }
+            Self::stab(&node.right, x, out);
+        }
+    }
+}
+
+/// An index over tables keyed by a single range dimension.
+pub struct RangeIndex<const D: usize> {
+    root: IntervalLink<D>,
+}
+
+impl<const D: usize> Default for RangeIndex<D> {
+    fn default() -> Self {
+        Self { root: None }
+    }
+}
+
+impl<const D: usize> RangeIndex<D> {
+    fn bounds(key: &[Key; D]) -> (&BigUint, &BigUint) {
+        match &key[0] {
+            Key::Range(low, high) => (&low.value, &high.value),
+            k => panic!("non-range key {:?} in range index", k),
+        }
+    }
+
+    fn insert(&mut self, key: [Key; D]) {
+        let (low, high) = Self::bounds(&key);
+        let (low, high) = (low.clone(), high.clone());
+        self.root =
+            Some(IntervalNode::insert(self.root.take(), low, high, key));
+    }
+
+    fn remove(&mut self, key: &[Key; D]) -> bool {
+        let (low, high) = Self::bounds(key);
+        let (root, removed) =
+            IntervalNode::remove(self.root.take(), low, high, key);
+        self.root = root;
+        removed
+    }
+
+    fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        let mut result = Vec::new();
+        IntervalNode::stab(&self.root, &selector[0], &mut result);
+        result
+    }
+}
+
+/// How a classifier tuple treats one dimension of a selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DimMask {
+    /// The whole selector value is significant.
+    Full,
+    /// Only the masked bits of the selector are significant.
+    Masked(BigUint),
+    /// The dimension does not take part in hashing.
+    Any,
+}
+
+impl DimMask {
+    fn apply(&self, selector: &BigUint) -> BigUint {
+        match self {
+            Self::Full => selector.clone(),
+            Self::Masked(m) => selector & m,
+            Self::Any => BigUint::default(),
+        }
+    }
+
+    /// Returns the mask a key applies to its dimension along with the masked
+    /// key value.
+    fn of(key: &Key) -> (Self, BigUint) {
+        match key {
+            Key::Exact(x) | Key::Ternary(Ternary::Value(x)) => {
+                (Self::Full, x.value.clone())
+            }
+            Key::Ternary(Ternary::Masked(x, m, _)) => {
+                (Self::Masked(m.clone()), x & m)
+            }
+            Key::Lpm(p) => {
+                let (addr, width) = prefix_bits(p);
+                let mask = prefix_mask(p.len, width);
+                (Self::Masked(mask.into()), (addr & mask).into())
+            }
+            Key::Ternary(Ternary::DontCare) | Key::Range(..) => {
+                (Self::Any, BigUint::default())
+            }
+        }
+    }
+}
+
+/// A group of keys that apply the same masks.
+struct Tuple<const D: usize> {
+    masks: Vec<DimMask>,
+    buckets: HashMap<Vec<BigUint>, Vec<[Key; D]>>,
+}
+
+/// A tuple space search classifier, used for ternary and mixed keysets.
+pub struct Classifier<const D: usize> {
+    tuples: Vec<Tuple<D>>,
+}
+
+impl<const D: usize> Default for Classifier<D> {
+    fn default() -> Self {
+        Self { tuples: Vec::new() }
+    }
+}
+
+impl<const D: usize> Classifier<D> {
+    fn masks(key: &[Key; D]) -> (Vec<DimMask>, Vec<BigUint>) {
+        key.iter().map(DimMask::of).unzip()
+    }
+
+    fn insert(&mut self, key: [Key; D]) {
+        let (masks, values) = Self::masks(&key);
+        let i = match self.tuples.iter().position(|t| t.masks == masks) {
+            Some(i) => i,
+            None => {
+                self.tuples.push(Tuple {
+                    masks,
+                    buckets: HashMap::new(),
+                });
+                self.tuples.len() - 1
+            }
+        };
+        self.tuples[i].buckets.entry(values).or_default().push(key);
+    }
+
+    fn remove(&mut self, key: &[Key; D]) -> bool {
+        let (masks, values) = Self::masks(key);
+        let Some(i) = self.tuples.iter().position(|t| t.masks == masks) else {
+            return false;
+        };
+        let tuple = &mut self.tuples[i];
+        let Some(keys) = tuple.buckets.get_mut(&values) else {
+            return false;
+        };
+        let removed = remove_key(keys, key);
+        if keys.is_empty() {
+            tuple.buckets.remove(&values);
+        }
+        if tuple.buckets.is_empty() {
+            self.tuples.swap_remove(i);
+        }
+        removed
+    }
+
+    fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        let mut result = Vec::new();
+        for tuple in &self.tuples {
+            let probe: Vec<BigUint> = selector
+                .iter()
+                .zip(&tuple.masks)
+                .map(|(s, m)| m.apply(s))
+                .collect();
+            if let Some(keys) = tuple.buckets.get(&probe) {
+                // Hashing covers every dimension except ranges, the full
+                // check settles those.
+                result.extend(
+                    keys.iter().filter(|k| keyset_matches(selector, k)),
+                );
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Typed table keys and action parameters.
+//!
+//! [`crate::Pipeline::add_table_entry`] takes keys and action parameters as
+//! raw byte buffers. The types here produce those buffers in the layout the
+//! `extract_*` functions of this crate expect, and are what the table entry
+//! builders generated for each table are written in terms of.
+//!
+//! - Values are little endian and occupy the byte width of their field.
+//!   Fields too wide for an integer are byte arrays, least significant byte
+//!   first.
+//! - Ternary keys are preceded by a care byte, zero meaning don't care.
+//! - Ranges are the low value followed by the high value.
+//! - Prefixes are address octets in network order followed by a length byte.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::error::ValueError;
+
+/// Whether the little endian value in `bytes` fits in `width` bits.
+pub fn fits(bytes: &[u8], width: usize) -> bool {
+    bytes.iter().enumerate().all(|(i, b)| {
+        let used = width.saturating_sub(i << 3);
+        used >= 8 || b >> used == 0
+    })
+}
+
+/// Write the little endian value in `bytes` as a field of `width` bits,
+/// padding it with zeros to the byte width of the field.
+fn write_le(
+    bytes: &[u8],
+    width: usize,
+    data: &mut Vec<u8>,
+) -> Result<(), ValueError> {
+    if !fits(bytes, width) {
+        return Err(ValueError::TooWide { width });
+    }
+    let len = width.div_ceil(8);
+    data.extend(bytes.iter().copied().chain(std::iter::repeat(0)).take(len));
+    Ok(())
+}
+
+/// A value that may be written into keyset or parameter data.
+pub trait Value {
+    /// Write this value as a little endian field of `width` bits.
+    fn write(&self, width: usize, data: &mut Vec<u8>)
+        -> Result<(), ValueError>;
+}
+
+macro_rules! int_value {
+    ($($t:ty),*) => {
+        $(
+            impl Value for $t {
+                fn write(
+                    &self,
+                    width: usize,
+                    data: &mut Vec<u8>,
+                ) -> Result<(), ValueError> {
+                    write_le(&self.to_le_bytes(), width, data)
+                }
+            }
+        )*
+    };
+}
+
+int_value!(u8, u16, u32, u64, u128);
+
+impl Value for bool {
+    fn write(
+        &self,
+        width: usize,
+        data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        (*self as u8).write(width, data)
+    }
+}
+
+impl<const N: usize> Value for [u8; N] {
+    fn write(
+        &self,
+        width: usize,
+        data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        write_le(self, width, data)
+    }
+}
+
+/// An exact match key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exact<T>(pub T);
+
+impl<T: Value> Exact<T> {
+    pub fn write(
+        &self,
+        width: usize,
+        keyset_data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        self.0.write(width, keyset_data)
+    }
+}
+
+/// A range match key, matching values from the first bound through the second
+/// bound inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range<T>(pub T, pub T);
+
+impl<T: Value> Range<T> {
+    pub fn write(
+        &self,
+        width: usize,
+        keyset_data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        self.0.write(width, keyset_data)?;
+        self.1.write(width, keyset_data)
+    }
+}
+
+/// A ternary match key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ternary<T> {
+    DontCare,
+    Value(T),
+}
+
+impl<T: Value> Ternary<T> {
+    pub fn write(
+        &self,
+        width: usize,
+        keyset_data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        match self {
+            Self::DontCare => {
+                keyset_data
+                    .resize(keyset_data.len() + width.div_ceil(8) + 1, 0);
+                Ok(())
+            }
+            Self::Value(v) => {
+                keyset_data.push(1);
+                v.write(width, keyset_data)
+            }
+        }
+    }
+}
+
+/// An address a longest prefix match key is made of. Longest prefix match
+/// tables hold IPv4 and IPv6 addresses only.
+pub trait Address {
+    /// The address octets in network order.
+    fn octets(&self) -> Vec<u8>;
+}
+
+impl Address for Ipv4Addr {
+    fn octets(&self) -> Vec<u8> {
+        Ipv4Addr::octets(self).to_vec()
+    }
+}
+
+impl Address for Ipv6Addr {
+    fn octets(&self) -> Vec<u8> {
+        Ipv6Addr::octets(self).to_vec()
+    }
+}
+
+/// A longest prefix match key, an address and a prefix length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lpm<A>(pub A, pub u8);
+
+impl<A: Address> Lpm<A> {
+    pub fn write(
+        &self,
+        width: usize,
+        keyset_data: &mut Vec<u8>,
+    ) -> Result<(), ValueError> {
+        let octets = self.0.octets();
+        if octets.len() << 3 != width {
+            return Err(ValueError::TooWide { width });
+        }
+        if usize::from(self.1) > width {
+            return Err(ValueError::PrefixTooLong { width, len: self.1 });
+        }
+        keyset_data.extend_from_slice(&octets);
+        keyset_data.push(self.1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table;
+
+    #[test]
+    fn keys_round_trip() {
+        let mut data = Vec::new();
+        Exact(0x1701u16).write(16, &mut data).unwrap();
+        Ternary::Value(47u8).write(8, &mut data).unwrap();
+        Ternary::<u8>::DontCare.write(8, &mut data).unwrap();
+        Range(80u32, 443u32).write(32, &mut data).unwrap();
+        [1u8, 2, 3].write(24, &mut data).unwrap();
+        assert_eq!(
+            crate::extract_exact_key(&data, 0, 2),
+            crate::extract_exact_key(&[0x01, 0x17], 0, 2),
+        );
+        assert_eq!(
+            crate::extract_ternary_key(&data, 2, 1),
+            table::Key::Ternary(table::Ternary::Value(table::BigUintKey {
+                value: 47u8.into(),
+                width: 1,
+            })),
+        );
+        assert_eq!(
+            crate::extract_ternary_key(&data, 4, 1),
+            table::Key::Ternary(table::Ternary::DontCare),
+        );
+        assert_eq!(
+            crate::extract_range_key(&data, 6, 4),
+            table::Key::Range(
+                table::BigUintKey {
+                    value: 80u32.into(),
+                    width: 4,
+                },
+                table::BigUintKey {
+                    value: 443u32.into(),
+                    width: 4,
+                },
+            ),
+        );
+
+        assert_eq!(&data[14..], [1, 2, 3]);
+
+        let addr: Ipv6Addr = "fd00:1701::".parse().unwrap();
+        let mut data = Vec::new();
+        Lpm(addr, 32).write(128, &mut data).unwrap();
+        assert_eq!(
+            crate::extract_lpm_key(&data, 0, 16),
+            Some(table::Key::Lpm(table::Prefix {
+                addr: addr.into(),
+                len: 32,
+            })),
+        );
+
+        let addr = Ipv4Addr::new(10, 0, 0, 0);
+        let mut data = Vec::new();
+        Lpm(addr, 8).write(32, &mut data).unwrap();
+        assert_eq!(
+            crate::extract_lpm_key(&data, 0, 4),
+            Some(table::Key::Lpm(table::Prefix {
+                addr: addr.into(),
+                len: 8,
+            })),
+        );
+    }
+
+    #[test]
+    fn values_checked_against_width() {
+        let mut data = Vec::new();
+        assert_eq!(
+            Exact(0xffffu16).write(12, &mut data),
+            Err(ValueError::TooWide { width: 12 }),
+        );
+        assert_eq!(
+            [0u8, 0x10].write(12, &mut data),
+            Err(ValueError::TooWide { width: 12 }),
+        );
+        assert_eq!(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 40).write(32, &mut data),
+            Err(ValueError::PrefixTooLong { width: 32, len: 40 }),
+        );
+        assert_eq!(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 8).write(128, &mut data),
+            Err(ValueError::TooWide { width: 128 }),
+        );
+        assert!(data.is_empty());
+
+        // narrower values are padded to the width of the field
+        Exact(0xfffu16).write(12, &mut data).unwrap();
+        Exact(7u8).write(24, &mut data).unwrap();
+        assert_eq!(data, [0xff, 0x0f, 7, 0, 0]);
+    }
+}
@@ -9,7 +9,7 @@
 //! interfaces for table manipulation and packet i/o.
 //!
 //! ```rust
-//! use p4rs::{ packet_in, packet_out, Pipeline };
+//! use p4rs::{ packet_in, packet_out, Pipeline, TableUpdateError };
 //! use std::net::Ipv6Addr;
 //!
 //! struct Handler {
@@ -40,14 +40,18 @@
 //!
 //!     /// Add a routing table entry. Packets for the provided destination will
 //!     /// be sent out the specified port.
-//!     fn add_router_entry(&mut self, dest: Ipv6Addr, port: u16) {
+//!     fn add_router_entry(
+//!         &mut self,
+//!         dest: Ipv6Addr,
+//!         port: u16,
+//!     ) -> Result<(), TableUpdateError> {
 //!         self.pipe.add_table_entry(
 //!             "ingress.router.ipv6_routes", // qualified name of the table
 //!             "forward_out_port",           // action to invoke on a hit
 //!             &dest.octets(),
 //!             &port.to_le_bytes(),
 //!             0,
-//!         );
+//!         )
 //!     }
 //!
 //!     /// Send a packet out the specified port.
@@ -60,20 +64,26 @@
 #![allow(incomplete_features)]
 #![allow(non_camel_case_types)]
 
+use std::borrow::Cow;
 use std::fmt;
 use std::net::IpAddr;
 
-pub use error::TryFromSliceError;
+pub use error::{
+    ExtractError, PacketTooShort, TableUpdateError, TryFromSliceError,
+    ValueError,
+};
 use serde::{Deserialize, Serialize};
 
 use bitvec::prelude::*;
 
 pub mod error;
 //pub mod hicuts;
-//pub mod rice;
 pub mod bitmath;
 pub mod checksum;
 pub mod externs;
+pub mod index;
+pub mod key;
+pub mod rice;
 pub mod table;
 
 #[usdt::provider]
@@ -140,12 +150,71 @@ pub struct packet_in<'a> {
     /// Only data after `index` is eligble for extraction. Extraction is always
     /// for contiguous segments of the underlying packet ring data.
     pub index: usize,
+
+    /// The error a parser recorded when rejecting this packet, identified by
+    /// the name of the P4 error.
+    pub error: Option<&'static str>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct packet_out<'a> {
     pub header_data: Vec<u8>,
     pub payload_data: &'a [u8],
+
+    /// Headers a deparser has emitted, in emission order, that are yet to be
+    /// assembled into `header_data`.
+    emitted: BitVec<u8, Msb0>,
+}
+
+impl<'a> packet_out<'a> {
+    /// Assemble an outgoing packet from the emitted `headers` followed by
+    /// the packet `data` from bit `payload` onward. When either the headers
+    /// or the payload do not fall on a byte boundary, the payload is shifted
+    /// into the header data and the packet is padded out to a whole byte.
+    pub fn new(
+        mut headers: BitVec<u8, Msb0>,
+        data: &'a [u8],
+        payload: usize,
+    ) -> Self {
+        if headers.len().is_multiple_of(8) && payload.is_multiple_of(8) {
+            return Self {
+                header_data: headers.into_vec(),
+                payload_data: &data[payload >> 3..],
+                emitted: BitVec::new(),
+            };
+        }
+        headers.extend_from_bitslice(&data.view_bits::<Msb0>()[payload..]);
+        headers.resize(headers.len().div_ceil(8) << 3, false);
+        Self {
+            header_data: headers.into_vec(),
+            payload_data: &[],
+            emitted: BitVec::new(),
+        }
+    }
+
+    /// Append a header to the headers emitted so far. The compiler only emits
+    /// valid headers, this is the P4 `emit` method.
+    pub fn emit(&mut self, header: BitVec<u8, Msb0>) {
+        self.emitted.extend_from_bitslice(&header);
+    }
+
+    /// Copy the payload in behind the header data, so the packet no longer
+    /// borrows from the packet it was received in.
+    pub fn into_owned(self) -> packet_out<'static> {
+        let mut header_data = self.header_data;
+        header_data.extend_from_slice(self.payload_data);
+        packet_out {
+            header_data,
+            payload_data: &[],
+            emitted: self.emitted,
+        }
+    }
+
+    /// Assemble the emitted headers with the packet `data` from bit `payload`
+    /// onward, as in [`packet_out::new`].
+    pub fn assemble(self, data: &'a [u8], payload: usize) -> Self {
+        Self::new(self.emitted, data, payload)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -155,6 +224,46 @@ pub struct TableEntry {
     pub parameter_data: Vec<u8>,
 }
 
+/// A change to a table, see [`Pipeline::apply_table_updates`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TableUpdate {
+    Add {
+        table_id: String,
+        action_id: String,
+        keyset_data: Vec<u8>,
+        parameter_data: Vec<u8>,
+        priority: u32,
+    },
+    Modify {
+        table_id: String,
+        action_id: String,
+        keyset_data: Vec<u8>,
+        parameter_data: Vec<u8>,
+    },
+    Remove {
+        table_id: String,
+        keyset_data: Vec<u8>,
+    },
+}
+
+impl TableUpdate {
+    pub fn table_id(&self) -> &str {
+        match self {
+            Self::Add { table_id, .. }
+            | Self::Modify { table_id, .. }
+            | Self::Remove { table_id, .. } => table_id,
+        }
+    }
+
+    pub fn keyset_data(&self) -> &[u8] {
+        match self {
+            Self::Add { keyset_data, .. }
+            | Self::Modify { keyset_data, .. }
+            | Self::Remove { keyset_data, .. } => keyset_data,
+        }
+    }
+}
+
 pub trait Pipeline: Send {
     /// Process an input packet and produce a set of output packets. Normally
     /// there will be a single output packet. However, if the pipeline sets
@@ -166,7 +275,8 @@ pub trait Pipeline: Send {
     ) -> Vec<(packet_out<'a>, u16)>;
 
     //TODO use struct TableEntry?
-    /// Add an entry to a table identified by table_id.
+    /// Add an entry to a table identified by table_id. This is a batch of
+    /// one for [`Pipeline::apply_table_updates`].
     fn add_table_entry(
         &mut self,
         table_id: &str,
@@ -174,22 +284,64 @@ pub trait Pipeline: Send {
         keyset_data: &[u8],
         parameter_data: &[u8],
         priority: u32,
-    );
+    ) -> Result<(), TableUpdateError>;
 
-    /// Remove an entry from a table identified by table_id.
-    fn remove_table_entry(&mut self, table_id: &str, keyset_data: &[u8]);
+    /// Remove an entry from a table identified by table_id. This is a batch
+    /// of one for [`Pipeline::apply_table_updates`].
+    fn remove_table_entry(
+        &mut self,
+        table_id: &str,
+        keyset_data: &[u8],
+    ) -> Result<(), TableUpdateError>;
+
+    /// Change the action of the entry with the given keyset in a table
+    /// identified by table_id. The entry keeps its key and priority.
+    fn modify_table_entry(
+        &mut self,
+        table_id: &str,
+        action_id: &str,
+        keyset_data: &[u8],
+        parameter_data: &[u8],
+    ) -> Result<(), TableUpdateError>;
+
+    /// Apply a batch of table updates in order. If any update fails, the
+    /// updates before it are rolled back and its error is returned, so
+    /// either the whole batch is applied or none of it is. Packets are never
+    /// processed against a partially applied batch.
+    fn apply_table_updates(
+        &mut self,
+        updates: &[TableUpdate],
+    ) -> Result<(), TableUpdateError>;
 
     /// Get all the entries in a table.
     fn get_table_entries(&self, table_id: &str) -> Option<Vec<TableEntry>>;
 
+    /// Set the action a table identified by table_id runs when no entry
+    /// matches. Tables with a const default action cannot be changed.
+    fn set_table_default_action(
+        &mut self,
+        table_id: &str,
+        action_id: &str,
+        parameter_data: &[u8],
+    ) -> Result<(), TableUpdateError>;
+
     /// Get a list of table ids
     fn get_table_ids(&self) -> Vec<&str>;
 }
 
-/// A fixed length header trait.
+/// A header trait. Headers may contain a variable length `varbit` field,
+/// so the size of a header is a property of each instance.
 pub trait Header {
     fn new() -> Self;
-    fn size() -> usize;
+    /// The size of this header in bits, including the current length of any
+    /// variable length field.
+    fn size(&self) -> usize;
+    /// The maximum length in bits of the variable length field of this
+    /// header, which is zero if the header has none.
+    fn max_variable_size(&self) -> usize;
+    /// Set the length in bits of the variable length field of this header, if
+    /// the header has one.
+    fn set_variable_size(&mut self, size: usize);
     fn set(&mut self, buf: &[u8]) -> Result<(), TryFromSliceError>;
     fn set_valid(&mut self);
     fn set_invalid(&mut self);
@@ -197,42 +349,316 @@ pub trait Header {
     fn to_bitvec(&self) -> BitVec<u8, Msb0>;
 }
 
+/// A fixed size stack of headers.
+///
+/// Elements are accessed by indexing the stack, by position or with
+/// [`StackNext`] and [`StackLast`]. Accesses out of range, such as `next` on a
+/// full stack or `last` on an empty one, are directed at a spare element
+/// instead. Writes to it have no effect on the stack and reads
+/// of it give an unspecified value, as P4 prescribes outside of parsers.
+#[derive(Debug, Clone)]
+pub struct HeaderStack<H: Header, const N: usize> {
+    pub elements: [H; N],
+    /// The index of the element the next extraction will populate.
+    pub next_index: usize,
+    spare: H,
+}
+
+impl<H: Header, const N: usize> Default for HeaderStack<H, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: Header, const N: usize> HeaderStack<H, N> {
+    pub fn new() -> Self {
+        Self {
+            elements: std::array::from_fn(|_| H::new()),
+            next_index: 0,
+            spare: H::new(),
+        }
+    }
+
+    /// Whether `next` refers to an element of the stack, that is the stack
+    /// is not full.
+    pub fn has_next(&self) -> bool {
+        self.next_index < N
+    }
+
+    /// Whether `last` refers to an element of the stack, that is the stack
+    /// is not empty.
+    pub fn has_last(&self) -> bool {
+        self.next_index > 0
+    }
+
+    /// Get the next element of the stack and advance past it. Returns `None`
+    /// when every element of the stack has already been used.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&mut H> {
+        if self.next_index >= N {
+            return None;
+        }
+        self.next_index += 1;
+        Some(&mut self.elements[self.next_index - 1])
+    }
+
+    /// The index of the most recently extracted element. This is out of
+    /// range for an empty stack.
+    pub fn last_index(&self) -> usize {
+        StackLast.position(self.next_index)
+    }
+
+    /// The P4 `lastIndex` value, which wraps around for an empty stack.
+    pub fn last_index_bits(&self) -> BitVec<u8, Msb0> {
+        let mut bv = bitvec![mut u8, Msb0; 0; 32];
+        bv.store_le((self.next_index as u32).wrapping_sub(1));
+        bv
+    }
+
+    /// The P4 `size` value of the stack.
+    pub fn size_bits(&self) -> BitVec<u8, Msb0> {
+        let mut bv = bitvec![mut u8, Msb0; 0; 32];
+        bv.store_le(N as u32);
+        bv
+    }
+
+    /// Shift elements towards the end of the stack by `count`. The elements
+    /// shifted in at the front are invalid.
+    pub fn push_front(&mut self, count: usize) {
+        let count = count.min(N);
+        self.elements.rotate_right(count);
+        for h in &mut self.elements[..count] {
+            *h = H::new();
+        }
+        self.next_index = (self.next_index + count).min(N);
+    }
+
+    /// Shift elements towards the front of the stack by `count`. The elements
+    /// shifted in at the end are invalid.
+    pub fn pop_front(&mut self, count: usize) {
+        let count = count.min(N);
+        self.elements.rotate_left(count);
+        for h in &mut self.elements[N - count..] {
+            *h = H::new();
+        }
+        self.next_index = self.next_index.saturating_sub(count);
+    }
+
+    /// Serialize the valid elements of the stack in order.
+    pub fn to_bitvec(&self) -> BitVec<u8, Msb0> {
+        let mut bv = BitVec::new();
+        for h in self.elements.iter().filter(|h| h.is_valid()) {
+            bv.extend_from_bitslice(&h.to_bitvec());
+        }
+        bv
+    }
+
+    /// The size in bits of the valid elements of the stack.
+    pub fn valid_size(&self) -> usize {
+        self.elements
+            .iter()
+            .filter(|h| h.is_valid())
+            .map(|h| h.size())
+            .sum()
+    }
+}
+
+/// Indexes the element of a [`HeaderStack`] P4 refers to as `next`.
+pub struct StackNext;
+
+/// Indexes the element of a [`HeaderStack`] P4 refers to as `last`.
+pub struct StackLast;
+
+/// A way of indexing a [`HeaderStack`], given the stack's next index.
+pub trait StackIndex {
+    fn position(&self, next_index: usize) -> usize;
+}
+
+impl StackIndex for usize {
+    fn position(&self, _next_index: usize) -> usize {
+        *self
+    }
+}
+
+impl StackIndex for StackNext {
+    fn position(&self, next_index: usize) -> usize {
+        next_index
+    }
+}
+
+impl StackIndex for StackLast {
+    fn position(&self, next_index: usize) -> usize {
+        next_index.wrapping_sub(1)
+    }
+}
+
+impl<H: Header, const N: usize, I: StackIndex> std::ops::Index<I>
+    for HeaderStack<H, N>
+{
+    type Output = H;
+
+    fn index(&self, index: I) -> &H {
+        let i = index.position(self.next_index);
+        self.elements.get(i).unwrap_or(&self.spare)
+    }
+}
+
+impl<H: Header, const N: usize, I: StackIndex> std::ops::IndexMut<I>
+    for HeaderStack<H, N>
+{
+    fn index_mut(&mut self, index: I) -> &mut H {
+        let i = index.position(self.next_index);
+        match self.elements.get_mut(i) {
+            Some(h) => h,
+            None => {
+                self.spare = H::new();
+                &mut self.spare
+            }
+        }
+    }
+}
+
 impl<'a> packet_in<'a> {
     pub fn new(data: &'a [u8]) -> Self {
-        Self { data, index: 0 }
-    }
-
-    // TODO: this function signature is a bit unforunate in the sense that the
-    // p4 compiler generates call sites based on a p4 `packet_in` extern
-    // definition. But based on that definition, there is no way for the
-    // compiler to know that this function returns a result that needs to be
-    // interrogated. In fact, the signature for packet_in::extract from the p4
-    // standard library requires the return type to be `void`, so this signature
-    // cannot return a result without the compiler having special knowledge of
-    // functions that happen to be called "extract".
-    pub fn extract<H: Header>(&mut self, h: &mut H) {
-        //TODO what if a header does not end on a byte boundary?
-        let n = H::size();
-        let start = if self.index > 0 { self.index >> 3 } else { 0 };
-        match h.set(&self.data[start..start + (n >> 3)]) {
-            Ok(_) => {}
-            Err(e) => {
-                //TODO better than this
-                println!("packet extraction failed: {}", e);
-            }
+        Self {
+            data,
+            index: 0,
+            error: None,
+        }
+    }
+
+    /// The number of bits left in the packet after the extraction index.
+    fn remaining(&self) -> usize {
+        (self.data.len() << 3).saturating_sub(self.index)
+    }
+
+    /// The `n` bits of the packet following the extraction index, starting
+    /// on a byte boundary. When the extraction index falls within a byte the
+    /// bits are copied out so that headers always see aligned input.
+    fn header_data(&self, n: usize) -> Option<Cow<'a, [u8]>> {
+        if n > self.remaining() {
+            return None;
+        }
+        if self.index.is_multiple_of(8) {
+            let start = self.index >> 3;
+            return self.data.get(start..start + n.div_ceil(8)).map(Cow::from);
+        }
+        let mut buf = bitvec![u8, Msb0; 0; n];
+        buf.copy_from_bitslice(
+            &self.data.view_bits::<Msb0>()[self.index..self.index + n],
+        );
+        Some(Cow::from(buf.into_vec()))
+    }
+
+    /// Extract the next header in the packet into `h`. If the packet ends
+    /// before the header does, the header and the extraction index are left
+    /// untouched. The compiler turns an error into a parser reject with
+    /// `PacketTooShort` recorded as the parser error. Neither the header nor
+    /// the extraction index need be byte aligned.
+    pub fn extract<H: Header>(
+        &mut self,
+        h: &mut H,
+    ) -> Result<(), PacketTooShort> {
+        let n = h.size();
+        let too_short = PacketTooShort {
+            needed: n,
+            available: self.remaining(),
+        };
+        let buf = match self.header_data(n) {
+            Some(buf) => buf,
+            None => return Err(too_short),
+        };
+        if h.set(&buf).is_err() {
+            return Err(too_short);
         }
         self.index += n;
         h.set_valid();
+        Ok(())
+    }
+
+    /// Extract a header with a variable length field of `variable_size` bits.
+    /// This is the two argument form of the P4 `extract` method. A size larger
+    /// than the maximum width of the field is an invalid argument, and a size
+    /// larger than what is left of the packet leaves the header untouched.
+    pub fn extract_variable<H: Header>(
+        &mut self,
+        h: &mut H,
+        variable_size: usize,
+    ) -> Result<(), ExtractError> {
+        let max = h.max_variable_size();
+        if variable_size > max {
+            return Err(ExtractError::InvalidArgument {
+                size: variable_size,
+                max,
+            });
+        }
+        let available = self.remaining();
+        if variable_size > available {
+            return Err(ExtractError::PacketTooShort(PacketTooShort {
+                needed: variable_size,
+                available,
+            }));
+        }
+        h.set_variable_size(variable_size);
+        Ok(self.extract(h)?)
+    }
+
+    /// Return the header that the next extraction would produce, without
+    /// advancing the extraction index. If there is not enough data left in
+    /// the packet for the header, the header returned is invalid.
+    pub fn lookahead<H: Header>(&self) -> H {
+        let mut h = H::new();
+        if let Some(buf) = self.header_data(h.size()) {
+            if h.set(&buf).is_ok() {
+                h.set_valid();
+            }
+        }
+        h
+    }
+
+    /// Return the value of the next `n` bits of the packet, without advancing
+    /// the extraction index. Bits beyond the end of the packet read as zero.
+    pub fn lookahead_bits(&self, n: usize) -> BitVec<u8, Msb0> {
+        let bits = self.data.view_bits::<Msb0>();
+        let end = usize::min(self.index + n, bits.len());
+        let mut wire = bitvec![u8, Msb0; 0; n];
+        if self.index < end {
+            wire[..end - self.index].copy_from_bitslice(&bits[self.index..end]);
+        }
+        field_from_wire(&wire)
+    }
+
+    /// Skip over the next `bits` bits of the packet. Advancing past the end
+    /// of the packet leaves the extraction index untouched.
+    pub fn advance(&mut self, bits: usize) -> Result<(), PacketTooShort> {
+        let available = self.remaining();
+        if bits > available {
+            return Err(PacketTooShort {
+                needed: bits,
+                available,
+            });
+        }
+        self.index += bits;
+        Ok(())
+    }
+
+    /// The length of the packet in bytes.
+    pub fn length(&self) -> BitVec<u8, Msb0> {
+        let mut x = bitvec![mut u8, Msb0; 0; 32];
+        x.store_le(self.data.len() as u32);
+        x
     }
 
     // This is the same as extract except we return a new header instead of
     // modifying an existing one.
     pub fn extract_new<H: Header>(&mut self) -> Result<H, TryFromSliceError> {
-        let n = H::size();
-        let start = if self.index > 0 { self.index >> 3 } else { 0 };
-        self.index += n;
         let mut x = H::new();
-        x.set(&self.data[start..start + (n >> 3)])?;
+        let n = x.size();
+        match self.header_data(n) {
+            Some(buf) => x.set(&buf)?,
+            None => return Err(TryFromSliceError(n)),
+        }
+        self.index += n;
         Ok(x)
     }
 }
@@ -246,10 +672,23 @@ pub fn bitvec_to_biguint(bv: &BitVec<u8, Msb0>) -> table::BigUintKey {
     }
 }
 
-pub fn bitvec_to_ip6addr(bv: &BitVec<u8, Msb0>) -> std::net::IpAddr {
-    let mut arr: [u8; 16] = bv.as_raw_slice().try_into().unwrap();
+/// The low `N` bytes of a little endian value in network order. Values
+/// narrower than `N` bytes are zero extended.
+fn bitvec_to_octets<const N: usize>(bv: &BitVec<u8, Msb0>) -> [u8; N] {
+    let mut arr = [0u8; N];
+    for (a, b) in arr.iter_mut().zip(bv.as_raw_slice()) {
+        *a = *b;
+    }
     arr.reverse();
-    std::net::IpAddr::V6(std::net::Ipv6Addr::from(arr))
+    arr
+}
+
+pub fn bitvec_to_ip4addr(bv: &BitVec<u8, Msb0>) -> std::net::IpAddr {
+    std::net::IpAddr::V4(std::net::Ipv4Addr::from(bitvec_to_octets(bv)))
+}
+
+pub fn bitvec_to_ip6addr(bv: &BitVec<u8, Msb0>) -> std::net::IpAddr {
+    std::net::IpAddr::V6(std::net::Ipv6Addr::from(bitvec_to_octets(bv)))
 }
 
 #[repr(C, align(16))]
@@ -267,6 +706,36 @@ pub fn bitvec_to_bitvec16(mut x: BitVec<u8, Msb0>) -> BitVec<u8, Msb0> {
     x
 }
 
+/// Read a field from its wire representation, most significant bit first,
+/// into the little endian layout that values are computed on. The field may
+/// be any width and `wire` may start at any bit offset.
+pub fn field_from_wire(wire: &BitSlice<u8, Msb0>) -> BitVec<u8, Msb0> {
+    let n = wire.len();
+    let mut x = bitvec![u8, Msb0; 0; n];
+    for (i, chunk) in x.chunks_mut(8).enumerate() {
+        let hi = n - (i << 3);
+        let lo = hi - chunk.len();
+        chunk.store_be(wire[lo..hi].load_be::<u8>());
+    }
+    x
+}
+
+/// Write a field in the little endian layout values are computed on to its
+/// wire representation, most significant bit first. This is the inverse of
+/// [`field_from_wire`]. Bits of `x` that do not fit in `wire` are dropped.
+pub fn field_to_wire(x: &BitSlice<u8, Msb0>, wire: &mut BitSlice<u8, Msb0>) {
+    let n = wire.len();
+    for (i, chunk) in x.chunks(8).enumerate() {
+        let hi = match n.checked_sub(i << 3) {
+            Some(0) | None => break,
+            Some(hi) => hi,
+        };
+        let lo = hi.saturating_sub(chunk.len());
+        let len = hi - lo;
+        wire[lo..hi].store_be(chunk[chunk.len() - len..].load_be::<u8>());
+    }
+}
+
 pub fn dump_bv(x: &BitVec<u8, Msb0>) -> String {
     if x.is_empty() {
         "∅".into()
@@ -331,30 +800,29 @@ pub fn extract_ternary_key(
     }
 }
 
+/// Extract a longest prefix match key from the provided keyset data, an
+/// address of `len` bytes followed by a prefix length. Returns `None` if the
+/// address is not an IPv4 or IPv6 address, or the prefix is longer than the
+/// address.
 pub fn extract_lpm_key(
     keyset_data: &[u8],
     offset: usize,
-    _len: usize,
-) -> table::Key {
-    let (addr, len) = match keyset_data.len() {
-        // IPv4
-        5 => {
-            let data: [u8; 4] =
-                keyset_data[offset..offset + 4].try_into().unwrap();
-            (IpAddr::from(data), keyset_data[offset + 4])
-        }
-        // IPv6
-        17 => {
-            let data: [u8; 16] =
-                keyset_data[offset..offset + 16].try_into().unwrap();
-            (IpAddr::from(data), keyset_data[offset + 16])
-        }
-        x => {
-            panic!("lpm: key must be len 5 (ipv4) or 17 (ipv6) found {}", x);
-        }
+    len: usize,
+) -> Option<table::Key> {
+    let data = &keyset_data[offset..offset + len];
+    let addr = match len {
+        4 => IpAddr::from(<[u8; 4]>::try_from(data).unwrap()),
+        16 => IpAddr::from(<[u8; 16]>::try_from(data).unwrap()),
+        _ => return None,
     };
-
-    table::Key::Lpm(table::Prefix { addr, len })
+    let prefix_len = keyset_data[offset + len];
+    if usize::from(prefix_len) > len << 3 {
+        return None;
+    }
+    Some(table::Key::Lpm(table::Prefix {
+        addr,
+        len: prefix_len,
+    }))
 }
 
 pub fn extract_bool_action_parameter(
@@ -370,7 +838,7 @@ pub fn extract_bit_action_parameter(
     size: usize,
 ) -> BitVec<u8, Msb0> {
     let mut byte_size = size >> 3;
-    if size % 8 != 0 {
+    if !size.is_multiple_of(8) {
         byte_size += 1;
     }
     let mut b: BitVec<u8, Msb0> =
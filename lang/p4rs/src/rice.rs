@@ -115,6 +115,7 @@
 //! The don't care value is what makes these entries ternary matches instead of
 //! exact matches. The don't care values are wildcards and match anything.
 //!
+//! ```text
 //! - ([true, true], [0, 0xffff], [false, true]) @(d0)
 //! |
 //! | - ([true, tue], [0, 2], [false, true]) @(d0, d1)
@@ -141,6 +142,7 @@
 //! | |  
 //! @ @ @
 //! 0 1 2
+//! ```
 //!
 //! In general 1 decision is needed for each field, in the above case we only
 //! have one bifurcation point for the ingress port because there is one entry.
@@ -163,6 +165,7 @@
 //!
 //! Without any additional splitting we have
 //!
+//! ```text
 //! - ([true, true], [0, 0xffff], [false, true]) @(d0)
 //! |
 //! | - ([true, tue], [0, 2], [false, true]) @(d0, d1)
@@ -201,6 +204,7 @@
 //! | |  
 //! @ @ @
 //! 0 1 2
+//! ```
 //!
 //! This requires linear iteration of up to 7 entries. In reality this small
 //! number is fine. But let's say our table size jumps up 2 orders of magnitued,
@@ -213,6 +217,7 @@
 //! 33, and 47. We have 6 entries so the logical place to split is at 7. So if
 //! we split at 7 instead of 2, and we have a look at our tree we see
 //!
+//! ```text
 //! - ([true, true], [0, 0xffff], [false, true]) @(d0)
 //! |
 //! | - ([true, tue], [0, 7], [false, true]) @(d0, d1)
@@ -249,12 +254,14 @@
 //! | |  
 //! @ @ @
 //! 0 1 2
+//! ```
 //!
 //! If we want to have a smaller linear search, we need to partition the space
 //! more than once. It's a trivial difference here, but for larger tables can
 //! become critical. Consider splitting the space evenly twice, once at 5 and
 //! once at 20 which gives us 3 even partitions of [2,4], [7, 19] and [33,47].
 //!
+//! ```text
 //! - ([true, true], [0, 0xffff], [false, true]) @(d0)
 //! |
 //! | - ([true, tue], [0, 5], [false, true]) @(d0, d1)
@@ -301,6 +308,7 @@
 //! | |  
 //! @ @ @
 //! 0 1 2
+//! ```
 //!
 //! In the description above, we repartitioned a particular dimension of the
 //! tree and reconstructed the whole thing for presentation purposes. Rice
@@ -321,8 +329,9 @@
 //!
 //! ### Range Matching
 //!
-//! TODO: We don't actually need range matching right now, so shelving this for
-//! later.
+//! A range is already an interval of the keyspace along its dimension, so it
+//! is cut the same way ternary values are: at the boundaries of the ranges
+//! resident in a leaf.
 //!
 //! ### Combined Matching
 //!
@@ -376,12 +385,15 @@
 //!
 //! #### Insert a0
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - a0
+//! ```
 //!
 //! #### Insert a1
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - a0
@@ -389,9 +401,11 @@
 //! - (_, true, _, false)
 //! |
 //! | - a1
+//! ```
 //!
 //! #### Insert a2
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - a0
@@ -401,9 +415,11 @@
 //! |
 //! | - a1
 //! | - a2
+//! ```
 //!
 //! #### Insert a3
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - a0
@@ -415,6 +431,7 @@
 //! | - a1
 //! | - a2
 //! | - a3
+//! ```
 //!
 //! #### Insert a4
 //!
@@ -426,15 +443,16 @@
 //!
 //! The list of values we have so far are [2, 4], so let's split at 3.
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a3
 //! | | - a4
@@ -451,6 +469,7 @@
 //! | | - a1
 //! | | - a3
 //! | | - a4
+//! ```
 //!
 //! #### Insert a5
 //!
@@ -458,15 +477,16 @@
 //! leaf node to split currently spans [4, 0xffff] and contains the values 4 and
 //! 7 in the ingress port dimension. Let's split at 8
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -497,20 +517,22 @@
 //! |   |
 //! |   | - a1
 //! |   | - a5
+//! ```
 //!
 //! #### Insert a6
 //!
 //! No splits needed, huzza!
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -543,21 +565,23 @@
 //! |   | - a1
 //! |   | - a5
 //! |   | - a6
+//! ```
 //!
 //! #### Insert a7
-//! 
+//!
 //! Need to split the range [9, 0xffff], we hve 19 and 33 as resident values,
 //! let's split at 34.
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -604,6 +628,7 @@
 //! |     |
 //! |     | - a1
 //! |     | - a7
+//! ```
 //!
 //! Alrighty now it's time to insert some prefixes.
 //!
@@ -615,16 +640,17 @@
 //! is inserted (in the next step). The a22 action is wrapped in angle brackets
 //! below to indicate this is a lpm match kind.
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! | | - <a22>
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -678,6 +704,7 @@
 //! |     | - a1
 //! |     | - a7
 //! |     | - <a22>
+//! ```
 //!
 //! #### Insert a8
 //!
@@ -686,17 +713,18 @@
 //! the prefix we are inserting contains that prefix. So we add a22 as a child
 //! to a8 and replace a22 in the leaf node with z8
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! | | - <a8>
 //! |   | - <a22>
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -756,6 +784,7 @@
 //! |     | - a7            H1
 //! |     | - <a8>
 //! |       | - <a22>       H2
+//! ```
 //!
 //! Let's take a moment to evalutate the problematic packet that matches two
 //! actions described above. This packet has a destination address of
@@ -799,10 +828,11 @@
 //! TODO this table is incomplete and wrong, generate it and then paste the
 //! result here....
 //!
+//! ```text
 //! - (_, true, _, true)
 //! |
 //! | - (_, [0, 3], _, true)
-//! | | 
+//! | |
 //! | | - a0
 //! | | - a2
 //! | | - (fd00:4700::/24, [0, 3], _, true)
@@ -815,7 +845,7 @@
 //! |   | - <a23>
 //! |
 //! | - (_, [4, 0xffff], _, true)
-//! | | 
+//! | |
 //! | | - (_, [4, 8], _, true)
 //! | | |
 //! | | | - a0
@@ -892,83 +922,445 @@
 //! |     | - a7            H1
 //! |     | - <a8>
 //! |       | - <a22>       H2
+//! ```
+
+//! ## Implementation
+//!
+//! Working through the examples above, every match kind boils down to the
+//! same thing: a rule covers an interval of the keyspace in each dimension.
+//!
+//! - Exact and ternary values cover a single point.
+//! - Ranges cover themselves.
+//! - Prefixes cover the addresses from their network address to their
+//!   broadcast address, so a containing prefix covers the intervals of the
+//!   prefixes it contains.
+//! - Masked ternary values are bounded below by their masked value and are
+//!   not bounded above, don't care values cover everything.
+//!
+//! Masked values are the only rules whose interval is wider than the set of
+//! selectors they match. This is fine, intervals only decide which leaves a
+//! rule lives in. Each rule resident in the leaf a selector lands in is fully
+//! checked with [`keyset_matches`], and ordering matches by prefix length and
+//! priority is left to the table.
+//!
+//! The tree is a binary decision tree. Internal nodes cut the keyspace of one
+//! dimension at a point, and rules are replicated into each side of the cut
+//! they overlap. When an insertion takes a leaf beyond the mtl, the leaf is
+//! cut at the rule boundary that most evenly partitions its rules. When a
+//! removal leaves a pair of sibling leaves that would fit into a single leaf,
+//! they are merged back together.
+
+use std::sync::Arc;
 
 use num::bigint::BigUint;
 
-#[derive(Debug, Clone)]
-pub struct Node<const D: usize> {
-    pub keys: [Key; D],
-    pub actions: Vec<Action<D>>,
-    pub nodes: Vec<Box<Node<D>>>,
-}
+use crate::table::{keyset_matches, Key, Ternary};
 
-impl<const D: usize> Node<D> {
-    pub fn insert(&mut self, action: Action<D>) {
+/// The default max ternary leaf size.
+pub const DEFAULT_MTL: usize = 8;
 
-        for (i, action_key) in action.keys.iter().enumerate() {
+/// An interval of the keyspace along one dimension. An interval with no upper
+/// bound covers everything from `lo` up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Interval {
+    pub lo: BigUint,
+    pub hi: Option<BigUint>,
+}
 
-            if action_key == &Key::Ternary(Ternary::DontCare) {
-                continue;
+impl Interval {
+    fn of(key: &Key) -> Self {
+        match key {
+            Key::Exact(x) | Key::Ternary(Ternary::Value(x)) => Self {
+                lo: x.value.clone(),
+                hi: Some(x.value.clone()),
+            },
+            Key::Range(lo, hi) => Self {
+                lo: lo.value.clone(),
+                hi: Some(hi.value.clone()),
+            },
+            Key::Ternary(Ternary::Masked(x, m, _)) => Self {
+                lo: x & m,
+                hi: None,
+            },
+            Key::Ternary(Ternary::DontCare) => Self::default(),
+            Key::Lpm(p) => {
+                let (addr, width) = match p.addr {
+                    std::net::IpAddr::V4(a) => (u32::from(a) as u128, 32),
+                    std::net::IpAddr::V6(a) => (u128::from(a), 128),
+                };
+                let host = u128::MAX
+                    .checked_shr(128 - width + p.len as u32)
+                    .unwrap_or(0);
+                Self {
+                    lo: (addr & !host).into(),
+                    hi: Some((addr | host).into()),
+                }
             }
+        }
+    }
 
-            
+    /// Returns true if the interval extends beyond `cut`.
+    fn above(&self, cut: &BigUint) -> bool {
+        self.hi.as_ref().is_none_or(|hi| hi > cut)
+    }
+}
 
-        }
+#[derive(Debug)]
+pub struct Rule<const D: usize> {
+    pub key: [Key; D],
+    pub bounds: [Interval; D],
+}
+
+impl<const D: usize> Rule<D> {
+    pub fn new(key: [Key; D]) -> Self {
+        let bounds = std::array::from_fn(|i| Interval::of(&key[i]));
+        Self { key, bounds }
     }
 }
 
+#[derive(Debug)]
+pub enum Node<const D: usize> {
+    Leaf(Vec<Arc<Rule<D>>>),
+    Internal {
+        /// The dimension this node cuts.
+        dim: usize,
+        /// The last point of the keyspace that belongs to the left subtree.
+        cut: BigUint,
+        left: Box<Node<D>>,
+        right: Box<Node<D>>,
+    },
+}
+
 impl<const D: usize> Default for Node<D> {
     fn default() -> Self {
-        Self{
-            keys: [(); D].map(|_| Key::default()),
-            actions: Vec::new(),
-            nodes: Vec::new(),
-        }
+        Self::Leaf(Vec::new())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Action<const D: usize> {
-    pub name: String,
-    pub keys: [Key; D]
+/// Split a region of the keyspace at `cut` in dimension `dim`.
+fn split<const D: usize>(
+    region: &[Interval; D],
+    dim: usize,
+    cut: &BigUint,
+) -> ([Interval; D], [Interval; D]) {
+    let mut left = region.clone();
+    let mut right = region.clone();
+    left[dim].hi = Some(cut.clone());
+    right[dim].lo = cut + 1u8;
+    (left, right)
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Key {
-    Exact(BigUint),
-    Range(BigUint, BigUint),
-    Ternary(Ternary),
-}
+impl<const D: usize> Node<D> {
+    /// Build a subtree covering `region` that holds `rules`, cutting until
+    /// each leaf holds no more than `mtl` rules or cannot be cut further.
+    fn build(
+        region: &[Interval; D],
+        rules: Vec<Arc<Rule<D>>>,
+        mtl: usize,
+    ) -> Self {
+        if rules.len() <= mtl {
+            return Self::Leaf(rules);
+        }
+        let Some((dim, cut)) = Self::choose_cut(region, &rules) else {
+            return Self::Leaf(rules);
+        };
+        let (left_region, right_region) = split(region, dim, &cut);
+        let (left, right): (Vec<_>, Vec<_>) = (
+            rules
+                .iter()
+                .filter(|r| r.bounds[dim].lo <= cut)
+                .cloned()
+                .collect(),
+            rules
+                .iter()
+                .filter(|r| r.bounds[dim].above(&cut))
+                .cloned()
+                .collect(),
+        );
+        Self::Internal {
+            dim,
+            cut,
+            left: Box::new(Self::build(&left_region, left, mtl)),
+            right: Box::new(Self::build(&right_region, right, mtl)),
+        }
+    }
 
-impl Default for Key {
-    fn default() -> Self {
-        Self::Ternary(Ternary::default())
+    /// Find the rule boundary that most evenly partitions a set of rules. Ties
+    /// go to the cut that replicates the fewest rules. Returns `None` if no
+    /// cut leaves both sides with fewer rules than there are now.
+    fn choose_cut(
+        region: &[Interval; D],
+        rules: &[Arc<Rule<D>>],
+    ) -> Option<(usize, BigUint)> {
+        let mut best: Option<((usize, usize), usize, BigUint)> = None;
+        for (dim, space) in region.iter().enumerate() {
+            let mut los: Vec<&BigUint> =
+                rules.iter().map(|r| &r.bounds[dim].lo).collect();
+            let mut his: Vec<&BigUint> = rules
+                .iter()
+                .filter_map(|r| r.bounds[dim].hi.as_ref())
+                .collect();
+            los.sort();
+            his.sort();
+
+            // Cutting just before a rule starts or right where a rule ends
+            // puts a rule boundary on the cut.
+            let candidates = los
+                .iter()
+                .filter(|lo| **lo > &space.lo)
+                .map(|lo| *lo - 1u8)
+                .chain(
+                    his.iter()
+                        .filter(|hi| space.above(hi) && ***hi >= space.lo)
+                        .map(|hi| (*hi).clone()),
+                );
+            for cut in candidates {
+                let left = los.partition_point(|lo| **lo <= cut);
+                let right = rules.len() - his.partition_point(|hi| **hi <= cut);
+                let score = (left.max(right), left + right);
+                if best.as_ref().is_none_or(|(s, _, _)| score < *s) {
+                    best = Some((score, dim, cut));
+                }
+            }
+        }
+        match best {
+            Some(((worst, _), dim, cut)) if worst < rules.len() => {
+                Some((dim, cut))
+            }
+            _ => None,
+        }
+    }
+
+    fn insert(
+        &mut self,
+        region: &[Interval; D],
+        rule: Arc<Rule<D>>,
+        mtl: usize,
+    ) {
+        match self {
+            Self::Internal {
+                dim,
+                cut,
+                left,
+                right,
+            } => {
+                let (left_region, right_region) = split(region, *dim, cut);
+                if rule.bounds[*dim].lo <= *cut {
+                    left.insert(&left_region, rule.clone(), mtl);
+                }
+                if rule.bounds[*dim].above(cut) {
+                    right.insert(&right_region, rule, mtl);
+                }
+            }
+            Self::Leaf(rules) => {
+                rules.push(rule);
+                if rules.len() > mtl {
+                    let rules = std::mem::take(rules);
+                    *self = Self::build(region, rules, mtl);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, rule: &Rule<D>, mtl: usize) -> bool {
+        match self {
+            Self::Internal {
+                dim,
+                cut,
+                left,
+                right,
+            } => {
+                let mut removed = false;
+                if rule.bounds[*dim].lo <= *cut {
+                    removed |= left.remove(rule, mtl);
+                }
+                if rule.bounds[*dim].above(cut) {
+                    removed |= right.remove(rule, mtl);
+                }
+                if let (Self::Leaf(l), Self::Leaf(r)) = (&**left, &**right) {
+                    let mut rules = l.clone();
+                    for x in r {
+                        if !l.iter().any(|y| Arc::ptr_eq(x, y)) {
+                            rules.push(x.clone());
+                        }
+                    }
+                    if rules.len() <= mtl {
+                        *self = Self::Leaf(rules);
+                    }
+                }
+                removed
+            }
+            Self::Leaf(rules) => {
+                match rules.iter().position(|r| r.key == rule.key) {
+                    Some(i) => {
+                        rules.swap_remove(i);
+                        true
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+
+    fn leaf(&self, selector: &[BigUint; D]) -> &[Arc<Rule<D>>] {
+        let mut node = self;
+        loop {
+            match node {
+                Self::Internal {
+                    dim,
+                    cut,
+                    left,
+                    right,
+                } => {
+                    node = if &selector[*dim] <= cut { left } else { right };
+                }
+                Self::Leaf(rules) => return rules,
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Ternary {
-    DontCare,
-    Value(BigUint),
-    Masked(BigUint, BigUint),
+/// A Rice decision tree over table keys.
+#[derive(Debug)]
+pub struct Rice<const D: usize> {
+    pub root: Node<D>,
+    /// Max ternary leaf, the number of rules a leaf may hold before Rice
+    /// tries to cut it.
+    pub mtl: usize,
 }
 
-impl Default for Ternary {
+impl<const D: usize> Default for Rice<D> {
     fn default() -> Self {
-        Self::DontCare
+        Self::new(DEFAULT_MTL)
+    }
+}
+
+impl<const D: usize> Rice<D> {
+    pub fn new(mtl: usize) -> Self {
+        Self {
+            root: Node::default(),
+            mtl,
+        }
+    }
+
+    /// Add a key to the tree. The key must not already be present.
+    pub fn insert(&mut self, key: [Key; D]) {
+        let region = std::array::from_fn(|_| Interval::default());
+        self.root
+            .insert(&region, Arc::new(Rule::new(key)), self.mtl);
+    }
+
+    /// Remove a key from the tree, returns true if the key was present.
+    pub fn remove(&mut self, key: &[Key; D]) -> bool {
+        self.root.remove(&Rule::new(key.clone()), self.mtl)
+    }
+
+    /// Find the keys that match a selector.
+    pub fn lookup(&self, selector: &[BigUint; D]) -> Vec<&[Key; D]> {
+        self.root
+            .leaf(selector)
+            .iter()
+            .map(|r| &r.key)
+            .filter(|k| keyset_matches(selector, k))
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::table::{BigUintKey, Prefix};
+
+    fn ternary(value: Option<u64>, width: usize) -> Key {
+        match value {
+            Some(value) => Key::Ternary(Ternary::Value(BigUintKey {
+                value: value.into(),
+                width,
+            })),
+            None => Key::Ternary(Ternary::DontCare),
+        }
+    }
+
+    fn depth<const D: usize>(node: &Node<D>) -> usize {
+        match node {
+            Node::Leaf(_) => 0,
+            Node::Internal { left, right, .. } => {
+                1 + depth(left).max(depth(right))
+            }
+        }
+    }
+
+    fn names<const D: usize>(
+        rice: &Rice<D>,
+        keys: &[[Key; D]],
+        selector: &[BigUint; D],
+    ) -> Vec<usize> {
+        let mut result: Vec<usize> = rice
+            .lookup(selector)
+            .into_iter()
+            .map(|k| keys.iter().position(|x| x == k).unwrap())
+            .collect();
+        result.sort();
+        result
+    }
 
     #[test]
+    /// The prefix, switch addr, ingress port, is icmp table from the module
+    /// documentation with a mtl of 3.
     fn block_comment_example() {
+        let mut keys: Vec<[Key; 4]> = Vec::new();
+        let any = || ternary(None, 1);
+        let v6 = |addr: &str, len: u8| {
+            Key::Lpm(Prefix {
+                addr: addr.parse().unwrap(),
+                len,
+            })
+        };
+        let dont_care = || v6("::", 0);
+        keys.push([
+            dont_care(),
+            ternary(Some(1), 1),
+            any(),
+            ternary(Some(1), 1),
+        ]);
+        keys.push([
+            dont_care(),
+            ternary(Some(1), 1),
+            any(),
+            ternary(Some(0), 1),
+        ]);
+        for port in [2, 4, 7, 19, 33, 47] {
+            keys.push([dont_care(), any(), ternary(Some(port), 2), any()]);
+        }
+        keys.push([v6("fd00:4700::", 24), any(), any(), any()]);
+        keys.push([v6("fd00:4702:0002:0002::", 64), any(), any(), any()]);
+        keys.push([v6("fd00:1701::", 32), any(), any(), any()]);
 
-        let n = Node::<4>::default();
-        println!("{:#?}", n);
+        let mut rice = Rice::<4>::new(3);
+        for k in &keys {
+            rice.insert(k.clone());
+        }
+        assert!(depth(&rice.root) > 0);
 
-    }
+        let addr: std::net::Ipv6Addr =
+            "fd00:4702:0002:0002::1".parse().unwrap();
+        let addr = BigUint::from(u128::from(addr));
+        let selector = [addr.clone(), 0u8.into(), 47u16.into(), 0u8.into()];
+        assert_eq!(names(&rice, &keys, &selector), vec![7, 8, 9]);
+
+        let selector = [addr, 1u8.into(), 4u16.into(), 1u8.into()];
+        assert_eq!(names(&rice, &keys, &selector), vec![0, 3, 8, 9]);
 
+        let addr: std::net::Ipv6Addr = "fd00:1701::1".parse().unwrap();
+        let selector =
+            [u128::from(addr).into(), 0u8.into(), 5u16.into(), 1u8.into()];
+        assert_eq!(names(&rice, &keys, &selector), vec![10]);
+
+        // Removing everything collapses the tree back into a single leaf.
+        for k in &keys {
+            assert!(rice.remove(k));
+        }
+        assert!(!rice.remove(&keys[0]));
+        assert!(matches!(&rice.root, Node::Leaf(rules) if rules.is_empty()));
+    }
 }
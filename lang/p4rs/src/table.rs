@@ -1,5 +1,6 @@
 // Copyright 2022 Oxide Computer Company
 
+use std::borrow::Borrow;
 use std::collections::HashSet;
 use std::fmt::Write;
 use std::net::IpAddr;
@@ -8,6 +9,8 @@ use num::bigint::BigUint;
 use num::ToPrimitive;
 use serde::{Deserialize, Serialize};
 
+use crate::index::TableIndex;
+
 #[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct BigUintKey {
     pub value: BigUint,
@@ -83,27 +86,61 @@ impl Key {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize, Default,
+)]
 pub enum Ternary {
+    #[default]
     DontCare,
     Value(BigUintKey),
     Masked(BigUint, BigUint, usize),
 }
 
-impl Default for Ternary {
-    fn default() -> Self {
-        Self::DontCare
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
 pub struct Prefix {
     pub addr: IpAddr,
     pub len: u8,
 }
 
+/// The structure a table uses to look up entries matching a selector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// An index picked based on the match kinds of the table's keys, see
+    /// [`crate::index`].
+    #[default]
+    MatchKind,
+    /// A [`crate::rice`] decision tree.
+    Rice,
+}
+
 pub struct Table<const D: usize, A: Clone> {
-    pub entries: HashSet<TableEntry<D, A>>,
+    entries: HashSet<TableEntry<D, A>>,
+    backend: Backend,
+    /// Lookup index over the keys in `entries`, see [`crate::index`].
+    index: TableIndex<D>,
+    /// The action run when no entry matches. `None` means no action.
+    pub default_action: Option<DefaultAction<A>>,
+}
+
+/// The action a table runs on a miss, along with the parameters it was bound
+/// with.
+#[derive(Clone)]
+pub struct DefaultAction<A: Clone> {
+    pub action: A,
+    pub action_id: &'static str,
+    pub parameter_data: Vec<u8>,
+}
+
+/// The result of applying a table, as seen by P4 programs through
+/// `t.apply()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyResult {
+    /// A table entry matched the selector.
+    pub hit: bool,
+    /// No table entry matched and the default action ran.
+    pub miss: bool,
+    /// The name of the action that ran.
+    pub action_run: &'static str,
 }
 
 impl<const D: usize, A: Clone> Default for Table<D, A> {
@@ -114,21 +151,86 @@ impl<const D: usize, A: Clone> Default for Table<D, A> {
 
 impl<const D: usize, A: Clone> Table<D, A> {
     pub fn new() -> Self {
+        Self::with_backend(Backend::default())
+    }
+
+    pub fn with_backend(backend: Backend) -> Self {
         Self {
             entries: HashSet::new(),
+            backend,
+            index: TableIndex::default(),
+            default_action: None,
         }
     }
 
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Switch the table to a different lookup backend, re-indexing any
+    /// entries already in the table.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+        for entry in std::mem::take(&mut self.entries) {
+            self.insert(entry);
+        }
+    }
+
+    pub fn entries(&self) -> &HashSet<TableEntry<D, A>> {
+        &self.entries
+    }
+
+    /// Add an entry to the table. Returns false, leaving the table unchanged,
+    /// if an entry with the same key is already present.
+    pub fn insert(&mut self, entry: TableEntry<D, A>) -> bool {
+        if self.entries.contains(&entry.key) {
+            return false;
+        }
+        if self.entries.is_empty() {
+            self.index = TableIndex::new(self.backend, &entry.key);
+        } else if !self.index.accepts(&entry.key) {
+            self.index =
+                TableIndex::classifier(self.entries.iter().map(|e| &e.key));
+        }
+        self.index.insert(entry.key.clone());
+        self.entries.insert(entry)
+    }
+
+    /// Remove the entry with the given key from the table, returning it if
+    /// it was present.
+    pub fn remove(&mut self, key: &[Key; D]) -> Option<TableEntry<D, A>> {
+        let entry = self.entries.take(key)?;
+        self.index.remove(&entry.key);
+        Some(entry)
+    }
+
+    /// Replace the action of the entry with the same key as `entry`, keeping
+    /// the priority of the existing entry. Returns the replaced entry, or
+    /// `None`, leaving the table unchanged, if there is no such entry.
+    pub fn modify(
+        &mut self,
+        entry: TableEntry<D, A>,
+    ) -> Option<TableEntry<D, A>> {
+        let old = self.entries.take(&entry.key)?;
+        self.entries.insert(TableEntry {
+            priority: old.priority,
+            name: old.name.clone(),
+            ..entry
+        });
+        Some(old)
+    }
+
     pub fn match_selector(
         &self,
         keyset: &[BigUint; D],
     ) -> Vec<TableEntry<D, A>> {
-        let mut result = Vec::new();
-        for entry in &self.entries {
-            if keyset_matches(keyset, &entry.key) {
-                result.push(entry.clone());
-            }
-        }
+        let result = self
+            .index
+            .lookup(keyset)
+            .into_iter()
+            .filter_map(|key| self.entries.get(key))
+            .cloned()
+            .collect();
         sort_entries(result)
     }
 
@@ -180,11 +282,10 @@ pub fn sort_entries<const D: usize, A: Clone>(
     entries
 }
 
-// TODO - the data structures here are quite dumb. The point of this table
-// implemntation is not efficiency, it's a simple bruteish apprach that is easy
-// to move around until we nail down the, tbh, rather undefined (in terms of p4
-// spec)semantics of what the relative priorities between match types in a
-// common keyset are.
+// The pruning and sorting here is kept separate from the lookup indexes so it
+// is easy to move around until we nail down the, tbh, rather undefined (in
+// terms of p4 spec) semantics of what the relative priorities between match
+// types in a common keyset are.
 pub fn prune_entries_by_lpm<const D: usize, A: Clone>(
     d: usize,
     entries: &Vec<TableEntry<D, A>>,
@@ -322,9 +423,11 @@ pub struct TableEntry<const D: usize, A: Clone> {
     pub priority: u32,
     pub name: String,
 
+    // the name of the action, reported as `action_run` in apply results.
+    pub action_id: &'static str,
+
     // the following are not used operationally, strictly for observability as
     // the closure contained in `A` is hard to get at.
-    pub action_id: String,
     pub parameter_data: Vec<u8>,
 }
 
@@ -353,6 +456,24 @@ impl<const D: usize, A: Clone> std::fmt::Debug for TableEntry<D, A> {
 
 impl<const D: usize, A: Clone> std::cmp::Eq for TableEntry<D, A> {}
 
+// Entries hash and compare on their key alone, which allows looking entries up
+// by key.
+impl<const D: usize, A: Clone> Borrow<[Key; D]> for TableEntry<D, A> {
+    fn borrow(&self) -> &[Key; D] {
+        &self.key
+    }
+}
+
+impl<const D: usize, A: Clone> FromIterator<TableEntry<D, A>> for Table<D, A> {
+    fn from_iter<I: IntoIterator<Item = TableEntry<D, A>>>(iter: I) -> Self {
+        let mut table = Self::new();
+        for entry in iter {
+            table.insert(entry);
+        }
+        table
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -388,7 +509,7 @@ mod tests {
             priority,
             name: name.into(),
             action: (),
-            action_id: String::new(),
+            action_id: "",
             parameter_data: Vec::new(),
         }
     }
@@ -409,96 +530,94 @@ mod tests {
     /// | a7     | _           | 47           | _       |
     /// +--------+-------------+--------------+---------+
     fn match_ternary_1() {
-        let table = Table::<3, ()> {
-            entries: HashSet::from([
-                tk(
-                    "a0",
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    10,
-                ),
-                tk(
-                    "a1",
-                    Ternary::Value(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    }),
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 0u8.into(),
-                        width: 1,
-                    }),
-                    1,
-                ),
-                tk(
-                    "a2",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 2u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a3",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 4u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a4",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 7u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a5",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 19u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a6",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 33u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-                tk(
-                    "a7",
-                    Ternary::DontCare,
-                    Ternary::Value(BigUintKey {
-                        value: 47u16.into(),
-                        width: 2,
-                    }),
-                    Ternary::DontCare,
-                    1,
-                ),
-            ]),
-        };
+        let table = Table::<3, ()>::from_iter([
+            tk(
+                "a0",
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                10,
+            ),
+            tk(
+                "a1",
+                Ternary::Value(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                }),
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 0u8.into(),
+                    width: 1,
+                }),
+                1,
+            ),
+            tk(
+                "a2",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 2u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a3",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 4u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a4",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 7u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a5",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 19u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a6",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 33u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+            tk(
+                "a7",
+                Ternary::DontCare,
+                Ternary::Value(BigUintKey {
+                    value: 47u16.into(),
+                    width: 2,
+                }),
+                Ternary::DontCare,
+                1,
+            ),
+        ]);
 
         //println!("M1 ~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~~");
         let selector =
@@ -527,7 +646,7 @@ mod tests {
             priority: 1,
             name: name.into(),
             action: (),
-            action_id: String::new(),
+            action_id: "",
             parameter_data: Vec::new(),
         }
     }
@@ -557,32 +676,22 @@ mod tests {
     /// +--------+----------------+---------+
     fn match_lpm_1() {
         let mut table = Table::<1, ()>::new();
-        table.entries.insert(lpm("a0", "fd00:4700::", 24));
-        table.entries.insert(lpm("a1", "fd00:4701::", 32));
-        table.entries.insert(lpm("a2", "fd00:4702::", 32));
-        table.entries.insert(lpm("a3", "fd00:4701:0001::", 48));
-        table.entries.insert(lpm("a4", "fd00:4701:0002::", 48));
-        table.entries.insert(lpm("a5", "fd00:4702:0001::", 48));
-        table.entries.insert(lpm("a6", "fd00:4702:0002::", 48));
-        table.entries.insert(lpm("a7", "fd00:4701:0001:0001::", 64));
-        table.entries.insert(lpm("a8", "fd00:4701:0001:0002::", 64));
-        table.entries.insert(lpm("a9", "fd00:4702:0001:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a10", "fd00:4702:0001:0002::", 64));
-        table
-            .entries
-            .insert(lpm("a11", "fd00:4701:0002:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a12", "fd00:4701:0002:0002::", 64));
-        table
-            .entries
-            .insert(lpm("a13", "fd00:4702:0002:0001::", 64));
-        table
-            .entries
-            .insert(lpm("a14", "fd00:4702:0002:0002::", 64));
-        table.entries.insert(lpm("a15", "fd00:1701::", 32));
+        table.insert(lpm("a0", "fd00:4700::", 24));
+        table.insert(lpm("a1", "fd00:4701::", 32));
+        table.insert(lpm("a2", "fd00:4702::", 32));
+        table.insert(lpm("a3", "fd00:4701:0001::", 48));
+        table.insert(lpm("a4", "fd00:4701:0002::", 48));
+        table.insert(lpm("a5", "fd00:4702:0001::", 48));
+        table.insert(lpm("a6", "fd00:4702:0002::", 48));
+        table.insert(lpm("a7", "fd00:4701:0001:0001::", 64));
+        table.insert(lpm("a8", "fd00:4701:0001:0002::", 64));
+        table.insert(lpm("a9", "fd00:4702:0001:0001::", 64));
+        table.insert(lpm("a10", "fd00:4702:0001:0002::", 64));
+        table.insert(lpm("a11", "fd00:4701:0002:0001::", 64));
+        table.insert(lpm("a12", "fd00:4701:0002:0002::", 64));
+        table.insert(lpm("a13", "fd00:4702:0002:0001::", 64));
+        table.insert(lpm("a14", "fd00:4702:0002:0002::", 64));
+        table.insert(lpm("a15", "fd00:1701::", 32));
 
         let addr: Ipv6Addr = "fd00:4700::1".parse().unwrap();
         let selector = [BigUint::from(u128::from_be_bytes(addr.octets()))];
@@ -625,48 +734,46 @@ mod tests {
             priority,
             name: name.into(),
             action: (),
-            action_id: String::new(),
+            action_id: "",
             parameter_data: Vec::new(),
         }
     }
 
     #[test]
     fn match_lpm_ternary_1() {
-        let table = Table::<2, ()> {
-            entries: HashSet::from([
-                tlpm("a0", "fd00:1::", 64, Ternary::DontCare, 1),
-                tlpm(
-                    "a1",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 1u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-                tlpm(
-                    "a2",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 2u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-                tlpm(
-                    "a3",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 3u16.into(),
-                        width: 2,
-                    }),
-                    10,
-                ),
-            ]),
-        };
+        let table = Table::<2, ()>::from_iter([
+            tlpm("a0", "fd00:1::", 64, Ternary::DontCare, 1),
+            tlpm(
+                "a1",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 1u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+            tlpm(
+                "a2",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 2u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+            tlpm(
+                "a3",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 3u16.into(),
+                    width: 2,
+                }),
+                10,
+            ),
+        ]);
 
         let dst: Ipv6Addr = "fd00:1::1".parse().unwrap();
         let selector = [
@@ -717,7 +824,7 @@ mod tests {
             priority,
             name: name.into(),
             action: (),
-            action_id: String::new(),
+            action_id: "",
             parameter_data: Vec::new(),
         }
     }
@@ -728,42 +835,24 @@ mod tests {
 
     #[test]
     fn match_lpm_ternary_range() {
-        let table = Table::<4, ()> {
-            entries: HashSet::from([
-                lpre("a0", "fd00:1::", 64, Ternary::DontCare, (80, 80), 100, 1),
-                lpre(
-                    "a1",
-                    "fd00:1::",
-                    64,
-                    Ternary::DontCare,
-                    (443, 443),
-                    100,
-                    1,
-                ),
-                lpre("a2", "fd00:1::", 64, Ternary::DontCare, (80, 80), 200, 1),
-                lpre(
-                    "a3",
-                    "fd00:1::",
-                    64,
-                    Ternary::DontCare,
-                    (443, 443),
-                    200,
-                    1,
-                ),
-                lpre(
-                    "a4",
-                    "fd00:1::",
-                    64,
-                    Ternary::Value(BigUintKey {
-                        value: 99u16.into(),
-                        width: 2,
-                    }),
-                    (443, 443),
-                    200,
-                    10,
-                ),
-            ]),
-        };
+        let table = Table::<4, ()>::from_iter([
+            lpre("a0", "fd00:1::", 64, Ternary::DontCare, (80, 80), 100, 1),
+            lpre("a1", "fd00:1::", 64, Ternary::DontCare, (443, 443), 100, 1),
+            lpre("a2", "fd00:1::", 64, Ternary::DontCare, (80, 80), 200, 1),
+            lpre("a3", "fd00:1::", 64, Ternary::DontCare, (443, 443), 200, 1),
+            lpre(
+                "a4",
+                "fd00:1::",
+                64,
+                Ternary::Value(BigUintKey {
+                    value: 99u16.into(),
+                    width: 2,
+                }),
+                (443, 443),
+                200,
+                10,
+            ),
+        ]);
         let dst: Ipv6Addr = "fd00:1::1".parse().unwrap();
         let selector = [
             BigUint::from(u128::from_le_bytes(dst.octets())),
@@ -806,36 +895,34 @@ mod tests {
     fn match_with_action() {
         let mut data = ActionData { value: 47 };
 
-        let table = Table::<1, Arc<dyn Fn(&mut ActionData)>> {
-            entries: HashSet::from([
-                TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
-                    key: [Key::Exact(BigUintKey {
-                        value: 1u8.into(),
-                        width: 1,
-                    })],
-                    priority: 0,
-                    name: "a0".into(),
-                    action: Arc::new(|a: &mut ActionData| {
-                        a.value += 10;
-                    }),
-                    action_id: String::new(),
-                    parameter_data: Vec::new(),
-                },
-                TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
-                    key: [Key::Exact(BigUintKey {
-                        value: 2u8.into(),
-                        width: 1,
-                    })],
-                    priority: 0,
-                    name: "a1".into(),
-                    action: Arc::new(|a: &mut ActionData| {
-                        a.value -= 10;
-                    }),
-                    action_id: String::new(),
-                    parameter_data: Vec::new(),
-                },
-            ]),
-        };
+        let table = Table::<1, Arc<dyn Fn(&mut ActionData)>>::from_iter([
+            TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
+                key: [Key::Exact(BigUintKey {
+                    value: 1u8.into(),
+                    width: 1,
+                })],
+                priority: 0,
+                name: "a0".into(),
+                action: Arc::new(|a: &mut ActionData| {
+                    a.value += 10;
+                }),
+                action_id: "",
+                parameter_data: Vec::new(),
+            },
+            TableEntry::<1, Arc<dyn Fn(&mut ActionData)>> {
+                key: [Key::Exact(BigUintKey {
+                    value: 2u8.into(),
+                    width: 1,
+                })],
+                priority: 0,
+                name: "a1".into(),
+                action: Arc::new(|a: &mut ActionData| {
+                    a.value -= 10;
+                }),
+                action_id: "",
+                parameter_data: Vec::new(),
+            },
+        ]);
 
         let selector = [BigUint::from(1u8)];
         let matches = table.match_selector(&selector);
@@ -844,4 +931,253 @@ mod tests {
         (matches[0].action)(&mut data);
         assert_eq!(data.value, 57);
     }
+
+    // A small xorshift generator so the index tests below are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn uk(value: u64, width: usize) -> BigUintKey {
+        BigUintKey {
+            value: value.into(),
+            width,
+        }
+    }
+
+    fn v4(addr: u32, len: u8) -> Key {
+        Key::Lpm(Prefix {
+            addr: IpAddr::V4(addr.into()),
+            len,
+        })
+    }
+
+    fn v6(addr: u128, len: u8) -> Key {
+        Key::Lpm(Prefix {
+            addr: IpAddr::V6(addr.into()),
+            len,
+        })
+    }
+
+    fn ternary(rng: &mut Rng) -> Key {
+        Key::Ternary(match rng.below(3) {
+            0 => Ternary::DontCare,
+            1 => Ternary::Value(uk(rng.below(4), 1)),
+            _ => Ternary::Masked(rng.below(4).into(), rng.below(4).into(), 1),
+        })
+    }
+
+    fn range(rng: &mut Rng) -> Key {
+        let low = rng.below(100);
+        Key::Range(uk(low, 1), uk(low + rng.below(20), 1))
+    }
+
+    // Randomly fill a table, removing some entries along the way, and check
+    // every indexed lookup against a scan of all entries.
+    fn check_against_scan<const D: usize>(
+        backend: Backend,
+        rng: &mut Rng,
+        key: impl Fn(&mut Rng) -> [Key; D],
+        selector: impl Fn(&mut Rng) -> [BigUint; D],
+    ) {
+        let mut table = Table::<D, ()>::with_backend(backend);
+        let mut keys = Vec::new();
+        for i in 0..500 {
+            let k = key(rng);
+            let priority = rng.below(4) as u32;
+            if table.insert(TableEntry {
+                key: k.clone(),
+                priority,
+                name: format!("e{}", i),
+                action: (),
+                action_id: "",
+                parameter_data: Vec::new(),
+            }) {
+                keys.push(k);
+            }
+            if i % 3 == 0 && !keys.is_empty() {
+                let k = keys.swap_remove(rng.below(keys.len() as u64) as usize);
+                assert!(table.remove(&k).is_some());
+            }
+        }
+        assert_eq!(table.entries().len(), keys.len());
+
+        for _ in 0..1000 {
+            let selector = selector(rng);
+            let scanned: Vec<TableEntry<D, ()>> = table
+                .entries()
+                .iter()
+                .filter(|e| keyset_matches(&selector, &e.key))
+                .cloned()
+                .collect();
+            let mut scanned: Vec<String> =
+                sort_entries(scanned).into_iter().map(|e| e.name).collect();
+            let mut matched: Vec<String> = table
+                .match_selector(&selector)
+                .into_iter()
+                .map(|e| e.name)
+                .collect();
+            scanned.sort();
+            matched.sort();
+            assert_eq!(matched, scanned, "selector {:?}", selector);
+        }
+
+        for k in &keys {
+            assert!(table.remove(k).is_some());
+        }
+        assert!(table.entries().is_empty());
+        assert!(table.match_selector(&selector(rng)).is_empty());
+    }
+
+    fn check_exact(backend: Backend) {
+        check_against_scan(
+            backend,
+            &mut Rng(47),
+            |rng| {
+                [
+                    Key::Exact(uk(rng.below(8), 1)),
+                    Key::Exact(uk(rng.below(8), 2)),
+                ]
+            },
+            |rng| [rng.below(9).into(), rng.below(9).into()],
+        );
+    }
+
+    fn check_lpm(backend: Backend) {
+        check_against_scan(
+            backend,
+            &mut Rng(47),
+            |rng| {
+                let addr = (rng.below(4) << 30 | rng.below(4) << 20) as u32;
+                let len = rng.below(33) as u8;
+                [v4(
+                    addr & !(u32::MAX.checked_shr(len as u32).unwrap_or(0)),
+                    len,
+                )]
+            },
+            |rng| {
+                let addr = rng.below(4) << 30 | rng.below(4) << 20;
+                [(addr | rng.below(1 << 20)).into()]
+            },
+        );
+        check_against_scan(
+            backend,
+            &mut Rng(1701),
+            |rng| {
+                let addr = (rng.below(4) as u128) << 126
+                    | (rng.below(16) as u128) << 100;
+                [v6(addr, rng.below(129) as u8)]
+            },
+            |rng| {
+                let addr = (rng.below(4) as u128) << 126
+                    | (rng.below(16) as u128) << 100
+                    | rng.below(u64::MAX) as u128;
+                [addr.into()]
+            },
+        );
+    }
+
+    fn check_range(backend: Backend) {
+        check_against_scan(
+            backend,
+            &mut Rng(47),
+            |rng| [range(rng)],
+            |rng| [rng.below(130).into()],
+        );
+    }
+
+    fn check_mixed(backend: Backend) {
+        check_against_scan(
+            backend,
+            &mut Rng(47),
+            |rng| {
+                let addr = (rng.below(4) << 30 | rng.below(4) << 20) as u32;
+                [v4(addr, rng.below(33) as u8), ternary(rng), range(rng)]
+            },
+            |rng| {
+                let addr = rng.below(4) << 30 | rng.below(4) << 20;
+                [
+                    (addr | rng.below(1 << 20)).into(),
+                    rng.below(4).into(),
+                    rng.below(130).into(),
+                ]
+            },
+        );
+    }
+
+    #[test]
+    fn exact_index_matches_scan() {
+        check_exact(Backend::MatchKind);
+    }
+
+    #[test]
+    fn lpm_index_matches_scan() {
+        check_lpm(Backend::MatchKind);
+    }
+
+    #[test]
+    fn range_index_matches_scan() {
+        check_range(Backend::MatchKind);
+    }
+
+    #[test]
+    fn classifier_matches_scan() {
+        check_mixed(Backend::MatchKind);
+    }
+
+    #[test]
+    fn rice_matches_scan() {
+        check_exact(Backend::Rice);
+        check_lpm(Backend::Rice);
+        check_range(Backend::Rice);
+        check_mixed(Backend::Rice);
+    }
+
+    #[test]
+    fn set_backend_reindexes() {
+        let mut table = Table::<1, ()>::new();
+        table.insert(lpm("a0", "fd00:4700::", 24));
+        table.insert(lpm("a1", "fd00:4701::", 32));
+        table.set_backend(Backend::Rice);
+        assert_eq!(table.backend(), Backend::Rice);
+        assert_eq!(table.entries().len(), 2);
+
+        let addr: Ipv6Addr = "fd00:4701::1".parse().unwrap();
+        let selector = [BigUint::from(u128::from_be_bytes(addr.octets()))];
+        let matches = table.match_selector(&selector);
+        assert_eq!(matches.len(), 1);
+        assert!(contains_entry(&matches, "a1"));
+    }
+
+    #[test]
+    fn modify_keeps_key_and_priority() {
+        let mut table = Table::<1, ()>::new();
+        table.insert(lpm("a0", "fd00:4700::", 24));
+
+        let mut update = lpm("", "fd00:4700::", 24);
+        update.priority = 7;
+        update.action_id = "forward";
+        update.parameter_data = vec![1, 2];
+        let old = table.modify(update).unwrap();
+        assert_eq!(old.name, "a0");
+        assert_eq!(old.action_id, "");
+
+        let addr: Ipv6Addr = "fd00:4700::1".parse().unwrap();
+        let selector = [BigUint::from(u128::from_be_bytes(addr.octets()))];
+        let matches = table.match_selector(&selector);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "a0");
+        assert_eq!(matches[0].priority, 1);
+        assert_eq!(matches[0].action_id, "forward");
+        assert_eq!(matches[0].parameter_data, vec![1, 2]);
+
+        assert!(table.modify(lpm("a1", "fd00:4701::", 32)).is_none());
+        assert_eq!(table.entries().len(), 1);
+    }
 }
@@ -11,18 +11,40 @@ use crate::lexer::Token;
 pub struct AST {
     pub constants: Vec<Constant>,
     pub headers: Vec<Header>,
+    pub header_unions: Vec<HeaderUnion>,
     pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub errors: Vec<ErrorMember>,
     pub typedefs: Vec<Typedef>,
     pub controls: Vec<Control>,
     pub parsers: Vec<Parser>,
     pub packages: Vec<Package>,
     pub package_instance: Option<PackageInstance>,
+    /// Package instances declared ahead of `package_instance`, which it may
+    /// be instantiated with.
+    pub package_instances: Vec<PackageInstance>,
     pub externs: Vec<Extern>,
-}
+    pub extern_functions: Vec<ExternMethod>,
+    pub functions: Vec<Function>,
+}
+
+/// The errors declared by the P4 core library. These are members of the error
+/// namespace of every program, whether or not it declares them.
+pub const CORE_ERRORS: &[&str] = &[
+    "NoError",
+    "PacketTooShort",
+    "NoMatch",
+    "StackOutOfBounds",
+    "HeaderTooShort",
+    "ParserTimeout",
+    "ParserInvalidArgument",
+];
 
 pub enum UserDefinedType<'a> {
     Struct(&'a Struct),
     Header(&'a Header),
+    HeaderUnion(&'a HeaderUnion),
+    Enum(&'a Enum),
     Extern(&'a Extern),
 }
 
@@ -35,10 +57,35 @@ impl AST {
         self.headers.iter().find(|&h| h.name == name)
     }
 
+    pub fn get_header_union(&self, name: &str) -> Option<&HeaderUnion> {
+        self.header_unions.iter().find(|&u| u.name == name)
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&Enum> {
+        self.enums.iter().find(|&e| e.name == name)
+    }
+
+    /// The names of all members of the error namespace. The core errors come
+    /// first, followed by the errors the program declares in the order they
+    /// are declared.
+    pub fn error_names(&self) -> Vec<&str> {
+        let mut names = CORE_ERRORS.to_vec();
+        for e in &self.errors {
+            if !names.contains(&e.name.as_str()) {
+                names.push(e.name.as_str());
+            }
+        }
+        names
+    }
+
     pub fn get_extern(&self, name: &str) -> Option<&Extern> {
         self.externs.iter().find(|&e| e.name == name)
     }
 
+    pub fn get_extern_function(&self, name: &str) -> Option<&ExternMethod> {
+        self.extern_functions.iter().find(|&f| f.name == name)
+    }
+
     pub fn get_control(&self, name: &str) -> Option<&Control> {
         self.controls.iter().find(|&c| c.name == name)
     }
@@ -47,13 +94,103 @@ impl AST {
         self.parsers.iter().find(|&p| p.name == name)
     }
 
-    pub fn get_user_defined_type(&self, name: &str) -> Option<UserDefinedType> {
+    pub fn get_constant(&self, name: &str) -> Option<&Constant> {
+        self.constants.iter().find(|&c| c.name == name)
+    }
+
+    pub fn get_package(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|&p| p.name == name)
+    }
+
+    pub fn get_package_instance(&self, name: &str) -> Option<&PackageInstance> {
+        self.package_instances.iter().find(|&p| p.name == name)
+    }
+
+    /// The blocks a package instance is instantiated with, with the blocks of
+    /// nested package instances in their place.
+    pub fn package_instance_blocks<'a>(
+        &'a self,
+        inst: &'a PackageInstance,
+    ) -> Vec<&'a str> {
+        let mut blocks = Vec::new();
+        for arg in &inst.parameters {
+            match self.get_package_instance(arg) {
+                Some(nested) => {
+                    blocks.extend(self.package_instance_blocks(nested))
+                }
+                None => blocks.push(arg.as_str()),
+            }
+        }
+        blocks
+    }
+
+    /// Replace every use of a typedef name with the type it aliases.
+    pub fn resolve_typedefs(&mut self) {
+        let mut r = TypedefResolver {
+            types: HashMap::new(),
+        };
+        for t in &self.typedefs {
+            let mut ty = t.ty.clone();
+            ty.mut_accept(&r);
+            r.types.insert(t.name.clone(), ty);
+        }
+        self.mut_accept(&r);
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|&f| f.name == name)
+    }
+
+    /// Names declared at the top level that are visible from within controls
+    /// and parsers.
+    pub fn names(&self) -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        for c in &self.constants {
+            names.insert(
+                c.name.clone(),
+                NameInfo {
+                    ty: c.ty.clone(),
+                    decl: DeclarationInfo::Constant,
+                },
+            );
+        }
+        for f in &self.functions {
+            names.insert(
+                f.name.clone(),
+                NameInfo {
+                    ty: f.return_type.clone(),
+                    decl: DeclarationInfo::Function,
+                },
+            );
+        }
+        for f in &self.extern_functions {
+            names.insert(
+                f.name.clone(),
+                NameInfo {
+                    ty: Type::ExternFunction,
+                    decl: DeclarationInfo::Method,
+                },
+            );
+        }
+        names
+    }
+
+    pub fn get_user_defined_type(
+        &self,
+        name: &str,
+    ) -> Option<UserDefinedType<'_>> {
         if let Some(user_struct) = self.get_struct(name) {
             return Some(UserDefinedType::Struct(user_struct));
         }
         if let Some(user_header) = self.get_header(name) {
             return Some(UserDefinedType::Header(user_header));
         }
+        if let Some(user_union) = self.get_header_union(name) {
+            return Some(UserDefinedType::HeaderUnion(user_union));
+        }
+        if let Some(user_enum) = self.get_enum(name) {
+            return Some(UserDefinedType::Enum(user_enum));
+        }
         if let Some(platform_extern) = self.get_extern(name) {
             return Some(UserDefinedType::Extern(platform_extern));
         }
@@ -67,9 +204,15 @@ impl AST {
         for h in &self.headers {
             h.accept(v);
         }
+        for u in &self.header_unions {
+            u.accept(v);
+        }
         for s in &self.structs {
             s.accept(v);
         }
+        for e in &self.enums {
+            e.accept(v);
+        }
         for t in &self.typedefs {
             t.accept(v);
         }
@@ -85,6 +228,15 @@ impl AST {
         for e in &self.externs {
             e.accept(v);
         }
+        for f in &self.extern_functions {
+            f.accept(v);
+        }
+        for f in &self.functions {
+            f.accept(v);
+        }
+        for p in &self.package_instances {
+            p.accept(v);
+        }
         if let Some(p) = &self.package_instance {
             p.accept(v);
         }
@@ -97,9 +249,15 @@ impl AST {
         for h in &self.headers {
             h.accept_mut(v);
         }
+        for u in &self.header_unions {
+            u.accept_mut(v);
+        }
         for s in &self.structs {
             s.accept_mut(v);
         }
+        for e in &self.enums {
+            e.accept_mut(v);
+        }
         for t in &self.typedefs {
             t.accept_mut(v);
         }
@@ -115,6 +273,15 @@ impl AST {
         for e in &self.externs {
             e.accept_mut(v);
         }
+        for f in &self.extern_functions {
+            f.accept_mut(v);
+        }
+        for f in &self.functions {
+            f.accept_mut(v);
+        }
+        for p in &self.package_instances {
+            p.accept_mut(v);
+        }
         if let Some(p) = &self.package_instance {
             p.accept_mut(v);
         }
@@ -127,9 +294,15 @@ impl AST {
         for h in &mut self.headers {
             h.mut_accept(v);
         }
+        for u in &mut self.header_unions {
+            u.mut_accept(v);
+        }
         for s in &mut self.structs {
             s.mut_accept(v);
         }
+        for e in &mut self.enums {
+            e.mut_accept(v);
+        }
         for t in &mut self.typedefs {
             t.mut_accept(v);
         }
@@ -145,6 +318,15 @@ impl AST {
         for e in &mut self.externs {
             e.mut_accept(v);
         }
+        for f in &mut self.extern_functions {
+            f.mut_accept(v);
+        }
+        for f in &mut self.functions {
+            f.mut_accept(v);
+        }
+        for p in &mut self.package_instances {
+            p.mut_accept(v);
+        }
         if let Some(p) = &mut self.package_instance {
             p.mut_accept(v);
         }
@@ -157,9 +339,15 @@ impl AST {
         for h in &mut self.headers {
             h.mut_accept_mut(v);
         }
+        for u in &mut self.header_unions {
+            u.mut_accept_mut(v);
+        }
         for s in &mut self.structs {
             s.mut_accept_mut(v);
         }
+        for e in &mut self.enums {
+            e.mut_accept_mut(v);
+        }
         for t in &mut self.typedefs {
             t.mut_accept_mut(v);
         }
@@ -175,6 +363,15 @@ impl AST {
         for e in &mut self.externs {
             e.mut_accept_mut(v);
         }
+        for f in &mut self.extern_functions {
+            f.mut_accept_mut(v);
+        }
+        for f in &mut self.functions {
+            f.mut_accept_mut(v);
+        }
+        for p in &mut self.package_instances {
+            p.mut_accept_mut(v);
+        }
         if let Some(p) = &mut self.package_instance {
             p.mut_accept_mut(v);
         }
@@ -186,14 +383,16 @@ pub struct PackageInstance {
     pub instance_type: String,
     pub name: String,
     pub parameters: Vec<String>,
+    pub token: Token,
 }
 
 impl PackageInstance {
-    pub fn new(instance_type: String) -> Self {
+    pub fn new(instance_type: String, token: Token) -> Self {
         Self {
             instance_type,
             name: "".into(),
             parameters: Vec::new(),
+            token,
         }
     }
 
@@ -305,6 +504,8 @@ pub enum Type {
     Table,
     Void,
     List(Vec<Box<Type>>),
+    /// A stack of headers given by element header type name and size.
+    HeaderStack(String, usize),
     State,
     Action,
     HeaderMethod,
@@ -364,6 +565,7 @@ impl fmt::Display for Type {
             Type::State => write!(f, "state"),
             Type::Action => write!(f, "action"),
             Type::HeaderMethod => write!(f, "header method"),
+            Type::HeaderStack(name, size) => write!(f, "{}[{}]", name, size),
             Type::List(elems) => {
                 write!(f, "list<")?;
                 for e in elems {
@@ -375,6 +577,20 @@ impl fmt::Display for Type {
     }
 }
 
+struct TypedefResolver {
+    types: HashMap<String, Type>,
+}
+
+impl MutVisitor for TypedefResolver {
+    fn typ(&self, t: &mut Type) {
+        if let Type::UserDefined(name) = t {
+            if let Some(ty) = self.types.get(name) {
+                *t = ty.clone();
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Typedef {
     pub ty: Type,
@@ -511,6 +727,13 @@ impl Expression {
                 op.accept(v);
                 rhs.accept(v);
             }
+            ExpressionKind::Unary(_, xpr) => xpr.accept(v),
+            ExpressionKind::Cast(_, xpr) => xpr.accept(v),
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                cond.accept(v);
+                then.accept(v);
+                otherwise.accept(v);
+            }
             ExpressionKind::Index(lval, xpr) => {
                 lval.accept(v);
                 xpr.accept(v);
@@ -525,6 +748,7 @@ impl Expression {
                     xp.accept(v);
                 }
             }
+            ExpressionKind::Member(xpr, _) => xpr.accept(v),
             _ => {} // covered by top level visit
         }
     }
@@ -538,6 +762,13 @@ impl Expression {
                 op.accept_mut(v);
                 rhs.accept_mut(v);
             }
+            ExpressionKind::Unary(_, xpr) => xpr.accept_mut(v),
+            ExpressionKind::Cast(_, xpr) => xpr.accept_mut(v),
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                cond.accept_mut(v);
+                then.accept_mut(v);
+                otherwise.accept_mut(v);
+            }
             ExpressionKind::Index(lval, xpr) => {
                 lval.accept_mut(v);
                 xpr.accept_mut(v);
@@ -552,6 +783,7 @@ impl Expression {
                     xp.accept_mut(v);
                 }
             }
+            ExpressionKind::Member(xpr, _) => xpr.accept_mut(v),
             _ => {} // covered by top level visit
         }
     }
@@ -565,6 +797,13 @@ impl Expression {
                 op.mut_accept(v);
                 rhs.mut_accept(v);
             }
+            ExpressionKind::Unary(_, xpr) => xpr.mut_accept(v),
+            ExpressionKind::Cast(_, xpr) => xpr.mut_accept(v),
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                cond.mut_accept(v);
+                then.mut_accept(v);
+                otherwise.mut_accept(v);
+            }
             ExpressionKind::Index(lval, xpr) => {
                 lval.mut_accept(v);
                 xpr.mut_accept(v);
@@ -579,6 +818,7 @@ impl Expression {
                     xp.mut_accept(v);
                 }
             }
+            ExpressionKind::Member(xpr, _) => xpr.mut_accept(v),
             _ => {} // covered by top level visit
         }
     }
@@ -592,6 +832,13 @@ impl Expression {
                 op.mut_accept_mut(v);
                 rhs.mut_accept_mut(v);
             }
+            ExpressionKind::Unary(_, xpr) => xpr.mut_accept_mut(v),
+            ExpressionKind::Cast(_, xpr) => xpr.mut_accept_mut(v),
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                cond.mut_accept_mut(v);
+                then.mut_accept_mut(v);
+                otherwise.mut_accept_mut(v);
+            }
             ExpressionKind::Index(lval, xpr) => {
                 lval.mut_accept_mut(v);
                 xpr.mut_accept_mut(v);
@@ -606,6 +853,7 @@ impl Expression {
                     xp.mut_accept_mut(v);
                 }
             }
+            ExpressionKind::Member(xpr, _) => xpr.mut_accept_mut(v),
             _ => {} // covered by top level visit
         }
     }
@@ -633,16 +881,26 @@ pub enum ExpressionKind {
     SignedLit(u16, i128),
     Lvalue(Lvalue),
     Binary(Box<Expression>, BinOp, Box<Expression>),
+    Unary(UnOp, Box<Expression>),
+    /// A conditional expression `cond ? then : else`.
+    Ternary(Box<Expression>, Box<Expression>, Box<Expression>),
+    /// An explicit conversion `(type) expr`.
+    Cast(Type, Box<Expression>),
     Index(Lvalue, Box<Expression>),
     Slice(Box<Expression>, Box<Expression>),
     Call(Call),
     List(Vec<Box<Expression>>),
+    /// Member access on the result of an expression that is not an lvalue,
+    /// such as `t.apply().action_run`.
+    Member(Box<Expression>, String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinOp {
     Add,
     Subtract,
+    SatAdd,
+    SatSubtract,
     Mod,
     Geq,
     Gt,
@@ -654,6 +912,13 @@ pub enum BinOp {
     BitAnd,
     BitOr,
     Xor,
+    Shl,
+    Shr,
+    Mul,
+    Div,
+    LogicalAnd,
+    LogicalOr,
+    Concat,
 }
 
 impl BinOp {
@@ -661,6 +926,8 @@ impl BinOp {
         match self {
             BinOp::Add => "add",
             BinOp::Subtract => "subtract",
+            BinOp::SatAdd => "saturating add",
+            BinOp::SatSubtract => "saturating subtract",
             BinOp::Mod => "mod",
             BinOp::Geq | BinOp::Gt | BinOp::Leq | BinOp::Lt | BinOp::Eq => {
                 "compare"
@@ -670,9 +937,49 @@ impl BinOp {
             BinOp::BitAnd => "bitwise and",
             BinOp::BitOr => "bitwise or",
             BinOp::Xor => "xor",
+            BinOp::Shl | BinOp::Shr => "shift",
+            BinOp::Mul => "multiply",
+            BinOp::Div => "divide",
+            BinOp::LogicalAnd => "logical and",
+            BinOp::LogicalOr => "logical or",
+            BinOp::Concat => "concatenate",
         }
     }
 
+    /// The binding strength of the operator, higher values bind tighter.
+    pub fn precedence(&self) -> usize {
+        match self {
+            BinOp::Mask => 1,
+            BinOp::LogicalOr => 2,
+            BinOp::LogicalAnd => 3,
+            BinOp::BitOr => 4,
+            BinOp::Xor => 5,
+            BinOp::BitAnd => 6,
+            BinOp::Eq | BinOp::NotEq => 7,
+            BinOp::Geq | BinOp::Gt | BinOp::Leq | BinOp::Lt => 8,
+            BinOp::Shl | BinOp::Shr => 9,
+            BinOp::Add
+            | BinOp::Subtract
+            | BinOp::SatAdd
+            | BinOp::SatSubtract
+            | BinOp::Concat => 10,
+            BinOp::Mul | BinOp::Div | BinOp::Mod => 11,
+        }
+    }
+
+    /// Whether the operator produces a boolean from its operands.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Geq
+                | BinOp::Gt
+                | BinOp::Leq
+                | BinOp::Lt
+                | BinOp::Eq
+                | BinOp::NotEq
+        )
+    }
+
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.binop(self);
     }
@@ -690,6 +997,26 @@ impl BinOp {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    /// Logical negation `!`.
+    Not,
+    /// Bitwise complement `~`.
+    Complement,
+    /// Arithmetic negation `-`.
+    Negate,
+}
+
+impl UnOp {
+    pub fn english_verb(&self) -> &str {
+        match self {
+            UnOp::Not => "logically negate",
+            UnOp::Complement => "complement",
+            UnOp::Negate => "negate",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Header {
     pub name: String,
@@ -796,6 +1123,99 @@ impl HeaderMember {
     }
 }
 
+/// A header union, of which at most one member header is valid at a time.
+#[derive(Debug, Clone)]
+pub struct HeaderUnion {
+    pub name: String,
+    pub members: Vec<HeaderUnionMember>,
+}
+
+impl HeaderUnion {
+    pub fn new(name: String) -> Self {
+        HeaderUnion {
+            name,
+            members: Vec::new(),
+        }
+    }
+    pub fn names(&self) -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        names.insert(
+            "isValid".into(),
+            NameInfo {
+                ty: Type::HeaderMethod,
+                decl: DeclarationInfo::Method,
+            },
+        );
+        for m in &self.members {
+            names.insert(
+                m.name.clone(),
+                NameInfo {
+                    ty: m.ty.clone(),
+                    decl: DeclarationInfo::StructMember,
+                },
+            );
+        }
+        names
+    }
+
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.header_union(self);
+        for m in &self.members {
+            m.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.header_union(self);
+        for m in &self.members {
+            m.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.header_union(self);
+        for m in &mut self.members {
+            m.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.header_union(self);
+        for m in &mut self.members {
+            m.mut_accept_mut(v);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeaderUnionMember {
+    pub ty: Type,
+    pub name: String,
+    pub token: Token,
+}
+
+impl HeaderUnionMember {
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.header_union_member(self);
+        self.ty.accept(v);
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.header_union_member(self);
+        self.ty.accept_mut(v);
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.header_union_member(self);
+        self.ty.mut_accept(v);
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.header_union_member(self);
+        self.ty.mut_accept_mut(v);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Struct {
     pub name: String,
@@ -881,6 +1301,122 @@ impl StructMember {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Enum {
+    pub name: String,
+
+    /// The underlying type of a serializable enum, e.g. `bit<8>` for
+    /// `enum bit<8> Code { ... }`. None for plain enums.
+    pub ty: Option<Type>,
+
+    pub members: Vec<EnumMember>,
+    pub token: Token,
+}
+
+impl Enum {
+    pub fn new(name: String, ty: Option<Type>, token: Token) -> Self {
+        Enum {
+            name,
+            ty,
+            members: Vec::new(),
+            token,
+        }
+    }
+
+    pub fn get_member(&self, name: &str) -> Option<&EnumMember> {
+        self.members.iter().find(|&m| m.name == name)
+    }
+
+    pub fn names(&self) -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        for m in &self.members {
+            names.insert(
+                m.name.clone(),
+                NameInfo {
+                    ty: Type::UserDefined(self.name.clone()),
+                    decl: DeclarationInfo::EnumMember,
+                },
+            );
+        }
+        names
+    }
+
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.p4enum(self);
+        for m in &self.members {
+            m.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.p4enum(self);
+        for m in &self.members {
+            m.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.p4enum(self);
+        for m in &mut self.members {
+            m.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.p4enum(self);
+        for m in &mut self.members {
+            m.mut_accept_mut(v);
+        }
+    }
+}
+
+/// A member of the error namespace, from an `error { ... }` declaration.
+#[derive(Debug, Clone)]
+pub struct ErrorMember {
+    pub name: String,
+    pub token: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumMember {
+    pub name: String,
+
+    /// Explicit value of a serializable enum member.
+    pub value: Option<Box<Expression>>,
+
+    pub token: Token,
+}
+
+impl EnumMember {
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.enum_member(self);
+        if let Some(value) = &self.value {
+            value.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.enum_member(self);
+        if let Some(value) = &self.value {
+            value.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.enum_member(self);
+        if let Some(value) = &mut self.value {
+            value.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.enum_member(self);
+        if let Some(value) = &mut self.value {
+            value.mut_accept_mut(v);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Control {
     pub name: String,
@@ -889,6 +1425,7 @@ pub struct Control {
     pub type_parameters: Vec<String>,
     pub parameters: Vec<ControlParameter>,
     pub actions: Vec<Action>,
+    pub functions: Vec<Function>,
     pub tables: Vec<Table>,
     pub apply: StatementBlock,
 }
@@ -902,6 +1439,7 @@ impl Control {
             type_parameters: Vec::new(),
             parameters: Vec::new(),
             actions: Vec::new(),
+            functions: Vec::new(),
             tables: Vec::new(),
             apply: StatementBlock::default(),
         }
@@ -915,6 +1453,10 @@ impl Control {
         self.actions.iter().find(|&a| a.name == name)
     }
 
+    pub fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|&f| f.name == name)
+    }
+
     pub fn get_table(&self, name: &str) -> Option<&Table> {
         self.tables.iter().find(|&t| t.name == name)
     }
@@ -926,7 +1468,7 @@ impl Control {
     pub fn tables<'a>(
         &'a self,
         ast: &'a AST,
-    ) -> Vec<(Vec<(String, &Control)>, &Table)> {
+    ) -> Vec<(Vec<(String, &'a Control)>, &'a Table)> {
         self.tables_rec(ast, String::new(), Vec::new())
     }
 
@@ -935,7 +1477,7 @@ impl Control {
         ast: &'a AST,
         name: String,
         mut chain: Vec<(String, &'a Control)>,
-    ) -> Vec<(Vec<(String, &Control)>, &Table)> {
+    ) -> Vec<(Vec<(String, &'a Control)>, &'a Table)> {
         let mut result = Vec::new();
         chain.push((name, self));
         for table in &self.tables {
@@ -1002,6 +1544,14 @@ impl Control {
                 },
             );
         }
+        // NoAction is declared by core.p4 and may be listed by any table
+        names.insert(
+            "NoAction".into(),
+            NameInfo {
+                ty: Type::Action,
+                decl: DeclarationInfo::Action,
+            },
+        );
         for a in &self.actions {
             names.insert(
                 a.name.clone(),
@@ -1011,6 +1561,15 @@ impl Control {
                 },
             );
         }
+        for f in &self.functions {
+            names.insert(
+                f.name.clone(),
+                NameInfo {
+                    ty: f.return_type.clone(),
+                    decl: DeclarationInfo::Function,
+                },
+            );
+        }
         names
     }
 
@@ -1028,6 +1587,9 @@ impl Control {
         for a in &self.actions {
             a.accept(v);
         }
+        for f in &self.functions {
+            f.accept(v);
+        }
         for t in &self.tables {
             t.accept(v);
         }
@@ -1050,6 +1612,9 @@ impl Control {
         for a in &self.actions {
             a.accept_mut(v);
         }
+        for f in &self.functions {
+            f.accept_mut(v);
+        }
         for t in &self.tables {
             t.accept_mut(v);
         }
@@ -1072,6 +1637,9 @@ impl Control {
         for a in &mut self.actions {
             a.mut_accept(v);
         }
+        for f in &mut self.functions {
+            f.mut_accept(v);
+        }
         for t in &mut self.tables {
             t.mut_accept(v);
         }
@@ -1094,6 +1662,9 @@ impl Control {
         for a in &mut self.actions {
             a.mut_accept_mut(v);
         }
+        for f in &mut self.functions {
+            f.mut_accept_mut(v);
+        }
         for t in &mut self.tables {
             t.mut_accept_mut(v);
         }
@@ -1367,11 +1938,84 @@ impl ActionParameter {
     }
 }
 
+/// A function declared with the `function` keyword, either at the top level or
+/// local to a control.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub return_type: Type,
+    pub parameters: Vec<ControlParameter>,
+    pub statement_block: StatementBlock,
+    pub token: Token,
+}
+
+impl Function {
+    pub fn names(&self) -> HashMap<String, NameInfo> {
+        let mut names = HashMap::new();
+        for p in &self.parameters {
+            names.insert(
+                p.name.clone(),
+                NameInfo {
+                    ty: p.ty.clone(),
+                    decl: DeclarationInfo::Parameter(p.direction),
+                },
+            );
+        }
+        names
+    }
+
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.function(self);
+        self.return_type.accept(v);
+        for p in &self.parameters {
+            p.accept(v);
+        }
+        for s in &self.statement_block.statements {
+            s.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.function(self);
+        self.return_type.accept_mut(v);
+        for p in &self.parameters {
+            p.accept_mut(v);
+        }
+        for s in &self.statement_block.statements {
+            s.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.function(self);
+        self.return_type.mut_accept(v);
+        for p in &mut self.parameters {
+            p.mut_accept(v);
+        }
+        for s in &mut self.statement_block.statements {
+            s.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.function(self);
+        self.return_type.mut_accept_mut(v);
+        for p in &mut self.parameters {
+            p.mut_accept_mut(v);
+        }
+        for s in &mut self.statement_block.statements {
+            s.mut_accept_mut(v);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Table {
     pub name: String,
     pub actions: Vec<Lvalue>,
-    pub default_action: String,
+    pub default_action: ActionRef,
+    /// The default action may not be changed at runtime.
+    pub const_default_action: bool,
     pub key: Vec<(Lvalue, MatchKind)>,
     pub const_entries: Vec<ConstTableEntry>,
     pub size: usize,
@@ -1383,7 +2027,8 @@ impl Table {
         Self {
             name,
             actions: Vec::new(),
-            default_action: String::new(),
+            default_action: ActionRef::new(String::new(), token.clone()),
+            const_default_action: false,
             key: Vec::new(),
             const_entries: Vec::new(),
             size: 0,
@@ -1403,6 +2048,7 @@ impl Table {
         for e in &self.const_entries {
             e.accept(v);
         }
+        self.default_action.accept(v);
     }
 
     pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
@@ -1417,6 +2063,7 @@ impl Table {
         for e in &self.const_entries {
             e.accept_mut(v);
         }
+        self.default_action.accept_mut(v);
     }
 
     pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
@@ -1431,6 +2078,7 @@ impl Table {
         for e in &mut self.const_entries {
             e.mut_accept(v);
         }
+        self.default_action.mut_accept(v);
     }
 
     pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
@@ -1445,6 +2093,7 @@ impl Table {
         for e in &mut self.const_entries {
             e.mut_accept_mut(v);
         }
+        self.default_action.mut_accept_mut(v);
     }
 }
 
@@ -1677,6 +2326,7 @@ pub enum Statement {
     Variable(Variable),
     Constant(Constant),
     Transition(Transition),
+    Switch(Switch),
     Return(Option<Box<Expression>>),
     // TODO ...
 }
@@ -1695,6 +2345,7 @@ impl Statement {
             Statement::Variable(var) => var.accept(v),
             Statement::Constant(constant) => constant.accept(v),
             Statement::Transition(transition) => transition.accept(v),
+            Statement::Switch(switch) => switch.accept(v),
             Statement::Return(xpr) => {
                 if let Some(rx) = xpr {
                     rx.accept(v);
@@ -1716,6 +2367,7 @@ impl Statement {
             Statement::Variable(var) => var.accept_mut(v),
             Statement::Constant(constant) => constant.accept_mut(v),
             Statement::Transition(transition) => transition.accept_mut(v),
+            Statement::Switch(switch) => switch.accept_mut(v),
             Statement::Return(xpr) => {
                 if let Some(rx) = xpr {
                     rx.accept_mut(v);
@@ -1737,6 +2389,7 @@ impl Statement {
             Statement::Variable(var) => var.mut_accept(v),
             Statement::Constant(constant) => constant.mut_accept(v),
             Statement::Transition(transition) => transition.mut_accept(v),
+            Statement::Switch(switch) => switch.mut_accept(v),
             Statement::Return(xpr) => {
                 if let Some(rx) = xpr {
                     rx.mut_accept(v);
@@ -1758,6 +2411,7 @@ impl Statement {
             Statement::Variable(var) => var.mut_accept_mut(v),
             Statement::Constant(constant) => constant.mut_accept_mut(v),
             Statement::Transition(transition) => transition.mut_accept_mut(v),
+            Statement::Switch(switch) => switch.mut_accept_mut(v),
             Statement::Return(xpr) => {
                 if let Some(rx) = xpr {
                     rx.mut_accept_mut(v);
@@ -1881,10 +2535,120 @@ impl ElseIfBlock {
     }
 }
 
+/// A switch statement. The expression is either the `action_run` member of a
+/// table apply result or an expression of bit or enum type.
+#[derive(Debug, Clone)]
+pub struct Switch {
+    pub expression: Box<Expression>,
+    pub cases: Vec<SwitchCase>,
+}
+
+impl Switch {
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.switch(self);
+        self.expression.accept(v);
+        for c in &self.cases {
+            c.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.switch(self);
+        self.expression.accept_mut(v);
+        for c in &self.cases {
+            c.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.switch(self);
+        self.expression.mut_accept(v);
+        for c in &mut self.cases {
+            c.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.switch(self);
+        self.expression.mut_accept_mut(v);
+        for c in &mut self.cases {
+            c.mut_accept_mut(v);
+        }
+    }
+}
+
+/// A group of labels sharing a statement block. Labels without a block of
+/// their own fall through to the next block in the source, so consecutive
+/// labels are collected into the same case.
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub labels: Vec<SwitchLabel>,
+    pub block: StatementBlock,
+    pub token: Token,
+}
+
+impl SwitchCase {
+    pub fn accept<V: Visitor>(&self, v: &V) {
+        v.switch_case(self);
+        for l in &self.labels {
+            if let SwitchLabel::Expression(xpr) = l {
+                xpr.accept(v);
+            }
+        }
+        for s in &self.block.statements {
+            s.accept(v);
+        }
+    }
+
+    pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
+        v.switch_case(self);
+        for l in &self.labels {
+            if let SwitchLabel::Expression(xpr) = l {
+                xpr.accept_mut(v);
+            }
+        }
+        for s in &self.block.statements {
+            s.accept_mut(v);
+        }
+    }
+
+    pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
+        v.switch_case(self);
+        for l in &mut self.labels {
+            if let SwitchLabel::Expression(xpr) = l {
+                xpr.mut_accept(v);
+            }
+        }
+        for s in &mut self.block.statements {
+            s.mut_accept(v);
+        }
+    }
+
+    pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
+        v.switch_case(self);
+        for l in &mut self.labels {
+            if let SwitchLabel::Expression(xpr) = l {
+                xpr.mut_accept_mut(v);
+            }
+        }
+        for s in &mut self.block.statements {
+            s.mut_accept_mut(v);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SwitchLabel {
+    Default,
+    Expression(Box<Expression>),
+}
+
 /// A function or method call
 #[derive(Debug, Clone)]
 pub struct Call {
     pub lval: Lvalue,
+    /// Explicit type arguments, as in `pkt.lookahead<bit<8>>()`.
+    pub type_args: Vec<Type>,
     pub args: Vec<Box<Expression>>,
 }
 
@@ -1892,6 +2656,9 @@ impl Call {
     pub fn accept<V: Visitor>(&self, v: &V) {
         v.call(self);
         self.lval.accept(v);
+        for t in &self.type_args {
+            t.accept(v);
+        }
         for a in &self.args {
             a.accept(v);
         }
@@ -1900,6 +2667,9 @@ impl Call {
     pub fn accept_mut<V: VisitorMut>(&self, v: &mut V) {
         v.call(self);
         self.lval.accept_mut(v);
+        for t in &self.type_args {
+            t.accept_mut(v);
+        }
         for a in &self.args {
             a.accept_mut(v);
         }
@@ -1908,6 +2678,9 @@ impl Call {
     pub fn mut_accept<V: MutVisitor>(&mut self, v: &V) {
         v.call(self);
         self.lval.mut_accept(v);
+        for t in &mut self.type_args {
+            t.mut_accept(v);
+        }
         for a in &mut self.args {
             a.mut_accept(v);
         }
@@ -1916,6 +2689,9 @@ impl Call {
     pub fn mut_accept_mut<V: MutVisitorMut>(&mut self, v: &mut V) {
         v.call(self);
         self.lval.mut_accept_mut(v);
+        for t in &mut self.type_args {
+            t.mut_accept_mut(v);
+        }
         for a in &mut self.args {
             a.mut_accept_mut(v);
         }
@@ -1926,6 +2702,12 @@ impl Call {
 pub struct Lvalue {
     pub name: String,
     pub token: Token,
+    /// A header stack index `[i]` applied to one of the parts of the name,
+    /// given as the position of the indexed part and the index expression.
+    pub index: Option<(usize, Box<Expression>)>,
+    /// The upper and lower bounds of a bit slice `[hi:lo]` of the named
+    /// value, if the lvalue only refers to part of it.
+    pub slice: Option<(Box<Expression>, Box<Expression>)>,
 }
 
 impl Lvalue {
@@ -1942,30 +2724,66 @@ impl Lvalue {
     pub fn degree(&self) -> usize {
         self.parts().len()
     }
+    /// The header stack index applied to the part at position `part`, if
+    /// any.
+    pub fn stack_index(&self, part: usize) -> Option<&Expression> {
+        match &self.index {
+            Some((p, index)) if *p == part => Some(index.as_ref()),
+            _ => None,
+        }
+    }
     pub fn pop_left(&self) -> Self {
         let parts = self.parts();
+        let mut col = self.token.col + parts[0].len() + 1;
+        if let Some(ExpressionKind::IntegerLit(i)) =
+            self.stack_index(0).map(|x| &x.kind)
+        {
+            col += i.to_string().len() + 2;
+        }
         Lvalue {
             name: parts[1..].join("."),
             token: Token {
                 kind: self.token.kind.clone(),
                 line: self.token.line,
-                col: self.token.col + parts[0].len() + 1,
+                col,
                 file: self.token.file.clone(),
             },
+            index: match &self.index {
+                Some((p, index)) if *p > 0 => Some((p - 1, index.clone())),
+                _ => None,
+            },
+            slice: self.slice.clone(),
         }
     }
     pub fn pop_right(&self) -> Self {
+        self.prefix(self.degree() - 1)
+    }
+    /// The lvalue made up of the first `n` parts of this one.
+    pub fn prefix(&self, n: usize) -> Self {
         let parts = self.parts();
         Lvalue {
-            name: parts[..parts.len() - 1].join("."),
+            name: parts[..n].join("."),
             token: Token {
                 kind: self.token.kind.clone(),
                 line: self.token.line,
                 col: self.token.col,
                 file: self.token.file.clone(),
             },
+            index: match &self.index {
+                Some((p, index)) if *p < n => Some((*p, index.clone())),
+                _ => None,
+            },
+            slice: None,
         }
     }
+    /// The position and value of the header stack index of this lvalue, for
+    /// comparing lvalues. Indices that are not literals compare equal.
+    fn index_key(&self) -> Option<(usize, Option<i128>)> {
+        self.index.as_ref().map(|(p, index)| match index.kind {
+            ExpressionKind::IntegerLit(v) => (*p, Some(v)),
+            _ => (*p, None),
+        })
+    }
     fn accept<V: Visitor>(&self, v: &V) {
         v.lvalue(self);
     }
@@ -1983,15 +2801,33 @@ impl Lvalue {
     }
 }
 
+impl fmt::Display for Lvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, part) in self.parts().iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", part)?;
+            match self.stack_index(i).map(|x| &x.kind) {
+                Some(ExpressionKind::IntegerLit(v)) => write!(f, "[{}]", v)?,
+                Some(_) => write!(f, "[..]")?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
 impl std::hash::Hash for Lvalue {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.name.hash(state);
+        self.index_key().hash(state);
     }
 }
 
 impl PartialEq for Lvalue {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
+        self.name == other.name && self.index_key() == other.index_key()
     }
 }
 impl Eq for Lvalue {}
@@ -2004,7 +2840,9 @@ impl PartialOrd for Lvalue {
 
 impl Ord for Lvalue {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.name.cmp(&other.name)
+        self.name
+            .cmp(&other.name)
+            .then_with(|| self.index_key().cmp(&other.index_key()))
     }
 }
 
@@ -2280,12 +3118,16 @@ pub enum DeclarationInfo {
     Method,
     StructMember,
     HeaderMember,
+    EnumMember,
+    ErrorMember,
     Local,
     ControlTable,
     ControlMember,
     State,
     Action,
     ActionParameter(Direction),
+    Function,
+    Constant,
 }
 
 #[derive(Debug, Clone)]
@@ -2297,7 +3139,9 @@ pub struct NameInfo {
 pub trait Visitor {
     fn constant(&self, _: &Constant) {}
     fn header(&self, _: &Header) {}
+    fn header_union(&self, _: &HeaderUnion) {}
     fn p4struct(&self, _: &Struct) {}
+    fn p4enum(&self, _: &Enum) {}
     fn typedef(&self, _: &Typedef) {}
     fn control(&self, _: &Control) {}
     fn parser(&self, _: &Parser) {}
@@ -2307,11 +3151,14 @@ pub trait Visitor {
 
     fn statement(&self, _: &Statement) {}
     fn action(&self, _: &Action) {}
+    fn function(&self, _: &Function) {}
     fn control_parameter(&self, _: &ControlParameter) {}
     fn action_parameter(&self, _: &ActionParameter) {}
     fn expression(&self, _: &Expression) {}
     fn header_member(&self, _: &HeaderMember) {}
+    fn header_union_member(&self, _: &HeaderUnionMember) {}
     fn struct_member(&self, _: &StructMember) {}
+    fn enum_member(&self, _: &EnumMember) {}
     fn call(&self, _: &Call) {}
     fn typ(&self, _: &Type) {}
     fn binop(&self, _: &BinOp) {}
@@ -2319,6 +3166,8 @@ pub trait Visitor {
     fn variable(&self, _: &Variable) {}
     fn if_block(&self, _: &IfBlock) {}
     fn else_if_block(&self, _: &ElseIfBlock) {}
+    fn switch(&self, _: &Switch) {}
+    fn switch_case(&self, _: &SwitchCase) {}
     fn transition(&self, _: &Transition) {}
     fn select(&self, _: &Select) {}
     fn select_element(&self, _: &SelectElement) {}
@@ -2336,7 +3185,9 @@ pub trait Visitor {
 pub trait VisitorMut {
     fn constant(&mut self, _: &Constant) {}
     fn header(&mut self, _: &Header) {}
+    fn header_union(&mut self, _: &HeaderUnion) {}
     fn p4struct(&mut self, _: &Struct) {}
+    fn p4enum(&mut self, _: &Enum) {}
     fn typedef(&mut self, _: &Typedef) {}
     fn control(&mut self, _: &Control) {}
     fn parser(&mut self, _: &Parser) {}
@@ -2346,11 +3197,14 @@ pub trait VisitorMut {
 
     fn statement(&mut self, _: &Statement) {}
     fn action(&mut self, _: &Action) {}
+    fn function(&mut self, _: &Function) {}
     fn control_parameter(&mut self, _: &ControlParameter) {}
     fn action_parameter(&mut self, _: &ActionParameter) {}
     fn expression(&mut self, _: &Expression) {}
     fn header_member(&mut self, _: &HeaderMember) {}
+    fn header_union_member(&mut self, _: &HeaderUnionMember) {}
     fn struct_member(&mut self, _: &StructMember) {}
+    fn enum_member(&mut self, _: &EnumMember) {}
     fn call(&mut self, _: &Call) {}
     fn typ(&mut self, _: &Type) {}
     fn binop(&mut self, _: &BinOp) {}
@@ -2358,6 +3212,8 @@ pub trait VisitorMut {
     fn variable(&mut self, _: &Variable) {}
     fn if_block(&mut self, _: &IfBlock) {}
     fn else_if_block(&mut self, _: &ElseIfBlock) {}
+    fn switch(&mut self, _: &Switch) {}
+    fn switch_case(&mut self, _: &SwitchCase) {}
     fn transition(&mut self, _: &Transition) {}
     fn select(&mut self, _: &Select) {}
     fn select_element(&mut self, _: &SelectElement) {}
@@ -2375,7 +3231,9 @@ pub trait VisitorMut {
 pub trait MutVisitor {
     fn constant(&self, _: &mut Constant) {}
     fn header(&self, _: &mut Header) {}
+    fn header_union(&self, _: &mut HeaderUnion) {}
     fn p4struct(&self, _: &mut Struct) {}
+    fn p4enum(&self, _: &mut Enum) {}
     fn typedef(&self, _: &mut Typedef) {}
     fn control(&self, _: &mut Control) {}
     fn parser(&self, _: &mut Parser) {}
@@ -2385,11 +3243,14 @@ pub trait MutVisitor {
 
     fn statement(&self, _: &mut Statement) {}
     fn action(&self, _: &mut Action) {}
+    fn function(&self, _: &mut Function) {}
     fn control_parameter(&self, _: &mut ControlParameter) {}
     fn action_parameter(&self, _: &mut ActionParameter) {}
     fn expression(&self, _: &mut Expression) {}
     fn header_member(&self, _: &mut HeaderMember) {}
+    fn header_union_member(&self, _: &mut HeaderUnionMember) {}
     fn struct_member(&self, _: &mut StructMember) {}
+    fn enum_member(&self, _: &mut EnumMember) {}
     fn call(&self, _: &mut Call) {}
     fn typ(&self, _: &mut Type) {}
     fn binop(&self, _: &mut BinOp) {}
@@ -2397,6 +3258,8 @@ pub trait MutVisitor {
     fn variable(&self, _: &mut Variable) {}
     fn if_block(&self, _: &mut IfBlock) {}
     fn else_if_block(&self, _: &mut ElseIfBlock) {}
+    fn switch(&self, _: &mut Switch) {}
+    fn switch_case(&self, _: &mut SwitchCase) {}
     fn transition(&self, _: &mut Transition) {}
     fn select(&self, _: &mut Select) {}
     fn select_element(&self, _: &mut SelectElement) {}
@@ -2414,7 +3277,9 @@ pub trait MutVisitor {
 pub trait MutVisitorMut {
     fn constant(&mut self, _: &mut Constant) {}
     fn header(&mut self, _: &mut Header) {}
+    fn header_union(&mut self, _: &mut HeaderUnion) {}
     fn p4struct(&mut self, _: &mut Struct) {}
+    fn p4enum(&mut self, _: &mut Enum) {}
     fn typedef(&mut self, _: &mut Typedef) {}
     fn control(&mut self, _: &mut Control) {}
     fn parser(&mut self, _: &mut Parser) {}
@@ -2424,11 +3289,14 @@ pub trait MutVisitorMut {
 
     fn statement(&mut self, _: &mut Statement) {}
     fn action(&mut self, _: &mut Action) {}
+    fn function(&mut self, _: &mut Function) {}
     fn control_parameter(&mut self, _: &mut ControlParameter) {}
     fn action_parameter(&mut self, _: &mut ActionParameter) {}
     fn expression(&mut self, _: &mut Expression) {}
     fn header_member(&mut self, _: &mut HeaderMember) {}
+    fn header_union_member(&mut self, _: &mut HeaderUnionMember) {}
     fn struct_member(&mut self, _: &mut StructMember) {}
+    fn enum_member(&mut self, _: &mut EnumMember) {}
     fn call(&mut self, _: &mut Call) {}
     fn typ(&mut self, _: &mut Type) {}
     fn binop(&mut self, _: &mut BinOp) {}
@@ -2436,6 +3304,8 @@ pub trait MutVisitorMut {
     fn variable(&mut self, _: &mut Variable) {}
     fn if_block(&mut self, _: &mut IfBlock) {}
     fn else_if_block(&mut self, _: &mut ElseIfBlock) {}
+    fn switch(&mut self, _: &mut Switch) {}
+    fn switch_case(&mut self, _: &mut SwitchCase) {}
     fn transition(&mut self, _: &mut Transition) {}
     fn select(&mut self, _: &mut Select) {}
     fn select_element(&mut self, _: &mut SelectElement) {}
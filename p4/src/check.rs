@@ -3,12 +3,14 @@
 use std::collections::HashMap;
 
 use crate::ast::{
-    Call, Control, DeclarationInfo, Expression, ExpressionKind, Header, Lvalue,
-    NameInfo, Parser, State, Statement, StatementBlock, Struct, Table,
+    Call, Control, DeclarationInfo, Enum, Expression, ExpressionKind, Function,
+    Header, HeaderUnion, Lvalue, NameInfo, Package, PackageInstance, Parser,
+    State, Statement, StatementBlock, Struct, Switch, SwitchLabel, Table,
     Transition, Type, VisitorMut, AST,
 };
 use crate::hlir::{Hlir, HlirGenerator};
 use crate::lexer::Token;
+use crate::util::{header_stack_names, stack_index_value};
 use colored::Colorize;
 
 // TODO Check List
@@ -65,6 +67,9 @@ pub fn all(ast: &AST) -> (Hlir, Diagnostics) {
         return (hg.hlir, diags);
     }
 
+    for f in &ast.functions {
+        diags.extend(&FunctionChecker::check(f, ast, &ast.names()));
+    }
     for p in &ast.parsers {
         diags.extend(&ParserChecker::check(p, ast));
     }
@@ -77,6 +82,20 @@ pub fn all(ast: &AST) -> (Hlir, Diagnostics) {
     for h in &ast.headers {
         diags.extend(&HeaderChecker::check(h, ast));
     }
+    for u in &ast.header_unions {
+        diags.extend(&HeaderUnionChecker::check(u, ast));
+    }
+    for e in &ast.enums {
+        diags.extend(&EnumChecker::check(e));
+    }
+    diags.extend(&ErrorChecker::check(ast));
+    for inst in &ast.package_instances {
+        diags.extend(&PackageChecker::check(inst, ast));
+    }
+    if let Some(inst) = &ast.package_instance {
+        diags.extend(&PackageChecker::check(inst, ast));
+        diags.extend(&PackageChecker::check_pipeline(inst, ast));
+    }
     (hg.hlir, diags)
 }
 
@@ -85,19 +104,25 @@ pub struct ControlChecker {}
 impl ControlChecker {
     pub fn check(c: &Control, ast: &AST, hlir: &Hlir) -> Diagnostics {
         let mut diags = Diagnostics::new();
-        let names = c.names();
+        let mut names = ast.names();
+        names.extend(c.names());
         Self::check_params(c, ast, &mut diags);
-        Self::check_tables(c, &names, ast, &mut diags);
+        Self::check_tables(c, &names, ast, hlir, &mut diags);
         Self::check_variables(c, ast, &mut diags);
         Self::check_actions(c, ast, hlir, &mut diags);
-        Self::check_apply(c, ast, hlir, &mut diags);
+        for f in &c.functions {
+            diags.extend(&FunctionChecker::check(f, ast, &names));
+        }
+        Self::check_apply(c, &names, ast, hlir, &mut diags);
         diags
     }
 
     pub fn check_params(c: &Control, ast: &AST, diags: &mut Diagnostics) {
         for p in &c.parameters {
             if let Type::UserDefined(typename) = &p.ty {
-                if ast.get_user_defined_type(typename).is_none() {
+                if ast.get_user_defined_type(typename).is_none()
+                    && !c.is_type_parameter(typename)
+                {
                     diags.push(Diagnostic {
                         level: Level::Error,
                         message: format!("Typename {} not found", typename),
@@ -112,10 +137,11 @@ impl ControlChecker {
         c: &Control,
         names: &HashMap<String, NameInfo>,
         ast: &AST,
+        hlir: &Hlir,
         diags: &mut Diagnostics,
     ) {
         for t in &c.tables {
-            Self::check_table(c, t, names, ast, diags);
+            Self::check_table(c, t, names, ast, hlir, diags);
         }
     }
 
@@ -124,18 +150,50 @@ impl ControlChecker {
         t: &Table,
         names: &HashMap<String, NameInfo>,
         ast: &AST,
+        hlir: &Hlir,
         diags: &mut Diagnostics,
     ) {
         for (lval, _match_kind) in &t.key {
-            diags.extend(&check_lvalue(lval, ast, names, Some(&c.name)))
+            diags.extend(&check_lvalue(lval, ast, names, Some(&c.name)));
+            let e = hlir
+                .lvalue_decls
+                .get(lval)
+                .and_then(|info| unsized_enum(&info.ty, ast));
+            if let Some(e) = e {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "Table key {} is enum {}, which has no underlying \
+                        type",
+                        lval.name.bright_blue(),
+                        e.name.bright_blue(),
+                    ),
+                    token: lval.token.clone(),
+                });
+            }
         }
-        if t.default_action.is_empty() {
+        if t.default_action.name.is_empty() {
             diags.push(Diagnostic {
                 level: Level::Error,
                 message: "Table must have a default action".into(),
                 token: t.token.clone(),
             });
         }
+        if let Some(a) = c.get_action(&t.default_action.name) {
+            if a.parameters.len() != t.default_action.parameters.len() {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "{} arguments provided to default action {}, {} \
+                        required",
+                        t.default_action.parameters.len(),
+                        a.name.bright_blue(),
+                        a.parameters.len(),
+                    ),
+                    token: t.default_action.token.clone(),
+                });
+            }
+        }
     }
 
     pub fn check_variables(c: &Control, ast: &AST, diags: &mut Diagnostics) {
@@ -166,37 +224,61 @@ impl ControlChecker {
             Self::check_table_action_reference(c, t, ast, diags);
         }
         for a in &c.actions {
-            check_statement_block(&a.statement_block, hlir, diags);
+            check_statement_block(&a.statement_block, ast, hlir, diags);
         }
     }
 
     pub fn check_table_action_reference(
         c: &Control,
         t: &Table,
-        _ast: &AST,
+        ast: &AST,
         diags: &mut Diagnostics,
     ) {
         for a in &t.actions {
-            if c.get_action(&a.name).is_none() {
-                diags.push(Diagnostic {
-                    level: Level::Error,
-                    message: format!(
-                        "Table {} does not have action {}",
-                        t.name, &a.name,
-                    ),
-                    token: a.token.clone(), //TODO plumb token for lvalue
-                });
+            if a.name == "NoAction" {
+                continue;
+            }
+            let action = match c.get_action(&a.name) {
+                Some(action) => action,
+                None => {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "Table {} does not have action {}",
+                            t.name, &a.name,
+                        ),
+                        token: a.token.clone(), //TODO plumb token for lvalue
+                    });
+                    continue;
+                }
+            };
+            // parameters of table actions are laid out in table entries
+            for p in &action.parameters {
+                if let Some(e) = unsized_enum(&p.ty, ast) {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "Parameter {} of table action {} is enum {}, \
+                            which has no underlying type",
+                            p.name.bright_blue(),
+                            action.name.bright_blue(),
+                            e.name.bright_blue(),
+                        ),
+                        token: p.ty_token.clone(),
+                    });
+                }
             }
         }
     }
 
     pub fn check_apply(
         c: &Control,
+        names: &HashMap<String, NameInfo>,
         ast: &AST,
         hlir: &Hlir,
         diags: &mut Diagnostics,
     ) {
-        diags.extend(&check_statement_block_lvalues(&c.apply, ast, &c.names()));
+        diags.extend(&check_statement_block_lvalues(&c.apply, ast, names));
 
         let mut apc = ApplyCallChecker {
             c,
@@ -210,6 +292,7 @@ impl ControlChecker {
 
 fn check_statement_block(
     block: &StatementBlock,
+    ast: &AST,
     hlir: &Hlir,
     diags: &mut Diagnostics,
 ) {
@@ -245,7 +328,17 @@ fn check_statement_block(
                         }
                     };
 
-                if &name_info.ty != expression_type {
+                // serializable enums may be assigned to their underlying type
+                let enum_ty = match expression_type {
+                    Type::UserDefined(name) => {
+                        ast.get_enum(name).and_then(|e| e.ty.as_ref())
+                    }
+                    _ => None,
+                };
+
+                if &name_info.ty != expression_type
+                    && Some(&name_info.ty) != enum_ty
+                {
                     diags.push(Diagnostic {
                         level: Level::Error,
                         message: format!(
@@ -272,9 +365,16 @@ pub struct ApplyCallChecker<'a> {
 }
 
 impl<'a> VisitorMut for ApplyCallChecker<'a> {
+    fn statement(&mut self, stmt: &Statement) {
+        if let Statement::Switch(sw) = stmt {
+            self.check_switch(sw);
+        }
+    }
+
     fn call(&mut self, call: &Call) {
         let name = call.lval.root();
-        let names = self.c.names();
+        let mut names = self.ast.names();
+        names.extend(self.c.names());
         let name_info = match names.get(name) {
             Some(info) => info,
             None => {
@@ -306,6 +406,86 @@ impl<'a> VisitorMut for ApplyCallChecker<'a> {
 }
 
 impl<'a> ApplyCallChecker<'a> {
+    pub fn check_switch(&mut self, sw: &Switch) {
+        for (i, case) in sw.cases.iter().enumerate() {
+            for (j, label) in case.labels.iter().enumerate() {
+                let last =
+                    i + 1 == sw.cases.len() && j + 1 == case.labels.len();
+                if let SwitchLabel::Default = label {
+                    if !last {
+                        self.diags.push(Diagnostic {
+                            level: Level::Error,
+                            message: "default must be the last switch label"
+                                .into(),
+                            token: case.token.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // labels of a switch on action_run must be actions of the table
+        let tbl = match &sw.expression.kind {
+            ExpressionKind::Member(xpr, member) if member == "action_run" => {
+                match &xpr.kind {
+                    ExpressionKind::Call(call) => {
+                        match self.c.get_table(call.lval.root()) {
+                            Some(tbl) => tbl,
+                            None => return,
+                        }
+                    }
+                    _ => return,
+                }
+            }
+            _ => return,
+        };
+
+        let mut seen = Vec::new();
+        for case in &sw.cases {
+            for label in &case.labels {
+                let xpr = match label {
+                    SwitchLabel::Expression(xpr) => xpr,
+                    SwitchLabel::Default => continue,
+                };
+                let lval = match &xpr.kind {
+                    ExpressionKind::Lvalue(lval) => lval,
+                    _ => {
+                        self.diags.push(Diagnostic {
+                            level: Level::Error,
+                            message: "switch on action_run requires action \
+                                names as labels"
+                                .into(),
+                            token: xpr.token.clone(),
+                        });
+                        continue;
+                    }
+                };
+                if !tbl.actions.iter().any(|a| a.name == lval.name) {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "{} is not an action of table {}",
+                            lval.name.bright_blue(),
+                            tbl.name.bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
+                if seen.contains(&lval.name) {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "duplicate switch label {}",
+                            lval.name.bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
+                seen.push(lval.name.clone());
+            }
+        }
+    }
+
     pub fn check_apply_table_apply(&mut self, _call: &Call, _tbl: &Table) {
         //TODO
     }
@@ -426,7 +606,8 @@ impl ParserChecker {
             // create a name context for each parser state to pick up any
             // variables that may get created within parser states to reference
             // locally.
-            let names = parser.names();
+            let mut names = ast.names();
+            names.extend(parser.names());
             diags.extend(&check_statement_block_lvalues(
                 &state.statements,
                 ast,
@@ -454,9 +635,364 @@ impl StructChecker {
                     })
                 }
             }
+            if let Type::HeaderStack(typename, _) = &m.ty {
+                if ast.get_header(typename).is_none() {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "Header stack element type {} is not a header",
+                            typename.bright_blue()
+                        ),
+                        token: m.token.clone(),
+                    })
+                }
+            }
+        }
+        diags
+    }
+}
+
+/// Packages provided by the compiler that programs instantiate without
+/// declaring them, along with the fewest and most blocks an instance takes.
+const UNDECLARED_PACKAGES: &[(&str, usize, usize)] = &[("SoftNPU", 2, 4)];
+
+pub struct PackageChecker {}
+
+impl PackageChecker {
+    pub fn check(inst: &PackageInstance, ast: &AST) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        match ast.get_package(&inst.instance_type) {
+            Some(pkg) => Self::check_arguments(inst, pkg, ast, &mut diags),
+            None => Self::check_undeclared(inst, &mut diags),
+        }
+        diags
+    }
+
+    fn check_undeclared(inst: &PackageInstance, diags: &mut Diagnostics) {
+        let (min, max) = match UNDECLARED_PACKAGES
+            .iter()
+            .find(|(name, _, _)| *name == inst.instance_type)
+        {
+            Some((_, min, max)) => (*min, *max),
+            None => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "package {} not declared",
+                        inst.instance_type.bright_blue(),
+                    ),
+                    token: inst.token.clone(),
+                });
+                return;
+            }
+        };
+        let n = inst.parameters.len();
+        if n < min || n > max {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "{} instances take {} to {} parameters, found {}",
+                    inst.instance_type.bright_blue(),
+                    min,
+                    max,
+                    n,
+                ),
+                token: inst.token.clone(),
+            });
+        }
+    }
+
+    fn check_arguments(
+        inst: &PackageInstance,
+        pkg: &Package,
+        ast: &AST,
+        diags: &mut Diagnostics,
+    ) {
+        if pkg.parameters.len() != inst.parameters.len() {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "{} instances take {} parameters, found {}",
+                    pkg.name.bright_blue(),
+                    pkg.parameters.len(),
+                    inst.parameters.len(),
+                ),
+                token: inst.token.clone(),
+            });
+            return;
+        }
+        for (param, arg) in pkg.parameters.iter().zip(&inst.parameters) {
+            let (expected, found) =
+                if ast.get_parser(&param.type_name).is_some() {
+                    ("parser", ast.get_parser(arg).is_some())
+                } else if ast.get_control(&param.type_name).is_some() {
+                    ("control block", ast.get_control(arg).is_some())
+                } else {
+                    continue;
+                };
+            if !found {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "argument {} to {} must be a {}",
+                        arg.bright_blue(),
+                        pkg.name.bright_blue(),
+                        expected,
+                    ),
+                    token: inst.token.clone(),
+                });
+            }
+        }
+    }
+
+    /// Check the package instance a pipeline is generated for. Its blocks,
+    /// including those of nested package instances, must start with a
+    /// parser and include a control block.
+    pub fn check_pipeline(inst: &PackageInstance, ast: &AST) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        let mut blocks = Vec::new();
+        Self::blocks(inst, ast, &mut blocks);
+        for arg in &blocks {
+            if ast.get_parser(arg).is_none() && ast.get_control(arg).is_none() {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "argument {} to {} is not a parser, control block, \
+                        package instance or extern",
+                        arg.bright_blue(),
+                        inst.instance_type.bright_blue(),
+                    ),
+                    token: inst.token.clone(),
+                });
+            }
+        }
+        if !diags.errors().is_empty() {
+            return diags;
+        }
+        if blocks.first().is_none_or(|b| ast.get_parser(b).is_none()) {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "the first argument to {} must be a parser",
+                    inst.instance_type.bright_blue(),
+                ),
+                token: inst.token.clone(),
+            });
+        }
+        if !blocks.iter().any(|b| ast.get_control(b).is_some()) {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "{} is not instantiated with a control block",
+                    inst.instance_type.bright_blue(),
+                ),
+                token: inst.token.clone(),
+            });
+        }
+        diags
+    }
+
+    /// The blocks a package instance runs, with those of nested package
+    /// instances in place. Externs are not blocks.
+    fn blocks<'a>(
+        inst: &'a PackageInstance,
+        ast: &'a AST,
+        blocks: &mut Vec<&'a str>,
+    ) {
+        for arg in &inst.parameters {
+            if let Some(nested) = ast.get_package_instance(arg) {
+                Self::blocks(nested, ast, blocks);
+            } else if ast.get_extern(arg).is_none() {
+                blocks.push(arg);
+            }
+        }
+    }
+}
+
+pub struct ErrorChecker {}
+
+impl ErrorChecker {
+    pub fn check(ast: &AST) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        for (i, e) in ast.errors.iter().enumerate() {
+            if ast.errors[..i].iter().any(|x| x.name == e.name) {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "error {} declared more than once",
+                        e.name.bright_blue(),
+                    ),
+                    token: e.token.clone(),
+                });
+            }
+        }
+        diags
+    }
+}
+
+pub struct EnumChecker {}
+
+impl EnumChecker {
+    pub fn check(e: &Enum) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        let width = match &e.ty {
+            None => None,
+            Some(Type::Bit(w)) | Some(Type::Int(w)) => Some(*w),
+            Some(ty) => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "enum {} must have a bit or int underlying type, \
+                        found {}",
+                        e.name.bright_blue(),
+                        ty,
+                    ),
+                    token: e.token.clone(),
+                });
+                return diags;
+            }
+        };
+        for (i, m) in e.members.iter().enumerate() {
+            if e.members[..i].iter().any(|x| x.name == m.name) {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "enum {} has duplicate member {}",
+                        e.name.bright_blue(),
+                        m.name.bright_blue(),
+                    ),
+                    token: m.token.clone(),
+                });
+            }
+            match (width, &m.value) {
+                (None, Some(value)) => {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "members of enum {} cannot have values, \
+                            only enums with an underlying type can",
+                            e.name.bright_blue(),
+                        ),
+                        token: value.token.clone(),
+                    });
+                }
+                (Some(_), None) => {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "enum member {} requires a value",
+                            m.name.bright_blue(),
+                        ),
+                        token: m.token.clone(),
+                    });
+                }
+                (Some(w), Some(value)) => {
+                    Self::check_value(w, value, &mut diags);
+                }
+                (None, None) => {}
+            }
+        }
+        diags
+    }
+
+    fn check_value(width: usize, value: &Expression, diags: &mut Diagnostics) {
+        let fits = |v: u128| width >= 128 || v < (1u128 << width);
+        match &value.kind {
+            ExpressionKind::IntegerLit(v) => {
+                if *v < 0 || !fits(*v as u128) {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "enum value {} does not fit in {} bits",
+                            v, width,
+                        ),
+                        token: value.token.clone(),
+                    });
+                }
+            }
+            ExpressionKind::BitLit(w, v) => {
+                if *w as usize != width || !fits(*v) {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "enum value {}w{} does not match the \
+                            underlying width {}",
+                            w, v, width,
+                        ),
+                        token: value.token.clone(),
+                    });
+                }
+            }
+            _ => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: "enum values must be integer or bit literals"
+                        .into(),
+                    token: value.token.clone(),
+                });
+            }
+        }
+    }
+}
+
+pub struct FunctionChecker {}
+
+impl FunctionChecker {
+    pub fn check(
+        f: &Function,
+        ast: &AST,
+        names: &HashMap<String, NameInfo>,
+    ) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        for p in &f.parameters {
+            if let Type::UserDefined(typename) = &p.ty {
+                if ast.get_user_defined_type(typename).is_none() {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!("Typename {} not found", typename),
+                        token: p.ty_token.clone(),
+                    })
+                }
+            }
+        }
+        let mut local_names = names.clone();
+        local_names.extend(f.names());
+        diags.extend(&check_statement_block_lvalues(
+            &f.statement_block,
+            ast,
+            &local_names,
+        ));
+        if f.return_type != Type::Void && !Self::returns(&f.statement_block) {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "function {} does not return a value on every path",
+                    f.name.bright_blue(),
+                ),
+                token: f.token.clone(),
+            });
         }
         diags
     }
+
+    /// Whether every path through a block ends in a return statement.
+    fn returns(block: &StatementBlock) -> bool {
+        match block.statements.last() {
+            Some(Statement::Return(_)) => true,
+            Some(Statement::If(ifb)) => match &ifb.else_block {
+                Some(else_block) => {
+                    Self::returns(&ifb.block)
+                        && ifb
+                            .else_ifs
+                            .iter()
+                            .all(|ei| Self::returns(&ei.block))
+                        && Self::returns(else_block)
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
 }
 
 pub struct HeaderChecker {}
@@ -464,7 +1000,34 @@ pub struct HeaderChecker {}
 impl HeaderChecker {
     pub fn check(h: &Header, ast: &AST) -> Diagnostics {
         let mut diags = Diagnostics::new();
+        let varbits: Vec<_> = h
+            .members
+            .iter()
+            .filter(|m| matches!(m.ty, Type::Varbit(_)))
+            .collect();
+        if let [_, extra, ..] = varbits.as_slice() {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "Header {} has more than one varbit field",
+                    h.name.bright_blue(),
+                ),
+                token: extra.token.clone(),
+            })
+        }
         for m in &h.members {
+            if let Some(e) = unsized_enum(&m.ty, ast) {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "Header member {} is enum {}, which has no \
+                        underlying type",
+                        m.name.bright_blue(),
+                        e.name.bright_blue(),
+                    ),
+                    token: m.token.clone(),
+                });
+            }
             if let Type::UserDefined(typename) = &m.ty {
                 if ast.get_user_defined_type(typename).is_none() {
                     diags.push(Diagnostic {
@@ -482,6 +1045,65 @@ impl HeaderChecker {
     }
 }
 
+pub struct HeaderUnionChecker {}
+
+impl HeaderUnionChecker {
+    pub fn check(u: &HeaderUnion, ast: &AST) -> Diagnostics {
+        let mut diags = Diagnostics::new();
+        for m in &u.members {
+            let is_header = match &m.ty {
+                Type::UserDefined(typename) => {
+                    ast.get_header(typename).is_some()
+                }
+                _ => false,
+            };
+            if !is_header {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "Header union member {} must be a header, found {}",
+                        m.name.bright_blue(),
+                        m.ty.to_string().bright_blue(),
+                    ),
+                    token: m.token.clone(),
+                })
+            }
+        }
+        diags
+    }
+}
+
+/// The enum `ty` refers to if it has no underlying type. Such enums have no
+/// size, so they cannot be used where values are laid out as bits, such as
+/// in headers and table entries.
+fn unsized_enum<'a>(ty: &Type, ast: &'a AST) -> Option<&'a Enum> {
+    match ty {
+        Type::UserDefined(name) => {
+            ast.get_enum(name).filter(|e| e.ty.is_none())
+        }
+        _ => None,
+    }
+}
+
+/// Whether a value of type `from` may be converted to type `to`, either by
+/// an explicit cast or by assignment. Bit and signed values may be
+/// truncated or extended, but only change signedness at the same width.
+pub fn conversion_allowed(from: &Type, to: &Type, ast: &AST) -> bool {
+    let underlying = |ty: &Type| match ty {
+        Type::UserDefined(name) => ast.get_enum(name)?.ty.clone(),
+        _ => None,
+    };
+    match (from, to) {
+        _ if from == to => true,
+        (Type::Bit(_), Type::Bit(_)) | (Type::Int(_), Type::Int(_)) => true,
+        (Type::Bit(a), Type::Int(b)) | (Type::Int(a), Type::Bit(b)) => a == b,
+        (Type::Bool, Type::Bit(1)) | (Type::Bit(1), Type::Bool) => true,
+        (Type::UserDefined(_), _) => underlying(from).as_ref() == Some(to),
+        (_, Type::UserDefined(_)) => underlying(to).as_ref() == Some(from),
+        _ => false,
+    }
+}
+
 fn check_name(
     name: &str,
     names: &HashMap<String, NameInfo>,
@@ -517,15 +1139,12 @@ fn check_statement_lvalues(
     match stmt {
         Statement::Empty => {}
         Statement::Variable(v) => {
-            match &v.initializer {
-                Some(expr) => {
-                    diags.extend(&check_expression_lvalues(
-                        expr.as_ref(),
-                        ast,
-                        names,
-                    ));
-                }
-                None => {}
+            if let Some(expr) = &v.initializer {
+                diags.extend(&check_expression_lvalues(
+                    expr.as_ref(),
+                    ast,
+                    names,
+                ));
             };
             names.insert(
                 v.name.clone(),
@@ -585,16 +1204,55 @@ fn check_statement_lvalues(
                 ));
             }
         }
-        Statement::Transition(transition) => {
-            match transition {
-                Transition::Reference(lval) => {
-                    if lval.name != "accept" && lval.name != "reject" {
-                        diags.extend(&check_lvalue(lval, ast, names, None));
+        Statement::Transition(transition) => match transition {
+            Transition::Reference(lval) => {
+                if lval.name != "accept" && lval.name != "reject" {
+                    diags.extend(&check_lvalue(lval, ast, names, None));
+                }
+            }
+            Transition::Select(sel) => {
+                for p in &sel.parameters {
+                    diags.extend(&check_expression_lvalues(
+                        p.as_ref(),
+                        ast,
+                        names,
+                    ));
+                }
+                for e in &sel.elements {
+                    if e.name == "accept" || e.name == "reject" {
+                        continue;
                     }
+                    let lval = Lvalue {
+                        name: e.name.clone(),
+                        token: e.keyset[0].token.clone(),
+                        index: None,
+                        slice: None,
+                    };
+                    diags.extend(&check_lvalue(&lval, ast, names, None));
                 }
-                Transition::Select(_sel) => {
-                    //TODO
+            }
+        },
+        Statement::Switch(sw) => {
+            diags.extend(&check_expression_lvalues(
+                sw.expression.as_ref(),
+                ast,
+                names,
+            ));
+            for case in &sw.cases {
+                for label in &case.labels {
+                    if let SwitchLabel::Expression(xpr) = label {
+                        diags.extend(&check_expression_lvalues(
+                            xpr.as_ref(),
+                            ast,
+                            names,
+                        ));
+                    }
                 }
+                diags.extend(&check_statement_block_lvalues(
+                    &case.block,
+                    ast,
+                    names,
+                ));
             }
         }
         Statement::Return(xpr) => {
@@ -636,6 +1294,20 @@ fn check_expression_lvalues(
             diags.extend(&check_expression_lvalues(rhs.as_ref(), ast, names));
             diags
         }
+        ExpressionKind::Unary(_, xpr) | ExpressionKind::Cast(_, xpr) => {
+            check_expression_lvalues(xpr.as_ref(), ast, names)
+        }
+        ExpressionKind::Ternary(cond, then, otherwise) => {
+            let mut diags = Diagnostics::new();
+            diags.extend(&check_expression_lvalues(cond.as_ref(), ast, names));
+            diags.extend(&check_expression_lvalues(then.as_ref(), ast, names));
+            diags.extend(&check_expression_lvalues(
+                otherwise.as_ref(),
+                ast,
+                names,
+            ));
+            diags
+        }
         ExpressionKind::Index(lval, xpr) => {
             let mut diags = Diagnostics::new();
             diags.extend(&check_lvalue(lval, ast, names, None));
@@ -648,6 +1320,9 @@ fn check_expression_lvalues(
             diags.extend(&check_expression_lvalues(rhs.as_ref(), ast, names));
             diags
         }
+        ExpressionKind::Member(xpr, _) => {
+            check_expression_lvalues(xpr.as_ref(), ast, names)
+        }
         _ => Diagnostics::new(),
     }
 }
@@ -660,13 +1335,72 @@ fn check_lvalue(
 ) -> Diagnostics {
     let parts = lval.parts();
 
-    let ty = match check_name(parts[0], names, &lval.token, parent) {
+    if !names.contains_key(parts[0]) {
+        if let Some(e) = ast.get_enum(parts[0]) {
+            return check_enum_member_ref(lval, e);
+        }
+        if parts[0] == "error" {
+            return check_error_member_ref(lval, ast);
+        }
+    }
+
+    let base = parts[0];
+    let ty = match check_name(base, names, &lval.token, parent) {
         (_, Some(ty)) => ty,
         (diags, None) => return diags,
     };
 
     let mut diags = Diagnostics::new();
 
+    let index = match lval.stack_index(0) {
+        None => None,
+        Some(index) => match stack_index_value(index) {
+            Some(i) => Some(i),
+            None => {
+                diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "index of {} must be a non-negative integer literal",
+                        base.bright_blue(),
+                    ),
+                    token: index.token.clone(),
+                });
+                return diags;
+            }
+        },
+    };
+
+    let ty = match (index, ty) {
+        (None, ty) => ty,
+        (Some(i), Type::HeaderStack(element, size)) if i < size => {
+            Type::UserDefined(element)
+        }
+        (Some(i), Type::HeaderStack(_, size)) => {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "index {} out of bounds for header stack {} of size {}",
+                    i,
+                    base.bright_blue(),
+                    size,
+                ),
+                token: lval.token.clone(),
+            });
+            return diags;
+        }
+        (Some(_), ty) => {
+            diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "type {} cannot be indexed",
+                    ty.to_string().bright_blue(),
+                ),
+                token: lval.token.clone(),
+            });
+            return diags;
+        }
+    };
+
     match ty {
         Type::Bool => {
             if parts.len() > 1 {
@@ -829,6 +1563,20 @@ fn check_lvalue(
                 });
             }
         }
+        Type::HeaderStack(element, _) => {
+            if parts.len() > 1 {
+                let mut stack_names = names.clone();
+                stack_names.extend(header_stack_names(&element));
+                let sub_lval = lval.pop_left();
+                let sub_diags = check_lvalue(
+                    &sub_lval,
+                    ast,
+                    &stack_names,
+                    Some(&format!("{}[]", element)),
+                );
+                diags.extend(&sub_diags);
+            }
+        }
         Type::UserDefined(name) => {
             // get the parent type definition from the AST and check for the
             // referenced member
@@ -836,12 +1584,7 @@ fn check_lvalue(
                 if parts.len() > 1 {
                     let mut struct_names = names.clone();
                     struct_names.extend(parent.names());
-                    let mut token = lval.token.clone();
-                    token.col += parts[0].len() + 1;
-                    let sub_lval = Lvalue {
-                        name: parts[1..].join("."),
-                        token,
-                    };
+                    let sub_lval = lval.pop_left();
                     let sub_diags = check_lvalue(
                         &sub_lval,
                         ast,
@@ -854,12 +1597,7 @@ fn check_lvalue(
                 if parts.len() > 1 {
                     let mut header_names = names.clone();
                     header_names.extend(parent.names());
-                    let mut token = lval.token.clone();
-                    token.col += parts[0].len() + 1;
-                    let sub_lval = Lvalue {
-                        name: parts[1..].join("."),
-                        token,
-                    };
+                    let sub_lval = lval.pop_left();
                     let sub_diags = check_lvalue(
                         &sub_lval,
                         ast,
@@ -868,16 +1606,24 @@ fn check_lvalue(
                     );
                     diags.extend(&sub_diags);
                 }
+            } else if let Some(parent) = ast.get_header_union(&name) {
+                if parts.len() > 1 {
+                    let mut union_names = names.clone();
+                    union_names.extend(parent.names());
+                    let sub_lval = lval.pop_left();
+                    let sub_diags = check_lvalue(
+                        &sub_lval,
+                        ast,
+                        &union_names,
+                        Some(&parent.name),
+                    );
+                    diags.extend(&sub_diags);
+                }
             } else if let Some(parent) = ast.get_extern(&name) {
                 if parts.len() > 1 {
                     let mut extern_names = names.clone();
                     extern_names.extend(parent.names());
-                    let mut token = lval.token.clone();
-                    token.col += parts[0].len() + 1;
-                    let sub_lval = Lvalue {
-                        name: parts[1..].join("."),
-                        token,
-                    };
+                    let sub_lval = lval.pop_left();
                     let sub_diags = check_lvalue(
                         &sub_lval,
                         ast,
@@ -886,6 +1632,18 @@ fn check_lvalue(
                     );
                     diags.extend(&sub_diags);
                 }
+            } else if ast.get_enum(&name).is_some() {
+                if parts.len() > 1 {
+                    diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "type {} does not have a member {}",
+                            name.bright_blue(),
+                            parts[1].bright_blue(),
+                        ),
+                        token: lval.token.clone(),
+                    });
+                }
             } else if let Some(_control) = ast.get_control(&name) {
                 if parts.len() > 1 && parts.last() != Some(&"apply") {
                     diags.push(Diagnostic {
@@ -913,6 +1671,55 @@ fn check_lvalue(
     diags
 }
 
+fn check_enum_member_ref(lval: &Lvalue, e: &Enum) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    let parts = lval.parts();
+    if parts.len() != 2 {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!(
+                "enum {} must be referenced as {}.<member>",
+                e.name.bright_blue(),
+                e.name.bright_blue(),
+            ),
+            token: lval.token.clone(),
+        });
+    } else if e.get_member(parts[1]).is_none() {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!(
+                "enum {} does not have member {}",
+                e.name.bright_blue(),
+                parts[1].bright_blue(),
+            ),
+            token: lval.token.clone(),
+        });
+    }
+    diags
+}
+
+fn check_error_member_ref(lval: &Lvalue, ast: &AST) -> Diagnostics {
+    let mut diags = Diagnostics::new();
+    let parts = lval.parts();
+    if parts.len() != 2 {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!(
+                "errors must be referenced as {}.<member>",
+                "error".bright_blue(),
+            ),
+            token: lval.token.clone(),
+        });
+    } else if !ast.error_names().contains(&parts[1]) {
+        diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!("error {} not declared", parts[1].bright_blue()),
+            token: lval.token.clone(),
+        });
+    }
+    diags
+}
+
 pub struct ExpressionTypeChecker {
     //ast: &'a mut AST,
     //ast: RefCell::<AST>,
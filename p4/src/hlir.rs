@@ -1,10 +1,13 @@
 // Copyright 2022 Oxide Computer Company
 
 use crate::ast::{
-    BinOp, Constant, Control, DeclarationInfo, Expression, ExpressionKind,
-    Lvalue, NameInfo, Parser, Statement, StatementBlock, Type, AST,
+    BinOp, Call, Constant, Control, ControlParameter, DeclarationInfo,
+    Direction, Expression, ExpressionKind, ExternMethod, Function,
+    KeySetElement, KeySetElementValue, Lvalue, NameInfo, Parser, Select,
+    Statement, StatementBlock, Switch, SwitchLabel, Transition, Type, UnOp,
+    AST,
 };
-use crate::check::{Diagnostic, Diagnostics, Level};
+use crate::check::{conversion_allowed, Diagnostic, Diagnostics, Level};
 use crate::util::resolve_lvalue;
 use std::collections::HashMap;
 
@@ -28,6 +31,12 @@ pub struct HlirGenerator<'a> {
     ast: &'a AST,
     pub hlir: Hlir,
     pub diags: Diagnostics,
+
+    /// The control being processed, if any, for resolving local functions.
+    control: Option<&'a Control>,
+
+    /// The function whose body is being processed, if any.
+    function: Option<&'a Function>,
 }
 
 impl<'a> HlirGenerator<'a> {
@@ -36,12 +45,17 @@ impl<'a> HlirGenerator<'a> {
             ast,
             hlir: Hlir::default(),
             diags: Diagnostics::default(),
+            control: None,
+            function: None,
         }
     }
     pub fn run(&mut self) {
         for c in &self.ast.constants {
             self.constant(c);
         }
+        for f in &self.ast.functions {
+            self.function(f, self.ast.names());
+        }
         for c in &self.ast.controls {
             self.control(c);
         }
@@ -50,12 +64,18 @@ impl<'a> HlirGenerator<'a> {
         }
     }
 
-    fn constant(&mut self, _c: &Constant) {
-        // TODO
+    fn constant(&mut self, c: &Constant) {
+        let mut names = self.ast.names();
+        self.expression(c.initializer.as_ref(), &mut names);
     }
 
-    fn control(&mut self, c: &Control) {
-        let mut names = c.names();
+    fn control(&mut self, c: &'a Control) {
+        self.control = Some(c);
+        let mut names = self.ast.names();
+        names.extend(c.names());
+        for f in &c.functions {
+            self.function(f, names.clone());
+        }
         for a in &c.actions {
             let mut local_names = names.clone();
             local_names.extend(a.names());
@@ -71,6 +91,185 @@ impl<'a> HlirGenerator<'a> {
             }
         }
         self.statement_block(&c.apply, &mut names);
+        self.control = None;
+    }
+
+    fn function(
+        &mut self,
+        f: &'a Function,
+        mut names: HashMap<String, NameInfo>,
+    ) {
+        self.function = Some(f);
+        names.extend(f.names());
+        self.statement_block(&f.statement_block, &mut names);
+        self.function = None;
+    }
+
+    /// Return the declaration of the function an lvalue calls, if it calls
+    /// one. Functions local to the control being processed take precedence
+    /// over top level functions.
+    fn called_function(
+        &self,
+        lval: &Lvalue,
+        names: &HashMap<String, NameInfo>,
+    ) -> Option<&'a Function> {
+        if lval.degree() != 1 {
+            return None;
+        }
+        let name_info = names.get(lval.root())?;
+        if name_info.decl != DeclarationInfo::Function {
+            return None;
+        }
+        self.control
+            .and_then(|c| c.get_function(lval.root()))
+            .or_else(|| self.ast.get_function(lval.root()))
+    }
+
+    fn function_call(
+        &mut self,
+        call: &Call,
+        f: &Function,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Type {
+        self.hlir.lvalue_decls.insert(
+            call.lval.clone(),
+            NameInfo {
+                ty: f.return_type.clone(),
+                decl: DeclarationInfo::Function,
+            },
+        );
+        self.call_arguments(call, &f.name, &f.parameters, names);
+        f.return_type.clone()
+    }
+
+    fn called_extern_function(
+        &self,
+        lval: &Lvalue,
+        names: &HashMap<String, NameInfo>,
+    ) -> Option<&'a ExternMethod> {
+        if lval.degree() != 1 {
+            return None;
+        }
+        let name_info = names.get(lval.root())?;
+        if name_info.ty != Type::ExternFunction {
+            return None;
+        }
+        self.ast.get_extern_function(lval.root())
+    }
+
+    fn extern_function_call(
+        &mut self,
+        call: &Call,
+        f: &ExternMethod,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Type {
+        self.hlir.lvalue_decls.insert(
+            call.lval.clone(),
+            NameInfo {
+                ty: Type::ExternFunction,
+                decl: DeclarationInfo::Method,
+            },
+        );
+        if f.name == "verify"
+            && (self.control.is_some() || self.function.is_some())
+        {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                message: "verify may only be called from a parser".into(),
+                token: call.lval.token.clone(),
+            });
+        }
+        self.call_arguments(call, &f.name, &f.parameters, names);
+        f.return_type.clone()
+    }
+
+    /// Check the arguments of a call against the parameters of the function
+    /// being called.
+    fn call_arguments(
+        &mut self,
+        call: &Call,
+        name: &str,
+        parameters: &[ControlParameter],
+        names: &mut HashMap<String, NameInfo>,
+    ) {
+        if call.args.len() != parameters.len() {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "{} arguments provided to function {}, {} required",
+                    call.args.len(),
+                    name,
+                    parameters.len(),
+                ),
+                token: call.lval.token.clone(),
+            });
+        }
+        for (arg, p) in call.args.iter().zip(parameters.iter()) {
+            let ty = self.expression(arg.as_ref(), names);
+            if let Direction::Out | Direction::InOut = p.direction {
+                if !matches!(arg.kind, ExpressionKind::Lvalue(_)) {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "argument for {} parameter {} must be an lvalue",
+                            if p.direction == Direction::Out {
+                                "out"
+                            } else {
+                                "inout"
+                            },
+                            p.name,
+                        ),
+                        token: arg.token.clone(),
+                    });
+                }
+            }
+            if let Some(ty) = ty {
+                self.assignment(arg.as_ref(), &ty, &p.ty);
+            }
+        }
+        for arg in call.args.iter().skip(parameters.len()) {
+            self.expression(arg.as_ref(), names);
+        }
+    }
+
+    fn return_statement(
+        &mut self,
+        xpr: Option<&Expression>,
+        names: &mut HashMap<String, NameInfo>,
+    ) {
+        let ty = xpr.and_then(|xpr| self.expression(xpr, names));
+        let f = match self.function {
+            Some(f) => f,
+            None => return,
+        };
+        match (xpr, &f.return_type) {
+            (Some(xpr), Type::Void) => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "void function {} cannot return a value",
+                        f.name,
+                    ),
+                    token: xpr.token.clone(),
+                });
+            }
+            (Some(xpr), return_type) => {
+                if let Some(ty) = ty {
+                    self.assignment(xpr, &ty, return_type);
+                }
+            }
+            (None, Type::Void) => {}
+            (None, return_type) => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "function {} must return a {}",
+                        f.name, return_type,
+                    ),
+                    token: f.token.clone(),
+                });
+            }
+        }
     }
 
     fn statement_block(
@@ -82,10 +281,28 @@ impl<'a> HlirGenerator<'a> {
             match stmt {
                 Statement::Empty => {}
                 Statement::Assignment(lval, xpr) => {
-                    self.lvalue(lval, names);
-                    self.expression(xpr, names);
+                    let ty = self.lvalue(lval, names);
+                    let ty = match (&lval.slice, ty) {
+                        (Some((hi, lo)), Some(ty)) => {
+                            self.lvalue_slice(lval, hi, lo, &ty)
+                        }
+                        (_, ty) => ty,
+                    };
+                    let xpr_ty = self.expression(xpr, names);
+                    if let (Some(ty), Some(xpr_ty)) = (ty, xpr_ty) {
+                        self.assignment(xpr, &xpr_ty, &ty);
+                    }
                 }
                 Statement::Call(c) => {
+                    if let Some(f) = self.called_function(&c.lval, names) {
+                        self.function_call(c, f, names);
+                        continue;
+                    }
+                    if let Some(f) = self.called_extern_function(&c.lval, names)
+                    {
+                        self.extern_function_call(c, f, names);
+                        continue;
+                    }
                     // pop the function name off the lval before resolving
                     self.lvalue(&c.lval.pop_right(), names);
                     for xpr in &c.args {
@@ -112,7 +329,9 @@ impl<'a> HlirGenerator<'a> {
                         },
                     );
                     if let Some(initializer) = &v.initializer {
-                        self.expression(initializer, names);
+                        if let Some(ty) = self.expression(initializer, names) {
+                            self.assignment(initializer, &ty, &v.ty);
+                        }
                     }
                 }
                 Statement::Constant(c) => {
@@ -125,18 +344,48 @@ impl<'a> HlirGenerator<'a> {
                     );
                     self.expression(c.initializer.as_ref(), names);
                 }
-                Statement::Transition(_t) => {
-                    //TODO
+                Statement::Transition(t) => {
+                    if let Transition::Select(sel) = t {
+                        self.select(sel, names);
+                    }
                 }
+                Statement::Switch(sw) => self.switch(sw, names),
                 Statement::Return(xpr) => {
-                    if let Some(xpr) = xpr {
-                        self.expression(xpr.as_ref(), names);
-                    }
+                    self.return_statement(xpr.as_deref(), names);
                 }
             }
         }
     }
 
+    /// Check that the value of `xpr` can be converted to the type it is
+    /// assigned to.
+    fn assignment(&mut self, xpr: &Expression, from: &Type, to: &Type) {
+        let scalar = |ty: &Type| match ty {
+            Type::Bit(_) | Type::Int(_) | Type::Bool | Type::Error => true,
+            Type::UserDefined(name) => self.ast.get_enum(name).is_some(),
+            _ => false,
+        };
+        if !scalar(from) || !scalar(to) {
+            return;
+        }
+        if self.literal_conversion(xpr, to)
+            || conversion_allowed(from, to, self.ast)
+        {
+            return;
+        }
+        self.diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!("cannot assign a {} to a {}", from, to),
+            token: xpr.token.clone(),
+        });
+    }
+
+    /// Integer literals convert to bit and signed values of any width.
+    fn literal_conversion(&self, xpr: &Expression, to: &Type) -> bool {
+        matches!(xpr.kind, ExpressionKind::IntegerLit(_))
+            && matches!(to, Type::Bit(_) | Type::Int(_))
+    }
+
     fn expression(
         &mut self,
         xpr: &Expression,
@@ -172,6 +421,26 @@ impl<'a> HlirGenerator<'a> {
             ExpressionKind::Binary(lhs, op, rhs) => {
                 self.binary_expression(xpr, lhs, rhs, op, names)
             }
+            ExpressionKind::Unary(op, operand) => {
+                self.unary_expression(xpr, operand, op, names)
+            }
+            ExpressionKind::Ternary(cond, then, otherwise) => {
+                self.ternary_expression(xpr, cond, then, otherwise, names)
+            }
+            ExpressionKind::Cast(ty, operand) => {
+                let from = self.expression(operand, names)?;
+                if !self.literal_conversion(operand, ty)
+                    && !conversion_allowed(&from, ty, self.ast)
+                {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!("cannot cast a {} to {}", from, ty),
+                        token: xpr.token.clone(),
+                    });
+                }
+                self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                Some(ty.clone())
+            }
             ExpressionKind::Index(lval, i_xpr) => {
                 if let Some(ty) = self.index(lval, i_xpr, names) {
                     self.hlir.expression_types.insert(xpr.clone(), ty.clone());
@@ -189,6 +458,17 @@ impl<'a> HlirGenerator<'a> {
                 None
             }
             ExpressionKind::Call(call) => {
+                if let Some(f) = self.called_function(&call.lval, names) {
+                    let ty = self.function_call(call, f, names);
+                    self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                    return Some(ty);
+                }
+                if let Some(f) = self.called_extern_function(&call.lval, names)
+                {
+                    let ty = self.extern_function_call(call, f, names);
+                    self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                    return Some(ty);
+                }
                 self.lvalue(&call.lval.pop_right(), names)?;
                 for arg in &call.args {
                     self.expression(arg.as_ref(), names);
@@ -198,31 +478,232 @@ impl<'a> HlirGenerator<'a> {
                     if let Type::UserDefined(typename) = &name_info.ty {
                         if let Some(ext) = self.ast.get_extern(typename) {
                             if let Some(m) = ext.get_method(call.lval.leaf()) {
+                                let ty = self.method_return_type(call, m);
                                 self.hlir
                                     .expression_types
-                                    .insert(xpr.clone(), m.return_type.clone());
-                                return Some(m.return_type.clone());
+                                    .insert(xpr.clone(), ty.clone());
+                                return Some(ty);
                             }
                         }
                     }
                 };
                 //TODO less special case-y?
-                Some(match call.lval.leaf() {
+                let ty = match call.lval.leaf() {
                     "isValid" => Type::Bool,
                     _ => Type::Void,
-                })
+                };
+                self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                Some(ty)
             }
             ExpressionKind::List(elements) => {
                 let mut type_elements = Vec::new();
                 for e in elements {
-                    let ty = match self.expression(e.as_ref(), names) {
-                        Some(ty) => ty,
-                        None => return None,
-                    };
+                    let ty = self.expression(e.as_ref(), names)?;
                     type_elements.push(Box::new(ty));
                 }
                 Some(Type::List(type_elements))
             }
+            ExpressionKind::Member(call_xpr, member) => {
+                self.expression(call_xpr.as_ref(), names)?;
+                let ty = self.apply_result_member(call_xpr, member, names)?;
+                self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+                Some(ty)
+            }
+        }
+    }
+
+    /// The return type of an extern method call. A method returning one of
+    /// its type parameters, like `lookahead<T>`, returns the corresponding
+    /// type argument of the call.
+    fn method_return_type(&mut self, call: &Call, m: &ExternMethod) -> Type {
+        let name = match &m.return_type {
+            Type::UserDefined(name) => name,
+            ty => return ty.clone(),
+        };
+        let i = match m.type_parameters.iter().position(|p| p == name) {
+            Some(i) => i,
+            None => return m.return_type.clone(),
+        };
+        match call.type_args.get(i) {
+            Some(ty) => ty.clone(),
+            None => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "type argument {} of {} required",
+                        name,
+                        call.lval.leaf(),
+                    ),
+                    token: call.lval.token.clone(),
+                });
+                m.return_type.clone()
+            }
+        }
+    }
+
+    /// Members are only accessible on the result of a table apply call.
+    fn apply_result_member(
+        &mut self,
+        xpr: &Expression,
+        member: &str,
+        names: &HashMap<String, NameInfo>,
+    ) -> Option<Type> {
+        let is_table_apply = match &xpr.kind {
+            ExpressionKind::Call(call) => {
+                call.lval.leaf() == "apply"
+                    && matches!(
+                        names.get(call.lval.root()).map(|n| &n.ty),
+                        Some(Type::Table)
+                    )
+            }
+            _ => false,
+        };
+        if !is_table_apply {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                message: "members may only be accessed on table apply results"
+                    .into(),
+                token: xpr.token.clone(),
+            });
+            return None;
+        }
+        match member {
+            "hit" | "miss" => Some(Type::Bool),
+            "action_run" => Some(Type::Action),
+            _ => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!("apply result has no member {}", member),
+                    token: xpr.token.clone(),
+                });
+                None
+            }
+        }
+    }
+
+    fn switch(&mut self, sw: &Switch, names: &mut HashMap<String, NameInfo>) {
+        let ty = self.expression(sw.expression.as_ref(), names);
+        let ty = match ty {
+            Some(Type::UserDefined(name))
+                if self.ast.get_enum(&name).is_some() =>
+            {
+                Some(Type::UserDefined(name))
+            }
+            Some(ty @ (Type::Action | Type::Bit(_) | Type::Int(_))) => Some(ty),
+            Some(ty) => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!("cannot switch on a {}", ty),
+                    token: sw.expression.token.clone(),
+                });
+                None
+            }
+            None => None,
+        };
+        for case in &sw.cases {
+            for label in &case.labels {
+                let xpr = match label {
+                    SwitchLabel::Expression(xpr) => xpr,
+                    SwitchLabel::Default => continue,
+                };
+                let label_ty = match self.expression(xpr.as_ref(), names) {
+                    Some(label_ty) => label_ty,
+                    None => continue,
+                };
+                let ty = match &ty {
+                    Some(ty) => ty,
+                    None => continue,
+                };
+                // integer literals take on the width of the switch expression
+                if let ExpressionKind::IntegerLit(_) = &xpr.kind {
+                    continue;
+                }
+                if &label_ty != ty {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "cannot switch on a {} with a {} label",
+                            ty, label_ty,
+                        ),
+                        token: xpr.token.clone(),
+                    });
+                }
+            }
+            self.statement_block(&case.block, names);
+        }
+    }
+
+    fn select(&mut self, sel: &Select, names: &mut HashMap<String, NameInfo>) {
+        let mut param_types = Vec::new();
+        for p in &sel.parameters {
+            param_types.push(self.expression(p.as_ref(), names));
+        }
+        for e in &sel.elements {
+            if e.keyset.len() != param_types.len() {
+                // a lone default or dont-care matches any parameter list
+                if e.keyset.len() == 1
+                    && matches!(
+                        e.keyset[0].value,
+                        KeySetElementValue::Default
+                            | KeySetElementValue::DontCare
+                    )
+                {
+                    continue;
+                }
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "keyset has {} elements, select has {} parameters",
+                        e.keyset.len(),
+                        param_types.len(),
+                    ),
+                    token: e.keyset[0].token.clone(),
+                });
+                continue;
+            }
+            for (k, param_ty) in e.keyset.iter().zip(param_types.iter()) {
+                self.keyset_element(k, param_ty.as_ref(), names);
+            }
+        }
+    }
+
+    fn keyset_element(
+        &mut self,
+        k: &KeySetElement,
+        param_ty: Option<&Type>,
+        names: &mut HashMap<String, NameInfo>,
+    ) {
+        let xprs = match &k.value {
+            KeySetElementValue::Expression(xpr) => vec![xpr],
+            KeySetElementValue::Masked(val, mask) => vec![val, mask],
+            KeySetElementValue::Ranged(begin, end) => vec![begin, end],
+            KeySetElementValue::Default | KeySetElementValue::DontCare => {
+                return
+            }
+        };
+        for xpr in xprs {
+            let ty = match self.expression(xpr.as_ref(), names) {
+                Some(ty) => ty,
+                None => continue,
+            };
+            let param_ty = match param_ty {
+                Some(ty) => ty,
+                None => continue,
+            };
+            // integer literals take on the width of the select parameter
+            if let ExpressionKind::IntegerLit(_) = &xpr.kind {
+                continue;
+            }
+            if &ty != param_ty {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!(
+                        "cannot match a {} against a {}",
+                        ty, param_ty,
+                    ),
+                    token: xpr.token.clone(),
+                });
+            }
         }
     }
 
@@ -232,10 +713,7 @@ impl<'a> HlirGenerator<'a> {
         xpr: &Expression,
         names: &mut HashMap<String, NameInfo>,
     ) -> Option<Type> {
-        let base_type = match self.lvalue(lval, names) {
-            Some(ty) => ty,
-            None => return None,
-        };
+        let base_type = self.lvalue(lval, names)?;
         match base_type {
             Type::Bool => {
                 self.diags.push(Diagnostic {
@@ -302,7 +780,7 @@ impl<'a> HlirGenerator<'a> {
                 }
             },
             Type::Varbit(width) => match &xpr.kind {
-                ExpressionKind::Slice(begin, end) => {
+                ExpressionKind::Slice(end, begin) => {
                     let (begin_val, end_val) = self.slice(begin, end, width)?;
                     let w = end_val - begin_val + 1;
                     Some(Type::Varbit(w as usize))
@@ -318,10 +796,11 @@ impl<'a> HlirGenerator<'a> {
                 }
             },
             Type::Int(width) => match &xpr.kind {
-                ExpressionKind::Slice(begin, end) => {
+                ExpressionKind::Slice(end, begin) => {
+                    // slices of signed values are unsigned
                     let (begin_val, end_val) = self.slice(begin, end, width)?;
                     let w = end_val - begin_val + 1;
-                    Some(Type::Int(w as usize))
+                    Some(Type::Bit(w as usize))
                 }
                 _ => {
                     self.diags.push(Diagnostic {
@@ -341,6 +820,40 @@ impl<'a> HlirGenerator<'a> {
                 });
                 None
             }
+            Type::HeaderStack(element, size) => match &xpr.kind {
+                ExpressionKind::IntegerLit(i) if (*i as usize) < size => {
+                    Some(Type::UserDefined(element))
+                }
+                ExpressionKind::IntegerLit(i) => {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "index {} out of bounds for header stack of size {}",
+                            i, size,
+                        ),
+                        token: xpr.token.clone(),
+                    });
+                    None
+                }
+                // indices only known at runtime may be out of range, see
+                // p4rs::HeaderStack for how such accesses behave
+                _ => match self.expression(xpr, names)? {
+                    Type::Bit(_) | Type::Int(_) => {
+                        Some(Type::UserDefined(element))
+                    }
+                    ty => {
+                        self.diags.push(Diagnostic {
+                            level: Level::Error,
+                            message: format!(
+                                "cannot index a header stack with a {}",
+                                ty,
+                            ),
+                            token: xpr.token.clone(),
+                        });
+                        None
+                    }
+                },
+            },
             Type::UserDefined(_) => {
                 self.diags.push(Diagnostic {
                     level: Level::Error,
@@ -376,6 +889,30 @@ impl<'a> HlirGenerator<'a> {
         }
     }
 
+    /// The type of the bits of an lvalue that a slice assignment writes to.
+    fn lvalue_slice(
+        &mut self,
+        lval: &Lvalue,
+        hi: &Expression,
+        lo: &Expression,
+        ty: &Type,
+    ) -> Option<Type> {
+        match ty {
+            Type::Bit(width) | Type::Int(width) => {
+                let (lo, hi) = self.slice(lo, hi, *width)?;
+                Some(Type::Bit((hi - lo + 1) as usize))
+            }
+            _ => {
+                self.diags.push(Diagnostic {
+                    level: Level::Error,
+                    message: format!("cannot assign to a slice of a {}", ty),
+                    token: lval.token.clone(),
+                });
+                None
+            }
+        }
+    }
+
     fn slice(
         &mut self,
         begin: &Expression,
@@ -429,11 +966,11 @@ impl<'a> HlirGenerator<'a> {
             });
             return None;
         }
-        if begin_val >= end_val {
+        if begin_val > end_val {
             self.diags.push(Diagnostic {
                 level: Level::Error,
-                message: "slice upper bound must be \
-                    greater than the lower bound"
+                message: "slice upper bound must not be \
+                    less than the lower bound"
                     .into(),
                 token: begin.token.clone(),
             });
@@ -452,6 +989,7 @@ impl<'a> HlirGenerator<'a> {
                 self.hlir
                     .lvalue_decls
                     .insert(lval.clone(), name_info.clone());
+                self.lvalue_prefixes(lval, names);
                 Some(name_info.ty)
             }
             Err(e) => {
@@ -459,7 +997,7 @@ impl<'a> HlirGenerator<'a> {
                     level: Level::Error,
                     message: format!(
                         "could not resolve lvalue: {}\n    {}",
-                        lval.name, e,
+                        lval, e,
                     ),
                     token: lval.token.clone(),
                 });
@@ -468,6 +1006,27 @@ impl<'a> HlirGenerator<'a> {
         }
     }
 
+    /// Record declarations for the prefixes along the path of an lvalue, so
+    /// the type each member is reached through is known. This is what tells
+    /// stack members like `next` and `last` and union members apart from
+    /// ordinary struct and header members.
+    fn lvalue_prefixes(
+        &mut self,
+        lval: &Lvalue,
+        names: &HashMap<String, NameInfo>,
+    ) {
+        let mut prefix = lval.pop_right();
+        while prefix.degree() > 0 && !prefix.name.is_empty() {
+            if let Ok(info) = resolve_lvalue(&prefix, self.ast, names) {
+                self.hlir.lvalue_decls.insert(prefix.clone(), info);
+            }
+            if prefix.degree() == 1 {
+                break;
+            }
+            prefix = prefix.pop_right();
+        }
+    }
+
     fn binary_expression(
         &mut self,
         xpr: &Expression,
@@ -476,41 +1035,209 @@ impl<'a> HlirGenerator<'a> {
         op: &BinOp,
         names: &mut HashMap<String, NameInfo>,
     ) -> Option<Type> {
-        let lhs_ty = match self.expression(lhs, names) {
-            Some(ty) => ty,
-            None => return None,
+        let lhs_ty = self.expression(lhs, names)?;
+
+        let rhs_ty = self.expression(rhs, names)?;
+
+        // integer literals take on the type of the other operand
+        let (lhs_ty, rhs_ty) = match (&lhs.kind, &rhs.kind) {
+            (ExpressionKind::IntegerLit(_), ExpressionKind::IntegerLit(_)) => {
+                (lhs_ty, rhs_ty)
+            }
+            (ExpressionKind::IntegerLit(_), _) if is_numeric(&rhs_ty) => {
+                (rhs_ty.clone(), rhs_ty)
+            }
+            (_, ExpressionKind::IntegerLit(_)) if is_numeric(&lhs_ty) => {
+                (lhs_ty.clone(), lhs_ty)
+            }
+            _ => (lhs_ty, rhs_ty),
         };
 
-        let rhs_ty = match self.expression(rhs, names) {
-            Some(ty) => ty,
-            None => return None,
+        let ty = match op {
+            BinOp::Shl | BinOp::Shr => {
+                if !is_numeric(&lhs_ty) || !is_numeric(&rhs_ty) {
+                    self.operand_mismatch(xpr, op, &lhs_ty, &rhs_ty);
+                }
+                lhs_ty
+            }
+            BinOp::Concat => {
+                let ty = match (&lhs_ty, &rhs_ty) {
+                    (Type::Bit(l), Type::Bit(r)) => Type::Bit(l + r),
+                    (Type::Int(l), Type::Bit(r)) => Type::Int(l + r),
+                    _ => {
+                        self.operand_mismatch(xpr, op, &lhs_ty, &rhs_ty);
+                        lhs_ty
+                    }
+                };
+                if let Type::Bit(w) | Type::Int(w) = ty {
+                    if w > 128 {
+                        self.diags.push(Diagnostic {
+                            level: Level::Error,
+                            message: format!(
+                                "concatenation produces a {}, wider than \
+                                the 128 bit maximum",
+                                ty,
+                            ),
+                            token: xpr.token.clone(),
+                        });
+                    }
+                }
+                ty
+            }
+            BinOp::Div | BinOp::Mod => {
+                // According to P4-16 section 8.6, division and modulo are only
+                // defined for positive values. Require the divisor be a
+                // positive literal so the result is defined for every packet.
+                let positive = match &rhs.kind {
+                    ExpressionKind::IntegerLit(v) => *v > 0,
+                    ExpressionKind::BitLit(_, v) => *v > 0,
+                    ExpressionKind::SignedLit(_, v) => *v > 0,
+                    _ => false,
+                };
+                if !positive {
+                    self.diags.push(Diagnostic {
+                        level: Level::Error,
+                        message: format!(
+                            "cannot {} by a value that is not a positive \
+                            literal",
+                            op.english_verb(),
+                        ),
+                        token: rhs.token.clone(),
+                    });
+                }
+                if lhs_ty != rhs_ty {
+                    self.operand_mismatch(xpr, op, &lhs_ty, &rhs_ty);
+                }
+                lhs_ty
+            }
+            BinOp::LogicalAnd | BinOp::LogicalOr => {
+                if lhs_ty != Type::Bool || rhs_ty != Type::Bool {
+                    self.operand_mismatch(xpr, op, &lhs_ty, &rhs_ty);
+                }
+                Type::Bool
+            }
+            _ => {
+                // TODO just checking that types are the same for now.
+                if lhs_ty != rhs_ty
+                    && self.enum_underlying_type(&lhs_ty) != Some(&rhs_ty)
+                    && self.enum_underlying_type(&rhs_ty) != Some(&lhs_ty)
+                {
+                    self.operand_mismatch(xpr, op, &lhs_ty, &rhs_ty);
+                }
+                if op.is_comparison() {
+                    Type::Bool
+                } else {
+                    lhs_ty
+                }
+            }
         };
 
-        // TODO just checking that types are the same for now.
-        if lhs_ty != rhs_ty {
+        self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+        Some(ty)
+    }
+
+    fn operand_mismatch(
+        &mut self,
+        xpr: &Expression,
+        op: &BinOp,
+        lhs_ty: &Type,
+        rhs_ty: &Type,
+    ) {
+        self.diags.push(Diagnostic {
+            level: Level::Error,
+            message: format!(
+                "cannot {} a {} and a {}",
+                op.english_verb(),
+                lhs_ty,
+                rhs_ty,
+            ),
+            token: xpr.token.clone(),
+        });
+    }
+
+    fn unary_expression(
+        &mut self,
+        xpr: &Expression,
+        operand: &Expression,
+        op: &UnOp,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Option<Type> {
+        let ty = self.expression(operand, names)?;
+        let ok = match op {
+            UnOp::Not => ty == Type::Bool,
+            UnOp::Complement => matches!(ty, Type::Bit(_) | Type::Int(_)),
+            UnOp::Negate => is_numeric(&ty),
+        };
+        if !ok {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!("cannot {} a {}", op.english_verb(), ty),
+                token: xpr.token.clone(),
+            });
+        }
+        self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+        Some(ty)
+    }
+
+    fn ternary_expression(
+        &mut self,
+        xpr: &Expression,
+        cond: &Expression,
+        then: &Expression,
+        otherwise: &Expression,
+        names: &mut HashMap<String, NameInfo>,
+    ) -> Option<Type> {
+        let cond_ty = self.expression(cond, names)?;
+        if cond_ty != Type::Bool {
             self.diags.push(Diagnostic {
                 level: Level::Error,
                 message: format!(
-                    "cannot {} a {} and a {}",
-                    op.english_verb(),
-                    lhs_ty,
-                    rhs_ty,
+                    "condition must be a bool, found {}",
+                    cond_ty,
+                ),
+                token: cond.token.clone(),
+            });
+        }
+        let then_ty = self.expression(then, names)?;
+        let otherwise_ty = self.expression(otherwise, names)?;
+        let ty = match (&then.kind, &otherwise.kind) {
+            (ExpressionKind::IntegerLit(_), _) => otherwise_ty.clone(),
+            _ => then_ty.clone(),
+        };
+        let literal =
+            |x: &Expression| matches!(x.kind, ExpressionKind::IntegerLit(_));
+        if then_ty != otherwise_ty && !literal(then) && !literal(otherwise) {
+            self.diags.push(Diagnostic {
+                level: Level::Error,
+                message: format!(
+                    "conditional branches have different types {} and {}",
+                    then_ty, otherwise_ty,
                 ),
                 token: xpr.token.clone(),
             });
         }
+        self.hlir.expression_types.insert(xpr.clone(), ty.clone());
+        Some(ty)
+    }
 
-        self.hlir
-            .expression_types
-            .insert(xpr.clone(), lhs_ty.clone());
-        Some(lhs_ty)
+    /// If `ty` is a serializable enum, return its underlying type.
+    fn enum_underlying_type(&self, ty: &Type) -> Option<&'a Type> {
+        match ty {
+            Type::UserDefined(name) => self.ast.get_enum(name)?.ty.as_ref(),
+            _ => None,
+        }
     }
 
     fn parser(&mut self, p: &Parser) {
-        let names = p.names();
+        let mut names = self.ast.names();
+        names.extend(p.names());
         for s in &p.states {
             let mut local_names = names.clone();
             self.statement_block(&s.statements, &mut local_names);
         }
     }
 }
+
+fn is_numeric(ty: &Type) -> bool {
+    matches!(ty, Type::Bit(_) | Type::Int(_))
+}
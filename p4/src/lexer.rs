@@ -11,9 +11,11 @@ pub enum Kind {
     //
     Const,
     Header,
+    HeaderUnion,
     Typedef,
     Control,
     Struct,
+    Enum,
     Action,
     Parser,
     Table,
@@ -38,6 +40,9 @@ pub enum Kind {
     If,
     Else,
     Return,
+    Default,
+    Switch,
+    Function,
 
     //
     // types
@@ -48,6 +53,7 @@ pub enum Kind {
     Varbit,
     Int,
     String,
+    Void,
 
     //
     // lexical elements
@@ -83,13 +89,20 @@ pub enum Kind {
     Minus,
     Mod,
     Dot,
+    DotDot,
     Mask,
     LogicalAnd,
+    LogicalOr,
     And,
     Bang,
     Tilde,
     Shl,
     Pipe,
+    SaturatingPlus,
+    SaturatingMinus,
+    PlusPlus,
+    Star,
+    Question,
     Carat,
     GreaterThanEquals,
     LessThanEquals,
@@ -139,9 +152,11 @@ impl fmt::Display for Kind {
             //
             Kind::Const => write!(f, "keyword const"),
             Kind::Header => write!(f, "keyword header"),
+            Kind::HeaderUnion => write!(f, "keyword header_union"),
             Kind::Typedef => write!(f, "keyword typedef"),
             Kind::Control => write!(f, "keyword control"),
             Kind::Struct => write!(f, "keyword struct"),
+            Kind::Enum => write!(f, "keyword enum"),
             Kind::Action => write!(f, "keyword action"),
             Kind::Parser => write!(f, "keyword parser"),
             Kind::Table => write!(f, "keyword table"),
@@ -166,6 +181,9 @@ impl fmt::Display for Kind {
             Kind::If => write!(f, "keyword if"),
             Kind::Else => write!(f, "keyword else"),
             Kind::Return => write!(f, "keyword return"),
+            Kind::Default => write!(f, "keyword default"),
+            Kind::Switch => write!(f, "keyword switch"),
+            Kind::Function => write!(f, "keyword function"),
 
             //
             // types
@@ -176,6 +194,7 @@ impl fmt::Display for Kind {
             Kind::Varbit => write!(f, "type varbit"),
             Kind::Int => write!(f, "type int"),
             Kind::String => write!(f, "type string"),
+            Kind::Void => write!(f, "type void"),
 
             //
             // lexical elements
@@ -211,13 +230,20 @@ impl fmt::Display for Kind {
             Kind::Minus => write!(f, "operator -"),
             Kind::Mod => write!(f, "operator %"),
             Kind::Dot => write!(f, "operator ."),
+            Kind::DotDot => write!(f, "operator .."),
             Kind::Mask => write!(f, "operator &&&"),
             Kind::LogicalAnd => write!(f, "operator &&"),
+            Kind::LogicalOr => write!(f, "operator ||"),
             Kind::And => write!(f, "operator &"),
             Kind::Bang => write!(f, "operator !"),
             Kind::Tilde => write!(f, "operator ~"),
             Kind::Shl => write!(f, "operator <<"),
             Kind::Pipe => write!(f, "operator |"),
+            Kind::SaturatingPlus => write!(f, "operator |+|"),
+            Kind::SaturatingMinus => write!(f, "operator |-|"),
+            Kind::PlusPlus => write!(f, "operator ++"),
+            Kind::Star => write!(f, "operator *"),
+            Kind::Question => write!(f, "operator ?"),
             Kind::Carat => write!(f, "operator ^"),
             Kind::GreaterThanEquals => write!(f, "operator >="),
             Kind::LessThanEquals => write!(f, "operator <="),
@@ -389,6 +415,18 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("default", Kind::Default) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("switch", Kind::Switch) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("function", Kind::Function) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("&&", Kind::LogicalAnd) {
             return Ok(t);
         }
@@ -405,6 +443,18 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("|+|", Kind::SaturatingPlus) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("|-|", Kind::SaturatingMinus) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("||", Kind::LogicalOr) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("|", Kind::Pipe) {
             return Ok(t);
         }
@@ -425,7 +475,7 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
-        if let Some(t) = self.match_token(">=", Kind::LessThanEquals) {
+        if let Some(t) = self.match_token("<=", Kind::LessThanEquals) {
             return Ok(t);
         }
 
@@ -457,6 +507,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("++", Kind::PlusPlus) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("+", Kind::Plus) {
             return Ok(t);
         }
@@ -465,6 +519,14 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("*", Kind::Star) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("?", Kind::Question) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("%", Kind::Mod) {
             return Ok(t);
         }
@@ -485,6 +547,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("..", Kind::DotDot) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token(".", Kind::Dot) {
             return Ok(t);
         }
@@ -529,6 +595,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("void", Kind::Void) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("typedef", Kind::Typedef) {
             return Ok(t);
         }
@@ -537,6 +607,10 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("header_union", Kind::HeaderUnion) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("const", Kind::Const) {
             return Ok(t);
         }
@@ -549,6 +623,14 @@ impl<'a> Lexer<'a> {
             return Ok(t);
         }
 
+        if let Some(t) = self.match_token("enum", Kind::Enum) {
+            return Ok(t);
+        }
+
+        if let Some(t) = self.match_token("error", Kind::Error) {
+            return Ok(t);
+        }
+
         if let Some(t) = self.match_token("actions", Kind::Actions) {
             return Ok(t);
         }
@@ -946,7 +1028,10 @@ impl<'a> Lexer<'a> {
         match chars.next() {
             Some(';') => return &self.cursor[..1],
             Some(',') => return &self.cursor[..1],
-            Some('+') => return &self.cursor[..1],
+            Some('+') => match chars.next() {
+                Some('+') => return &self.cursor[..2],
+                _ => return &self.cursor[..1],
+            },
             Some('-') => return &self.cursor[..1],
             Some('(') => return &self.cursor[..1],
             Some(')') => return &self.cursor[..1],
@@ -954,11 +1039,20 @@ impl<'a> Lexer<'a> {
             Some('}') => return &self.cursor[..1],
             Some('[') => return &self.cursor[..1],
             Some(']') => return &self.cursor[..1],
-            Some('.') => return &self.cursor[..1],
+            Some('.') => match chars.next() {
+                Some('.') => return &self.cursor[..2],
+                _ => return &self.cursor[..1],
+            },
             Some(':') => return &self.cursor[..1],
             Some('*') => return &self.cursor[..1],
-            Some('|') => return &self.cursor[..1],
+            Some('|') => match (chars.next(), chars.next()) {
+                (Some('+'), Some('|')) => return &self.cursor[..3],
+                (Some('-'), Some('|')) => return &self.cursor[..3],
+                (Some('|'), _) => return &self.cursor[..2],
+                _ => return &self.cursor[..1],
+            },
             Some('~') => return &self.cursor[..1],
+            Some('?') => return &self.cursor[..1],
             Some('^') => return &self.cursor[..1],
             Some('\\') => return &self.cursor[..1],
             Some('/') => return &self.cursor[..1],
@@ -1066,6 +1160,9 @@ impl<'a> Lexer<'a> {
         if c == '~' {
             return true;
         }
+        if c == '?' {
+            return true;
+        }
         if c == '\\' {
             return true;
         }
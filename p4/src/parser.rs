@@ -2,12 +2,13 @@
 
 use crate::ast::{
     self, Action, ActionParameter, ActionRef, BinOp, Call, ConstTableEntry,
-    Constant, Control, ControlParameter, Direction, ElseIfBlock, Expression,
-    ExpressionKind, Extern, ExternMethod, Header, HeaderMember, IfBlock,
+    Constant, Control, ControlParameter, Direction, ElseIfBlock, Enum,
+    EnumMember, ErrorMember, Expression, ExpressionKind, Extern, ExternMethod,
+    Function, Header, HeaderMember, HeaderUnion, HeaderUnionMember, IfBlock,
     KeySetElement, KeySetElementValue, Lvalue, MatchKind, Package,
     PackageInstance, PackageParameter, Select, SelectElement, State, Statement,
-    StatementBlock, Struct, StructMember, Table, Transition, Type, Typedef,
-    Variable, AST,
+    StatementBlock, Struct, StructMember, Switch, SwitchCase, SwitchLabel,
+    Table, Transition, Type, Typedef, UnOp, Variable, AST,
 };
 use crate::error::{Error, ParserError};
 use crate::lexer::{self, Kind, Lexer, Token};
@@ -30,6 +31,7 @@ impl<'a> Parser<'a> {
     pub fn run(&mut self, ast: &mut AST) -> Result<(), Error> {
         let mut gp = GlobalParser::new(self);
         gp.run(ast)?;
+        ast.resolve_typedefs();
         Ok(())
     }
 
@@ -41,6 +43,14 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Check whether the next token is an identifier without consuming it.
+    fn peek_identifier(&mut self) -> Result<bool, Error> {
+        let token = self.next_token()?;
+        let result = matches!(token.kind, Kind::Identifier(_));
+        self.backlog.push(token);
+        Ok(result)
+    }
+
     /// Consume a series of tokens constituting a path. Returns the first
     /// non-path element found.
     #[allow(dead_code)]
@@ -106,6 +116,8 @@ impl<'a> Parser<'a> {
     fn parse_lvalue(&mut self, what: &str) -> Result<Lvalue, Error> {
         let mut name = String::new();
         let mut first_token = None;
+        let mut index = None;
+        let mut part = 0;
         loop {
             let (ident, tk) = self.parse_identifier(what)?;
             match first_token {
@@ -113,6 +125,23 @@ impl<'a> Parser<'a> {
                 None => first_token = Some(tk),
             }
             name = name + &ident;
+            if let Some(i) = self.parse_optional_stack_index()? {
+                if index.is_some() {
+                    return Err(ParserError {
+                        at: i.token.clone(),
+                        message: "only one header stack index may appear in \
+                            a path"
+                            .into(),
+                        source: self.lexer.lines[i.token.line].into(),
+                    }
+                    .into());
+                }
+                index = Some((part, i));
+                name += ".";
+                part += 1;
+                continue;
+            }
+            part += 1;
             let token = self.next_token()?;
             match token.kind {
                 lexer::Kind::Dot => name += ".",
@@ -125,9 +154,45 @@ impl<'a> Parser<'a> {
         Ok(Lvalue {
             name,
             token: first_token.unwrap(),
+            index,
+            slice: None,
         })
     }
 
+    /// Consume a header stack index of the form `[N].` within a path. If the
+    /// upcoming tokens are not of that form they are left unconsumed, so
+    /// index and slice expressions can be parsed as such.
+    fn parse_optional_stack_index(
+        &mut self,
+    ) -> Result<Option<Box<Expression>>, Error> {
+        let mut tokens = Vec::new();
+        let mut index = None;
+        for i in 0..4 {
+            let token = self.next_token()?;
+            let ok = match (i, &token.kind) {
+                (0, lexer::Kind::SquareOpen) => true,
+                (1, lexer::Kind::IntLiteral(n)) => {
+                    index = Some(Expression::new(
+                        token.clone(),
+                        ExpressionKind::IntegerLit(*n),
+                    ));
+                    true
+                }
+                (2, lexer::Kind::SquareClose) => true,
+                (3, lexer::Kind::Dot) => true,
+                _ => false,
+            };
+            tokens.push(token);
+            if !ok {
+                for t in tokens.into_iter().rev() {
+                    self.backlog.push(t);
+                }
+                return Ok(None);
+            }
+        }
+        Ok(index)
+    }
+
     fn parse_type(&mut self) -> Result<(Type, Token), Error> {
         let token = self.next_token()?;
         Ok((
@@ -135,6 +200,7 @@ impl<'a> Parser<'a> {
                 lexer::Kind::Bool => Type::Bool,
                 lexer::Kind::Error => Type::Error,
                 lexer::Kind::String => Type::String,
+                lexer::Kind::Void => Type::Void,
                 lexer::Kind::Bit => {
                     Type::Bit(self.parse_optional_width_parameter()?)
                 }
@@ -148,7 +214,10 @@ impl<'a> Parser<'a> {
                 }
 
                 lexer::Kind::Identifier(name) => {
-                    Type::UserDefined(name.clone())
+                    match self.parse_optional_stack_size()? {
+                        Some(size) => Type::HeaderStack(name.clone(), size),
+                        None => Type::UserDefined(name.clone()),
+                    }
                 }
 
                 _ => {
@@ -169,6 +238,32 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    fn parse_optional_stack_size(&mut self) -> Result<Option<usize>, Error> {
+        let token = self.next_token()?;
+        if token.kind != lexer::Kind::SquareOpen {
+            self.backlog.push(token);
+            return Ok(None);
+        }
+        let token = self.next_token()?;
+        let size = match &token.kind {
+            lexer::Kind::IntLiteral(size) if *size > 0 => *size as usize,
+            _ => {
+                return Err(ParserError {
+                    at: token.clone(),
+                    message: format!(
+                        "Positive integer literal expected for header stack \
+                        size, found {}",
+                        token.kind,
+                    ),
+                    source: self.lexer.lines[token.line].into(),
+                }
+                .into())
+            }
+        };
+        self.expect_token(lexer::Kind::SquareClose)?;
+        Ok(Some(size))
+    }
+
     fn parse_optional_width_parameter(&mut self) -> Result<usize, Error> {
         let token = self.next_token()?;
         match &token.kind {
@@ -275,111 +370,25 @@ impl<'a> Parser<'a> {
 
     pub fn parse_keyset(&mut self) -> Result<Vec<KeySetElement>, Error> {
         let token = self.next_token()?;
-        match token.kind {
-            lexer::Kind::ParenOpen => {
-                // handle tuple set below
-            }
-            lexer::Kind::Underscore => {
-                return Ok(vec![KeySetElement {
-                    value: KeySetElementValue::DontCare,
-                    token,
-                }]);
-            }
-            _ => {
-                self.backlog.push(token.clone());
-                let mut ep = ExpressionParser::new(self);
-                let expr = ep.run()?;
-                return Ok(vec![KeySetElement {
-                    value: KeySetElementValue::Expression(expr),
-                    token,
-                }]);
-            }
+        if token.kind != lexer::Kind::ParenOpen {
+            self.backlog.push(token);
+            return Ok(vec![self.parse_keyset_element()?]);
         }
 
+        // handle tuple set
         let mut elements = Vec::new();
-
         loop {
-            let token = self.next_token()?;
-            // handle dont-care special case
-            match token.kind {
-                lexer::Kind::Underscore => {
-                    elements.push(KeySetElement {
-                        value: KeySetElementValue::DontCare,
-                        token: token.clone(),
-                    });
-                    let token = self.next_token()?;
-                    match token.kind {
-                        lexer::Kind::Comma => continue,
-                        lexer::Kind::ParenClose => return Ok(elements),
-                        _ => {
-                            return Err(ParserError {
-                                at: token.clone(),
-                                message: format!(
-                                    "Found {} expected: \
-                                    comma or paren close after \
-                                    dont-care match",
-                                    token.kind,
-                                ),
-                                source: self.lexer.lines[token.line].into(),
-                            }
-                            .into())
-                        }
-                    }
-                }
-                _ => {
-                    self.backlog.push(token);
-                }
-            }
-            let mut ep = ExpressionParser::new(self);
-            let expr = ep.run()?;
+            elements.push(self.parse_keyset_element()?);
             let token = self.next_token()?;
             match token.kind {
-                lexer::Kind::Comma => {
-                    elements.push(KeySetElement {
-                        value: KeySetElementValue::Expression(expr),
-                        token: token.clone(),
-                    });
-                    continue;
-                }
-                lexer::Kind::ParenClose => {
-                    elements.push(KeySetElement {
-                        value: KeySetElementValue::Expression(expr),
-                        token,
-                    });
-                    return Ok(elements);
-                }
-                lexer::Kind::Mask => {
-                    let mut ep = ExpressionParser::new(self);
-                    let mask_expr = ep.run()?;
-                    elements.push(KeySetElement {
-                        value: KeySetElementValue::Masked(expr, mask_expr),
-                        token: token.clone(),
-                    });
-                    let token = self.next_token()?;
-                    match token.kind {
-                        lexer::Kind::Comma => continue,
-                        lexer::Kind::ParenClose => return Ok(elements),
-                        _ => {
-                            return Err(ParserError {
-                                at: token.clone(),
-                                message: format!(
-                                    "Found {} expected: \
-                                    comma or close paren after mask",
-                                    token.kind,
-                                ),
-                                source: self.lexer.lines[token.line].into(),
-                            }
-                            .into())
-                        }
-                    }
-                }
-                //TODO Default case
-                //TODO DontCare case
+                lexer::Kind::Comma => continue,
+                lexer::Kind::ParenClose => return Ok(elements),
                 _ => {
                     return Err(ParserError {
                         at: token.clone(),
                         message: format!(
-                            "Found {} expected: keyset expression",
+                            "Found {} expected: \
+                            comma or close paren after keyset element",
                             token.kind,
                         ),
                         source: self.lexer.lines[token.line].into(),
@@ -390,6 +399,62 @@ impl<'a> Parser<'a> {
         }
     }
 
+    // parse a single element of a keyset, one of
+    //
+    //  - `_`
+    //  - `default`
+    //  - <expr>
+    //  - <expr> &&& <expr>
+    //  - <expr> .. <expr>
+    pub fn parse_keyset_element(&mut self) -> Result<KeySetElement, Error> {
+        let token = self.next_token()?;
+        match token.kind {
+            lexer::Kind::Underscore => {
+                return Ok(KeySetElement {
+                    value: KeySetElementValue::DontCare,
+                    token,
+                });
+            }
+            lexer::Kind::Default => {
+                return Ok(KeySetElement {
+                    value: KeySetElementValue::Default,
+                    token,
+                });
+            }
+            _ => {
+                self.backlog.push(token.clone());
+            }
+        }
+
+        let mut ep = ExpressionParser::new(self);
+        let expr = ep.run()?;
+
+        // The expression parser treats &&& as a binary operator, so masks
+        // come back as a binary expression.
+        if let ExpressionKind::Binary(lhs, BinOp::Mask, rhs) = expr.kind {
+            return Ok(KeySetElement {
+                value: KeySetElementValue::Masked(lhs, rhs),
+                token,
+            });
+        }
+
+        let next = self.next_token()?;
+        if next.kind == lexer::Kind::DotDot {
+            let mut ep = ExpressionParser::new(self);
+            let end = ep.run()?;
+            return Ok(KeySetElement {
+                value: KeySetElementValue::Ranged(expr, end),
+                token,
+            });
+        }
+        self.backlog.push(next);
+
+        Ok(KeySetElement {
+            value: KeySetElementValue::Expression(expr),
+            token,
+        })
+    }
+
     // parse a tuple of expressions (<expr>, <expr> ...), used for both tuples
     // and function call sites
     pub fn parse_expr_parameters(
@@ -432,26 +497,60 @@ impl<'a> Parser<'a> {
         Ok(result)
     }
 
-    fn try_parse_binop(&mut self) -> Result<Option<BinOp>, Error> {
+    /// Parse a binary operator if one is next and binds at least as tightly
+    /// as `min_precedence`. Otherwise the token stream is left untouched.
+    fn try_parse_binop(
+        &mut self,
+        min_precedence: usize,
+    ) -> Result<Option<(BinOp, Token)>, Error> {
         let token = self.next_token()?;
-        match token.kind {
-            lexer::Kind::GreaterThanEquals => Ok(Some(BinOp::Geq)),
-            lexer::Kind::AngleClose => Ok(Some(BinOp::Gt)),
-            lexer::Kind::LessThanEquals => Ok(Some(BinOp::Leq)),
-            lexer::Kind::AngleOpen => Ok(Some(BinOp::Lt)),
-            lexer::Kind::NotEquals => Ok(Some(BinOp::NotEq)),
-            lexer::Kind::DoubleEquals => Ok(Some(BinOp::Eq)),
-            lexer::Kind::Plus => Ok(Some(BinOp::Add)),
-            lexer::Kind::Minus => Ok(Some(BinOp::Subtract)),
-            lexer::Kind::Mod => Ok(Some(BinOp::Mod)),
-            lexer::Kind::Mask => Ok(Some(BinOp::Mask)),
-            lexer::Kind::And => Ok(Some(BinOp::BitAnd)),
-            lexer::Kind::Pipe => Ok(Some(BinOp::BitOr)),
-            lexer::Kind::Carat => Ok(Some(BinOp::Xor)),
-
-            // TODO other binops
+        let mut consumed = vec![token.clone()];
+        let op = match token.kind {
+            lexer::Kind::GreaterThanEquals => Some(BinOp::Geq),
+            lexer::Kind::AngleClose => {
+                // >> is lexed as two angle brackets so nested type
+                // parameters close cleanly
+                let next = self.next_token()?;
+                if next.kind == lexer::Kind::AngleClose
+                    && next.line == token.line
+                    && next.col == token.col + 1
+                {
+                    consumed.push(next);
+                    Some(BinOp::Shr)
+                } else {
+                    self.backlog.push(next);
+                    Some(BinOp::Gt)
+                }
+            }
+            lexer::Kind::LessThanEquals => Some(BinOp::Leq),
+            lexer::Kind::AngleOpen => Some(BinOp::Lt),
+            lexer::Kind::NotEquals => Some(BinOp::NotEq),
+            lexer::Kind::DoubleEquals => Some(BinOp::Eq),
+            lexer::Kind::Plus => Some(BinOp::Add),
+            lexer::Kind::Minus => Some(BinOp::Subtract),
+            lexer::Kind::SaturatingPlus => Some(BinOp::SatAdd),
+            lexer::Kind::SaturatingMinus => Some(BinOp::SatSubtract),
+            lexer::Kind::PlusPlus => Some(BinOp::Concat),
+            lexer::Kind::Star => Some(BinOp::Mul),
+            lexer::Kind::Forwardslash => Some(BinOp::Div),
+            lexer::Kind::Mod => Some(BinOp::Mod),
+            lexer::Kind::Shl => Some(BinOp::Shl),
+            lexer::Kind::Mask => Some(BinOp::Mask),
+            lexer::Kind::And => Some(BinOp::BitAnd),
+            lexer::Kind::Pipe => Some(BinOp::BitOr),
+            lexer::Kind::Carat => Some(BinOp::Xor),
+            lexer::Kind::LogicalAnd => Some(BinOp::LogicalAnd),
+            lexer::Kind::LogicalOr => Some(BinOp::LogicalOr),
+            _ => None,
+        };
+        match op {
+            Some(op) if op.precedence() >= min_precedence => {
+                Ok(Some((op, token)))
+            }
             _ => {
-                self.backlog.push(token);
+                while let Some(t) = consumed.pop() {
+                    self.backlog.push(t);
+                }
                 Ok(None)
             }
         }
@@ -487,6 +586,14 @@ impl<'a> Parser<'a> {
                     result.statements.push(Statement::Constant(c));
                 }
 
+                // a pair of identifiers is a declaration of a variable with
+                // a user defined type
+                lexer::Kind::Identifier(_) if self.peek_identifier()? => {
+                    self.backlog.push(token);
+                    let var = self.parse_variable()?;
+                    result.statements.push(Statement::Variable(var));
+                }
+
                 lexer::Kind::Identifier(_)
                 | lexer::Kind::If
                 | lexer::Kind::Return => {
@@ -502,6 +609,11 @@ impl<'a> Parser<'a> {
                         .statements
                         .push(Statement::Transition(self.parse_transition()?));
                 }
+                lexer::Kind::Switch => {
+                    result
+                        .statements
+                        .push(Statement::Switch(self.parse_switch()?));
+                }
 
                 _ => {
                     return Err(ParserError {
@@ -534,6 +646,8 @@ impl<'a> Parser<'a> {
                 let result = Transition::Reference(Lvalue {
                     name: name.clone(),
                     token: token.clone(),
+                    index: None,
+                    slice: None,
                 });
                 self.expect_token(lexer::Kind::Semicolon)?;
                 Ok(result)
@@ -550,6 +664,60 @@ impl<'a> Parser<'a> {
         }
     }
 
+    pub fn parse_switch(&mut self) -> Result<Switch, Error> {
+        self.expect_token(lexer::Kind::ParenOpen)?;
+        let expression = self.parse_expression()?;
+        self.expect_token(lexer::Kind::ParenClose)?;
+        self.expect_token(lexer::Kind::CurlyOpen)?;
+
+        let mut cases = Vec::new();
+        let mut labels = Vec::new();
+        let mut last_label = None;
+        loop {
+            let token = self.next_token()?;
+            if token.kind == lexer::Kind::CurlyClose {
+                break;
+            }
+            last_label = Some(token.clone());
+            match token.kind {
+                lexer::Kind::Default => labels.push(SwitchLabel::Default),
+                _ => {
+                    self.backlog.push(token.clone());
+                    labels.push(SwitchLabel::Expression(
+                        self.parse_expression()?,
+                    ));
+                }
+            }
+            self.expect_token(lexer::Kind::Colon)?;
+
+            // a label without a block falls through to the next label
+            let next = self.next_token()?;
+            self.backlog.push(next.clone());
+            if next.kind == lexer::Kind::CurlyOpen {
+                let block = self.parse_statement_block()?;
+                cases.push(SwitchCase {
+                    labels: std::mem::take(&mut labels),
+                    block,
+                    token,
+                });
+            }
+        }
+
+        // the last label in a switch may not fall through
+        if let Some(token) = last_label {
+            if !labels.is_empty() {
+                return Err(ParserError {
+                    at: token.clone(),
+                    message: "switch label without a statement block".into(),
+                    source: self.lexer.lines[token.line].into(),
+                }
+                .into());
+            }
+        }
+
+        Ok(Switch { expression, cases })
+    }
+
     pub fn parse_parameters(&mut self) -> Result<Vec<ControlParameter>, Error> {
         let mut result = Vec::new();
         self.expect_token(lexer::Kind::ParenOpen)?;
@@ -627,6 +795,38 @@ impl<'a> Parser<'a> {
 
         Ok(result)
     }
+
+    /// Parse the type arguments of a call, such as the `<bit<8>>` in
+    /// `pkt.lookahead<bit<8>>()`.
+    pub fn parse_type_arguments(&mut self) -> Result<Vec<Type>, Error> {
+        let mut result = Vec::new();
+
+        self.expect_token(lexer::Kind::AngleOpen)?;
+
+        loop {
+            let (ty, _) = self.parse_type()?;
+            result.push(ty);
+
+            let token = self.next_token()?;
+            match token.kind {
+                lexer::Kind::AngleClose => break,
+                lexer::Kind::Comma => continue,
+                _ => {
+                    return Err(ParserError {
+                        at: token.clone(),
+                        message: format!(
+                            "Found {} expected: type argument",
+                            token.kind,
+                        ),
+                        source: self.lexer.lines[token.line].into(),
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(result)
+    }
 }
 
 /// Top level parser for parsing elements are global scope.
@@ -663,14 +863,18 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         match token.kind {
             lexer::Kind::Const => self.handle_const_decl(ast)?,
             lexer::Kind::Header => self.handle_header_decl(ast)?,
+            lexer::Kind::HeaderUnion => self.handle_header_union_decl(ast)?,
             lexer::Kind::Struct => self.handle_struct_decl(ast)?,
+            lexer::Kind::Enum => self.handle_enum_decl(ast, token)?,
+            lexer::Kind::Error => self.handle_error_decl(ast)?,
             lexer::Kind::Typedef => self.handle_typedef(ast)?,
             lexer::Kind::Control => self.handle_control(ast)?,
             lexer::Kind::Parser => self.handle_parser(ast, token)?,
             lexer::Kind::Package => self.handle_package(ast)?,
             lexer::Kind::Extern => self.handle_extern(ast)?,
-            lexer::Kind::Identifier(typ) => {
-                self.handle_package_instance(typ, ast)?
+            lexer::Kind::Function => self.handle_function(ast)?,
+            lexer::Kind::Identifier(ref typ) => {
+                self.handle_package_instance(typ.clone(), token.clone(), ast)?
             }
             _ => {}
         }
@@ -737,6 +941,49 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         Ok(())
     }
 
+    pub fn handle_header_union_decl(
+        &mut self,
+        ast: &mut AST,
+    ) -> Result<(), Error> {
+        // the first token of a header union must be an identifier
+        let (name, _) = self.parser.parse_identifier("header union name")?;
+
+        // next the header union body starts with an open curly brace
+        self.parser.expect_token(lexer::Kind::CurlyOpen)?;
+
+        let mut union = HeaderUnion::new(name);
+
+        // iterate over header union members
+        loop {
+            let token = self.parser.next_token()?;
+
+            // check if we've reached the end of the header union body
+            if token.kind == lexer::Kind::CurlyClose {
+                break;
+            }
+
+            // if the token was not a closing curly bracket push it into the
+            // backlog and carry on.
+            self.parser.backlog.push(token);
+
+            // parse a header union member
+            let (ty, tyt) = self.parser.parse_type()?;
+            let (name, _) =
+                self.parser.parse_identifier("header union member name")?;
+            self.parser.expect_token(lexer::Kind::Semicolon)?;
+
+            union.members.push(HeaderUnionMember {
+                ty,
+                name,
+                token: tyt,
+            });
+        }
+
+        ast.header_unions.push(union);
+
+        Ok(())
+    }
+
     pub fn handle_struct_decl(&mut self, ast: &mut AST) -> Result<(), Error> {
         // the first token of a struct must be an identifier
         let (name, _) = self.parser.parse_identifier("struct name")?;
@@ -777,6 +1024,83 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         Ok(())
     }
 
+    pub fn handle_enum_decl(
+        &mut self,
+        ast: &mut AST,
+        start: Token,
+    ) -> Result<(), Error> {
+        // a serializable enum has an underlying type before the name
+        let token = self.parser.next_token()?;
+        let ty = match token.kind {
+            lexer::Kind::Identifier(_) => {
+                self.parser.backlog.push(token);
+                None
+            }
+            _ => {
+                self.parser.backlog.push(token);
+                let (ty, _) = self.parser.parse_type()?;
+                Some(ty)
+            }
+        };
+
+        let (name, _) = self.parser.parse_identifier("enum name")?;
+
+        // next the enum body starts with an open curly brace
+        self.parser.expect_token(lexer::Kind::CurlyOpen)?;
+
+        let mut p4_enum = Enum::new(name, ty, start);
+
+        // iterate over comma separated enum members
+        loop {
+            let token = self.parser.next_token()?;
+            match token.kind {
+                lexer::Kind::CurlyClose => break,
+                lexer::Kind::Comma => continue,
+                _ => self.parser.backlog.push(token),
+            }
+
+            let (name, token) =
+                self.parser.parse_identifier("enum member name")?;
+
+            // serializable enum members may have an explicit value
+            let next = self.parser.next_token()?;
+            let value = if next.kind == lexer::Kind::Equals {
+                Some(self.parser.parse_expression()?)
+            } else {
+                self.parser.backlog.push(next);
+                None
+            };
+
+            p4_enum.members.push(EnumMember { name, value, token });
+        }
+
+        ast.enums.push(p4_enum);
+
+        Ok(())
+    }
+
+    /// Parse an `error { ... }` declaration. The members of all error
+    /// declarations in a program form a single error namespace.
+    pub fn handle_error_decl(&mut self, ast: &mut AST) -> Result<(), Error> {
+        self.parser.expect_token(lexer::Kind::CurlyOpen)?;
+
+        // iterate over comma separated error members
+        loop {
+            let token = self.parser.next_token()?;
+            match token.kind {
+                lexer::Kind::CurlyClose => break,
+                lexer::Kind::Comma => continue,
+                _ => self.parser.backlog.push(token),
+            }
+
+            let (name, token) =
+                self.parser.parse_identifier("error member name")?;
+            ast.errors.push(ErrorMember { name, token });
+        }
+
+        Ok(())
+    }
+
     pub fn handle_typedef(&mut self, ast: &mut AST) -> Result<(), Error> {
         // first token must be a type
         let (ty, _) = self.parser.parse_type()?;
@@ -798,6 +1122,13 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         Ok(())
     }
 
+    pub fn handle_function(&mut self, ast: &mut AST) -> Result<(), Error> {
+        let mut fp = FunctionParser::new(self.parser);
+        let function = fp.run()?;
+        ast.functions.push(function);
+        Ok(())
+    }
+
     pub fn handle_parser(
         &mut self,
         ast: &mut AST,
@@ -833,6 +1164,20 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
     }
 
     pub fn handle_extern(&mut self, ast: &mut AST) -> Result<(), Error> {
+        // an extern object is a name followed by a block of methods, anything
+        // else is the signature of an extern function
+        let token = self.parser.next_token()?;
+        let next = self.parser.next_token()?;
+        let object = matches!(token.kind, lexer::Kind::Identifier(_))
+            && next.kind == lexer::Kind::CurlyOpen;
+        self.parser.backlog.push(next);
+        self.parser.backlog.push(token);
+        if !object {
+            let f = self.parse_extern_method()?;
+            ast.extern_functions.push(f);
+            return Ok(());
+        }
+
         let (name, token) = self.parser.parse_identifier("extern name")?;
         self.parser.expect_token(lexer::Kind::CurlyOpen)?;
 
@@ -844,34 +1189,27 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
 
         // parse functions
         loop {
-            let (return_type, _) = self.parser.parse_type()?;
-            let (name, _) =
-                self.parser.parse_identifier("extern function name")?;
-
-            let token = self.parser.next_token()?;
-            let type_parameters = if token.kind == lexer::Kind::AngleOpen {
-                self.parser.backlog.push(token);
-                self.parser.parse_type_parameters()?
-            } else {
-                self.parser.backlog.push(token);
-                Vec::new()
-            };
-            let parameters = self.parser.parse_parameters()?;
-            self.parser.expect_token(lexer::Kind::Semicolon)?;
-
-            ext.methods.push(ExternMethod {
-                return_type,
-                name,
-                type_parameters,
-                parameters,
-            });
-
             let token = self.parser.next_token()?;
             if token.kind == lexer::Kind::CurlyClose {
                 break;
-            } else {
-                self.parser.backlog.push(token);
             }
+
+            // constructors are named after the extern and have no return
+            // type, instances are created with no arguments so they are
+            // parsed and set aside
+            let next = self.parser.next_token()?;
+            let constructor = token.kind
+                == lexer::Kind::Identifier(ext.name.clone())
+                && next.kind == lexer::Kind::ParenOpen;
+            self.parser.backlog.push(next);
+            if constructor {
+                self.parser.parse_parameters()?;
+                self.parser.expect_token(lexer::Kind::Semicolon)?;
+                continue;
+            }
+            self.parser.backlog.push(token);
+
+            ext.methods.push(self.parse_extern_method()?);
         }
 
         ast.externs.push(ext);
@@ -879,6 +1217,29 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         Ok(())
     }
 
+    fn parse_extern_method(&mut self) -> Result<ExternMethod, Error> {
+        let (return_type, _) = self.parser.parse_type()?;
+        let (name, _) = self.parser.parse_identifier("extern function name")?;
+
+        let token = self.parser.next_token()?;
+        let type_parameters = if token.kind == lexer::Kind::AngleOpen {
+            self.parser.backlog.push(token);
+            self.parser.parse_type_parameters()?
+        } else {
+            self.parser.backlog.push(token);
+            Vec::new()
+        };
+        let parameters = self.parser.parse_parameters()?;
+        self.parser.expect_token(lexer::Kind::Semicolon)?;
+
+        Ok(ExternMethod {
+            return_type,
+            name,
+            type_parameters,
+            parameters,
+        })
+    }
+
     pub fn parse_package_parameters(
         &mut self,
         pkg: &mut Package,
@@ -923,15 +1284,22 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
     pub fn handle_package_instance(
         &mut self,
         typ: String,
+        token: Token,
         ast: &mut AST,
     ) -> Result<(), Error> {
-        let mut inst = PackageInstance::new(typ);
+        let mut inst = PackageInstance::new(typ, token);
 
         self.parser.expect_token(lexer::Kind::ParenOpen)?;
         loop {
+            // arguments are either instantiated in place, or are package
+            // instances declared ahead of this one
             let (arg, _) = self.parser.parse_identifier("package name")?;
-            self.parser.expect_token(lexer::Kind::ParenOpen)?;
-            self.parser.expect_token(lexer::Kind::ParenClose)?;
+            let token = self.parser.next_token()?;
+            if token.kind == lexer::Kind::ParenOpen {
+                self.parser.expect_token(lexer::Kind::ParenClose)?;
+            } else {
+                self.parser.backlog.push(token);
+            }
             inst.parameters.push(arg);
             let token = self.parser.next_token()?;
             match token.kind {
@@ -949,7 +1317,9 @@ impl<'a, 'b> GlobalParser<'a, 'b> {
         inst.name = name;
         self.parser.expect_token(lexer::Kind::Semicolon)?;
 
-        ast.package_instance = Some(inst);
+        if let Some(previous) = ast.package_instance.replace(inst) {
+            ast.package_instances.push(previous);
+        }
         Ok(())
     }
 }
@@ -1021,6 +1391,7 @@ impl<'a, 'b> ControlParser<'a, 'b> {
             match token.kind {
                 lexer::Kind::CurlyClose => break,
                 lexer::Kind::Action => self.parse_action(control)?,
+                lexer::Kind::Function => self.parse_function(control)?,
                 lexer::Kind::Table => self.parse_table(control)?,
                 lexer::Kind::Apply => self.parse_apply(control)?,
                 lexer::Kind::Const => {
@@ -1037,9 +1408,10 @@ impl<'a, 'b> ControlParser<'a, 'b> {
                         at: token.clone(),
                         message: format!(
                             "Found {} expected: \
-                            {}, {}, {}, or end of {}",
+                            {}, {}, {}, {}, or end of {}",
                             token.kind.to_string().bright_blue(),
                             "action".bright_blue(),
+                            "function".bright_blue(),
                             "table".bright_blue(),
                             "apply".bright_blue(),
                             "control".bright_blue()
@@ -1062,6 +1434,17 @@ impl<'a, 'b> ControlParser<'a, 'b> {
         Ok(())
     }
 
+    pub fn parse_function(
+        &mut self,
+        control: &mut Control,
+    ) -> Result<(), Error> {
+        let mut fp = FunctionParser::new(self.parser);
+        let function = fp.run()?;
+        control.functions.push(function);
+
+        Ok(())
+    }
+
     pub fn parse_table(&mut self, control: &mut Control) -> Result<(), Error> {
         let mut tp = TableParser::new(self.parser);
         let table = tp.run()?;
@@ -1157,6 +1540,31 @@ impl<'a, 'b> ActionParser<'a, 'b> {
     }
 }
 
+pub struct FunctionParser<'a, 'b> {
+    parser: &'b mut Parser<'a>,
+}
+
+impl<'a, 'b> FunctionParser<'a, 'b> {
+    pub fn new(parser: &'b mut Parser<'a>) -> Self {
+        Self { parser }
+    }
+
+    pub fn run(&mut self) -> Result<Function, Error> {
+        let (return_type, _) = self.parser.parse_type()?;
+        let (name, token) = self.parser.parse_identifier("function name")?;
+        let parameters = self.parser.parse_parameters()?;
+        let statement_block = self.parser.parse_statement_block()?;
+
+        Ok(Function {
+            name,
+            return_type,
+            parameters,
+            statement_block,
+            token,
+        })
+    }
+}
+
 pub struct TableParser<'a, 'b> {
     parser: &'b mut Parser<'a>,
 }
@@ -1212,12 +1620,17 @@ impl<'a, 'b> TableParser<'a, 'b> {
                     let token = self.parser.next_token()?;
                     match token.kind {
                         lexer::Kind::Entries => self.parse_entries(table)?,
+                        lexer::Kind::DefaultAction => {
+                            self.parse_default_action(table)?;
+                            table.const_default_action = true;
+                        }
                         //TODO need handle regular constants?
                         _ => {
                             return Err(ParserError {
                                 at: token.clone(),
                                 message: format!(
-                                    "Found {} expected: entries",
+                                    "Found {} expected: entries or \
+                                    default_action",
                                     token.kind,
                                 ),
                                 source: self.parser.lexer.lines[token.line]
@@ -1308,6 +1721,8 @@ impl<'a, 'b> TableParser<'a, 'b> {
             table.actions.push(Lvalue {
                 name: action_name,
                 token: atk,
+                index: None,
+                slice: None,
             });
         }
 
@@ -1319,8 +1734,7 @@ impl<'a, 'b> TableParser<'a, 'b> {
         table: &mut Table,
     ) -> Result<(), Error> {
         self.parser.expect_token(lexer::Kind::Equals)?;
-        (table.default_action, _) =
-            self.parser.parse_identifier("default action name")?;
+        table.default_action = self.parse_actionref()?;
         self.parser.expect_token(lexer::Kind::Semicolon)?;
         Ok(())
     }
@@ -1357,7 +1771,10 @@ impl<'a, 'b> TableParser<'a, 'b> {
         let token = self.parser.next_token()?;
         let mut actionref = ActionRef::new(name, aref_tk);
         match token.kind {
-            lexer::Kind::Semicolon => Ok(actionref),
+            lexer::Kind::Semicolon => {
+                self.parser.backlog.push(token);
+                Ok(actionref)
+            }
             lexer::Kind::ParenOpen => {
                 let token = self.parser.next_token()?;
                 if token.kind == lexer::Kind::ParenClose {
@@ -1432,7 +1849,9 @@ impl<'a, 'b> StatementParser<'a, 'b> {
                 } else {
                     self.parser.backlog.push(token);
                     let mut ep = ExpressionParser::new(self.parser);
-                    return Ok(Statement::Return(Some(ep.run()?)));
+                    let xpr = ep.run()?;
+                    self.parser.expect_token(lexer::Kind::Semicolon)?;
+                    return Ok(Statement::Return(Some(xpr)));
                 }
             }
             _ => {
@@ -1442,16 +1861,33 @@ impl<'a, 'b> StatementParser<'a, 'b> {
 
         // wrap the identifier as an lvalue, consuming any dot
         // concatenated references
-        let lval = self.parser.parse_lvalue("identifier")?;
+        let mut lval = self.parser.parse_lvalue("identifier")?;
 
         let token = self.parser.next_token()?;
+
+        // a slice of the lvalue may be assigned to
+        if token.kind == lexer::Kind::SquareOpen {
+            let hi = ExpressionParser::new(self.parser).run()?;
+            self.parser.expect_token(lexer::Kind::Colon)?;
+            let lo = ExpressionParser::new(self.parser).run()?;
+            self.parser.expect_token(lexer::Kind::SquareClose)?;
+            self.parser.expect_token(lexer::Kind::Equals)?;
+            lval.slice = Some((hi, lo));
+            let statement = self.parse_assignment(lval)?;
+            self.parser.expect_token(lexer::Kind::Semicolon)?;
+            return Ok(statement);
+        }
+
         let statement = match token.kind {
             lexer::Kind::Equals => self.parse_assignment(lval)?,
             lexer::Kind::ParenOpen => {
                 self.parser.backlog.push(token);
                 self.parse_call(lval)?
             }
-            lexer::Kind::AngleOpen => self.parse_parameterized_call(lval)?,
+            lexer::Kind::AngleOpen => {
+                self.parser.backlog.push(token);
+                self.parse_parameterized_call(lval)?
+            }
             _ => {
                 return Err(ParserError {
                     at: token.clone(),
@@ -1480,14 +1916,24 @@ impl<'a, 'b> StatementParser<'a, 'b> {
 
     pub fn parse_call(&mut self, lval: Lvalue) -> Result<Statement, Error> {
         let args = self.parser.parse_expr_parameters()?;
-        Ok(Statement::Call(Call { lval, args }))
+        Ok(Statement::Call(Call {
+            lval,
+            type_args: Vec::new(),
+            args,
+        }))
     }
 
     pub fn parse_parameterized_call(
         &mut self,
-        _lval: Lvalue,
+        lval: Lvalue,
     ) -> Result<Statement, Error> {
-        todo!();
+        let type_args = self.parser.parse_type_arguments()?;
+        let args = self.parser.parse_expr_parameters()?;
+        Ok(Statement::Call(Call {
+            lval,
+            type_args,
+            args,
+        }))
     }
 }
 
@@ -1555,8 +2001,146 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
     }
 
     pub fn run(&mut self) -> Result<Box<Expression>, Error> {
+        let xpr = self.parse_binary(1)?;
+
+        // check for a conditional expression
         let token = self.parser.next_token()?;
-        let lhs = match token.kind {
+        if token.kind != lexer::Kind::Question {
+            self.parser.backlog.push(token);
+            return Ok(xpr);
+        }
+        let then = ExpressionParser::new(self.parser).run()?;
+        self.parser.expect_token(lexer::Kind::Colon)?;
+        let otherwise = ExpressionParser::new(self.parser).run()?;
+        Ok(Expression::new(
+            token,
+            ExpressionKind::Ternary(xpr, then, otherwise),
+        ))
+    }
+
+    /// Parse a chain of binary operations whose operators bind at least as
+    /// tightly as `min_precedence`. Operators of equal precedence associate
+    /// to the left.
+    fn parse_binary(
+        &mut self,
+        min_precedence: usize,
+    ) -> Result<Box<Expression>, Error> {
+        let mut lhs = self.parse_unary()?;
+        while let Some((op, token)) =
+            self.parser.try_parse_binop(min_precedence)?
+        {
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Expression::new(token, ExpressionKind::Binary(lhs, op, rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Box<Expression>, Error> {
+        let token = self.parser.next_token()?;
+        let op = match token.kind {
+            lexer::Kind::Bang => UnOp::Not,
+            lexer::Kind::Tilde => UnOp::Complement,
+            lexer::Kind::Minus => UnOp::Negate,
+            lexer::Kind::ParenOpen => {
+                if let Some(ty) = self.try_parse_cast_type()? {
+                    let xpr = self.parse_unary()?;
+                    return Ok(Expression::new(
+                        token,
+                        ExpressionKind::Cast(ty, xpr),
+                    ));
+                }
+                let xpr = ExpressionParser::new(self.parser).run()?;
+                self.parser.expect_token(lexer::Kind::ParenClose)?;
+                return Ok(xpr);
+            }
+            _ => {
+                self.parser.backlog.push(token);
+                return self.parse_primary();
+            }
+        };
+        let xpr = self.parse_unary()?;
+        Ok(Expression::new(token, ExpressionKind::Unary(op, xpr)))
+    }
+
+    /// Following an open paren, parse the type and closing paren of a cast
+    /// if that is what comes next. A parenthesized name is only taken as a
+    /// type when an operand directly follows the closing paren.
+    fn try_parse_cast_type(&mut self) -> Result<Option<Type>, Error> {
+        let token = self.parser.next_token()?;
+        match &token.kind {
+            lexer::Kind::Bit
+            | lexer::Kind::Int
+            | lexer::Kind::Varbit
+            | lexer::Kind::Bool => {
+                self.parser.backlog.push(token);
+                let (ty, _) = self.parser.parse_type()?;
+                self.parser.expect_token(lexer::Kind::ParenClose)?;
+                Ok(Some(ty))
+            }
+            lexer::Kind::Identifier(name) => {
+                let close = self.parser.next_token()?;
+                let next = self.parser.next_token()?;
+                let operand = matches!(
+                    next.kind,
+                    lexer::Kind::Identifier(_)
+                        | lexer::Kind::IntLiteral(_)
+                        | lexer::Kind::BitLiteral(..)
+                        | lexer::Kind::SignedLiteral(..)
+                        | lexer::Kind::TrueLiteral
+                        | lexer::Kind::FalseLiteral
+                        | lexer::Kind::ParenOpen
+                        | lexer::Kind::Bang
+                        | lexer::Kind::Tilde
+                );
+                let cast = close.kind == lexer::Kind::ParenClose && operand;
+                self.parser.backlog.push(next);
+                if cast {
+                    return Ok(Some(Type::UserDefined(name.clone())));
+                }
+                self.parser.backlog.push(close);
+                self.parser.backlog.push(token);
+                Ok(None)
+            }
+            _ => {
+                self.parser.backlog.push(token);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Determine whether an open angle bracket following a name starts the
+    /// type arguments of a call rather than a comparison. Type arguments are
+    /// either a builtin type, or a type name directly followed by the closing
+    /// angle bracket and the open paren of the call arguments.
+    fn is_type_arguments(&mut self, open: &Token) -> Result<bool, Error> {
+        if open.kind != lexer::Kind::AngleOpen {
+            return Ok(false);
+        }
+        let token = self.parser.next_token()?;
+        let result = match &token.kind {
+            lexer::Kind::Bit
+            | lexer::Kind::Int
+            | lexer::Kind::Varbit
+            | lexer::Kind::Bool
+            | lexer::Kind::Error => true,
+            lexer::Kind::Identifier(_) => {
+                let close = self.parser.next_token()?;
+                let paren = self.parser.next_token()?;
+                let result = close.kind == lexer::Kind::AngleClose
+                    && paren.kind == lexer::Kind::ParenOpen;
+                self.parser.backlog.push(paren);
+                self.parser.backlog.push(close);
+                result
+            }
+            _ => false,
+        };
+        self.parser.backlog.push(token);
+        Ok(result)
+    }
+
+    fn parse_primary(&mut self) -> Result<Box<Expression>, Error> {
+        let token = self.parser.next_token()?;
+        let xpr = match token.kind {
             lexer::Kind::TrueLiteral => {
                 Expression::new(token.clone(), ExpressionKind::BoolLit(true))
             }
@@ -1603,19 +2187,43 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
                             ),
                         )
                     } else {
-                        self.parser.backlog.push(token.clone());
+                        self.parser.backlog.push(slice_token);
                         self.parser.expect_token(lexer::Kind::SquareClose)?;
                         Expression::new(token, ExpressionKind::Index(lval, xpr))
                     }
                 }
                 // check for call
-                else if token.kind == lexer::Kind::ParenOpen {
+                else if token.kind == lexer::Kind::ParenOpen
+                    || self.is_type_arguments(&token)?
+                {
                     self.parser.backlog.push(token.clone());
+                    let type_args = if token.kind == lexer::Kind::AngleOpen {
+                        self.parser.parse_type_arguments()?
+                    } else {
+                        Vec::new()
+                    };
                     let args = self.parser.parse_expr_parameters()?;
-                    Expression::new(
+                    let call = Expression::new(
                         token,
-                        ExpressionKind::Call(Call { lval, args }),
-                    )
+                        ExpressionKind::Call(Call {
+                            lval,
+                            type_args,
+                            args,
+                        }),
+                    );
+                    // check for member access on the call result
+                    let token = self.parser.next_token()?;
+                    if token.kind == lexer::Kind::Dot {
+                        let (member, _) =
+                            self.parser.parse_identifier("member name")?;
+                        Expression::new(
+                            token,
+                            ExpressionKind::Member(call, member),
+                        )
+                    } else {
+                        self.parser.backlog.push(token);
+                        call
+                    }
                 }
                 // if it's not an index and it's not a call, it's an lvalue
                 else {
@@ -1623,6 +2231,20 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
                     Expression::new(this_token, ExpressionKind::Lvalue(lval))
                 }
             }
+            // error members are referenced through the error namespace
+            lexer::Kind::Error => {
+                self.parser.expect_token(lexer::Kind::Dot)?;
+                let (member, _) = self.parser.parse_identifier("error name")?;
+                Expression::new(
+                    token.clone(),
+                    ExpressionKind::Lvalue(Lvalue {
+                        name: format!("error.{}", member),
+                        token: token.clone(),
+                        index: None,
+                        slice: None,
+                    }),
+                )
+            }
             lexer::Kind::CurlyOpen => {
                 let mut elements = Vec::new();
                 loop {
@@ -1653,16 +2275,7 @@ impl<'a, 'b> ExpressionParser<'a, 'b> {
             }
         };
 
-        // check for binary operator
-        match self.parser.try_parse_binop()? {
-            Some(op) => {
-                // recurse to rhs
-                let mut ep = ExpressionParser::new(self.parser);
-                let rhs = ep.run()?;
-                Ok(Expression::new(token, ExpressionKind::Binary(lhs, op, rhs)))
-            }
-            None => Ok(lhs),
-        }
+        Ok(xpr)
     }
 }
 
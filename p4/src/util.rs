@@ -1,6 +1,8 @@
 // Copyright 2022 Oxide Computer Company
 
-use crate::ast::{Lvalue, NameInfo, Type, AST};
+use crate::ast::{
+    DeclarationInfo, Expression, ExpressionKind, Lvalue, NameInfo, Type, AST,
+};
 use std::collections::HashMap;
 
 pub fn resolve_lvalue(
@@ -8,9 +10,37 @@ pub fn resolve_lvalue(
     ast: &AST,
     names: &HashMap<String, NameInfo>,
 ) -> Result<NameInfo, String> {
-    let root = match names.get(lval.root()) {
-        Some(name_info) => name_info,
-        None => return Err(format!("{} not found", lval.root())),
+    let root_name = lval.root();
+    let root = match names.get(root_name) {
+        Some(name_info) => match lval.stack_index(0) {
+            None => name_info.clone(),
+            Some(index) => match stack_index_value(index) {
+                Some(i) => stack_element(root_name, name_info, i)?,
+                None => {
+                    return Err(format!(
+                        "index of {} is not an integer literal",
+                        root_name
+                    ))
+                }
+            },
+        },
+        None => {
+            // enum members are referenced through the enum type name
+            if let Some(e) = ast.get_enum(lval.root()) {
+                return resolve_lvalue(&lval.pop_left(), ast, &e.names());
+            }
+            // error members are referenced through the error namespace
+            if lval.root() == "error" && lval.degree() == 2 {
+                if !ast.error_names().contains(&lval.leaf()) {
+                    return Err(format!("error {} not declared", lval.leaf()));
+                }
+                return Ok(NameInfo {
+                    ty: Type::Error,
+                    decl: DeclarationInfo::ErrorMember,
+                });
+            }
+            return Err(format!("{} not found", lval.root()));
+        }
     };
     let result = match &root.ty {
         Type::Bool => root.clone(),
@@ -26,6 +56,17 @@ pub fn resolve_lvalue(
         Type::List(_) => root.clone(),
         Type::State => root.clone(),
         Type::Action => root.clone(),
+        Type::HeaderStack(element, _) => {
+            if lval.degree() == 1 {
+                root.clone()
+            } else {
+                resolve_lvalue(
+                    &lval.pop_left(),
+                    ast,
+                    &header_stack_names(element),
+                )?
+            }
+        }
         Type::UserDefined(name) => {
             if lval.degree() == 1 {
                 root.clone()
@@ -33,8 +74,15 @@ pub fn resolve_lvalue(
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
             } else if let Some(parent) = ast.get_header(name) {
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
+            } else if let Some(parent) = ast.get_header_union(name) {
+                resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
             } else if let Some(parent) = ast.get_extern(name) {
                 resolve_lvalue(&lval.pop_left(), ast, &parent.names())?
+            } else if ast.get_enum(name).is_some() {
+                return Err(format!(
+                    "Enum value of type '{}' does not have members",
+                    name
+                ));
             } else {
                 return Err(format!(
                     "User defined name '{}' does not exist",
@@ -45,3 +93,67 @@ pub fn resolve_lvalue(
     };
     Ok(result)
 }
+
+/// The value of a header stack index appearing in an lvalue, if it is a
+/// non-negative integer literal.
+pub fn stack_index_value(index: &Expression) -> Option<usize> {
+    match index.kind {
+        ExpressionKind::IntegerLit(i) => usize::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+fn stack_element(
+    name: &str,
+    stack: &NameInfo,
+    index: usize,
+) -> Result<NameInfo, String> {
+    match &stack.ty {
+        Type::HeaderStack(element, size) => {
+            if index >= *size {
+                return Err(format!(
+                    "index {} out of bounds for {} of size {}",
+                    index, name, size,
+                ));
+            }
+            Ok(NameInfo {
+                ty: Type::UserDefined(element.clone()),
+                decl: stack.decl.clone(),
+            })
+        }
+        ty => Err(format!("{} of type {} cannot be indexed", name, ty)),
+    }
+}
+
+/// The members of a header stack whose elements are of header type `element`.
+pub fn header_stack_names(element: &str) -> HashMap<String, NameInfo> {
+    let mut names = HashMap::new();
+    for name in ["next", "last"] {
+        names.insert(
+            name.into(),
+            NameInfo {
+                ty: Type::UserDefined(element.into()),
+                decl: DeclarationInfo::StructMember,
+            },
+        );
+    }
+    for name in ["lastIndex", "size"] {
+        names.insert(
+            name.into(),
+            NameInfo {
+                ty: Type::Bit(32),
+                decl: DeclarationInfo::StructMember,
+            },
+        );
+    }
+    for name in ["push_front", "pop_front"] {
+        names.insert(
+            name.into(),
+            NameInfo {
+                ty: Type::HeaderMethod,
+                decl: DeclarationInfo::Method,
+            },
+        );
+    }
+    names
+}
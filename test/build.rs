@@ -8,7 +8,35 @@ fn main() {
         "src/p4/router.p4",
         "src/p4/sidecar-lite.p4",
         "src/p4/decap.p4",
+        "src/p4/select.p4",
+        "src/p4/enums.p4",
+        "src/p4/switch.p4",
+        "src/p4/apply_result.p4",
+        "src/p4/default_action.p4",
+        "src/p4/header_stack.p4",
+        "src/p4/header_union.p4",
+        "src/p4/varbit.p4",
+        "src/p4/signed.p4",
+        "src/p4/operators.p4",
+        "src/p4/casts.p4",
+        "src/p4/slices.p4",
+        "src/p4/functions.p4",
+        "src/p4/lookahead.p4",
+        "src/p4/parser_error.p4",
+        "src/p4/unaligned.p4",
+        "src/p4/deparser.p4",
+        "src/p4/package.p4",
+        "src/p4/v1model.p4",
+        "src/p4/v1model_basic.p4",
+        "src/p4/psa.p4",
+        "src/p4/psa_basic.p4",
+        "src/p4/psa_clone.p4",
         "src/p4/router.p4",
+        "src/p4/entry_builder.p4",
+        "src/p4/header_stack_bounds.p4",
+        "src/p4/parser_error_unread.p4",
+        "src/p4/const_lpm.p4",
+        "src/p4/header_union_varbit.p4",
     ];
     for x in src {
         println!("cargo:rerun-if-changed={}", x);
@@ -0,0 +1,35 @@
+use crate::expect_frames;
+use crate::softnpu::{Interface4, RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/apply_result.p4",
+    pipeline_name = "apply_result"
+);
+
+#[test]
+fn apply_result() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    let if0 = Interface4::new(phy0.clone(), "1.0.0.1".parse().unwrap());
+
+    npu.run();
+
+    // a hit in the known table forwards out port 1
+    if0.send(phy1.mac, "10.0.0.1".parse().unwrap(), b"hit")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"hit")]);
+
+    // a miss forwards out port 2
+    phy0.send(&[TxFrame::new(phy2.mac, 0x1234, b"miss")])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x1234, b"miss")]);
+
+    // a hit in the blocked table drops the frame
+    phy0.send(&[TxFrame::new(phy1.mac, 0x9000, b"blocked")])?;
+    if0.send(phy1.mac, "10.0.0.2".parse().unwrap(), b"allowed")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"allowed")]);
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/casts.p4", pipeline_name = "casts");
+
+const CAST: u16 = 0x88b5;
+
+fn cast(small: u8, long: u32, offset: i8, color: u8) -> Vec<u8> {
+    [
+        &[small][..],
+        &long.to_be_bytes(),
+        &offset.to_be_bytes(),
+        &[color],
+        &[0; 9],
+    ]
+    .concat()
+}
+
+fn converted(small: u8, long: u32, offset: i8, color: u8) -> Vec<u8> {
+    [
+        &[small][..],
+        &long.to_be_bytes(),
+        &offset.to_be_bytes(),
+        &[color],
+        &(small as u32 * 2).to_be_bytes(),
+        &(long as u16).to_be_bytes(),
+        &(offset as i16).to_be_bytes(),
+        &[small & 1],
+    ]
+    .concat()
+}
+
+#[test]
+fn casts() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    for (small, long, offset, color, phy) in [
+        (0xff, 0x12345678, -2, 1, &phy1),
+        // 7 is not a color, and is kept through the cast to one and back
+        (0x80, 0xdeadbeef, 100, 7, &phy2),
+        (0x03, 0x0000ffff, -128, 2, &phy3),
+    ] {
+        let pkt = cast(small, long, offset, color);
+        phy0.send(&[TxFrame::new(phy.mac, CAST, &pkt)])?;
+        let expected = converted(small, long, offset, color);
+        expect_frames!(phy, &[RxFrame::new(phy0.mac, CAST, &expected)]);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::net::Ipv4Addr;
+
+p4_macro::use_p4!(p4 = "test/src/p4/const_lpm.p4", pipeline_name = "const_lpm");
+
+fn frame(dst: Ipv4Addr) -> Vec<u8> {
+    let mut ip = vec![0x45, 0, 0, 27, 0, 0, 0x40, 0, 64, 17, 0, 0];
+    ip.extend_from_slice(&Ipv4Addr::new(192, 168, 0, 1).octets());
+    ip.extend_from_slice(&dst.octets());
+
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+    frame.extend_from_slice(&ip);
+    frame.extend_from_slice(b"muffins");
+    frame
+}
+
+/// Run a packet through the pipeline, returning the egress port if it was not
+/// dropped.
+fn send(pipeline: &mut main_pipeline, dst: &str) -> Option<u16> {
+    let data = frame(dst.parse().unwrap());
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(0, &mut pkt);
+    out.first().map(|(_, port)| *port)
+}
+
+#[test]
+fn const_lpm() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // masks give the prefix length within the 32 bit key, and a value
+    // without a mask matches the whole address
+    assert_eq!(send(&mut pipeline, "10.2.0.1"), Some(1));
+    assert_eq!(send(&mut pipeline, "10.1.9.9"), Some(2));
+    assert_eq!(send(&mut pipeline, "10.1.2.3"), Some(3));
+    assert_eq!(send(&mut pipeline, "10.1.2.4"), Some(2));
+    assert_eq!(send(&mut pipeline, "172.16.0.1"), None);
+}
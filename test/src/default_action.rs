@@ -0,0 +1,83 @@
+use crate::expect_frames;
+use crate::softnpu::{Interface4, RxFrame, SoftNpu, TxFrame};
+use p4rs::{Pipeline, TableUpdateError};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/default_action.p4",
+    pipeline_name = "default_action"
+);
+
+#[test]
+fn default_action() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy3 = npu.phy(3);
+
+    let if0 = Interface4::new(phy0.clone(), "1.0.0.1".parse().unwrap());
+
+    npu.run();
+
+    if0.send(phy1.mac, "10.0.0.1".parse().unwrap(), b"hit")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"hit")]);
+
+    // a miss runs the default action with its constant argument
+    phy0.send(&[TxFrame::new(phy3.mac, 0x1234, b"miss")])?;
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, 0x1234, b"miss")]);
+
+    Ok(())
+}
+
+#[test]
+fn set_default_action() -> Result<(), anyhow::Error> {
+    let mut pipeline = main_pipeline::new(4);
+    pipeline
+        .set_table_default_action("ingress.fib", "forward", &2u16.to_le_bytes())
+        .unwrap();
+
+    // const default actions cannot be changed, and unknown tables and
+    // actions or the wrong amount of parameter data are errors
+    assert_eq!(
+        pipeline.set_table_default_action("ingress.guard", "drop", &[]),
+        Err(TableUpdateError::ConstDefaultAction {
+            table_id: "ingress.guard".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline.set_table_default_action("ingress.nope", "drop", &[]),
+        Err(TableUpdateError::UnknownTable("ingress.nope".into())),
+    );
+    assert_eq!(
+        pipeline.set_table_default_action("ingress.fib", "nope", &[]),
+        Err(TableUpdateError::UnknownAction {
+            table_id: "ingress.fib".into(),
+            action_id: "nope".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline.set_table_default_action("ingress.fib", "forward", &[2]),
+        Err(TableUpdateError::BadParameterData {
+            table_id: "ingress.fib".into(),
+            action_id: "forward".into(),
+            expected: 2,
+            found: 1,
+        }),
+    );
+
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    phy0.send(&[TxFrame::new(phy2.mac, 0x1234, b"miss")])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x1234, b"miss")]);
+
+    // a hit in the guard table still drops
+    phy0.send(&[TxFrame::new(phy2.mac, 0x9999, b"dropped")])?;
+    phy0.send(&[TxFrame::new(phy2.mac, 0x1235, b"allowed")])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x1235, b"allowed")]);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/deparser.p4", pipeline_name = "deparser");
+
+const CHECKED: u16 = 0x88b5;
+const ENCAPSULATED: u16 = 0x88b6;
+
+#[test]
+fn deparser() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // the encapsulation is emitted ahead of the check header, even though it
+    // is declared after it
+    let pkt = [&[1, 64][..], b"muffins"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    let out = [&[0x0a, 0x0b, 0x88, 0xb5, 1, 64][..], b"muffins"].concat();
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, ENCAPSULATED, &out)]);
+
+    // the encapsulation is invalid and not emitted
+    let pkt = [&[2, 64][..], b"muffins"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, CHECKED, &pkt)]);
+
+    Ok(())
+}
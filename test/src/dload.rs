@@ -9,10 +9,7 @@ fn pipeline_create() -> Result<(), anyhow::Error> {
 
     let port: u16 = 47;
     let data = [0u8; 500];
-    let mut pkt: packet_in = packet_in {
-        data: &data,
-        index: 0,
-    };
+    let mut pkt: packet_in = packet_in::new(&data);
 
     // the goal is simply not to explode
     let result = p.process_packet(port, &mut pkt);
@@ -49,10 +46,7 @@ fn dynamic_load() -> Result<(), anyhow::Error> {
 
     let port: u16 = 47;
     let data = [0u8; 500];
-    let mut pkt: packet_in = packet_in {
-        data: &data,
-        index: 0,
-    };
+    let mut pkt: packet_in = packet_in::new(&data);
 
     // the goal is simply not to explode
     let result = p.process_packet(port, &mut pkt);
@@ -0,0 +1,538 @@
+use std::net::Ipv4Addr;
+
+use p4rs::key::{Exact, Lpm, Range, Ternary};
+use p4rs::{Pipeline, TableUpdate, TableUpdateError};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/entry_builder.p4",
+    pipeline_name = "entry_builder"
+);
+
+const ROUTER: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const HOST: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const NEXTHOP: u64 = 0x02_00_00_00_00_03;
+
+fn frame(src: Ipv4Addr, dst: Ipv4Addr) -> Vec<u8> {
+    let mut ip = vec![0x45, 0, 0, 27, 0, 0, 0x40, 0, 64, 17, 0, 0];
+    ip.extend_from_slice(&src.octets());
+    ip.extend_from_slice(&dst.octets());
+
+    let mut frame = [ROUTER, HOST].concat();
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+    frame.extend_from_slice(&ip);
+    frame.extend_from_slice(b"muffins");
+    frame
+}
+
+/// Run a packet through the pipeline, returning the egress port and the
+/// destination mac of the packet if it was not dropped.
+fn send(
+    pipeline: &mut main_pipeline,
+    src: &str,
+    dst: &str,
+) -> Option<(u16, [u8; 6])> {
+    let data = frame(src.parse().unwrap(), dst.parse().unwrap());
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(1, &mut pkt);
+    let (out, port) = out.first()?;
+    Some((*port, out.header_data[..6].try_into().unwrap()))
+}
+
+#[test]
+fn entry_builder() {
+    let mut pipeline = main_pipeline::new(4);
+    let nexthop = NEXTHOP.to_be_bytes()[2..].try_into().unwrap();
+
+    // lpm keys and multiple action parameters
+    pipeline
+        .ingress_routes()
+        .add(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 8),
+            ingress_routes::action::forward_mac {
+                port: 2,
+                mac: NEXTHOP,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((2, nexthop)),
+    );
+
+    // keys made of several fields of different match kinds
+    let source = || Ternary::Value(Ipv4Addr::new(192, 168, 0, 1).into());
+    let dests = |a: Ipv4Addr, b: Ipv4Addr| Range(a.into(), b.into());
+    pipeline
+        .ingress_acl()
+        .add(
+            (
+                Exact(0x0800),
+                source(),
+                dests(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(10, 0, 0, 255)),
+            ),
+            ingress_acl::action::forward { port: 3 },
+        )
+        .unwrap();
+    pipeline
+        .ingress_acl()
+        .add_with_priority(
+            (
+                Exact(0x0800),
+                Ternary::DontCare,
+                dests(
+                    Ipv4Addr::new(10, 9, 0, 0),
+                    Ipv4Addr::new(10, 9, 255, 255),
+                ),
+            ),
+            ingress_acl::action::drop,
+            10,
+        )
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.0.0.7"),
+        Some((3, nexthop)),
+    );
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.2", "10.0.0.7"),
+        Some((2, nexthop)),
+    );
+    assert_eq!(send(&mut pipeline, "192.168.0.2", "10.9.0.1"), None);
+
+    pipeline
+        .ingress_acl()
+        .remove((
+            Exact(0x0800),
+            Ternary::DontCare,
+            dests(Ipv4Addr::new(10, 9, 0, 0), Ipv4Addr::new(10, 9, 255, 255)),
+        ))
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.2", "10.9.0.1"),
+        Some((2, nexthop)),
+    );
+
+    // default actions
+    let miss = send(&mut pipeline, "192.168.0.2", "172.16.0.1");
+    pipeline
+        .ingress_routes()
+        .set_default_action(ingress_routes::action::forward_mac {
+            port: 1,
+            mac: NEXTHOP,
+        })
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.2", "172.16.0.1"),
+        Some((1, nexthop)),
+    );
+    pipeline.ingress_routes().clear_default_action().unwrap();
+    assert_eq!(send(&mut pipeline, "192.168.0.2", "172.16.0.1"), miss);
+}
+
+#[test]
+fn entry_builder_updates() {
+    let mut pipeline = main_pipeline::new(4);
+    let nexthop = NEXTHOP.to_be_bytes()[2..].try_into().unwrap();
+    let forward =
+        |port| ingress_routes::action::forward_mac { port, mac: NEXTHOP };
+
+    // NoAction entries shadow shorter prefixes
+    let miss = send(&mut pipeline, "192.168.0.1", "10.1.1.1");
+    pipeline
+        .ingress_routes()
+        .add(Lpm(Ipv4Addr::new(10, 0, 0, 0), 8), forward(2))
+        .unwrap();
+    pipeline
+        .ingress_routes()
+        .add(
+            Lpm(Ipv4Addr::new(10, 1, 0, 0), 16),
+            ingress_routes::action::NoAction,
+        )
+        .unwrap();
+    assert_eq!(send(&mut pipeline, "192.168.0.1", "10.1.1.1"), miss);
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.2.1.1"),
+        Some((2, nexthop)),
+    );
+
+    // adding an entry twice or removing one that is not there fails
+    assert_eq!(
+        pipeline
+            .ingress_routes()
+            .add(Lpm(Ipv4Addr::new(10, 0, 0, 0), 8), forward(3)),
+        Err(TableUpdateError::EntryExists {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline
+            .ingress_routes()
+            .remove(Lpm(Ipv4Addr::new(172, 16, 0, 0), 12)),
+        Err(TableUpdateError::NoSuchEntry {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+    pipeline
+        .ingress_routes()
+        .remove(Lpm(Ipv4Addr::new(10, 1, 0, 0), 16))
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((2, nexthop)),
+    );
+
+    // values too wide for an integer are little endian byte arrays
+    let mut wide = [0u8; 17];
+    wide[16] = 0x80;
+    pipeline
+        .ingress_wide()
+        .add(
+            Exact(Ipv4Addr::new(10, 3, 0, 1).into()),
+            ingress_wide::action::forward_wide { wide },
+        )
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.3.0.1"),
+        Some((3, nexthop)),
+    );
+    let entries = pipeline.get_table_entries("ingress.wide").unwrap();
+    assert_eq!(entries[0].parameter_data, wide);
+}
+
+#[test]
+fn modify_and_batch_updates() {
+    let mut pipeline = main_pipeline::new(4);
+    let nexthop = NEXTHOP.to_be_bytes()[2..].try_into().unwrap();
+    let route = |a, b, c, d, len| {
+        ingress_routes::keyset_data(&Lpm(Ipv4Addr::new(a, b, c, d), len))
+            .unwrap()
+    };
+    let via = |port| {
+        ingress_routes::Action::from(ingress_routes::action::forward_mac {
+            port,
+            mac: NEXTHOP,
+        })
+        .parameter_data()
+        .unwrap()
+    };
+
+    pipeline
+        .ingress_routes()
+        .add(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 8),
+            ingress_routes::action::forward_mac {
+                port: 2,
+                mac: NEXTHOP,
+            },
+        )
+        .unwrap();
+    let miss = send(&mut pipeline, "192.168.0.1", "172.16.0.1");
+
+    // modify keeps the key and changes where it sends packets
+    pipeline
+        .ingress_routes()
+        .modify(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 8),
+            ingress_routes::action::forward_mac {
+                port: 3,
+                mac: NEXTHOP,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((3, nexthop)),
+    );
+    assert_eq!(
+        pipeline.modify_table_entry(
+            "ingress.routes",
+            "forward_mac",
+            &route(172, 16, 0, 0, 12),
+            &via(1),
+        ),
+        Err(TableUpdateError::NoSuchEntry {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+
+    // a batch that fails part way through changes nothing
+    let add = TableUpdate::Add {
+        table_id: "ingress.routes".into(),
+        action_id: "forward_mac".into(),
+        keyset_data: route(172, 16, 0, 0, 12),
+        parameter_data: via(1),
+        priority: 0,
+    };
+    let modify = TableUpdate::Modify {
+        table_id: "ingress.routes".into(),
+        action_id: "forward_mac".into(),
+        keyset_data: route(10, 0, 0, 0, 8),
+        parameter_data: via(2),
+    };
+    let remove = TableUpdate::Remove {
+        table_id: "ingress.routes".into(),
+        keyset_data: route(10, 0, 0, 0, 8),
+    };
+    let result = pipeline.apply_table_updates(&[
+        add.clone(),
+        modify.clone(),
+        remove.clone(),
+        TableUpdate::Modify {
+            table_id: "ingress.routes".into(),
+            action_id: "forward_mac".into(),
+            keyset_data: route(10, 0, 0, 0, 8),
+            parameter_data: vec![1],
+        },
+    ]);
+    assert_eq!(
+        result,
+        Err(TableUpdateError::BadParameterData {
+            table_id: "ingress.routes".into(),
+            action_id: "forward_mac".into(),
+            expected: 8,
+            found: 1,
+        }),
+    );
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((3, nexthop)),
+    );
+    assert_eq!(send(&mut pipeline, "192.168.0.1", "172.16.0.1"), miss);
+    assert_eq!(
+        pipeline.get_table_entries("ingress.routes").unwrap().len(),
+        1
+    );
+
+    // entries that already exist and unknown tables also fail a batch
+    assert_eq!(
+        pipeline.apply_table_updates(&[add.clone(), add.clone()]),
+        Err(TableUpdateError::EntryExists {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline.apply_table_updates(&[
+            modify.clone(),
+            TableUpdate::Remove {
+                table_id: "ingress.nope".into(),
+                keyset_data: Vec::new(),
+            },
+        ]),
+        Err(TableUpdateError::UnknownTable("ingress.nope".into())),
+    );
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((3, nexthop)),
+    );
+
+    // and a batch that succeeds applies every update
+    pipeline.apply_table_updates(&[add, modify]).unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "10.1.1.1"),
+        Some((2, nexthop)),
+    );
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "172.16.0.1"),
+        Some((1, nexthop)),
+    );
+    pipeline.apply_table_updates(&[remove]).unwrap();
+    assert_eq!(send(&mut pipeline, "192.168.0.1", "10.1.1.1"), miss);
+}
+
+#[test]
+fn prefix_longer_than_address() {
+    let mut pipeline = main_pipeline::new(4);
+    let parameter_data =
+        ingress_routes::Action::from(ingress_routes::action::forward_mac {
+            port: 1,
+            mac: NEXTHOP,
+        })
+        .parameter_data()
+        .unwrap();
+
+    // a /40 of an IPv4 address is rejected before it reaches the table
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.routes",
+            "forward_mac",
+            &[10, 0, 0, 0, 40],
+            &parameter_data,
+            0,
+        ),
+        Err(TableUpdateError::UnsupportedPrefix {
+            table_id: "ingress.routes".into(),
+            width: 32,
+            len: 40,
+        }),
+    );
+
+    // as is one written through the builder
+    assert_eq!(
+        pipeline.ingress_routes().add(
+            Lpm(Ipv4Addr::new(10, 0, 0, 0), 40),
+            ingress_routes::action::drop,
+        ),
+        Err(TableUpdateError::UnsupportedPrefix {
+            table_id: "ingress.routes".into(),
+            width: 32,
+            len: 40,
+        }),
+    );
+    assert!(pipeline
+        .get_table_entries("ingress.routes")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn values_checked_against_field_width() {
+    let mut pipeline = main_pipeline::new(4);
+    let too_wide = Err(TableUpdateError::ValueTooWide {
+        table_id: "ingress.vlans".into(),
+        width: 12,
+    });
+
+    // 0xffff does not fit in a 12 bit vlan id, through the builder or raw
+    assert_eq!(
+        pipeline
+            .ingress_vlans()
+            .add(Exact(0xffff), ingress_vlans::action::forward { port: 1 }),
+        too_wide,
+    );
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.vlans",
+            "forward",
+            &[0xff, 0xff],
+            &[1, 0],
+            0,
+        ),
+        too_wide,
+    );
+
+    // and neither does a 13 bit parameter, on add or modify
+    assert_eq!(
+        pipeline
+            .ingress_vlans()
+            .add(Exact(0xfff), ingress_vlans::action::tag { vid: 0x1000 }),
+        too_wide,
+    );
+    pipeline
+        .ingress_vlans()
+        .add(Exact(0xfff), ingress_vlans::action::forward { port: 1 })
+        .unwrap();
+    assert_eq!(
+        pipeline
+            .ingress_vlans()
+            .modify(Exact(0xfff), ingress_vlans::action::tag { vid: 0x1000 }),
+        too_wide,
+    );
+    let entries = pipeline.get_table_entries("ingress.vlans").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].keyset_data, [0xff, 0x0f]);
+    assert_eq!(entries[0].action_id, "forward");
+}
+
+#[test]
+fn lpm_key_widths() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // an lpm key is read at its own offset in a multi key table
+    let key = (Exact(10), Lpm(Ipv4Addr::new(192, 168, 1, 0), 24));
+    let keyset_data = ingress_vlan_routes::keyset_data(&key).unwrap();
+    assert_eq!(keyset_data, [10, 0, 192, 168, 1, 0, 24]);
+    pipeline
+        .ingress_vlan_routes()
+        .add(key, ingress_vlan_routes::action::forward { port: 2 })
+        .unwrap();
+    let entries = pipeline.get_table_entries("ingress.vlan_routes").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].keyset_data, keyset_data);
+
+    // lpm keys that are not ip addresses are rejected, and have no builder
+    assert_eq!(
+        pipeline.add_table_entry(
+            "ingress.macs",
+            "drop",
+            &[0x02, 0, 0, 0, 0, 0x01, 48],
+            &[],
+            0,
+        ),
+        Err(TableUpdateError::UnsupportedPrefix {
+            table_id: "ingress.macs".into(),
+            width: 48,
+            len: 48,
+        }),
+    );
+    assert!(pipeline
+        .get_table_entries("ingress.macs")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn failed_batch_rolls_back_add() {
+    let mut pipeline = main_pipeline::new(4);
+    let nexthop = NEXTHOP.to_be_bytes()[2..].try_into().unwrap();
+    let route = |a, b, c, d, len| {
+        ingress_routes::keyset_data(&Lpm(Ipv4Addr::new(a, b, c, d), len))
+            .unwrap()
+    };
+    let forward = || ingress_routes::action::forward_mac {
+        port: 1,
+        mac: NEXTHOP,
+    };
+    let miss = send(&mut pipeline, "192.168.0.1", "172.16.0.1");
+
+    // the add succeeds on its own, the remove of a missing entry does not
+    let result = pipeline.apply_table_updates(&[
+        TableUpdate::Add {
+            table_id: "ingress.routes".into(),
+            action_id: "forward_mac".into(),
+            keyset_data: route(172, 16, 0, 0, 12),
+            parameter_data: ingress_routes::Action::from(forward())
+                .parameter_data()
+                .unwrap(),
+            priority: 0,
+        },
+        TableUpdate::Remove {
+            table_id: "ingress.routes".into(),
+            keyset_data: route(192, 168, 0, 0, 16),
+        },
+    ]);
+    assert_eq!(
+        result,
+        Err(TableUpdateError::NoSuchEntry {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+
+    // so the add was rolled back
+    assert!(pipeline
+        .get_table_entries("ingress.routes")
+        .unwrap()
+        .is_empty());
+    assert_eq!(send(&mut pipeline, "192.168.0.1", "172.16.0.1"), miss);
+    assert_eq!(
+        pipeline
+            .remove_table_entry("ingress.routes", &route(172, 16, 0, 0, 12)),
+        Err(TableUpdateError::NoSuchEntry {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+    assert_eq!(
+        pipeline
+            .ingress_routes()
+            .remove(Lpm(Ipv4Addr::new(172, 16, 0, 0), 12)),
+        Err(TableUpdateError::NoSuchEntry {
+            table_id: "ingress.routes".into(),
+        }),
+    );
+    pipeline
+        .ingress_routes()
+        .add(Lpm(Ipv4Addr::new(172, 16, 0, 0), 12), forward())
+        .unwrap();
+    assert_eq!(
+        send(&mut pipeline, "192.168.0.1", "172.16.0.1"),
+        Some((1, nexthop)),
+    );
+}
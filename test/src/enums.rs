@@ -0,0 +1,40 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/enums.p4", pipeline_name = "enums");
+
+#[test]
+fn enums() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // 0x1234 is not an EtherType member, and is carried through the header
+    // field unchanged
+    phy0.send(&[TxFrame::new(phy1.mac, 0x1234, b"left")])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x1234, b"left")]);
+
+    // IPv6 frames go right and have their ethertype rewritten
+    phy0.send(&[TxFrame::new(phy2.mac, 0x86dd, b"right")])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x1701, b"right")]);
+
+    Ok(())
+}
+
+#[test]
+fn enum_conversions() {
+    let bv: bitvec::vec::BitVec<u8, bitvec::order::Msb0> =
+        EtherType::IPv6.into();
+    assert_eq!(EtherType::from(&bv), EtherType::IPv6);
+    assert_eq!(Direction::default(), Direction::Left);
+
+    // values that do not name a member round trip through the enum
+    let mut bv = bv;
+    bitvec::field::BitField::store_le(bv.as_mut_bitslice(), 0x4747u16);
+    assert_eq!(EtherType::from(&bv), EtherType::Unknown(0x4747));
+    assert_eq!(BitVec::<u8, Msb0>::from(EtherType::from(&bv)), bv);
+}
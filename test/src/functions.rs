@@ -0,0 +1,36 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/functions.p4", pipeline_name = "functions");
+
+const CALC: u16 = 0x88b5;
+
+fn calc(ttl: u8, a: u16, b: u16, hops: u8) -> Vec<u8> {
+    [&[ttl][..], &a.to_be_bytes(), &b.to_be_bytes(), &[hops]].concat()
+}
+
+#[test]
+fn functions() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // the ttl is decremented by both ingress and egress, a and b are swapped
+    // and the packet is forwarded to the port given by b
+    phy0.send(&[TxFrame::new(phy1.mac, CALC, &calc(64, 7, 1, 0))])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, CALC, &calc(62, 1, 7, 1))]);
+
+    phy0.send(&[TxFrame::new(phy2.mac, CALC, &calc(2, 9, 2, 4))])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, CALC, &calc(0, 2, 9, 5))]);
+
+    // expired packets are dropped, the next packet is the first to arrive
+    phy0.send(&[TxFrame::new(phy1.mac, CALC, &calc(1, 0, 1, 0))])?;
+    phy0.send(&[TxFrame::new(phy1.mac, CALC, &calc(3, 0, 1, 0))])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, CALC, &calc(1, 1, 0, 1))]);
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_stack.p4",
+    pipeline_name = "header_stack"
+);
+
+const LABELED: u16 = 0x88b5;
+
+fn label(label: u16, bos: bool, ttl: u8) -> [u8; 4] {
+    let [hi, lo] = label.to_be_bytes();
+    [hi, lo, bos as u8, ttl]
+}
+
+#[test]
+fn header_stack() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    // the outer label is popped
+    let pkt = [label(100, false, 10), label(7, true, 10)].concat();
+    phy0.send(&[TxFrame::new(
+        phy1.mac,
+        LABELED,
+        &[&pkt[..], b"pop"].concat(),
+    )])?;
+    let expected = [&label(7, true, 10)[..], b"pop"].concat();
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    // the outer label is swapped and a new label is pushed on top
+    let pkt = label(200, true, 10);
+    phy0.send(&[TxFrame::new(
+        phy2.mac,
+        LABELED,
+        &[&pkt[..], b"push"].concat(),
+    )])?;
+    let expected =
+        [&label(300, false, 64)[..], &label(201, true, 63), b"push"].concat();
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    // a full stack is forwarded untouched
+    let pkt =
+        [label(1, false, 10), label(2, false, 10), label(3, true, 10)].concat();
+    phy0.send(&[TxFrame::new(
+        phy3.mac,
+        LABELED,
+        &[&pkt[..], b"full"].concat(),
+    )])?;
+    let expected = [&pkt[..], b"full"].concat();
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    // more labels than the stack holds are rejected by the parser, and a
+    // shallow stack without a known label is dropped
+    let pkt = [
+        label(1, false, 10),
+        label(2, false, 10),
+        label(3, false, 10),
+        label(4, true, 10),
+    ]
+    .concat();
+    phy0.send(&[TxFrame::new(
+        phy3.mac,
+        LABELED,
+        &[&pkt[..], b"deep"].concat(),
+    )])?;
+    let pkt = label(1, true, 10);
+    phy0.send(&[TxFrame::new(
+        phy3.mac,
+        LABELED,
+        &[&pkt[..], b"drop"].concat(),
+    )])?;
+    let pkt =
+        [label(1, false, 10), label(2, false, 10), label(3, true, 10)].concat();
+    phy0.send(&[TxFrame::new(phy3.mac, LABELED, &[&pkt[..], b"ok"].concat())])?;
+    let expected = [&pkt[..], b"ok"].concat();
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_stack_bounds.p4",
+    pipeline_name = "header_stack_bounds"
+);
+
+fn label(label: u16, bos: bool, ttl: u8) -> [u8; 4] {
+    let [hi, lo] = label.to_be_bytes();
+    [hi, lo, bos as u8, ttl]
+}
+
+/// Run a frame with the given ethertype and labels through the pipeline,
+/// returning the egress port and the headers following the ethernet header,
+/// if the packet was not dropped.
+fn send(
+    pipeline: &mut main_pipeline,
+    ether_type: u16,
+    labels: &[[u8; 4]],
+) -> Option<(u16, Vec<u8>)> {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(&ether_type.to_be_bytes());
+    data.extend_from_slice(&labels.concat());
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(0, &mut pkt);
+    let (out, port) = out.first()?;
+    Some((*port, out.header_data[14..].to_vec()))
+}
+
+#[test]
+fn header_stack_bounds() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // next and last out of range in the parser reject the packet
+    assert_eq!(send(&mut pipeline, 0x88b6, &[]), None);
+    let full = [label(1, false, 1), label(2, false, 1), label(3, true, 1)];
+    assert_eq!(send(&mut pipeline, 0x88b7, &full), None);
+    assert_eq!(
+        send(&mut pipeline, 0x88b7, &[label(1, true, 1)]),
+        Some((1, label(1, true, 1).to_vec())),
+    );
+
+    // in controls next pushes a label only when there is room, and the
+    // pushed label is found by a runtime index
+    assert_eq!(
+        send(&mut pipeline, 0x88b5, &[label(1, true, 1)]),
+        Some((2, [label(1, true, 1), label(0, false, 5)].concat())),
+    );
+    assert_eq!(
+        send(
+            &mut pipeline,
+            0x88b5,
+            &[label(1, false, 1), label(2, true, 1)]
+        ),
+        Some((
+            2,
+            [label(1, false, 1), label(2, true, 1), label(0, false, 5)]
+                .concat()
+        )),
+    );
+    assert_eq!(send(&mut pipeline, 0x88b5, &full), Some((2, full.concat())),);
+
+    // and last of an empty stack is left alone
+    assert_eq!(send(&mut pipeline, 0x0800, &[]), Some((1, Vec::new())));
+}
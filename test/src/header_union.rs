@@ -0,0 +1,64 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_union.p4",
+    pipeline_name = "header_union"
+);
+
+const TUNNEL: u16 = 0x88b5;
+
+fn tunnel(next: u16, vni: u16) -> Vec<u8> {
+    [next.to_be_bytes(), vni.to_be_bytes()].concat()
+}
+
+fn ipv4() -> Vec<u8> {
+    let mut h = vec![0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0];
+    h.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    h
+}
+
+fn ipv6(hop_limit: u8) -> Vec<u8> {
+    let mut h = vec![0x60, 0, 0, 0, 0, 0, 17, hop_limit];
+    h.extend_from_slice(&[0xfd; 32]);
+    h
+}
+
+#[test]
+fn header_union() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    // an inner ipv4 header is forwarded on port 1
+    let pkt = [tunnel(0x0800, 1), ipv4(), b"four".to_vec()].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &pkt)])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+
+    // an inner ipv6 header is forwarded on port 2
+    let pkt = [tunnel(0x86dd, 1), ipv6(64), b"six".to_vec()].concat();
+    phy0.send(&[TxFrame::new(phy2.mac, TUNNEL, &pkt)])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+
+    // making the inner ipv6 header valid invalidates the inner ipv4 header,
+    // so only the ipv6 header is emitted
+    let pkt = [tunnel(0x0800, 99), ipv4(), b"swap".to_vec()].concat();
+    phy0.send(&[TxFrame::new(phy3.mac, TUNNEL, &pkt)])?;
+    let mut inner = vec![0u8; 40];
+    inner[7] = 1;
+    let expected = [tunnel(0x86dd, 99), inner, b"swap".to_vec()].concat();
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, TUNNEL, &expected)]);
+
+    // a tunnel without an inner header is dropped
+    phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &tunnel(0x1234, 1))])?;
+    let pkt = [tunnel(0x0800, 1), ipv4(), b"ok".to_vec()].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &pkt)])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+
+    Ok(())
+}
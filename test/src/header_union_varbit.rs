@@ -0,0 +1,32 @@
+p4_macro::use_p4!(
+    p4 = "test/src/p4/header_union_varbit.p4",
+    pipeline_name = "header_union_varbit"
+);
+
+/// Run a frame carrying `option` through the pipeline, returning the egress
+/// port and the frame sent out of it.
+fn send(pipeline: &mut main_pipeline, option: &[u8]) -> (u16, Vec<u8>) {
+    let mut data = vec![0u8; 12];
+    data.extend_from_slice(&0x88b5u16.to_be_bytes());
+    data.extend_from_slice(option);
+    data.extend_from_slice(b"muffins");
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(0, &mut pkt);
+    let (out, port) = &out[0];
+    (*port, [&out.header_data[..], out.payload_data].concat())
+}
+
+#[test]
+fn header_union_varbit() {
+    let mut pipeline = main_pipeline::new(4);
+
+    let short = [1];
+    assert_eq!(send(&mut pipeline, &short).0, 1);
+
+    // the long option replaces the short one in the union, so only the long
+    // option is valid and it is what gets emitted
+    let long = [2, 5, 0xaa, 0xbb];
+    let (port, frame) = send(&mut pipeline, &long);
+    assert_eq!(port, 2);
+    assert_eq!(&frame[14..], [&long[1..], &b"muffins"[..]].concat());
+}
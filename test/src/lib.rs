@@ -1,25 +1,77 @@
 #[cfg(test)]
+mod apply_result;
+#[cfg(test)]
 mod basic_router;
 #[cfg(test)]
+mod casts;
+#[cfg(test)]
+mod const_lpm;
+#[cfg(test)]
 mod controller_multiple_instantiation;
 #[cfg(test)]
 mod decap;
 #[cfg(test)]
+mod default_action;
+#[cfg(test)]
+mod deparser;
+#[cfg(test)]
 mod disag_router;
 #[cfg(test)]
 mod dload;
 #[cfg(test)]
 mod dynamic_router;
 #[cfg(test)]
+mod entry_builder;
+#[cfg(test)]
+mod enums;
+#[cfg(test)]
+mod functions;
+#[cfg(test)]
+mod header_stack;
+#[cfg(test)]
+mod header_stack_bounds;
+#[cfg(test)]
+mod header_union;
+#[cfg(test)]
+mod header_union_varbit;
+#[cfg(test)]
 mod headers;
 #[cfg(test)]
 mod hub;
 #[cfg(test)]
+mod lookahead;
+#[cfg(test)]
 mod mac_rewrite;
 #[cfg(test)]
+mod operators;
+#[cfg(test)]
+mod package;
+#[cfg(test)]
+mod parser_error;
+#[cfg(test)]
+mod parser_error_unread;
+#[cfg(test)]
+mod psa_basic;
+#[cfg(test)]
+mod psa_clone;
+#[cfg(test)]
 mod range;
 #[cfg(test)]
+mod select;
+#[cfg(test)]
+mod signed;
+#[cfg(test)]
+mod slices;
+#[cfg(test)]
+mod switch;
+#[cfg(test)]
 mod table_in_egress_and_ingress;
+#[cfg(test)]
+mod unaligned;
+#[cfg(test)]
+mod v1model_basic;
+#[cfg(test)]
+mod varbit;
 
 pub mod data;
 pub mod packet;
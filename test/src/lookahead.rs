@@ -0,0 +1,87 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/lookahead.p4", pipeline_name = "lookahead");
+
+const LABELED: u16 = 0x88b5;
+const PAD: [u8; 2] = [0xee, 0xee];
+const LABEL: [u8; 4] = [0, 1, 1, 64];
+
+#[test]
+fn lookahead() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // the padding is skipped, and the version nibble following the label
+    // selects the header to extract
+    let v4 = [0x45, 64, 0, 0];
+    let pkt = [&PAD[..], &LABEL, &v4, b"v4"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, LABELED, &pkt)])?;
+    let len = (14 + pkt.len()) as u16;
+    let expected =
+        [&LABEL[..], &[0x45, 64], &len.to_be_bytes(), b"v4"].concat();
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    let v6 = [0x60, 64, 0, 8];
+    let pkt = [&PAD[..], &LABEL, &v6, b"v6"].concat();
+    phy0.send(&[TxFrame::new(phy2.mac, LABELED, &pkt)])?;
+    let expected = [&LABEL[..], &v6, b"v6"].concat();
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    // packets failing verification or with an unknown version are rejected
+    let bad_label = [0, 1, 0, 64];
+    for pkt in [
+        [&PAD[..], &bad_label, &v4, b"bos"].concat(),
+        [&PAD[..], &LABEL, &[0x45, 0, 0, 0], b"ttl"].concat(),
+        [&PAD[..], &LABEL, &[0x60, 0, 0, 8], b"hop"].concat(),
+        [&PAD[..], &LABEL, &[0x50, 64, 0, 0], b"ver"].concat(),
+    ] {
+        phy0.send(&[TxFrame::new(phy1.mac, LABELED, &pkt)])?;
+    }
+    let pkt = [&PAD[..], &LABEL, &v4, b"ok"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, LABELED, &pkt)])?;
+    let len = (14 + pkt.len()) as u16;
+    let expected =
+        [&LABEL[..], &[0x45, 64], &len.to_be_bytes(), b"ok"].concat();
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, LABELED, &expected)]);
+
+    Ok(())
+}
+
+#[test]
+fn verify_records_error() {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&LABELED.to_be_bytes());
+    frame.extend_from_slice(
+        &[&PAD[..], &[0, 1, 1, 64], &[0x45, 0, 0, 0]].concat(),
+    );
+
+    let mut pkt = p4rs::packet_in::new(&frame);
+    let mut hdr = headers_t::default();
+    let mut ingress = ingress_metadata_t::default();
+    assert!(!parse_start(&mut pkt, &mut hdr, &mut ingress));
+    assert_eq!(pkt.error, Some("ParserInvalidArgument"));
+}
+
+#[test]
+fn wide_lookahead() {
+    // lookahead is not limited to the width of an integer, and bits beyond
+    // the end of the packet read as zero
+    let data: Vec<u8> = (1..=20).collect();
+    let mut pkt = p4rs::packet_in::new(&data);
+    pkt.advance(4).unwrap();
+    let bits = data.view_bits::<Msb0>();
+    assert_eq!(
+        pkt.lookahead_bits(136),
+        p4rs::field_from_wire(&bits[4..140]),
+    );
+
+    let mut wire = bits[4..].to_bitvec();
+    wire.resize(200, false);
+    assert_eq!(pkt.lookahead_bits(200), p4rs::field_from_wire(&wire));
+}
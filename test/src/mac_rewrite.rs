@@ -7,27 +7,26 @@ p4_macro::use_p4!(
     pipeline_name = "mac_rewrite",
 );
 
-///
-///   ~~~~~~~~~~                 
-///   ~        ~                 
-///   ~   p4   ~                 *   *=======*
-///   ~        ~                 |   |       |
-///   ~~~~~~~~~~                 |---| phy 1 |
-///       |                      |   |       |
-///       |                      |   *=======*
-///  *==========*                |   *=======*
-///  |          | <-- ( rx ) --- |   |       |
-///  | pipeline |                |---| phy 2 |
-///  |          | --- ( tx ) --> |   |       |
-///  *==========*                |   *=======*
-///      |  |                    |   *=======*
-///      |  |                    |   |       |
-///   *========*                 |---| phy 3 |
-///   |        |                 |   |       |
-///   |   sc   |                 *   *=======*
-///   | (phy0) |                 
-///   *========*                 
-///
+//
+//   ~~~~~~~~~~
+//   ~        ~
+//   ~   p4   ~                 *   *=======*
+//   ~        ~                 |   |       |
+//   ~~~~~~~~~~                 |---| phy 1 |
+//       |                      |   |       |
+//       |                      |   *=======*
+//  *==========*                |   *=======*
+//  |          | <-- ( rx ) --- |   |       |
+//  | pipeline |                |---| phy 2 |
+//  |          | --- ( tx ) --> |   |       |
+//  *==========*                |   *=======*
+//      |  |                    |   *=======*
+//      |  |                    |   |       |
+//   *========*                 |---| phy 3 |
+//   |        |                 |   |       |
+//   |   sc   |                 *   *=======*
+//   | (phy0) |
+//   *========*
 
 fn v6_arg(addr: Ipv6Addr) -> [u8; 16] {
     let k: u128 = addr.into();
@@ -45,47 +44,65 @@ fn mac_rewrite2() -> Result<(), anyhow::Error> {
     let addr_d: Ipv6Addr = "fe80::aae1:deff:fe01:701d".parse().unwrap();
     let addr_e: Ipv6Addr = "fe80::aae1:deff:fe01:701e".parse().unwrap();
 
-    pipeline.add_ingress_local_local_entry(
-        "set_local",
-        &v6_arg(addr_c),
-        &Vec::new(),
-        0,
-    );
-    pipeline.add_ingress_local_local_entry(
-        "set_local",
-        &v6_arg(addr_d),
-        &Vec::new(),
-        0,
-    );
-    pipeline.add_ingress_local_local_entry(
-        "set_local",
-        &v6_arg(addr_e),
-        &Vec::new(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.local.local",
+            "set_local",
+            &v6_arg(addr_c),
+            &Vec::new(),
+            0,
+        )
+        .unwrap();
+    pipeline
+        .add_table_entry(
+            "ingress.local.local",
+            "set_local",
+            &v6_arg(addr_d),
+            &Vec::new(),
+            0,
+        )
+        .unwrap();
+    pipeline
+        .add_table_entry(
+            "ingress.local.local",
+            "set_local",
+            &v6_arg(addr_e),
+            &Vec::new(),
+            0,
+        )
+        .unwrap();
 
     // resolver table entries
 
-    pipeline.add_ingress_router_resolver_resolver_entry(
-        "rewrite_dst",
-        &v6_arg(addr_c),
-        &[0x44, 0x44, 0x44, 0x44, 0x44, 0x44],
-        0,
-    );
-
-    pipeline.add_ingress_router_resolver_resolver_entry(
-        "rewrite_dst",
-        &v6_arg(addr_d),
-        &[0x33, 0x33, 0x33, 0x33, 0x33, 0x33],
-        0,
-    );
-
-    pipeline.add_ingress_router_resolver_resolver_entry(
-        "rewrite_dst",
-        &v6_arg(addr_e),
-        &[0x22, 0x22, 0x22, 0x22, 0x22, 0x22],
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.router.resolver.resolver",
+            "rewrite_dst",
+            &v6_arg(addr_c),
+            &[0x44, 0x44, 0x44, 0x44, 0x44, 0x44],
+            0,
+        )
+        .unwrap();
+
+    pipeline
+        .add_table_entry(
+            "ingress.router.resolver.resolver",
+            "rewrite_dst",
+            &v6_arg(addr_d),
+            &[0x33, 0x33, 0x33, 0x33, 0x33, 0x33],
+            0,
+        )
+        .unwrap();
+
+    pipeline
+        .add_table_entry(
+            "ingress.router.resolver.resolver",
+            "rewrite_dst",
+            &v6_arg(addr_e),
+            &[0x22, 0x22, 0x22, 0x22, 0x22, 0x22],
+            0,
+        )
+        .unwrap();
 
     // routing table entries
 
@@ -94,21 +111,27 @@ fn mac_rewrite2() -> Result<(), anyhow::Error> {
     key.push(24); // prefix length
     let mut args = 1u16.to_le_bytes().to_vec();
     args.extend_from_slice(&v6_arg(addr_c));
-    pipeline.add_ingress_router_router_entry("forward", &key, &args, 0);
+    pipeline
+        .add_table_entry("ingress.router.router", "forward", &key, &args, 0)
+        .unwrap();
 
     let prefix: Ipv6Addr = "fd00:2000::".parse().unwrap();
     let mut key = prefix.octets().to_vec();
     key.push(24); // prefix length
     let mut args = 2u16.to_le_bytes().to_vec();
     args.extend_from_slice(&v6_arg(addr_d));
-    pipeline.add_ingress_router_router_entry("forward", &key, &args, 0);
+    pipeline
+        .add_table_entry("ingress.router.router", "forward", &key, &args, 0)
+        .unwrap();
 
     let prefix: Ipv6Addr = "fd00:3000::".parse().unwrap();
     let mut key = prefix.octets().to_vec();
     key.push(24); // prefix length
     let mut args = 3u16.to_le_bytes().to_vec();
     args.extend_from_slice(&v6_arg(addr_e));
-    pipeline.add_ingress_router_router_entry("forward", &key, &args, 0);
+    pipeline
+        .add_table_entry("ingress.router.router", "forward", &key, &args, 0)
+        .unwrap();
 
     //
     // run program
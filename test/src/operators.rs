@@ -0,0 +1,69 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/operators.p4", pipeline_name = "operators");
+
+const OPS: u16 = 0x88b5;
+
+fn ops(ttl: u8, flags: u8, a: u16, b: u16) -> Vec<u8> {
+    [
+        &[ttl, flags][..],
+        &a.to_be_bytes(),
+        &b.to_be_bytes(),
+        &[0; 14],
+    ]
+    .concat()
+}
+
+fn evaluated(ttl: u8, flags: u8, a: u16, b: u16) -> Vec<u8> {
+    let result = a.wrapping_mul(3).wrapping_add(b / 2);
+    let joined = ((a as u32) << 16) | b as u32;
+    let shifted = (a << 4) | (b >> 8);
+    [
+        &[ttl, flags][..],
+        &a.to_be_bytes(),
+        &b.to_be_bytes(),
+        &result.to_be_bytes(),
+        &joined.to_be_bytes(),
+        &shifted.to_be_bytes(),
+        &(!a).to_be_bytes(),
+        &b.wrapping_neg().to_be_bytes(),
+        &a.max(b).to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn operators() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // arithmetic, shifts, concatenation, unary and conditional operators
+    for (ttl, flags, a, b, phy) in [
+        (64, 1, 0x1234, 0x0abc, &phy2),
+        (64, 3, 0x0abc, 0x1234, &phy1),
+        (5, 1, 0xffff, 0x0001, &phy1),
+        (11, 1, 0x8000, 0x8000, &phy2),
+    ] {
+        let pkt = ops(ttl, flags, a, b);
+        phy0.send(&[TxFrame::new(phy.mac, OPS, &pkt)])?;
+        let expected = evaluated(ttl, flags, a, b);
+        expect_frames!(phy, &[RxFrame::new(phy0.mac, OPS, &expected)]);
+    }
+
+    // expiring ttls and cleared flags are dropped
+    phy0.send(&[TxFrame::new(phy1.mac, OPS, &ops(1, 1, 1, 1))])?;
+    phy0.send(&[TxFrame::new(phy1.mac, OPS, &ops(64, 2, 1, 1))])?;
+    phy0.send(&[TxFrame::new(phy1.mac, OPS, &ops(64, 1, 1, 2))])?;
+    expect_frames!(
+        phy1,
+        &[RxFrame::new(phy0.mac, OPS, &evaluated(64, 1, 1, 2))]
+    );
+
+    Ok(())
+}
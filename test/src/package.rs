@@ -0,0 +1,38 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/package.p4", pipeline_name = "package");
+
+const CHECKED: u16 = 0x88b5;
+const TAGGED: u16 = 0x88b6;
+
+#[test]
+fn package() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(2);
+    let mut npu = SoftNpu::new(2, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+
+    npu.run();
+
+    // the ingress deparser places the tag ahead of the check header, and the
+    // egress parser finds it there to record the hop and egress port
+    let pkt = [&[1, 64][..], b"muffins"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    let out = [&[1, 1, 1, 64][..], b"muffins"].concat();
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, TAGGED, &out)]);
+
+    Ok(())
+}
+
+#[test]
+fn package_drop() {
+    let mut pipeline = main_pipeline::new(2);
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&CHECKED.to_be_bytes());
+    frame.extend_from_slice(&[2, 64]);
+
+    // ingress drops everything that is not kind 1
+    let mut pkt = packet_in::new(&frame);
+    assert!(pipeline.process_packet(0, &mut pkt).is_empty());
+}
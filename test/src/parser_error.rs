@@ -0,0 +1,65 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(
+    p4 = "test/src/p4/parser_error.p4",
+    pipeline_name = "parser_error"
+);
+
+const CHECKED: u16 = 0x88b5;
+
+#[test]
+fn parser_error() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    // well formed packets are forwarded as usual
+    let pkt = [&[1, 64][..], b"ok"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, CHECKED, &pkt)]);
+
+    // a failed verification is visible to ingress, which punts the packet
+    // unmodified
+    let pkt = [&[1, 0][..], b"ttl"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, CHECKED, &pkt)]);
+
+    // as is a select without a matching keyset
+    let pkt = [&[2, 64][..], b"kind"].concat();
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, CHECKED, &pkt)]);
+
+    // and a packet that ends before the header being extracted
+    let pkt = [1];
+    phy0.send(&[TxFrame::new(phy1.mac, CHECKED, &pkt)])?;
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, CHECKED, &pkt)]);
+
+    Ok(())
+}
+
+#[test]
+fn truncated_packets() {
+    let mut pipeline = main_pipeline::new(4);
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&CHECKED.to_be_bytes());
+    frame.extend_from_slice(&[1, 64]);
+
+    // every truncation of the frame is punted whole, the complete frame is
+    // forwarded
+    for n in 0..=frame.len() {
+        let mut pkt = packet_in::new(&frame[..n]);
+        let out = pipeline.process_packet(0, &mut pkt);
+        let port = if n < frame.len() { 3 } else { 1 };
+        assert_eq!(out.len(), 1, "output for {} bytes", n);
+        assert_eq!(out[0].1, port, "port for {} bytes", n);
+        let (out, _) = &out[0];
+        let data = [&out.header_data[..], out.payload_data].concat();
+        assert_eq!(data, &frame[..n]);
+    }
+}
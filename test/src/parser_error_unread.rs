@@ -0,0 +1,31 @@
+p4_macro::use_p4!(
+    p4 = "test/src/p4/parser_error_unread.p4",
+    pipeline_name = "parser_error_unread"
+);
+
+const CHECKED: u16 = 0x88b5;
+
+fn frame(check: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&CHECKED.to_be_bytes());
+    frame.extend_from_slice(check);
+    frame
+}
+
+#[test]
+fn parser_error_unread() {
+    let mut pipeline = main_pipeline::new(3);
+
+    // well formed packets are forwarded as usual
+    let pkt = frame(&[0, 64]);
+    let out = pipeline.process_packet(0, &mut packet_in::new(&pkt));
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+
+    // rejected packets are dropped by the parser, as the program never reads
+    // the parser error
+    for pkt in [frame(&[0, 0]), frame(&[0])] {
+        let out = pipeline.process_packet(0, &mut packet_in::new(&pkt));
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+p4_macro::use_p4!(p4 = "test/src/p4/psa_basic.p4", pipeline_name = "psa_basic");
+
+const CHECKED: u16 = 0x88b5;
+
+fn frame(kind: u8) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&CHECKED.to_be_bytes());
+    frame.extend_from_slice(&[kind, 0, 0]);
+    frame.extend_from_slice(b"muffins");
+    frame
+}
+
+/// Process a frame, returning the output for each port it is sent to.
+fn process(pipeline: &mut main_pipeline, frame: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let mut pkt = packet_in::new(frame);
+    pipeline
+        .process_packet(0, &mut pkt)
+        .into_iter()
+        .map(|(out, port)| {
+            (port, [&out.header_data[..], out.payload_data].concat())
+        })
+        .collect()
+}
+
+fn forwarded(kind: u8, port: u8) -> Vec<u8> {
+    let mut frame = frame(kind);
+    frame[15] = 1;
+    frame[16] = port;
+    frame
+}
+
+#[test]
+fn psa_basic() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // kind 1 is sent to port 2, kind 2 to multicast group 7
+    pipeline
+        .add_table_entry("ingress.fwd", "forward", &[1], &2u32.to_le_bytes(), 0)
+        .unwrap();
+    pipeline
+        .add_table_entry("ingress.fwd", "flood", &[2], &7u32.to_le_bytes(), 0)
+        .unwrap();
+    for port in 1..=3 {
+        pipeline.pre.add_group_member(7, port);
+    }
+
+    // egress records the hop and the port it ran for
+    assert_eq!(process(&mut pipeline, &frame(1)), [(2, forwarded(1, 2))]);
+
+    // each group member gets a copy, egress drops the copy for port 3
+    assert_eq!(
+        process(&mut pipeline, &frame(2)),
+        [(1, forwarded(2, 1)), (2, forwarded(2, 2))],
+    );
+
+    // ingress drops packets it does not send anywhere
+    assert!(process(&mut pipeline, &frame(3)).is_empty());
+    let mut other = frame(1);
+    other[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    assert!(process(&mut pipeline, &other).is_empty());
+}
@@ -0,0 +1,83 @@
+p4_macro::use_p4!(p4 = "test/src/p4/psa_clone.p4", pipeline_name = "psa_clone");
+
+const CHECKED: u16 = 0x88b5;
+
+/// A frame of the given kind, with the paths it took and the extra value as
+/// check_h records them.
+fn frame(kind: u8, ingress_path: u8, egress_path: u8, extra: u8) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&CHECKED.to_be_bytes());
+    frame.extend_from_slice(&[kind, ingress_path, egress_path, extra]);
+    frame.extend_from_slice(b"muffins");
+    frame
+}
+
+/// Process a frame, returning the output for each port it is sent to.
+fn process(pipeline: &mut main_pipeline, frame: &[u8]) -> Vec<(u16, Vec<u8>)> {
+    let mut pkt = packet_in::new(frame);
+    pipeline
+        .process_packet(0, &mut pkt)
+        .into_iter()
+        .map(|(out, port)| {
+            (port, [&out.header_data[..], out.payload_data].concat())
+        })
+        .collect()
+}
+
+#[test]
+fn psa_clone() {
+    let mut pipeline = main_pipeline::new(4);
+    pipeline.pre.set_clone_session(5, 3);
+    pipeline.pre.set_clone_session(6, 2);
+
+    // ingress clones the packet as it arrived, whether or not it drops it
+    assert_eq!(
+        process(&mut pipeline, &frame(1, 0, 0, 0)),
+        [(3, frame(1, 0, 3, 0)), (1, frame(1, 1, 1, 0))],
+    );
+    assert_eq!(
+        process(&mut pipeline, &frame(2, 0, 0, 0)),
+        [(3, frame(2, 0, 3, 0))],
+    );
+
+    // egress clones the packet it puts out, and hands the clone what its
+    // deparser put out
+    assert_eq!(
+        process(&mut pipeline, &frame(6, 0, 0, 0)),
+        [(1, frame(6, 1, 1, 0)), (2, frame(6, 1, 4, 9))],
+    );
+
+    // clones of clones are bounded
+    assert_eq!(
+        process(&mut pipeline, &frame(7, 0, 0, 0)),
+        vec![(2, frame(7, 1, 4, 9)); p4rs::externs::MAX_EGRESS_CLONES],
+    );
+
+    // clones through sessions that send clones nowhere are not made
+    assert_eq!(
+        process(&mut pipeline, &frame(8, 0, 0, 0)),
+        [(1, frame(8, 1, 1, 0))],
+    );
+    pipeline.pre.remove_clone_session(5);
+    assert_eq!(
+        process(&mut pipeline, &frame(1, 0, 0, 0)),
+        [(1, frame(1, 1, 1, 0))],
+    );
+}
+
+#[test]
+fn psa_resubmit() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // each pass is handed what the ingress deparser put out in the last
+    assert_eq!(
+        process(&mut pipeline, &frame(3, 0, 0, 0)),
+        [(2, frame(3, 2, 1, 2))],
+    );
+
+    // packets resubmitted without end are dropped
+    assert!(process(&mut pipeline, &frame(4, 0, 0, 0)).is_empty());
+
+    // dropping takes precedence over resubmission
+    assert!(process(&mut pipeline, &frame(5, 0, 0, 0)).is_empty());
+}
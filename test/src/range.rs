@@ -22,48 +22,60 @@ fn range() -> Result<(), anyhow::Error> {
     let mut buf = begin.to_vec();
     buf.extend_from_slice(&end);
 
-    pipeline.add_ingress_power_ranger_entry(
-        "forward",
-        &buf,
-        &0u16.to_le_bytes(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.power_ranger",
+            "forward",
+            &buf,
+            &0u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
 
     let begin = v4_range_key("6.0.0.0".parse().unwrap());
     let end = v4_range_key("8.0.0.0".parse().unwrap());
     let mut buf = begin.to_vec();
     buf.extend_from_slice(&end);
 
-    pipeline.add_ingress_power_ranger_entry(
-        "forward",
-        &buf,
-        &1u16.to_le_bytes(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.power_ranger",
+            "forward",
+            &buf,
+            &1u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
 
     let begin = v4_range_key("10.0.0.0".parse().unwrap());
     let end = v4_range_key("12.0.0.0".parse().unwrap());
     let mut buf = begin.to_vec();
     buf.extend_from_slice(&end);
 
-    pipeline.add_ingress_power_ranger_entry(
-        "forward",
-        &buf,
-        &2u16.to_le_bytes(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.power_ranger",
+            "forward",
+            &buf,
+            &2u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
 
     let begin = v4_range_key("14.0.0.0".parse().unwrap());
     let end = v4_range_key("16.0.0.0".parse().unwrap());
     let mut buf = begin.to_vec();
     buf.extend_from_slice(&end);
 
-    pipeline.add_ingress_power_ranger_entry(
-        "forward",
-        &buf,
-        &3u16.to_le_bytes(),
-        0,
-    );
+    pipeline
+        .add_table_entry(
+            "ingress.power_ranger",
+            "forward",
+            &buf,
+            &3u16.to_le_bytes(),
+            0,
+        )
+        .unwrap();
 
     //
     // run program
@@ -0,0 +1,51 @@
+use crate::expect_frames;
+use crate::softnpu::{Interface4, Interface6, RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/select.p4", pipeline_name = "select");
+
+#[test]
+fn select() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    let if0 = Interface4::new(phy0.clone(), "1.0.0.1".parse().unwrap());
+    let if0_6 = Interface6::new(phy0.clone(), "fd00::1".parse().unwrap());
+
+    npu.run();
+
+    // exact match on ethertype followed by a range match on the ipv4
+    // destination
+    if0.send(phy1.mac, "10.1.2.3".parse().unwrap(), b"range")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"range")]);
+
+    // masked match on the ipv4 destination
+    if0.send(phy1.mac, "11.7.7.7".parse().unwrap(), b"mask")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"mask")]);
+
+    // no ipv4 keyset matches, so the parser rejects the first packet
+    if0.send(phy1.mac, "12.0.0.1".parse().unwrap(), b"rejected")?;
+    if0.send(phy1.mac, "10.0.0.1".parse().unwrap(), b"accepted")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"accepted")]);
+
+    if0_6.send(phy2.mac, "fd00::2".parse().unwrap(), b"v6")?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x86dd, b"v6")]);
+
+    // masked and ranged matches on the ethertype, the default case rejects
+    // the unknown ethertype
+    phy0.send(&[TxFrame::new(phy3.mac, 0x9047, b"masked")])?;
+    phy0.send(&[TxFrame::new(phy3.mac, 0x0101, b"default")])?;
+    phy0.send(&[TxFrame::new(phy3.mac, 0x1234, b"ranged")])?;
+    expect_frames!(
+        phy3,
+        &[
+            RxFrame::new(phy0.mac, 0x9047, b"masked"),
+            RxFrame::new(phy0.mac, 0x1234, b"ranged"),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/signed.p4", pipeline_name = "signed");
+
+const ADJUST: u16 = 0x88b5;
+
+fn adjust(offset: i16, delta: i8, level: i8, wide: i32) -> Vec<u8> {
+    [
+        &offset.to_be_bytes()[..],
+        &delta.to_be_bytes(),
+        &level.to_be_bytes(),
+        &wide.to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn signed() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    // negative sums compare below zero, the delta is added to the level
+    // saturating at the bounds of int<8> and sign extended to 32 bits
+    let cases = [
+        (-9, -3, -126, &phy1, adjust(-5, -3, -128, -3)),
+        (-5, 100, 100, &phy2, adjust(-1, 100, 127, 100)),
+        (3, 1, 7, &phy2, adjust(7, 1, 8, 1)),
+        (6, -128, 0, &phy3, adjust(10, -128, -128, -128)),
+        (i16::MAX, 2, -1, &phy1, adjust(i16::MIN + 3, 2, 1, 2)),
+    ];
+
+    for (offset, delta, level, phy, expected) in cases {
+        let pkt = adjust(offset, delta, level, 0);
+        phy0.send(&[TxFrame::new(phy.mac, ADJUST, &pkt)])?;
+        expect_frames!(phy, &[RxFrame::new(phy0.mac, ADJUST, &expected)]);
+    }
+
+    Ok(())
+}
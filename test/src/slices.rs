@@ -0,0 +1,45 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/slices.p4", pipeline_name = "slices");
+
+const NAT: u16 = 0x88b5;
+
+fn nat(v4_src: u32, flags: u8) -> Vec<u8> {
+    [&v4_src.to_be_bytes()[..], &[0; 16], &[flags], &[0; 3]].concat()
+}
+
+fn synthesized(v4_src: u32, flags: u8) -> Vec<u8> {
+    let v6_dst = (0x0064ff9bu128 << 96) | v4_src as u128;
+    let flags = (flags & !0b0011_1000) | 0b0010_1000;
+    let extracted = (v4_src >> 4) as u8;
+    let swapped = (v4_src as u16).swap_bytes();
+    [
+        &v4_src.to_be_bytes()[..],
+        &v6_dst.to_be_bytes(),
+        &[flags, extracted],
+        &swapped.to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn slices() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(3);
+    let mut npu = SoftNpu::new(3, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    for (v4_src, flags, phy) in
+        [(0x0a000102, 0xff, &phy1), (0xc0a8640a, 0x00, &phy2)]
+    {
+        phy0.send(&[TxFrame::new(phy.mac, NAT, &nat(v4_src, flags))])?;
+        let expected = synthesized(v4_src, flags);
+        expect_frames!(phy, &[RxFrame::new(phy0.mac, NAT, &expected)]);
+    }
+
+    Ok(())
+}
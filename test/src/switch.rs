@@ -0,0 +1,41 @@
+use crate::expect_frames;
+use crate::softnpu::{Interface4, Interface6, RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/switch.p4", pipeline_name = "switch");
+
+#[test]
+fn switch() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    let if0 = Interface4::new(phy0.clone(), "1.0.0.1".parse().unwrap());
+    let if0_6 = Interface6::new(phy0.clone(), "fd00::1".parse().unwrap());
+
+    npu.run();
+
+    // the action the table ran selects the egress port
+    if0.send(phy1.mac, "10.0.0.1".parse().unwrap(), b"v4")?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, 0x0800, b"v4")]);
+
+    if0_6.send(phy2.mac, "fd00::2".parse().unwrap(), b"v6")?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, 0x86dd, b"v6")]);
+
+    // the default action falls through to a switch on the ethertype, where
+    // consecutive labels share a block and the default label drops
+    phy0.send(&[TxFrame::new(phy3.mac, 0x9000, b"first")])?;
+    phy0.send(&[TxFrame::new(phy3.mac, 0x1234, b"dropped")])?;
+    phy0.send(&[TxFrame::new(phy3.mac, 0x9001, b"second")])?;
+    expect_frames!(
+        phy3,
+        &[
+            RxFrame::new(phy0.mac, 0x9000, b"first"),
+            RxFrame::new(phy0.mac, 0x9001, b"second"),
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+use bitvec::prelude::*;
+
+p4_macro::use_p4!(p4 = "test/src/p4/unaligned.p4", pipeline_name = "unaligned");
+
+const SHIMMED: u16 = 0x88b5;
+
+/// Pack `(value, width)` fields back to back followed by `payload`, padding
+/// the result out to a whole byte.
+fn pack(fields: &[(u16, usize)], payload: &[u8]) -> Vec<u8> {
+    let mut bv = BitVec::<u8, Msb0>::new();
+    for (value, width) in fields {
+        let mut f = bitvec![u8, Msb0; 0; *width];
+        f.store_be(*value);
+        bv.extend_from_bitslice(&f);
+    }
+    bv.extend_from_bitslice(payload.view_bits::<Msb0>());
+    bv.resize(bv.len().div_ceil(8) << 3, false);
+    bv.into_vec()
+}
+
+#[test]
+fn unaligned() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+    let phy3 = npu.phy(3);
+
+    npu.run();
+
+    // fields at sub-byte offsets are read and written in place, and the
+    // payload following the headers is carried over bit for bit
+    let pkt = pack(&[(1, 3), (5, 5), (0xa, 4), (4, 4), (0x123, 12)], b"abc");
+    phy0.send(&[TxFrame::new(phy1.mac, SHIMMED, &pkt)])?;
+    let out = pack(&[(1, 3), (6, 5), (0xa, 4), (4, 4), (0x124, 12)], b"abc");
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, SHIMMED, &out)]);
+
+    // removing the shim shifts the payload up by half a byte, and the four
+    // bits that padded out the input round the output up to a zero byte
+    let pkt = pack(&[(2, 3), (5, 5), (0xa, 4), (4, 4), (0x123, 12)], b"abc");
+    phy0.send(&[TxFrame::new(phy1.mac, SHIMMED, &pkt)])?;
+    let out = [pack(&[(4, 4), (0x123, 12)], b"abc"), vec![0]].concat();
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, SHIMMED, &out)]);
+
+    // an unexpected id read from an unaligned offset is sent to port 3
+    let pkt = pack(&[(1, 3), (5, 5), (0xa, 4), (4, 4), (0x321, 12)], b"abc");
+    phy0.send(&[TxFrame::new(phy1.mac, SHIMMED, &pkt)])?;
+    expect_frames!(phy3, &[RxFrame::new(phy0.mac, SHIMMED, &pkt)]);
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+p4_macro::use_p4!(
+    p4 = "test/src/p4/v1model_basic.p4",
+    pipeline_name = "v1model_basic"
+);
+
+const ROUTER: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const HOST: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const NEXTHOP: [u8; 6] = [0x02, 0, 0, 0, 0, 0x03];
+
+/// The ones' complement checksum of an IPv4 header.
+fn checksum(header: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for pair in header.chunks(2) {
+        sum += u16::from_be_bytes([pair[0], pair[1]]) as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn frame(dst: [u8; 4], ttl: u8, valid: bool) -> Vec<u8> {
+    let mut ip =
+        vec![0x45, 0, 0, 27, 0, 0, 0x40, 0, ttl, 17, 0, 0, 10, 0, 1, 1];
+    ip.extend_from_slice(&dst);
+    let mut csum = checksum(&ip);
+    if !valid {
+        csum ^= 0x0101;
+    }
+    ip[10..12].copy_from_slice(&csum.to_be_bytes());
+
+    let mut frame = [ROUTER, HOST].concat();
+    frame.extend_from_slice(&0x0800u16.to_be_bytes());
+    frame.extend_from_slice(&ip);
+    frame.extend_from_slice(b"muffins");
+    frame
+}
+
+#[test]
+fn v1model_basic() {
+    let mut pipeline = main_pipeline::new(4);
+
+    // 10.0.2.0/24 goes to the next hop on port 2, action parameter data is
+    // laid out little endian
+    let mut mac = NEXTHOP;
+    mac.reverse();
+    let parameters = [&mac[..], &2u16.to_le_bytes()].concat();
+    pipeline
+        .add_table_entry(
+            "MyIngress.ipv4_lpm",
+            "ipv4_forward",
+            &[10, 0, 2, 0, 24],
+            &parameters,
+            0,
+        )
+        .unwrap();
+
+    // forwarded packets have their addresses rewritten, ttl decremented and
+    // checksum updated
+    let data = frame([10, 0, 2, 7], 64, true);
+    let mut pkt = packet_in::new(&data);
+    let out = pipeline.process_packet(1, &mut pkt);
+    assert_eq!(out.len(), 1);
+    let (out, port) = &out[0];
+    assert_eq!(*port, 2);
+    let out = [&out.header_data[..], out.payload_data].concat();
+    let mut expected = frame([10, 0, 2, 7], 63, true);
+    expected[..6].copy_from_slice(&NEXTHOP);
+    expected[6..12].copy_from_slice(&ROUTER);
+    assert_eq!(out, expected);
+
+    // packets without a route are dropped by the default action
+    let data = frame([10, 0, 3, 7], 64, true);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.process_packet(1, &mut pkt).is_empty());
+
+    // as are packets with a bad checksum
+    let data = frame([10, 0, 2, 7], 64, false);
+    let mut pkt = packet_in::new(&data);
+    assert!(pipeline.process_packet(1, &mut pkt).is_empty());
+}
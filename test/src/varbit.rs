@@ -0,0 +1,85 @@
+use crate::expect_frames;
+use crate::softnpu::{RxFrame, SoftNpu, TxFrame};
+
+p4_macro::use_p4!(p4 = "test/src/p4/varbit.p4", pipeline_name = "varbit");
+
+const TUNNEL: u16 = 0x88b5;
+
+fn tunnel(kind: u8, options: &[u8], tag: u16, payload: &[u8]) -> Vec<u8> {
+    let option_bits = (options.len() as u32) * 8;
+    [
+        &0x0800u16.to_be_bytes()[..],
+        &option_bits.to_be_bytes(),
+        &[kind],
+        options,
+        &tag.to_be_bytes(),
+        payload,
+    ]
+    .concat()
+}
+
+#[test]
+fn varbit() -> Result<(), anyhow::Error> {
+    let pipeline = main_pipeline::new(4);
+    let mut npu = SoftNpu::new(4, pipeline, false);
+    let phy0 = npu.phy(0);
+    let phy1 = npu.phy(1);
+    let phy2 = npu.phy(2);
+
+    npu.run();
+
+    // the header following the options is found regardless of how long the
+    // options are, and the options are emitted intact
+    for options in [&[][..], &[1, 2, 3, 4], &[0xa; 12]] {
+        let pkt = tunnel(1, options, 7, b"tagged");
+        phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &pkt)])?;
+        expect_frames!(phy1, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+    }
+
+    let pkt = tunnel(1, &[5; 8], 8, b"untagged");
+    phy0.send(&[TxFrame::new(phy2.mac, TUNNEL, &pkt)])?;
+    expect_frames!(phy2, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+
+    // the fixed field preceding the options is parsed
+    phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &tunnel(2, &[1; 4], 7, b"x"))])?;
+    let pkt = tunnel(1, &[1; 4], 7, b"ok");
+    phy0.send(&[TxFrame::new(phy1.mac, TUNNEL, &pkt)])?;
+    expect_frames!(phy1, &[RxFrame::new(phy0.mac, TUNNEL, &pkt)]);
+
+    Ok(())
+}
+
+#[test]
+fn varbit_bad_lengths() {
+    let mut pipeline = main_pipeline::new(4);
+    let frame = |tunnel: Vec<u8>| {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&TUNNEL.to_be_bytes());
+        frame.extend_from_slice(&tunnel);
+        frame
+    };
+
+    // options longer than the 256 bit options field are an invalid argument,
+    // whether or not the packet holds that many bits
+    let mut huge = tunnel(1, &[], 7, b"huge");
+    huge[2..6].copy_from_slice(&u32::MAX.to_be_bytes());
+    let long = tunnel(1, &[0xa; 33], 7, b"long");
+    for pkt in [frame(huge), frame(long)] {
+        let out = pipeline.process_packet(0, &mut packet_in::new(&pkt));
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].1, 3);
+    }
+
+    // options longer than the rest of the packet leave it too short
+    let mut truncated = tunnel(1, &[], 7, b"");
+    truncated[2..6].copy_from_slice(&64u32.to_be_bytes());
+    let pkt = frame(truncated);
+    let out = pipeline.process_packet(0, &mut packet_in::new(&pkt));
+    assert!(out.is_empty());
+
+    // the largest options field is accepted
+    let pkt = frame(tunnel(1, &[0xa; 32], 7, b"max"));
+    let out = pipeline.process_packet(0, &mut packet_in::new(&pkt));
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1, 1);
+}
@@ -34,6 +34,7 @@ fn run() -> Result<()> {
                 &opts.out,
                 p4_rust::Settings {
                     pipeline_name: "main".to_owned(),
+                    architectures: Vec::new(),
                 },
             )?;
         }